@@ -0,0 +1,40 @@
+//! Builds the runtime of compiled programs, the `alpha-runtime` crate in
+//! `runtime/`, as a static library that src/comp.rs embeds and links into
+//! what it builds. The runtime is a crate of its own because it needs
+//! `panic = "abort"`, so it is built with a cargo of its own.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=runtime/Cargo.toml");
+    println!("cargo:rerun-if-changed=runtime/src");
+
+    let out = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let target = env::var("TARGET").unwrap();
+    let target_dir = out.join("runtime");
+    let status = Command::new(env::var("CARGO").unwrap())
+        .args([
+            "build",
+            "--release",
+            "--manifest-path",
+            "runtime/Cargo.toml",
+        ])
+        .args(["--target", &target])
+        .arg("--target-dir")
+        .arg(&target_dir)
+        // What this crate is built with, like clippy, is not for the runtime,
+        // which keeps frame pointers for runtime/src/trace.rs to walk.
+        .env("CARGO_ENCODED_RUSTFLAGS", "-Cforce-frame-pointers=yes")
+        .env_remove("RUSTFLAGS")
+        .env_remove("RUSTC_WRAPPER")
+        .env_remove("RUSTC_WORKSPACE_WRAPPER")
+        .status()
+        .unwrap();
+    assert!(status.success(), "Cannot build the runtime");
+
+    let lib = target_dir.join(target).join("release/libalpha_runtime.a");
+    fs::copy(lib, out.join("libalpha_runtime.a")).unwrap();
+}
@@ -0,0 +1,1165 @@
+//! Values of any type for compiled alpha programs. What the compiler cannot
+//! type statically, like the items of `[1, "a"]` or the parameters of a
+//! function called with both ints and strings, it holds in a box together
+//! with the description of the type of the value inside, in the grammar of
+//! print.rs. The functions here do what src/value.rs does to values, deciding
+//! by the descriptions at runtime, and fail with the same messages.
+//!
+//! Containers whose items have one type keep it: a list of ints that gets a
+//! string is copied into a list of boxes.
+
+use core::cmp::Ordering;
+use core::ffi::{c_char, CStr};
+use core::fmt;
+use core::mem::size_of;
+use core::ptr;
+
+use crate::bigint::{self, BigInt};
+use crate::gc;
+use crate::libc;
+use crate::list::{self, List};
+use crate::map::{self, Map};
+use crate::print::{error, number, skip, text, word, Typed};
+
+/// A value and the description of its type, which is never any itself.
+#[repr(C)]
+pub struct Boxed {
+    description: *const c_char,
+    value: i64,
+}
+
+/// The names of the operators of ast::Op, in the order compiled code
+/// numbers them.
+const OPS: [&str; 12] = [
+    "Add", "Sub", "Mul", "Div", "Mod", "Pow", "Eq", "Neq", "Gt", "Ge", "Lt", "Le",
+];
+const ADD: i64 = 0;
+const SUB: i64 = 1;
+const MUL: i64 = 2;
+const DIV: i64 = 3;
+const MOD: i64 = 4;
+const POW: i64 = 5;
+const EQ: i64 = 6;
+const NEQ: i64 = 7;
+const GT: i64 = 8;
+const GE: i64 = 9;
+const LT: i64 = 10;
+
+/// Same messages as the constants in src/value.rs.
+const OVERFLOW: &str = "Integer overflow";
+const DIVISION_BY_ZERO: &str = "Division by zero";
+const FLOAT_TO_INT: &str = "Float out of int range";
+
+/// A description made at runtime, in a list of all of them.
+struct Interned {
+    next: *const Interned,
+    description: *const c_char,
+}
+
+static mut INTERNED: *const Interned = ptr::null();
+
+/// The description that is `parts` one after the other, made once for all
+/// values of that type.
+fn intern(parts: &[&[u8]]) -> *const c_char {
+    let len = parts.iter().map(|part| part.len()).sum::<usize>();
+    let equal = |mut bytes: &[u8]| {
+        parts.iter().all(|part| match bytes.strip_prefix(*part) {
+            Some(rest) => {
+                bytes = rest;
+                true
+            }
+            None => false,
+        })
+    };
+    unsafe {
+        let first = *ptr::addr_of!(INTERNED);
+        let mut interned = first;
+        while !interned.is_null() {
+            let bytes = CStr::from_ptr((*interned).description).to_bytes();
+            if bytes.len() == len && equal(bytes) {
+                return (*interned).description;
+            }
+            interned = (*interned).next;
+        }
+        let description = libc::alloc(len + 1);
+        let mut end = description;
+        for part in parts {
+            end.copy_from_nonoverlapping(part.as_ptr(), part.len());
+            end = end.add(part.len());
+        }
+        *end = 0;
+        let interned = libc::alloc(size_of::<Interned>()) as *mut Interned;
+        interned.write(Interned {
+            next: first,
+            description: description as *const c_char,
+        });
+        *ptr::addr_of_mut!(INTERNED) = interned;
+        description as *const c_char
+    }
+}
+
+/// The definitions that follow the type of a description.
+fn definitions(types: &[u8]) -> &[u8] {
+    let start = types.iter().position(|&c| c == b'\n');
+    &types[start.unwrap_or(types.len())..]
+}
+
+/// The type at the start of `ty`, without what follows it.
+fn head(ty: &[u8]) -> &[u8] {
+    &ty[..ty.len() - skip(ty).len()]
+}
+
+/// The name a type annotation uses for the type at the start of `ty`.
+fn name(ty: &[u8]) -> &str {
+    match ty[0] {
+        b'u' | b'v' => "unit",
+        b'i' => "int",
+        b'f' => "float",
+        b'b' => "bool",
+        b'n' => "bigint",
+        b's' => "str",
+        b'l' => "list",
+        b't' => "tuple",
+        b'm' => "map",
+        b'o' => "option",
+        b'@' => word(&ty[1..]).0,
+        b'r' => "range",
+        b'F' => "fn",
+        _ => "any",
+    }
+}
+
+/// A value, of the type that its description describes.
+type Value = Typed<'static>;
+
+fn int(n: i64) -> Value {
+    unsafe { Typed::new(n, c"i".as_ptr()) }
+}
+
+fn float(x: f64) -> Value {
+    unsafe { Typed::new(x.to_bits() as i64, c"f".as_ptr()) }
+}
+
+fn boolean(b: bool) -> Value {
+    unsafe { Typed::new(b as i64, c"b".as_ptr()) }
+}
+
+fn big(n: &BigInt) -> Value {
+    unsafe { Typed::new(n as *const BigInt as i64, c"n".as_ptr()) }
+}
+
+/// A list of the values of `items`, boxed.
+fn boxes(items: impl ExactSizeIterator<Item = Value>) -> Value {
+    let list = list::alpha_list_new(items.len() as i64);
+    for (slot, item) in list.items_mut().iter_mut().zip(items) {
+        *slot = item.boxed();
+    }
+    unsafe { Typed::new(list as *mut List as i64, c"la".as_ptr()) }
+}
+
+/// The value `value` of the type that `description` describes, which
+/// compiled code passes as it holds it.
+unsafe fn typed(value: i64, description: *const c_char) -> Value {
+    Typed::new(value, description)
+}
+
+/// A value for a place of the type of `place`: boxed if that is any.
+fn fit(value: Value, place: Value) -> Value {
+    match place.ty[0] {
+        b'a' => unsafe { typed(value.boxed(), c"a".as_ptr()) },
+        _ => value,
+    }
+}
+
+/// A number, as `Value::binary` sees them.
+#[derive(Clone, Copy)]
+enum Number {
+    Int(i64),
+    Big(&'static BigInt),
+    Float(f64),
+}
+
+impl Number {
+    fn to_f64(self) -> f64 {
+        match self {
+            Number::Int(n) => n as f64,
+            Number::Big(n) => bigint::alpha_bigint_to_f64(n),
+            Number::Float(x) => x,
+        }
+    }
+
+    fn to_big(self) -> &'static BigInt {
+        match self {
+            Number::Int(n) => bigint::alpha_bigint_from_i64(n),
+            Number::Big(n) => n,
+            Number::Float(_) => unreachable!("floats are not widened to bigints"),
+        }
+    }
+}
+
+/// A map key, in which bigints that fit are ints.
+#[derive(PartialEq)]
+enum Key {
+    Int(i64),
+    Big(&'static BigInt),
+    Bool(bool),
+    Str(&'static CStr),
+}
+
+impl PartialEq for BigInt {
+    fn eq(&self, other: &BigInt) -> bool {
+        bigint::alpha_bigint_cmp(self, other) == 0
+    }
+}
+
+impl Typed<'static> {
+    /// The value in the box at `value`.
+    pub unsafe fn unbox(value: i64) -> Value {
+        let boxed = &*(value as *const Boxed);
+        Typed::new(boxed.value, boxed.description)
+    }
+
+    /// The value itself, out of its box if it is of type any.
+    pub fn resolve(self) -> Value {
+        match self.ty[0] {
+            b'a' => unsafe { Typed::unbox(self.value) },
+            _ => self,
+        }
+    }
+
+    /// The name a type annotation uses for the type of the value.
+    pub fn type_name(&self) -> &'static str {
+        name(self.resolve().ty)
+    }
+
+    /// A box of the value, which is the value itself if it is of type any.
+    pub fn boxed(&self) -> i64 {
+        if self.ty[0] == b'a' {
+            return self.value;
+        }
+        let (head, definitions) = (head(self.ty), definitions(self.types));
+        let description = match head.len() + definitions.len() == self.types.len() {
+            // The description of the value is the one it came with.
+            true if ptr::eq(head.as_ptr(), self.types.as_ptr()) => self.types.as_ptr().cast(),
+            _ => intern(&[head, definitions]),
+        };
+        let boxed = gc::alloc(size_of::<Boxed>(), true) as *mut Boxed;
+        unsafe {
+            (*boxed).description = description;
+            (*boxed).value = self.value;
+        }
+        boxed as i64
+    }
+
+    fn number(&self) -> Option<Number> {
+        let value = self.resolve();
+        match value.ty[0] {
+            b'i' => Some(Number::Int(value.value)),
+            b'n' => Some(Number::Big(value.big())),
+            b'f' => Some(Number::Float(f64::from_bits(value.value as u64))),
+            _ => None,
+        }
+    }
+
+    fn big(&self) -> &'static BigInt {
+        unsafe { &*(self.value as *const BigInt) }
+    }
+
+    fn str(&self) -> &'static CStr {
+        unsafe { CStr::from_ptr(self.value as *const c_char) }
+    }
+
+    fn list(&self) -> &'static List {
+        unsafe { &*(self.value as *const List) }
+    }
+
+    fn map(&self) -> &'static Map {
+        unsafe { &*(self.value as *const Map) }
+    }
+
+    /// The key this value is in a map, failing like `Map::key` for types
+    /// that cannot be keys.
+    fn key(&self) -> Key {
+        let value = self.resolve();
+        match value.ty[0] {
+            b'i' => Key::Int(value.value),
+            b'n' => bigint::to_i64(value.big()).map_or(Key::Big(value.big()), Key::Int),
+            b'b' => Key::Bool(value.value != 0),
+            b's' => Key::Str(value.str()),
+            _ => error(format_args!(
+                "Cannot use {} as a map key",
+                value.type_name()
+            )),
+        }
+    }
+
+    /// Whether this is a struct rather than an enum.
+    fn is_struct(&self) -> bool {
+        self.ty[0] == b'@' && self.definition(word(&self.ty[1..]).0)[0] == b'r'
+    }
+
+    /// The tag of an option or an enum value.
+    fn tag(&self) -> i64 {
+        match self.ty[0] {
+            b'o' | b'@' if !self.is_struct() => self.items()[0],
+            _ => 0,
+        }
+    }
+
+    /// The number of values that make up a list, tuple, option, struct or
+    /// enum value.
+    fn count(&self) -> usize {
+        match self.ty[0] {
+            b'l' | b't' => self.list().items().len(),
+            b'o' => (self.items()[0] == 0) as usize,
+            b'@' if self.is_struct() => self.list().items().len(),
+            b'@' => self.variant().1,
+            _ => 0,
+        }
+    }
+
+    /// Value `i` of those that make up a list, tuple, option, struct or
+    /// enum value, after the tag of the last two.
+    fn part(&self, i: usize) -> Value {
+        let rest = &self.ty[1..];
+        let mut types = match self.ty[0] {
+            b'l' => return self.of(self.items()[i], rest),
+            b'o' => return self.of(self.items()[1], rest),
+            b't' => number(rest).1,
+            _ if self.is_struct() => {
+                let definition = self.definition(word(rest).0);
+                let mut types = number(&definition[1..]).1;
+                for _ in 0..i {
+                    types = skip(word(types).1);
+                }
+                return self.of(self.items()[i], word(types).1);
+            }
+            _ => {
+                let (_, _, payload) = self.variant();
+                let mut types = payload;
+                for _ in 0..i {
+                    types = skip(types);
+                }
+                return self.of(self.items()[1 + i], types);
+            }
+        };
+        for _ in 0..i {
+            types = skip(types);
+        }
+        self.of(self.items()[i], types)
+    }
+
+    /// The position and type of `field` in a struct, failing like
+    /// `StructType::position` if it has none of that name.
+    fn field_of(&self, field: &str) -> (usize, &'static [u8]) {
+        let name = word(&self.ty[1..]).0;
+        let definition = self.definition(name);
+        let (count, mut types) = number(&definition[1..]);
+        for position in 0..count {
+            let (other, ty) = word(types);
+            if other == field {
+                return (position, ty);
+            }
+            types = skip(ty);
+        }
+        error(format_args!("No field '{}' in {}", field, name))
+    }
+
+    /// What `for x in self` goes through: the items of a list, the keys of
+    /// a map and the ints of a range, or with `which` 1 the keys of a map
+    /// and the positions of the others, and with 2 the values.
+    fn entries(&self, which: i64) -> Value {
+        let value = self.resolve();
+        match (value.ty[0], which) {
+            (b'l', 1) => boxes((0..value.count()).map(|i| int(i as i64))),
+            (b'l', _) => boxes((0..value.count()).map(|i| value.part(i))),
+            (b'm', 2) => {
+                let values = skip(&value.ty[1..]);
+                boxes(value.map().values().iter().map(|&v| value.of(v, values)))
+            }
+            (b'm', _) => {
+                let keys = &value.ty[1..];
+                boxes(value.map().keys().iter().map(|&k| value.of(k, keys)))
+            }
+            (b'r', _) => {
+                let (start, end) = list::bounds(value.items());
+                let len = end.saturating_sub(start).max(0) as usize;
+                match which {
+                    1 => boxes((0..len).map(|i| int(i as i64))),
+                    _ => boxes((0..len).map(|i| int(start + i as i64))),
+                }
+            }
+            _ => error(format_args!("Not an iterable: '{:?}'", value)),
+        }
+    }
+}
+
+/// Whether two values are of the same type.
+fn same_type(a: &Value, b: &Value) -> bool {
+    head(a.ty) == head(b.ty)
+}
+
+/// The position of `key` in `map`, failing like `Map::key` for values that
+/// cannot be keys.
+pub fn find(map: &Map, key: Value) -> Option<usize> {
+    let key = key.key();
+    let keys = unsafe { typed(0, map.key()) };
+    map.keys()
+        .iter()
+        .position(|&other| keys.of(other, keys.ty).key() == key)
+}
+
+/// The order of `sort`, `min` and `max`, like `Value::compare`: numbers by
+/// value, and strings and bools among themselves.
+pub fn compare(a: Value, b: Value) -> Ordering {
+    let (a, b) = (a.resolve(), b.resolve());
+    let ordering = match (a.ty[0], b.ty[0]) {
+        (b'i', b'i') | (b'b', b'b') => Some(a.value.cmp(&b.value)),
+        (b's', b's') => Some(a.str().cmp(b.str())),
+        _ => match (a.number(), b.number()) {
+            (Some(Number::Int(x)), Some(Number::Int(y))) => Some(x.cmp(&y)),
+            (Some(x @ Number::Float(_)), Some(y)) | (Some(x), Some(y @ Number::Float(_))) => {
+                x.to_f64().partial_cmp(&y.to_f64())
+            }
+            (Some(x), Some(y)) => Some(bigint::alpha_bigint_cmp(x.to_big(), y.to_big()).cmp(&0)),
+            _ => None,
+        },
+    };
+    ordering.unwrap_or_else(|| error(format_args!("Cannot compare {:?} and {:?}", a, b)))
+}
+
+/// Whether two values are equal for `contains`, like `Value::equals`: the
+/// values that make up lists, tuples, structs and enum values pairwise,
+/// and anything else that `compare` orders as equal.
+pub fn equals(a: Value, b: Value) -> bool {
+    let (a, b) = (a.resolve(), b.resolve());
+    match (a.ty[0], b.ty[0]) {
+        (b'u' | b'v', b'u' | b'v') => true,
+        (b'l', b'l') | (b't', b't') | (b'o', b'o') | (b'@', b'@') => {
+            (a.ty[0] != b'@' || word(&a.ty[1..]).0 == word(&b.ty[1..]).0)
+                && a.tag() == b.tag()
+                && a.count() == b.count()
+                && (0..a.count()).all(|i| equals(a.part(i), b.part(i)))
+        }
+        (b'i' | b'n' | b'f' | b's' | b'b', b'i' | b'n' | b'f' | b's' | b'b') => {
+            let comparable = matches!(
+                (a.ty[0], b.ty[0]),
+                (b's', b's') | (b'b', b'b') | (b'i' | b'n' | b'f', b'i' | b'n' | b'f')
+            );
+            comparable && compare(a, b).is_eq()
+        }
+        _ => false,
+    }
+}
+
+/// Applies the operator `op` to two values, like `Value::binary`, except
+/// that int arithmetic that overflows fails like it does in compiled code.
+fn binary(op: i64, a: Value, b: Value) -> Value {
+    let (a, b) = (a.resolve(), b.resolve());
+    match (a.number(), b.number()) {
+        (Some(Number::Int(x)), Some(Number::Int(y))) => int_op(op, x, y),
+        (Some(x @ Number::Float(_)), Some(y)) | (Some(x), Some(y @ Number::Float(_))) => {
+            float_op(op, x.to_f64(), y.to_f64())
+        }
+        (Some(x), Some(y)) => big_op(op, x.to_big(), y.to_big()),
+        (Some(_), None) if op == MUL && b.ty[0] == b'l' => {
+            boxes((0..b.count()).map(|i| binary(op, a, b.part(i))))
+        }
+        (None, Some(_)) if op == DIV && a.ty[0] == b'l' => {
+            boxes((0..a.count()).map(|i| binary(op, a.part(i), b)))
+        }
+        _ if op == ADD && a.ty[0] == b'l' && b.ty[0] == b'l' => concat(a, b),
+        _ => error(format_args!(
+            "Unsupported operation: {:?} {} {:?}",
+            a, OPS[op as usize], b
+        )),
+    }
+}
+
+/// Two lists one after the other, whose items keep their type if both
+/// have the same.
+fn concat(a: Value, b: Value) -> Value {
+    let (a_items, b_items) = (a.list().items(), b.list().items());
+    let list = list::alpha_list_new((a_items.len() + b_items.len()) as i64);
+    let (a_slots, b_slots) = list.items_mut().split_at_mut(a_items.len());
+    let ty = match (a.ty[1], b.ty[1]) {
+        _ if same_type(&a, &b) || b.ty[1] == b'v' => a,
+        (b'v', _) => b,
+        _ => {
+            for (i, slot) in a_slots.iter_mut().enumerate() {
+                *slot = a.part(i).boxed();
+            }
+            for (i, slot) in b_slots.iter_mut().enumerate() {
+                *slot = b.part(i).boxed();
+            }
+            return unsafe { typed(list as *mut List as i64, c"la".as_ptr()) };
+        }
+    };
+    a_slots.copy_from_slice(a_items);
+    b_slots.copy_from_slice(b_items);
+    ty.of(list as *mut List as i64, ty.ty)
+}
+
+fn int_op(op: i64, x: i64, y: i64) -> Value {
+    let checked = |n: Option<i64>| int(n.unwrap_or_else(|| error(format_args!("{}", OVERFLOW))));
+    match op {
+        ADD => checked(x.checked_add(y)),
+        SUB => checked(x.checked_sub(y)),
+        MUL => checked(x.checked_mul(y)),
+        DIV | MOD if y == 0 => error(format_args!("{}", DIVISION_BY_ZERO)),
+        DIV => checked(x.checked_div(y)),
+        MOD => checked(x.checked_rem(y)),
+        POW => int(bigint::alpha_int_pow(x, y)),
+        _ => boolean(ordered(op, x.cmp(&y))),
+    }
+}
+
+fn float_op(op: i64, x: f64, y: f64) -> Value {
+    match op {
+        ADD => float(x + y),
+        SUB => float(x - y),
+        MUL => float(x * y),
+        DIV => float(x / y),
+        MOD => float(unsafe { libc::fmod(x, y) }),
+        POW => float(unsafe { libc::pow(x, y) }),
+        // Comparisons with NaN are all false but !=.
+        _ => match x.partial_cmp(&y) {
+            Some(ordering) => boolean(ordered(op, ordering)),
+            None => boolean(op == NEQ),
+        },
+    }
+}
+
+fn big_op(op: i64, x: &BigInt, y: &BigInt) -> Value {
+    match op {
+        ADD => big(bigint::alpha_bigint_add(x, y)),
+        SUB => big(bigint::alpha_bigint_sub(x, y)),
+        MUL => big(bigint::alpha_bigint_mul(x, y)),
+        DIV => big(bigint::alpha_bigint_div(x, y)),
+        MOD => big(bigint::alpha_bigint_rem(x, y)),
+        POW => big(bigint::alpha_bigint_pow(x, y)),
+        _ => boolean(ordered(op, bigint::alpha_bigint_cmp(x, y).cmp(&0))),
+    }
+}
+
+/// Whether the comparison `op` holds for operands in `ordering`.
+fn ordered(op: i64, ordering: Ordering) -> bool {
+    match op {
+        EQ => ordering.is_eq(),
+        NEQ => ordering.is_ne(),
+        GT => ordering.is_gt(),
+        GE => ordering.is_ge(),
+        LT => ordering.is_lt(),
+        _ => ordering.is_le(),
+    }
+}
+
+/// Converts a value to the type at the start of `to`, whose value is
+/// unused: copies containers whose items change type, and fails with the
+/// message of `Value::check_type` for values of another type.
+fn convert(from: Value, to: Value) -> i64 {
+    if to.ty[0] == b'a' {
+        return from.boxed();
+    }
+    let from = from.resolve();
+    if same_type(&from, &to) || from.ty[0] == b'v' {
+        return from.value;
+    }
+    let item = |i: usize, ty: &'static [u8]| convert(from.part(i), to.of(0, ty));
+    match (from.ty[0], to.ty[0]) {
+        (b'i', b'n') => bigint::alpha_bigint_from_i64(from.value) as *mut BigInt as i64,
+        (b'l', b'l') => {
+            let list = list::alpha_list_new(from.count() as i64);
+            for (i, slot) in list.items_mut().iter_mut().enumerate() {
+                *slot = item(i, &to.ty[1..]);
+            }
+            list as *mut List as i64
+        }
+        (b't', b't') if from.count() == number(&to.ty[1..]).0 => {
+            let list = list::alpha_list_new(from.count() as i64);
+            let mut types = number(&to.ty[1..]).1;
+            for (i, slot) in list.items_mut().iter_mut().enumerate() {
+                *slot = item(i, types);
+                types = skip(types);
+            }
+            list as *mut List as i64
+        }
+        (b'o', b'o') => {
+            let option = list::list(from.items());
+            if from.count() > 0 {
+                option.items_mut()[1] = item(0, &to.ty[1..]);
+            }
+            option as *mut List as i64
+        }
+        (b'm', b'm') => {
+            let (keys, values) = (&to.ty[1..], skip(&to.ty[1..]));
+            let map = map::alpha_map_new(intern(&[head(keys), definitions(to.types)]));
+            let from_values = skip(&from.ty[1..]);
+            for (k, v) in from.map().entries() {
+                let k = convert(from.of(k, &from.ty[1..]), to.of(0, keys));
+                let v = convert(from.of(v, from_values), to.of(0, values));
+                map::alpha_map_put(map, k, v);
+            }
+            map as *mut Map as i64
+        }
+        _ => error(format_args!(
+            "Type mismatch: expected {}, found {}",
+            name(to.ty),
+            from.type_name()
+        )),
+    }
+}
+
+/// The container `container` after replacing what `steps`, boxes of the
+/// indices and field names of an assignment, lead to by `value`, like
+/// `Value::set_index`. A container keeps its type if `value` has the type
+/// of what it replaces, or else becomes a copy holding boxes.
+fn set(container: Value, steps: &[i64], value: Value) -> Value {
+    let Some((&step, rest)) = steps.split_first() else {
+        return value;
+    };
+    let container = container.resolve();
+    let step = unsafe { Typed::unbox(step) };
+    match (container.ty[0], step.ty[0]) {
+        (b'l', b'i') => {
+            let position = list::alpha_list_index(container.list(), step.value) as usize;
+            let item = container.part(position);
+            let value = fit(set(item, rest, value), item);
+            if same_type(&item, &value) {
+                let list = list::alpha_list_set(container.list(), step.value, value.value);
+                return container.of(list as *mut List as i64, container.ty);
+            }
+            let list = boxes((0..container.count()).map(|i| container.part(i)));
+            unsafe { (*(list.value as *mut List)).items_mut()[position] = value.boxed() };
+            list
+        }
+        (b'm', _) => {
+            let map = container.map();
+            let keys = container.of(0, &container.ty[1..]);
+            let values = container.of(0, skip(keys.ty));
+            let value = match (find(map, step), rest.is_empty()) {
+                (_, true) => value,
+                (Some(i), false) => set(values.of(map.values()[i], values.ty), rest, value),
+                (None, false) => error(format_args!("Key not found: {:?}", step)),
+            };
+            let (key, value) = (fit(step, keys), fit(value, values));
+            if same_type(&key, &keys) && same_type(&value, &values) {
+                let map = map::alpha_map_set(map, key.value, value.value);
+                return container.of(map as *mut Map as i64, container.ty);
+            }
+            // The keys or the values that change type become boxes.
+            let kept = |value: &Value, place: &Value| match same_type(value, place) {
+                true => head(place.ty),
+                false => b"a",
+            };
+            let ty = [kept(&key, &keys), kept(&value, &values)];
+            let to = unsafe { typed(0, intern(&[b"m", ty[0], ty[1], definitions(container.types)])) };
+            let map = unsafe { &mut *(convert(container, to) as *mut Map) };
+            let (keys, values) = (to.of(0, &to.ty[1..]), to.of(0, skip(&to.ty[1..])));
+            map::alpha_map_put(map, convert(key, keys), convert(value, values));
+            to.of(map as *mut Map as i64, to.ty)
+        }
+        (b'@', b's') if container.is_struct() => {
+            let field = step.str().to_str().unwrap_or_default();
+            let (position, ty) = container.field_of(field);
+            let field = container.of(container.items()[position], ty);
+            let value = convert(set(field, rest, value), container.of(0, ty));
+            let record = list::list(container.items());
+            record.items_mut()[position] = value;
+            container.of(record as *mut List as i64, container.ty)
+        }
+        _ => error(format_args!(
+            "Cannot assign to an index of {} with {}",
+            container.type_name(),
+            step.type_name()
+        )),
+    }
+}
+
+/// Fails with `format`, in which `{:?}` shows `value` like Debug and `{}`
+/// is the name of its type.
+fn raise(format: &str, value: Value) -> ! {
+    struct Message<'a>(&'a str, Value);
+
+    impl fmt::Display for Message<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let mut rest = self.0;
+            while let Some(start) = rest.find('{') {
+                f.write_str(&rest[..start])?;
+                rest = &rest[start..];
+                if let Some(after) = rest.strip_prefix("{:?}") {
+                    write!(f, "{:?}", self.1)?;
+                    rest = after;
+                } else if let Some(after) = rest.strip_prefix("{}") {
+                    f.write_str(self.1.type_name())?;
+                    rest = after;
+                } else {
+                    f.write_str("{")?;
+                    rest = &rest[1..];
+                }
+            }
+            f.write_str(rest)
+        }
+    }
+
+    error(format_args!("{}", Message(format, value)))
+}
+
+/// The map a value is, failing like `keys` and the other map builtins for
+/// anything else.
+fn map_of(value: Value) -> Value {
+    let value = value.resolve();
+    if value.ty[0] != b'm' {
+        raise("Not a map: '{:?}'", value);
+    }
+    value
+}
+
+/// A box of a value, for a place of type any.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_box(value: i64, description: *const c_char) -> i64 {
+    typed(value, description).boxed()
+}
+
+/// Converts a value of the type that `from` describes to the one that `to`
+/// does, boxing and unboxing what changes between any and other types.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_convert(
+    value: i64,
+    from: *const c_char,
+    to: *const c_char,
+) -> i64 {
+    convert(typed(value, from), typed(0, to))
+}
+
+/// Converts a value to the type that `to` describes like alpha_any_convert,
+/// after failing with `format`, as alpha_raise does, if it is not of that
+/// kind: a list for a list, a string for a string.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_cast(
+    value: i64,
+    from: *const c_char,
+    to: *const c_char,
+    format: *const c_char,
+) -> i64 {
+    let (value, to) = (typed(value, from).resolve(), typed(0, to));
+    if value.ty[0] != to.ty[0] {
+        raise(text(format).trim_end_matches('\0'), value);
+    }
+    convert(value, to)
+}
+
+/// Checks a boxed value against the annotation `typename`, like
+/// `Value::check_type`, widening ints annotated as bigint.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_check(value: i64, typename: *const c_char) -> i64 {
+    let typename = text(typename).trim_end_matches('\0');
+    let unboxed = Typed::unbox(value);
+    match (typename, unboxed.ty[0]) {
+        ("bigint", b'i') => big(bigint::alpha_bigint_from_i64(unboxed.value)).boxed(),
+        _ if unboxed.type_name() == typename => value,
+        _ => error(format_args!(
+            "Type mismatch: expected {}, found {}",
+            typename,
+            unboxed.type_name()
+        )),
+    }
+}
+
+/// Fails with `format`, in which `{:?}` shows the value like Debug and `{}`
+/// is the name of its type, for what compiled code finds wrong with a value.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_raise(
+    format: *const c_char,
+    value: i64,
+    description: *const c_char,
+) -> ! {
+    raise(text(format).trim_end_matches('\0'), typed(value, description))
+}
+
+/// Applies the operator `op`, numbered like `OPS`, to two values, boxing
+/// the result.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_binary(
+    op: i64,
+    a: i64,
+    a_description: *const c_char,
+    b: i64,
+    b_description: *const c_char,
+) -> i64 {
+    binary(op, typed(a, a_description), typed(b, b_description)).boxed()
+}
+
+/// The bool of a condition, failing like `if` for anything else.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_truth(value: i64, description: *const c_char) -> i64 {
+    let value = typed(value, description).resolve();
+    if value.ty[0] != b'b' {
+        raise("Not a bool: '{:?}'", value);
+    }
+    value.value
+}
+
+/// `value[index]`, like `Value::index`, boxed.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_index(
+    value: i64,
+    description: *const c_char,
+    index: i64,
+    index_description: *const c_char,
+) -> i64 {
+    let value = typed(value, description).resolve();
+    let index = typed(index, index_description).resolve();
+    let item = match (value.ty[0], index.ty[0]) {
+        (b'l' | b't', b'i') => {
+            value.part(list::alpha_list_index(value.list(), index.value) as usize)
+        }
+        (b'm', _) => match find(value.map(), index) {
+            Some(i) => value.of(value.map().values()[i], skip(&value.ty[1..])),
+            None => error(format_args!("Key not found: {:?}", index)),
+        },
+        (b'l', b'r') => {
+            let list = list::alpha_list_range(value.list(), index.list());
+            value.of(list as *mut List as i64, value.ty)
+        }
+        _ => error(format_args!(
+            "Cannot index {} with {}",
+            value.type_name(),
+            index.type_name()
+        )),
+    };
+    item.boxed()
+}
+
+/// `value[from:to]`, like `Value::slice`, with the bounds boxed and
+/// `bounds` saying which are given like for alpha_list_slice.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_slice(
+    value: i64,
+    description: *const c_char,
+    from: i64,
+    to: i64,
+    bounds: i64,
+) -> i64 {
+    let value = typed(value, description).resolve();
+    if value.ty[0] != b'l' {
+        error(format_args!("Cannot slice {}", value.type_name()));
+    }
+    let bound = |bound: i64, given: i64| {
+        if bounds & given == 0 {
+            return 0;
+        }
+        let bound = Typed::unbox(bound);
+        if bound.ty[0] != b'i' {
+            error(format_args!("Cannot slice with {}", bound.type_name()));
+        }
+        bound.value
+    };
+    let (from, to) = (bound(from, list::SLICE_FROM), bound(to, list::SLICE_TO));
+    let list = list::alpha_list_slice(value.list(), from, to, bounds);
+    value.of(list as *mut List as i64, value.ty).boxed()
+}
+
+/// `value.field`, like `Value::field`, boxed.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_field(
+    value: i64,
+    description: *const c_char,
+    field: *const c_char,
+) -> i64 {
+    let value = typed(value, description).resolve();
+    let field = text(field).trim_end_matches('\0');
+    if !value.is_struct() {
+        error(format_args!(
+            "No field '{}' in {}",
+            field,
+            value.type_name()
+        ));
+    }
+    let (position, ty) = value.field_of(field);
+    value.of(value.items()[position], ty).boxed()
+}
+
+/// The container `value` after an assignment to what `steps`, a list of
+/// boxes of the indices and field names, lead to in it, boxed.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_set(
+    value: i64,
+    description: *const c_char,
+    steps: &List,
+    item: i64,
+    item_description: *const c_char,
+) -> i64 {
+    let value = typed(value, description);
+    set(value, steps.items(), typed(item, item_description)).boxed()
+}
+
+/// `len(value)`, like the list builtin.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_len(value: i64, description: *const c_char) -> i64 {
+    let value = typed(value, description).resolve();
+    match value.ty[0] {
+        b'l' | b't' => value.count() as i64,
+        b'm' => value.map().keys().len() as i64,
+        b's' => list::alpha_str_len(value.value as *const c_char),
+        _ => error(format_args!(
+            "Cannot take len of {}",
+            value.type_name()
+        )),
+    }
+}
+
+/// What a `for` loop goes through, as a list of boxes: see `entries`.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_entries(
+    value: i64,
+    description: *const c_char,
+    which: i64,
+) -> i64 {
+    typed(value, description).entries(which).value
+}
+
+/// The items of a tuple or list that a pattern of `len` names binds, as a
+/// list of boxes, like `Value::destructure`.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_unpack(
+    value: i64,
+    description: *const c_char,
+    len: i64,
+) -> i64 {
+    let value = typed(value, description).resolve();
+    match value.ty[0] {
+        b'l' | b't' if value.count() as i64 == len => {
+            boxes((0..value.count()).map(|i| value.part(i))).value
+        }
+        b'l' | b't' => error(format_args!(
+            "Arity mismatch: expected {} values, found {}",
+            len,
+            value.count()
+        )),
+        _ => error(format_args!(
+            "Cannot destructure {}",
+            value.type_name()
+        )),
+    }
+}
+
+/// The function a value is, failing like a call of anything else.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_function(value: i64, description: *const c_char) -> i64 {
+    let value = typed(value, description).resolve();
+    if value.ty[0] != b'F' {
+        raise("Not a function: '{:?}'", value);
+    }
+    value.value
+}
+
+/// The argument at `index` of a call through a function value, which passes
+/// its arguments as a list of boxes: a box of a unit for one that is left
+/// out, like the parameters the interpreter leaves unbound.
+#[no_mangle]
+pub extern "C" fn alpha_any_arg(args: &List, index: i64) -> i64 {
+    match args.items().get(index as usize) {
+        Some(&arg) => arg,
+        None => unsafe { typed(0, c"u".as_ptr()).boxed() },
+    }
+}
+
+/// The option a value is, with its payload boxed, failing like `?` and
+/// unwrap_or for anything else.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_option(value: i64, description: *const c_char) -> i64 {
+    let value = typed(value, description).resolve();
+    if value.ty[0] != b'o' {
+        raise("Not an option: '{:?}'", value);
+    }
+    convert(value, typed(0, c"oa".as_ptr()))
+}
+
+/// `keys(value)`, as a list of boxes.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_keys(value: i64, description: *const c_char) -> i64 {
+    map_of(typed(value, description)).entries(1).value
+}
+
+/// `values(value)`, as a list of boxes.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_values(value: i64, description: *const c_char) -> i64 {
+    map_of(typed(value, description)).entries(2).value
+}
+
+/// `has(value, key)`.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_has(
+    value: i64,
+    description: *const c_char,
+    key: i64,
+    key_description: *const c_char,
+) -> i64 {
+    let map = map_of(typed(value, description));
+    find(map.map(), typed(key, key_description)).is_some() as i64
+}
+
+/// `remove(value, key)`, boxed.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_remove(
+    value: i64,
+    description: *const c_char,
+    key: i64,
+    key_description: *const c_char,
+) -> i64 {
+    let map = map_of(typed(value, description));
+    let position = find(map.map(), typed(key, key_description));
+    let removed = map::without(map.map(), position);
+    map.of(removed as *mut Map as i64, map.ty).boxed()
+}
+
+/// `int(value)`, like `Value::to_int`.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_int(value: i64, description: *const c_char) -> i64 {
+    let value = typed(value, description).resolve();
+    match value.ty[0] {
+        b'i' => value.value,
+        b'f' => {
+            let x = f64::from_bits(value.value as u64);
+            if !(-9223372036854775808.0..9223372036854775808.0).contains(&x) {
+                error(format_args!("{}", FLOAT_TO_INT));
+            }
+            x as i64
+        }
+        b'n' => bigint::alpha_bigint_to_i64(value.big()),
+        b's' => {
+            let s = text(value.value as *const c_char);
+            match s.trim_end_matches('\0').trim().parse() {
+                Ok(n) => n,
+                Err(_) => error(format_args!("Not an int: {:?}", s)),
+            }
+        }
+        _ => error(format_args!(
+            "Cannot convert {} to int",
+            value.type_name()
+        )),
+    }
+}
+
+/// `float(value)`, like `Value::to_float`.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_float(value: i64, description: *const c_char) -> f64 {
+    let value = typed(value, description).resolve();
+    match value.number() {
+        Some(number) => number.to_f64(),
+        None if value.ty[0] == b's' => {
+            let s = text(value.value as *const c_char);
+            match s.trim_end_matches('\0').trim().parse() {
+                Ok(x) => x,
+                Err(_) => error(format_args!("Not a float: {:?}", s)),
+            }
+        }
+        None => error(format_args!(
+            "Cannot convert {} to float",
+            value.type_name()
+        )),
+    }
+}
+
+/// `bigint(value)`, like `Value::to_bigint`.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_bigint(value: i64, description: *const c_char) -> i64 {
+    let value = typed(value, description).resolve();
+    let n: &BigInt = match value.ty[0] {
+        b'i' => bigint::alpha_bigint_from_i64(value.value),
+        b'n' => value.big(),
+        b'f' => bigint::alpha_bigint_from_f64(f64::from_bits(value.value as u64)),
+        b's' => bigint::alpha_bigint_parse(value.value as *const c_char),
+        _ => error(format_args!(
+            "Cannot convert {} to bigint",
+            value.type_name()
+        )),
+    };
+    n as *const BigInt as i64
+}
+
+/// Whether a value is of the kind of type that `pattern` describes, which
+/// a `match` pattern of that kind needs: a tuple of as many items, the same
+/// struct or enum, or any list or option.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_is(
+    value: i64,
+    description: *const c_char,
+    pattern: *const c_char,
+) -> i64 {
+    let value = typed(value, description).resolve();
+    let pattern = CStr::from_ptr(pattern).to_bytes();
+    let is = match pattern[0] {
+        b't' => value.ty[0] == b't' && value.count() == number(&pattern[1..]).0,
+        b'@' => value.ty[0] == b'@' && word(&value.ty[1..]).0 == word(&pattern[1..]).0,
+        kind => value.ty[0] == kind,
+    };
+    is as i64
+}
+
+/// Whether a value is an option or enum value of the variant `name`.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_variant(
+    value: i64,
+    description: *const c_char,
+    name: *const c_char,
+) -> i64 {
+    let value = typed(value, description).resolve();
+    let name = text(name).trim_end_matches('\0');
+    let is = match value.ty[0] {
+        b'o' => name == ["some", "none"][value.tag() as usize],
+        b'@' if !value.is_struct() => value.variant().0 == name,
+        _ => false,
+    };
+    is as i64
+}
+
+/// Whether a value matches a literal pattern, like `matching::matches`:
+/// the same value of the same type, or a bigint of the value of an int.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_matches(
+    value: i64,
+    description: *const c_char,
+    literal: i64,
+    literal_description: *const c_char,
+) -> i64 {
+    let value = typed(value, description).resolve();
+    let literal = typed(literal, literal_description);
+    let matches = match (literal.ty[0], value.ty[0]) {
+        (b'i', b'i') | (b'b', b'b') => literal.value == value.value,
+        (b'i', b'n') => bigint::to_i64(value.big()) == Some(literal.value),
+        (b'f', b'f') => f64::from_bits(literal.value as u64) == f64::from_bits(value.value as u64),
+        (b's', b's') => literal.str() == value.str(),
+        _ => false,
+    };
+    matches as i64
+}
+
+/// The range `from..to` with the bounds that `lower` and `upper` say are
+/// exclusive, failing like `Range` for bounds that are not ints.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_range(
+    from: i64,
+    from_description: *const c_char,
+    to: i64,
+    to_description: *const c_char,
+    lower: i64,
+    upper: i64,
+) -> i64 {
+    let from = typed(from, from_description).resolve();
+    let to = typed(to, to_description).resolve();
+    if from.ty[0] != b'i' || to.ty[0] != b'i' {
+        error(format_args!("Not an int: '{:?}..{:?}'", from, to));
+    }
+    list::list(&[from.value, to.value, lower, upper]) as *mut List as i64
+}
+
+/// A number as a float, for the math builtins, failing for anything else.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_any_number(value: i64, description: *const c_char) -> f64 {
+    match typed(value, description).number() {
+        Some(number) => number.to_f64(),
+        None => raise("Not a number: '{:?}'", typed(value, description).resolve()),
+    }
+}
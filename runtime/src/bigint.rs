@@ -0,0 +1,365 @@
+//! Bigints for compiled alpha programs, with the same representation as
+//! src/bigint.rs: sign and magnitude, base 10^9 limbs, least significant
+//! limb first, no trailing zero limbs. They are immutable once returned to
+//! compiled code, and live in the heap of gc.rs.
+
+use core::ffi::{c_char, c_int};
+use core::fmt::{self, Write};
+use core::mem::size_of;
+use core::slice;
+
+use crate::gc;
+use crate::libc;
+use crate::print::error;
+
+const BASE: u32 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+/// Same messages as the constants in src/value.rs.
+const OVERFLOW: &str = "Integer overflow";
+const DIVISION_BY_ZERO: &str = "Division by zero";
+const FLOAT_TO_INT: &str = "Float out of int range";
+const NEGATIVE_EXPONENT: &str = "Negative exponent";
+
+#[repr(C)]
+pub struct BigInt {
+    negative: bool,
+    len: usize,
+    limbs: [u32; 0],
+}
+
+impl BigInt {
+    fn limbs(&self) -> &[u32] {
+        unsafe { slice::from_raw_parts(self.limbs.as_ptr(), self.len) }
+    }
+
+    fn limbs_mut(&mut self) -> &mut [u32] {
+        unsafe { slice::from_raw_parts_mut(self.limbs.as_mut_ptr(), self.len) }
+    }
+
+    fn normalize(&mut self) -> &mut BigInt {
+        while self.len > 0 && self.limbs()[self.len - 1] == 0 {
+            self.len -= 1;
+        }
+        if self.len == 0 {
+            self.negative = false;
+        }
+        self
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((last, rest)) = self.limbs().split_last() else {
+            return write!(f, "0");
+        };
+        write!(f, "{}{}", if self.negative { "-" } else { "" }, last)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+fn alloc(len: usize) -> &'static mut BigInt {
+    let n = gc::alloc(size_of::<BigInt>() + len * size_of::<u32>(), false) as *mut BigInt;
+    let n = unsafe { &mut *n };
+    n.len = len;
+    n
+}
+
+fn free(n: &mut BigInt) {
+    gc::free(n as *mut BigInt as *mut u8);
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_from_i64(value: i64) -> &'static mut BigInt {
+    let mut magnitude = value.unsigned_abs();
+    let n = alloc(3);
+    for limb in n.limbs_mut() {
+        *limb = (magnitude % BASE as u64) as u32;
+        magnitude /= BASE as u64;
+    }
+    n.negative = value < 0;
+    n.normalize()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_bigint_parse(s: *const c_char) -> &'static mut BigInt {
+    let mut s = core::ffi::CStr::from_ptr(s).to_bytes();
+    let negative = s.first() == Some(&b'-');
+    if let Some(b'-' | b'+') = s.first() {
+        s = &s[1..];
+    }
+    let n = alloc(s.len().div_ceil(BASE_DIGITS));
+    for (limb, digits) in n.limbs_mut().iter_mut().zip(s.rchunks(BASE_DIGITS)) {
+        *limb = digits
+            .iter()
+            .fold(0, |limb, digit| limb * 10 + (digit - b'0') as u32);
+    }
+    n.negative = negative;
+    n.normalize()
+}
+
+fn cmp_magnitude(a: &BigInt, b: &BigInt) -> i64 {
+    let ordering = a
+        .len
+        .cmp(&b.len)
+        .then_with(|| a.limbs().iter().rev().cmp(b.limbs().iter().rev()));
+    ordering as i64
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_cmp(a: &BigInt, b: &BigInt) -> i64 {
+    if a.negative != b.negative {
+        return if a.negative { -1 } else { 1 };
+    }
+    let cmp = cmp_magnitude(a, b);
+    if a.negative {
+        -cmp
+    } else {
+        cmp
+    }
+}
+
+/// a + b, with the sign of b given separately so that subtraction can flip
+/// it.
+fn add_signed(a: &BigInt, b: &BigInt, b_negative: bool) -> &'static mut BigInt {
+    if a.negative == b_negative {
+        let n = alloc(a.len.max(b.len) + 1);
+        let mut carry = 0;
+        for (i, limb) in n.limbs_mut().iter_mut().enumerate() {
+            let sum = carry
+                + *a.limbs().get(i).unwrap_or(&0) as u64
+                + *b.limbs().get(i).unwrap_or(&0) as u64;
+            *limb = (sum % BASE as u64) as u32;
+            carry = sum / BASE as u64;
+        }
+        n.negative = a.negative;
+        return n.normalize();
+    }
+    let (large, small, negative) = match cmp_magnitude(a, b) < 0 {
+        true => (b, a, b_negative),
+        false => (a, b, a.negative),
+    };
+    let n = alloc(large.len);
+    let mut borrow = 0;
+    for (i, limb) in n.limbs_mut().iter_mut().enumerate() {
+        let diff = large.limbs()[i] as i64 - *small.limbs().get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = (diff < 0) as i64;
+        *limb = if diff < 0 { diff + BASE as i64 } else { diff } as u32;
+    }
+    n.negative = negative;
+    n.normalize()
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_add(a: &BigInt, b: &BigInt) -> &'static mut BigInt {
+    add_signed(a, b, b.negative)
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_sub(a: &BigInt, b: &BigInt) -> &'static mut BigInt {
+    add_signed(a, b, !b.negative)
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_mul(a: &BigInt, b: &BigInt) -> &'static mut BigInt {
+    let len = a.len + b.len + 1;
+    let buffer = gc::alloc(len * size_of::<u64>(), false);
+    let limbs = unsafe { slice::from_raw_parts_mut(buffer as *mut u64, len) };
+    for (i, &x) in a.limbs().iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.limbs().iter().enumerate() {
+            let n = limbs[i + j] + x as u64 * y as u64 + carry;
+            limbs[i + j] = n % BASE as u64;
+            carry = n / BASE as u64;
+        }
+        limbs[i + b.len] += carry;
+    }
+    let n = alloc(len);
+    for (limb, &wide) in n.limbs_mut().iter_mut().zip(limbs.iter()) {
+        *limb = wide as u32;
+    }
+    gc::free(buffer);
+    n.negative = a.negative != b.negative;
+    n.normalize()
+}
+
+fn mul_small(a: &BigInt, factor: u32) -> &'static mut BigInt {
+    let n = alloc(a.len + 1);
+    let mut carry = 0;
+    for (i, &limb) in a.limbs().iter().enumerate() {
+        let product = limb as u64 * factor as u64 + carry;
+        n.limbs_mut()[i] = (product % BASE as u64) as u32;
+        carry = product / BASE as u64;
+    }
+    n.limbs_mut()[a.len] = carry as u32;
+    n.normalize()
+}
+
+/// Truncating division; the remainder has the sign of the dividend.
+fn div_rem(a: &BigInt, b: &BigInt) -> (&'static mut BigInt, &'static mut BigInt) {
+    if b.len == 0 {
+        error(format_args!("{}", DIVISION_BY_ZERO));
+    }
+    let divisor = alloc(b.len);
+    divisor.limbs_mut().copy_from_slice(b.limbs());
+    let q = alloc(a.len);
+    let mut r = alloc(0);
+    for i in (0..a.len).rev() {
+        let shifted = alloc(r.len + 1);
+        shifted.limbs_mut()[0] = a.limbs()[i];
+        shifted.limbs_mut()[1..].copy_from_slice(r.limbs());
+        free(r);
+        r = shifted.normalize();
+        // Binary search for the largest digit with divisor * digit <= r.
+        let (mut low, mut high) = (0, BASE - 1);
+        while low < high {
+            let mid = low + (high - low).div_ceil(2);
+            let product = mul_small(divisor, mid);
+            if cmp_magnitude(product, r) <= 0 {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+            free(product);
+        }
+        if low > 0 {
+            let product = mul_small(divisor, low);
+            let next = add_signed(r, product, true);
+            free(product);
+            free(r);
+            r = next;
+        }
+        q.limbs_mut()[i] = low;
+    }
+    free(divisor);
+    q.negative = a.negative != b.negative;
+    r.negative = a.negative;
+    (q.normalize(), r.normalize())
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_div(a: &BigInt, b: &BigInt) -> &'static mut BigInt {
+    let (q, r) = div_rem(a, b);
+    free(r);
+    q
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_rem(a: &BigInt, b: &BigInt) -> &'static mut BigInt {
+    let (q, r) = div_rem(a, b);
+    free(q);
+    r
+}
+
+/// The value of `n` if it fits an int.
+pub fn to_i64(n: &BigInt) -> Option<i64> {
+    let mut magnitude: i128 = 0;
+    for &limb in n.limbs().iter().rev() {
+        magnitude = magnitude * BASE as i128 + limb as i128;
+        if magnitude > i64::MAX as i128 + 1 {
+            return None;
+        }
+    }
+    let value = if n.negative { -magnitude } else { magnitude };
+    i64::try_from(value).ok()
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_to_i64(n: &BigInt) -> i64 {
+    to_i64(n).unwrap_or_else(|| error(format_args!("{}", OVERFLOW)))
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_to_f64(n: &BigInt) -> f64 {
+    let value = n
+        .limbs()
+        .iter()
+        .rev()
+        .fold(0.0, |value, &limb| value * BASE as f64 + limb as f64);
+    if n.negative {
+        -value
+    } else {
+        value
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_bigint_pow(base: &BigInt, exponent: &BigInt) -> &'static mut BigInt {
+    if exponent.negative {
+        error(format_args!("{}", NEGATIVE_EXPONENT));
+    }
+    let mut e = alpha_bigint_to_i64(exponent);
+    let mut result = alpha_bigint_from_i64(1);
+    let mut square = base;
+    while e > 0 {
+        if e & 1 == 1 {
+            result = alpha_bigint_mul(result, square);
+        }
+        e >>= 1;
+        if e > 0 {
+            square = alpha_bigint_mul(square, square);
+        }
+    }
+    result
+}
+
+/// Truncates towards zero.
+#[no_mangle]
+pub extern "C" fn alpha_bigint_from_f64(value: f64) -> &'static mut BigInt {
+    if !value.is_finite() {
+        error(format_args!("{}", FLOAT_TO_INT));
+    }
+    let value = unsafe { libc::trunc(value) };
+    if value.abs() < 9223372036854775808.0 {
+        return alpha_bigint_from_i64(value as i64);
+    }
+    let mut exponent: c_int = 0;
+    let mantissa = unsafe { libc::frexp(value, &mut exponent) };
+    let n = alpha_bigint_from_i64(unsafe { libc::ldexp(mantissa, 53) } as i64);
+    let two = alpha_bigint_from_i64(2);
+    let scale = alpha_bigint_pow(two, alpha_bigint_from_i64(exponent as i64 - 53));
+    alpha_bigint_mul(n, scale)
+}
+
+/// Writes into a buffer that is known to be large enough.
+struct Buffer(*mut u8);
+
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        unsafe {
+            self.0.copy_from_nonoverlapping(s.as_ptr(), s.len());
+            self.0 = self.0.add(s.len());
+        }
+        Ok(())
+    }
+}
+
+/// A NUL terminated decimal string, for printf("%s").
+#[no_mangle]
+pub extern "C" fn alpha_bigint_to_str(n: &BigInt) -> *const c_char {
+    let s = gc::alloc(n.len * BASE_DIGITS + 2, false);
+    let _ = write!(Buffer(s), "{}", n);
+    s as *const c_char
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_int_pow(mut base: i64, mut exponent: i64) -> i64 {
+    if exponent < 0 {
+        error(format_args!("{}", NEGATIVE_EXPONENT));
+    }
+    let overflow = || -> i64 { error(format_args!("{}", OVERFLOW)) };
+    let mut result: i64 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result.checked_mul(base).unwrap_or_else(overflow);
+        }
+        exponent >>= 1;
+        if exponent > 0 {
+            base = base.checked_mul(base).unwrap_or_else(overflow);
+        }
+    }
+    result
+}
@@ -0,0 +1,286 @@
+//! The heap of compiled alpha programs, with a mark-sweep collector.
+//!
+//! The roots are the pointers compiled functions keep on the stack across
+//! calls: src/comp.rs records where with a stack map per call, which it puts
+//! in the table of compiled functions that trace.rs walks the stack with.
+//! List items and map entries do not say whether they are pointers, so the
+//! objects that may hold pointers are scanned for any word that points into
+//! another object.
+//!
+//! The runtime keeps pointers in local variables while it allocates, so
+//! nothing is collected then: allocating past the threshold, or at all in
+//! programs built with --gc-stress, asks for a collection, which happens at
+//! the next safepoint. Compiled code calls alpha_gc_safepoint after each call
+//! into the runtime. Objects returned to C code calling a library stay valid
+//! until the next call into it. ALPHA_GC_STATS in the environment prints
+//! allocation statistics to stderr at exit.
+
+use core::fmt::Write;
+use core::mem::size_of;
+use core::ptr;
+use core::slice;
+
+use crate::libc;
+use crate::print::Stream;
+use crate::trace::{Frame, Frames};
+
+#[repr(C)]
+struct Header {
+    size: usize,
+    scan: bool,
+    marked: bool,
+}
+
+extern "C" {
+    /// Whether to collect at every allocation, which the object of the
+    /// program defines, as comp::GC_STRESS.
+    static alpha_gc_stress: u8;
+}
+
+const MIN_THRESHOLD: usize = 1 << 20;
+
+/// A growable array of objects.
+struct Objects {
+    items: *mut *mut Header,
+    len: usize,
+    capacity: usize,
+}
+
+impl Objects {
+    const fn new() -> Self {
+        Objects {
+            items: ptr::null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+
+    unsafe fn push(&mut self, object: *mut Header) {
+        if self.len == self.capacity {
+            self.capacity = if self.capacity > 0 {
+                2 * self.capacity
+            } else {
+                1024
+            };
+            let size = self.capacity * size_of::<*mut Header>();
+            self.items = libc::grow(self.items.cast(), size).cast();
+        }
+        *self.items.add(self.len) = object;
+        self.len += 1;
+    }
+
+    fn as_mut(&mut self) -> &mut [*mut Header] {
+        match self.len {
+            0 => &mut [],
+            len => unsafe { slice::from_raw_parts_mut(self.items, len) },
+        }
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    allocations: usize,
+    bytes: usize,
+    collections: usize,
+    freed: usize,
+    peak: usize,
+}
+
+struct Heap {
+    objects: Objects,
+    pending: Objects,
+    since_collection: usize,
+    threshold: usize,
+    requested: bool,
+    stats: Stats,
+}
+
+static mut HEAP: Heap = Heap {
+    objects: Objects::new(),
+    pending: Objects::new(),
+    since_collection: 0,
+    threshold: MIN_THRESHOLD,
+    requested: false,
+    stats: Stats {
+        allocations: 0,
+        bytes: 0,
+        collections: 0,
+        freed: 0,
+        peak: 0,
+    },
+};
+
+fn heap() -> &'static mut Heap {
+    // Compiled programs have a single thread.
+    unsafe { &mut *ptr::addr_of_mut!(HEAP) }
+}
+
+extern "C" fn print_stats() {
+    let stats = &heap().stats;
+    let _ = writeln!(
+        Stream::stderr(),
+        "gc: {} allocations, {} bytes, {} collections, {} objects freed, {} bytes kept at most",
+        stats.allocations,
+        stats.bytes,
+        stats.collections,
+        stats.freed,
+        stats.peak
+    );
+}
+
+/// A zeroed object of `size` bytes, which holds pointers if `scan`.
+pub fn alloc(size: usize, scan: bool) -> *mut u8 {
+    let heap = heap();
+    unsafe {
+        if heap.stats.allocations == 0 && !libc::getenv(c"ALPHA_GC_STATS".as_ptr()).is_null() {
+            libc::atexit(print_stats);
+        }
+        let object = libc::calloc(1, size_of::<Header>() + size.max(1)) as *mut Header;
+        if object.is_null() {
+            crate::print::error(format_args!("Out of memory"));
+        }
+        (*object).size = size;
+        (*object).scan = scan;
+        heap.objects.push(object);
+
+        heap.stats.allocations += 1;
+        heap.stats.bytes += size;
+        heap.since_collection += size;
+        heap.requested |= alpha_gc_stress != 0 || heap.since_collection > heap.threshold;
+        object.add(1).cast()
+    }
+}
+
+/// Frees an object that the runtime allocated and nothing points to, like an
+/// intermediate result, usually one of the latest.
+pub fn free(p: *mut u8) {
+    let heap = heap();
+    let object = unsafe { (p as *mut Header).sub(1) };
+    let objects = heap.objects.as_mut();
+    if let Some(i) = objects.iter().rposition(|&o| o == object) {
+        objects.copy_within(i + 1.., i);
+        heap.objects.len -= 1;
+        unsafe { libc::free(object.cast()) };
+        heap.stats.freed += 1;
+    }
+}
+
+/// The start of the object after `object`'s header.
+fn start(object: *mut Header) -> usize {
+    unsafe { object.add(1) as usize }
+}
+
+/// The object that `word` points into, once objects are sorted.
+fn object_at(objects: &[*mut Header], word: usize) -> Option<*mut Header> {
+    let after = objects.partition_point(|&object| start(object) <= word);
+    let object = *objects.get(after.checked_sub(1)?)?;
+    let size = unsafe { (*object).size.max(1) };
+    (word < start(object) + size).then_some(object)
+}
+
+fn mark(heap: &mut Heap, word: usize) {
+    let Some(object) = object_at(heap.objects.as_mut(), word) else {
+        return;
+    };
+    unsafe {
+        if (*object).marked {
+            return;
+        }
+        (*object).marked = true;
+        if (*object).scan {
+            heap.pending.push(object);
+        }
+    }
+}
+
+/// Marks the pointers of the compiled frames above `frame`, the frame of
+/// alpha_gc_safepoint, which is a frame pointer followed by a return
+/// address.
+unsafe fn mark_stack(heap: &mut Heap, frame: *const usize) {
+    for Frame {
+        function: f,
+        pc,
+        sp,
+    } in Frames::from(frame)
+    {
+        let offset = (pc - f.start) as u32;
+        for &[at, slot] in slice::from_raw_parts(f.maps, f.count as usize) {
+            if at == offset {
+                mark(heap, *(sp.add(slot as usize) as *const usize));
+            }
+        }
+    }
+}
+
+unsafe fn collect(frame: *const usize) {
+    let heap = heap();
+    heap.objects.as_mut().sort_unstable();
+    mark_stack(heap, frame);
+    while heap.pending.len > 0 {
+        heap.pending.len -= 1;
+        let object = *heap.pending.items.add(heap.pending.len);
+        let words = slice::from_raw_parts(
+            object.add(1) as *const usize,
+            (*object).size / size_of::<usize>(),
+        );
+        for &word in words {
+            mark(heap, word);
+        }
+    }
+
+    let (mut kept, mut live) = (0, 0);
+    for i in 0..heap.objects.len {
+        let object = *heap.objects.items.add(i);
+        if (*object).marked {
+            (*object).marked = false;
+            live += (*object).size;
+            *heap.objects.items.add(kept) = object;
+            kept += 1;
+        } else {
+            if alpha_gc_stress != 0 {
+                // So that what still uses it fails rather than reads stale
+                // data.
+                ptr::write_bytes(object.add(1) as *mut u8, 0xa5, (*object).size);
+            }
+            libc::free(object.cast());
+            heap.stats.freed += 1;
+        }
+    }
+    heap.objects.len = kept;
+    heap.stats.collections += 1;
+    heap.stats.peak = heap.stats.peak.max(live);
+    heap.since_collection = 0;
+    heap.threshold = (2 * live).max(MIN_THRESHOLD);
+}
+
+extern "C" fn safepoint(frame: *const usize) {
+    let heap = heap();
+    if heap.requested {
+        heap.requested = false;
+        unsafe { collect(frame) };
+    }
+}
+
+/// Collects if an allocation asked to, from a frame of its own whose frame
+/// pointer leads to the compiled code that called it.
+#[cfg(target_arch = "x86_64")]
+#[unsafe(naked)]
+#[no_mangle]
+pub extern "C" fn alpha_gc_safepoint() {
+    core::arch::naked_asm!(
+        "push rbp",
+        "mov rbp, rsp",
+        "mov rdi, rbp",
+        "call {safepoint}",
+        "pop rbp",
+        "ret",
+        safepoint = sym safepoint,
+    )
+}
+
+/// Only x86-64 frames are walked; elsewhere objects are never collected.
+#[cfg(not(target_arch = "x86_64"))]
+#[no_mangle]
+pub extern "C" fn alpha_gc_safepoint() {
+    heap().requested = false;
+}
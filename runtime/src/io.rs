@@ -0,0 +1,167 @@
+//! The file and stdin builtins of src/io.rs for compiled alpha programs.
+//! Strings are NUL-terminated and live in the heap of gc.rs; failures print
+//! the messages of src/io.rs and exit with status 1.
+
+use core::ffi::{c_char, CStr};
+use core::slice;
+
+use crate::gc;
+use crate::libc::{self, File};
+use crate::list::{alpha_list_new, list, List};
+use crate::print::{error, text};
+
+/// Same tags as value::SOME and value::NONE.
+const SOME: i64 = 0;
+const NONE: i64 = 1;
+
+unsafe fn failed(action: &str, path: *const c_char) -> ! {
+    let reason = CStr::from_ptr(libc::strerror(*libc::__errno_location()));
+    let path = text(path).trim_end_matches('\0');
+    let reason = reason.to_str().unwrap_or_default();
+    error(format_args!("Cannot {} '{}': {}", action, path, reason))
+}
+
+/// A buffer of malloc that grows as bytes are pushed.
+struct Buffer {
+    bytes: *mut u8,
+    len: usize,
+    capacity: usize,
+}
+
+impl Buffer {
+    fn new(capacity: usize) -> Self {
+        let bytes = unsafe { libc::alloc(capacity) };
+        Buffer {
+            bytes,
+            len: 0,
+            capacity,
+        }
+    }
+
+    /// Makes room for at least one more byte after `len`.
+    fn reserve(&mut self) {
+        if self.len + 1 >= self.capacity {
+            self.capacity *= 2;
+            self.bytes = unsafe { libc::grow(self.bytes, self.capacity) };
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.reserve();
+        unsafe { *self.bytes.add(self.len) = byte };
+        self.len += 1;
+    }
+
+    /// The first `len` bytes as a string of gc.rs, freeing the buffer.
+    fn collected(self, len: usize) -> *mut c_char {
+        let s = gc::alloc(len + 1, false);
+        unsafe {
+            s.copy_from_nonoverlapping(self.bytes, len);
+            libc::free(self.bytes.cast());
+        }
+        s.cast()
+    }
+}
+
+/// The next line of stdin as an option, without its line break.
+#[no_mangle]
+pub extern "C" fn alpha_read_line() -> &'static mut List {
+    let mut line = Buffer::new(64);
+    let mut c;
+    loop {
+        c = unsafe { libc::getchar() };
+        if c == libc::EOF || c == b'\n' as i32 {
+            break;
+        }
+        line.push(c as u8);
+    }
+    if c == libc::EOF && line.len == 0 {
+        unsafe { libc::free(line.bytes.cast()) };
+        return list(&[NONE]);
+    }
+    let mut len = line.len;
+    if c == b'\n' as i32 && len > 0 && unsafe { *line.bytes.add(len - 1) } == b'\r' {
+        len -= 1;
+    }
+    list(&[SOME, line.collected(len) as i64])
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_read_file(path: *const c_char) -> *mut c_char {
+    let file = libc::fopen(path, c"rb".as_ptr());
+    if file.is_null() {
+        failed("read", path);
+    }
+    let mut contents = Buffer::new(4096);
+    loop {
+        let room = contents.capacity - contents.len - 1;
+        let read = libc::fread(contents.bytes.add(contents.len).cast(), 1, room, file);
+        if read == 0 {
+            break;
+        }
+        contents.len += read;
+        contents.reserve();
+    }
+    if libc::ferror(file) != 0 {
+        failed("read", path);
+    }
+    libc::fclose(file);
+    let len = contents.len;
+    contents.collected(len)
+}
+
+unsafe fn write_file(path: *const c_char, contents: *const c_char, mode: &CStr) {
+    let file: *mut File = libc::fopen(path, mode.as_ptr());
+    if file.is_null() {
+        failed("write", path);
+    }
+    if libc::fputs(contents, file) == libc::EOF || libc::fclose(file) == libc::EOF {
+        failed("write", path);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_write_file(path: *const c_char, contents: *const c_char) {
+    write_file(path, contents, c"w");
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_append_file(path: *const c_char, contents: *const c_char) {
+    write_file(path, contents, c"a");
+}
+
+/// The lines of a file like str::lines: without line breaks, and without an
+/// empty last line.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_lines(path: *const c_char) -> &'static mut List {
+    let contents = alpha_read_file(path) as *mut u8;
+    let len = CStr::from_ptr(contents.cast()).to_bytes().len();
+    let bytes = slice::from_raw_parts_mut(contents, len);
+    let lines = alpha_list_new(bytes.split_inclusive(|&c| c == b'\n').count() as i64);
+    let mut start = 0;
+    for item in lines.items_mut() {
+        let next = bytes[start..]
+            .iter()
+            .position(|&c| c == b'\n')
+            .map_or(len, |end| start + end + 1);
+        let mut end = if next > start && bytes[next - 1] == b'\n' {
+            next - 1
+        } else {
+            next
+        };
+        if end > start && bytes[end - 1] == b'\r' {
+            end -= 1;
+        }
+        if end < len {
+            bytes[end] = 0;
+        }
+        *item = contents.add(start) as i64;
+        start = next;
+    }
+    lines
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_exists(path: *const c_char) -> i64 {
+    (libc::access(path, libc::F_OK) == 0) as i64
+}
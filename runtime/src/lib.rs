@@ -0,0 +1,36 @@
+//! The runtime of compiled alpha programs, which `alpha build` links into
+//! every executable and library: the heap and its collector, bigints,
+//! lists, strings, maps, printing, files and the process. Compiled code
+//! calls the `alpha_` functions, which src/comp.rs declares as imports.
+//!
+//! Nothing here needs std: memory, I/O and the process come from libc and
+//! libm, which compiled programs link anyway. Failures print the messages
+//! of the interpreter to stdout, traced like its errors, and exit with
+//! status 1, like `alpha -f`.
+#![no_std]
+#![allow(clippy::missing_safety_doc)]
+
+mod any;
+mod bigint;
+mod gc;
+mod io;
+mod libc;
+mod list;
+mod map;
+mod math;
+mod print;
+mod process;
+mod trace;
+
+/// Bugs of the runtime itself, rather than of the program, abort.
+#[panic_handler]
+fn panic(info: &core::panic::PanicInfo) -> ! {
+    use core::fmt::Write;
+    let _ = writeln!(print::Stream::stderr(), "alpha runtime: {}", info);
+    unsafe { libc::abort() }
+}
+
+/// The precompiled core refers to the personality routine of unwinding,
+/// which never runs with `panic = "abort"`.
+#[no_mangle]
+extern "C" fn rust_eh_personality() {}
@@ -0,0 +1,61 @@
+//! The functions of libc and libm that the runtime uses.
+
+use core::ffi::{c_char, c_int, c_void};
+
+#[repr(C)]
+pub struct File {
+    _private: [u8; 0],
+}
+
+pub const EOF: c_int = -1;
+pub const F_OK: c_int = 0;
+
+extern "C" {
+    pub static stdout: *mut File;
+    pub static stderr: *mut File;
+
+    pub fn malloc(size: usize) -> *mut c_void;
+    pub fn calloc(count: usize, size: usize) -> *mut c_void;
+    pub fn realloc(p: *mut c_void, size: usize) -> *mut c_void;
+    pub fn free(p: *mut c_void);
+
+    pub fn exit(status: c_int) -> !;
+    pub fn abort() -> !;
+    pub fn atexit(f: extern "C" fn()) -> c_int;
+    pub fn getenv(name: *const c_char) -> *mut c_char;
+
+    pub fn fopen(path: *const c_char, mode: *const c_char) -> *mut File;
+    pub fn fclose(file: *mut File) -> c_int;
+    pub fn fread(p: *mut c_void, size: usize, count: usize, file: *mut File) -> usize;
+    pub fn fwrite(p: *const c_void, size: usize, count: usize, file: *mut File) -> usize;
+    pub fn fputs(s: *const c_char, file: *mut File) -> c_int;
+    pub fn fprintf(file: *mut File, format: *const c_char, ...) -> c_int;
+    pub fn ferror(file: *mut File) -> c_int;
+    pub fn getchar() -> c_int;
+    pub fn access(path: *const c_char, mode: c_int) -> c_int;
+    pub fn strerror(errno: c_int) -> *const c_char;
+    pub fn __errno_location() -> *mut c_int;
+
+    pub fn trunc(x: f64) -> f64;
+    pub fn fmod(x: f64, y: f64) -> f64;
+    pub fn pow(x: f64, y: f64) -> f64;
+    pub fn frexp(x: f64, exponent: *mut c_int) -> f64;
+    pub fn ldexp(x: f64, exponent: c_int) -> f64;
+}
+
+/// Memory from malloc, exiting when there is none.
+pub unsafe fn alloc(size: usize) -> *mut u8 {
+    checked(malloc(size.max(1)))
+}
+
+/// Memory from realloc, exiting when there is none.
+pub unsafe fn grow(p: *mut u8, size: usize) -> *mut u8 {
+    checked(realloc(p.cast(), size.max(1)))
+}
+
+unsafe fn checked(p: *mut c_void) -> *mut u8 {
+    if p.is_null() {
+        crate::print::error(format_args!("Out of memory"));
+    }
+    p.cast()
+}
@@ -0,0 +1,319 @@
+//! Lists for compiled alpha programs: a length followed by 8-byte items,
+//! which hold ints, floats and bools by value and everything else as
+//! pointers. Compiled code reads and initializes items directly; updates go
+//! through alpha_list_set, which copies so that lists keep value semantics.
+//! Lists and the strings made here live in the heap of gc.rs.
+//!
+//! Also the list and string builtins of src/lists.rs that need no alpha
+//! function, which are given the description of the type of the items, in
+//! the grammar of print.rs, to compare them like any.rs does.
+
+use core::ffi::{c_char, CStr};
+use core::mem::size_of;
+use core::slice;
+
+use crate::any;
+use crate::gc;
+use crate::libc;
+use crate::print::{error, Typed};
+
+#[repr(C)]
+pub struct List {
+    len: i64,
+    items: [i64; 0],
+}
+
+impl List {
+    pub fn items(&self) -> &[i64] {
+        unsafe { slice::from_raw_parts(self.items.as_ptr(), self.len as usize) }
+    }
+
+    pub fn items_mut(&mut self) -> &mut [i64] {
+        unsafe { slice::from_raw_parts_mut(self.items.as_mut_ptr(), self.len as usize) }
+    }
+}
+
+/// A list of the items of `items`.
+pub fn list(items: &[i64]) -> &'static mut List {
+    let list = alpha_list_new(items.len() as i64);
+    list.items_mut().copy_from_slice(items);
+    list
+}
+
+/// Same flags as SLICE_FROM and SLICE_TO in src/bytecode.rs.
+pub const SLICE_FROM: i64 = 1;
+pub const SLICE_TO: i64 = 2;
+
+#[no_mangle]
+pub extern "C" fn alpha_list_new(len: i64) -> &'static mut List {
+    let size = size_of::<List>() + len as usize * size_of::<i64>();
+    let list = unsafe { &mut *(gc::alloc(size, true) as *mut List) };
+    list.len = len;
+    list
+}
+
+/// The position of an index counting negative ones from the end, with the
+/// message of value::index_out_of_bounds if it is out of bounds.
+#[no_mangle]
+pub extern "C" fn alpha_list_index(list: &List, index: i64) -> i64 {
+    let position = if index < 0 { index + list.len } else { index };
+    if position < 0 || position >= list.len {
+        error(format_args!(
+            "Index out of bounds: {} (len {})",
+            index, list.len
+        ));
+    }
+    position
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_list_set(list: &List, index: i64, item: i64) -> &'static mut List {
+    let position = alpha_list_index(list, index);
+    let copy = self::list(list.items());
+    copy.items_mut()[position as usize] = item;
+    copy
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_list_slice(
+    list: &List,
+    from: i64,
+    to: i64,
+    bounds: i64,
+) -> &'static mut List {
+    let from = if bounds & SLICE_FROM != 0 { from } else { 0 };
+    let to = if bounds & SLICE_TO != 0 { to } else { list.len };
+    let start = if from < 0 { from + list.len } else { from };
+    let end = if to < 0 { to + list.len } else { to };
+    if start < 0 || start > end || end > list.len {
+        error(format_args!(
+            "Slice out of bounds: {}..{} (len {})",
+            from, to, list.len
+        ));
+    }
+    self::list(&list.items()[start as usize..end as usize])
+}
+
+/// The first value and the exclusive end of a range, which is laid out
+/// like a list of `from`, `to` and whether each bound is exclusive, like
+/// `Range::bounds`.
+pub fn bounds(range: &[i64]) -> (i64, i64) {
+    let start = match range[2] {
+        0 => range[0],
+        _ => range[0].saturating_add(1),
+    };
+    let end = match range[3] {
+        0 => range[1].saturating_add(1),
+        _ => range[1],
+    };
+    (start, end)
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_range_start(range: &List) -> i64 {
+    bounds(range.items()).0
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_range_end(range: &List) -> i64 {
+    bounds(range.items()).1
+}
+
+/// The items of a list that a range indexes, like `list[range]`.
+#[no_mangle]
+pub extern "C" fn alpha_list_range(list: &List, range: &List) -> &'static mut List {
+    let (from, to) = bounds(range.items());
+    alpha_list_slice(list, from, to, SLICE_FROM | SLICE_TO)
+}
+
+/// Checks that a list destructured into len names has as many items.
+#[no_mangle]
+pub extern "C" fn alpha_list_unpack(list: &List, len: i64) {
+    if list.len != len {
+        error(format_args!(
+            "Arity mismatch: expected {} values, found {}",
+            len, list.len
+        ));
+    }
+}
+
+pub unsafe fn str<'a>(s: i64) -> &'a CStr {
+    CStr::from_ptr(s as *const c_char)
+}
+
+/// The number of characters of a string, like len.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_str_len(s: *const c_char) -> i64 {
+    let bytes = CStr::from_ptr(s).to_bytes();
+    bytes.iter().filter(|&&c| c & 0xc0 != 0x80).count() as i64
+}
+
+/// The characters of a string as a list of strings, like chars.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_str_chars(s: *const c_char) -> &'static mut List {
+    let chars = alpha_list_new(alpha_str_len(s));
+    let mut bytes = CStr::from_ptr(s).to_bytes();
+    for item in chars.items_mut() {
+        let size = 1 + bytes[1..].iter().take_while(|&&c| c & 0xc0 == 0x80).count();
+        let c = gc::alloc(size + 1, false);
+        c.copy_from_nonoverlapping(bytes.as_ptr(), size);
+        *item = c as i64;
+        bytes = &bytes[size..];
+    }
+    chars
+}
+
+/// The strings of a list joined together, like concat. The items are of
+/// the type that `item` describes, and any else than strings fail.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_str_concat(list: &List, item: *const c_char) -> *const c_char {
+    let item = Typed::new(0, item);
+    for &s in list.items() {
+        let s = item.of(s, item.ty).resolve();
+        if s.ty[0] != b's' {
+            error(format_args!("Not a string: '{:?}'", s));
+        }
+    }
+    let strs = || {
+        list.items()
+            .iter()
+            .map(move |&s| item.of(s, item.ty).resolve().value)
+            .map(|s| str(s).to_bytes())
+    };
+    let s = gc::alloc(strs().map(<[u8]>::len).sum::<usize>() + 1, false);
+    let mut end = s;
+    for bytes in strs() {
+        unsafe {
+            end.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+            end = end.add(bytes.len());
+        }
+    }
+    s as *const c_char
+}
+
+/// The items of list in the stable order of keys, which has as many items
+/// of the type that `key` describes. Every key must compare with the first,
+/// which makes them all comparable.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_list_sort(
+    list: &List,
+    keys: &List,
+    key: *const c_char,
+) -> &'static mut List {
+    let key = Typed::new(0, key);
+    let keys: &[i64] = keys.items();
+    let compare = |a: i64, b: i64| any::compare(key.of(a, key.ty), key.of(b, key.ty));
+    if let Some(&first) = keys.first() {
+        for &other in keys {
+            compare(first, other);
+        }
+    }
+    let len = list.len as usize;
+    let buffer = unsafe { libc::alloc(2 * len * size_of::<usize>()) } as *mut usize;
+    let order = unsafe { slice::from_raw_parts_mut(buffer, len) };
+    let merged = unsafe { slice::from_raw_parts_mut(buffer.add(len), len) };
+    for (i, position) in order.iter_mut().enumerate() {
+        *position = i;
+    }
+    let mut width = 1;
+    while width < len {
+        for from in (0..len).step_by(2 * width) {
+            let mid = (from + width).min(len);
+            let to = (mid + width).min(len);
+            let (mut a, mut b) = (from, mid);
+            for slot in &mut merged[from..to] {
+                if b == to || (a < mid && compare(keys[order[a]], keys[order[b]]).is_le()) {
+                    *slot = order[a];
+                    a += 1;
+                } else {
+                    *slot = order[b];
+                    b += 1;
+                }
+            }
+        }
+        order.copy_from_slice(merged);
+        width *= 2;
+    }
+    let sorted = alpha_list_new(list.len);
+    for (item, &position) in sorted.items_mut().iter_mut().zip(order.iter()) {
+        *item = list.items()[position];
+    }
+    unsafe { libc::free(buffer.cast()) };
+    sorted
+}
+
+/// The first smallest item, or the first largest one if wanted is 1, of
+/// items of the type that `item` describes.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_list_extreme(list: &List, item: *const c_char, wanted: i64) -> i64 {
+    let item = Typed::new(0, item);
+    let Some(&first) = list.items().first() else {
+        error(format_args!(
+            "{} of an empty list",
+            if wanted > 0 { "max" } else { "min" }
+        ));
+    };
+    list.items().iter().fold(first, |best, &other| {
+        match any::compare(item.of(other, item.ty), item.of(best, item.ty)) as i64 == wanted {
+            true => other,
+            false => best,
+        }
+    })
+}
+
+/// Whether a list of items of the type that `items` describes has one equal
+/// to `item`, of the type that `description` describes.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_list_contains(
+    list: &List,
+    items: *const c_char,
+    item: i64,
+    description: *const c_char,
+) -> i64 {
+    let (items, item) = (Typed::new(0, items), Typed::new(item, description));
+    list.items()
+        .iter()
+        .any(|&other| any::equals(items.of(other, items.ty), item)) as i64
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_list_reverse(list: &List) -> &'static mut List {
+    let reversed = self::list(list.items());
+    reversed.items_mut().reverse();
+    reversed
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_list_push(list: &List, item: i64) -> &'static mut List {
+    let pushed = alpha_list_new(list.len + 1);
+    pushed.items_mut()[..list.len as usize].copy_from_slice(list.items());
+    pushed.items_mut()[list.len as usize] = item;
+    pushed
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_list_pop(list: &List) -> &'static mut List {
+    if list.len == 0 {
+        error(format_args!("pop of an empty list"));
+    }
+    alpha_list_slice(list, 0, -1, SLICE_TO)
+}
+
+/// Pairs of the items of a and b up to the shorter, as 2-tuples.
+#[no_mangle]
+pub extern "C" fn alpha_list_zip(a: &List, b: &List) -> &'static mut List {
+    let zipped = alpha_list_new(a.len.min(b.len));
+    for (i, item) in zipped.items_mut().iter_mut().enumerate() {
+        *item = list(&[a.items()[i], b.items()[i]]) as *mut List as i64;
+    }
+    zipped
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_list_enumerate(list: &List) -> &'static mut List {
+    let positions = alpha_list_new(list.len);
+    for (i, position) in positions.items_mut().iter_mut().enumerate() {
+        *position = i as i64;
+    }
+    alpha_list_zip(positions, list)
+}
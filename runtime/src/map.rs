@@ -0,0 +1,171 @@
+//! Maps for compiled alpha programs: parallel arrays of 8-byte keys and
+//! values in insertion order, searched linearly. The description of the
+//! key type, in the grammar of print.rs, is fixed when the map is created
+//! and decides how keys compare and print. Like lists,
+//! maps are copied on update so that they keep value semantics; only
+//! alpha_map_put changes a map in place, while compiled code builds a
+//! literal. Maps and their arrays live in the heap of gc.rs.
+
+use core::mem::size_of;
+use core::ptr;
+use core::ffi::c_char;
+use core::slice;
+
+use crate::any;
+use crate::gc;
+use crate::list::{self, List};
+use crate::print::{error, Typed};
+
+#[repr(C)]
+pub struct Map {
+    len: i64,
+    key: *const c_char,
+    keys: *mut i64,
+    values: *mut i64,
+}
+
+impl Map {
+    pub fn keys(&self) -> &[i64] {
+        unsafe { items(self.keys, self.len) }
+    }
+
+    pub fn values(&self) -> &[i64] {
+        unsafe { items(self.values, self.len) }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.keys()
+            .iter()
+            .copied()
+            .zip(self.values().iter().copied())
+    }
+
+    /// The description of the type of the keys.
+    pub fn key(&self) -> *const c_char {
+        self.key
+    }
+
+    fn find(&self, key: i64) -> Option<usize> {
+        match unsafe { *self.key } as u8 {
+            b'i' | b'b' => self.keys().iter().position(|&other| other == key),
+            _ => any::find(self, unsafe { Typed::new(key, self.key) }),
+        }
+    }
+}
+
+/// The `len` items at `items`, which is null while there are none.
+unsafe fn items<'a>(items: *mut i64, len: i64) -> &'a [i64] {
+    match len {
+        0 => &[],
+        len => slice::from_raw_parts(items, len as usize),
+    }
+}
+
+fn alloc(len: i64) -> *mut i64 {
+    gc::alloc(len as usize * size_of::<i64>(), true).cast()
+}
+
+/// The first len items of items in an array of len + 1.
+fn grow(items: *mut i64, len: i64) -> *mut i64 {
+    let grown = alloc(len + 1);
+    if !items.is_null() {
+        unsafe { grown.copy_from_nonoverlapping(items, len as usize) };
+        gc::free(items.cast());
+    }
+    grown
+}
+
+/// A map with keys of the type that `key` describes.
+#[no_mangle]
+pub extern "C" fn alpha_map_new(key: *const c_char) -> &'static mut Map {
+    let map = unsafe { &mut *(gc::alloc(size_of::<Map>(), true) as *mut Map) };
+    map.key = key;
+    map
+}
+
+/// Fails with the message of value::key_not_found, which prints the key
+/// with Debug.
+fn key_not_found(map: &Map, key: i64) -> ! {
+    let key = unsafe { Typed::new(key, map.key) };
+    error(format_args!("Key not found: {:?}", key))
+}
+
+/// Inserts or replaces a key in place.
+#[no_mangle]
+pub extern "C" fn alpha_map_put(map: &mut Map, key: i64, value: i64) -> &mut Map {
+    let i = map.find(key).unwrap_or_else(|| {
+        let i = map.len;
+        map.len += 1;
+        map.keys = grow(map.keys, i);
+        map.values = grow(map.values, i);
+        unsafe { *map.keys.add(i as usize) = key };
+        i as usize
+    });
+    unsafe { *map.values.add(i) = value };
+    map
+}
+
+pub fn copy(map: &Map) -> &'static mut Map {
+    let result = alpha_map_new(map.key);
+    result.len = map.len;
+    result.keys = alloc(map.len);
+    result.values = alloc(map.len);
+    if map.len > 0 {
+        unsafe {
+            result
+                .keys
+                .copy_from_nonoverlapping(map.keys, map.len as usize);
+            result
+                .values
+                .copy_from_nonoverlapping(map.values, map.len as usize);
+        }
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_map_get(map: &Map, key: i64) -> i64 {
+    match map.find(key) {
+        Some(i) => map.values()[i],
+        None => key_not_found(map, key),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_map_set(map: &Map, key: i64, value: i64) -> &'static mut Map {
+    alpha_map_put(copy(map), key, value)
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_map_has(map: &Map, key: i64) -> i64 {
+    map.find(key).is_some() as i64
+}
+
+/// A copy of `map` without the entry at `position`, if it has one.
+pub fn without(map: &Map, position: Option<usize>) -> &'static mut Map {
+    let result = copy(map);
+    if let Some(i) = position {
+        result.len -= 1;
+        let after = result.len as usize - i;
+        unsafe {
+            ptr::copy(result.keys.add(i + 1), result.keys.add(i), after);
+            ptr::copy(result.values.add(i + 1), result.values.add(i), after);
+        }
+    }
+    result
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_map_remove(map: &Map, key: i64) -> &'static mut Map {
+    without(map, map.find(key))
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_map_keys(map: &Map) -> &'static mut List {
+    list::list(map.keys())
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_map_values(map: &Map) -> &'static mut List {
+    list::list(map.values())
+}
@@ -0,0 +1,22 @@
+//! The generator behind random for compiled alpha programs: splitmix64, like
+//! math::Random in src/math.rs, so that a seed draws the same numbers.
+
+use core::ptr;
+
+static mut STATE: u64 = 0;
+
+#[no_mangle]
+pub extern "C" fn alpha_seed(seed: i64) {
+    unsafe { *ptr::addr_of_mut!(STATE) = seed as u64 };
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_random() -> f64 {
+    let state = unsafe { &mut *ptr::addr_of_mut!(STATE) };
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
@@ -0,0 +1,302 @@
+//! Printing for compiled alpha programs: printf and eprint, print, and the
+//! messages of failures, which trace.rs traces.
+//!
+//! Compiled code cannot make variadic calls, which on x86-64 pass the
+//! number of float arguments in %al, so the compiler splits a printf format
+//! into pieces of one conversion each and prints every piece with the
+//! function for the type of its argument. The file descriptor 1 prints to
+//! stdout and 2 to stderr.
+//!
+//! print shows values like Debug for value::Value, from a description of
+//! their type that comp::Ty::description writes:
+//!
+//! ```text
+//! description = type ("\n" name "=" definition)*
+//! type        = "u" | "i" | "f" | "b" | "n" | "s"     unit, int, float, bool, bigint, str
+//!             | "l" type | "o" type | "m" type type  list, option, map of keys and values
+//!             | "t" count "," type*                   tuple
+//!             | "@" name ","                          struct or enum, defined after the type
+//!             | "F" name ","                          function, anonymous if the name is empty
+//!             | "r"                                   range
+//!             | "a"                                   any, a box of any.rs
+//!             | "v"                                   never, of what never has a value
+//! definition  = "r" count "," (field "," type)*
+//!             | "e" count "," (variant "," count "," type*)*
+//! ```
+
+use core::ffi::{c_char, c_int, CStr};
+use core::fmt::{self, Write};
+
+use crate::bigint::BigInt;
+use crate::libc::{self, File};
+use crate::list::List;
+use crate::map::Map;
+use crate::trace::trace;
+
+/// A stdio stream, which Rust formatting writes to.
+pub struct Stream(*mut File);
+
+impl Stream {
+    pub fn stdout() -> Self {
+        Stream(unsafe { libc::stdout })
+    }
+
+    pub fn stderr() -> Self {
+        Stream(unsafe { libc::stderr })
+    }
+
+    fn fd(fd: i64) -> Self {
+        match fd {
+            2 => Stream::stderr(),
+            _ => Stream::stdout(),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        unsafe { libc::fwrite(s.as_ptr().cast(), 1, s.len(), self.0) };
+        Ok(())
+    }
+}
+
+/// Prints `message` like the interpreter prints an error, and exits with
+/// status 1.
+pub fn error(message: fmt::Arguments) -> ! {
+    let _ = writeln!(Stream::stdout(), "{}", trace(message));
+    unsafe { libc::exit(1) }
+}
+
+/// The string `s` points to, with its NUL like the strings of the
+/// interpreter, or as much of it as is UTF-8.
+pub unsafe fn text<'a>(s: *const c_char) -> &'a str {
+    let bytes = CStr::from_ptr(s).to_bytes_with_nul();
+    match core::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => core::str::from_utf8_unchecked(&bytes[..e.valid_up_to()]),
+    }
+}
+
+/// Prints `message` and exits with `status`, for the failures that compiled
+/// code checks itself.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_panic(message: *const c_char, status: i64) -> ! {
+    let message = text(message).trim_end_matches('\0');
+    let _ = writeln!(Stream::stdout(), "{}", trace(message));
+    libc::exit(status as c_int)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_printf_text(fd: i64, text: *const c_char) {
+    libc::fputs(text, Stream::fd(fd).0);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_printf_int(fd: i64, format: *const c_char, value: i64) {
+    libc::fprintf(Stream::fd(fd).0, format, value);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_printf_float(fd: i64, format: *const c_char, value: f64) {
+    libc::fprintf(Stream::fd(fd).0, format, value);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_printf_str(fd: i64, format: *const c_char, value: *const c_char) {
+    libc::fprintf(Stream::fd(fd).0, format, value);
+}
+
+/// Prints a value as compiled code holds it, of the type that `description`
+/// describes.
+#[no_mangle]
+pub unsafe extern "C" fn alpha_print(fd: i64, value: i64, description: *const c_char) {
+    let _ = write!(Stream::fd(fd), "{:?}", Typed::new(value, description));
+}
+
+/// A value of the type at the start of `ty`, whose structs and enums are
+/// defined in `types`.
+#[derive(Clone, Copy)]
+pub struct Typed<'a> {
+    pub value: i64,
+    pub ty: &'a [u8],
+    pub types: &'a [u8],
+}
+
+impl<'a> Typed<'a> {
+    /// A value of the type that the description `description` describes.
+    pub unsafe fn new(value: i64, description: *const c_char) -> Self {
+        let types = CStr::from_ptr(description).to_bytes();
+        Typed {
+            value,
+            ty: types,
+            types,
+        }
+    }
+
+    pub fn of(&self, value: i64, ty: &'a [u8]) -> Typed<'a> {
+        Typed {
+            value,
+            ty,
+            types: self.types,
+        }
+    }
+
+    /// The values of the items of a list, or of a tuple, struct or enum,
+    /// which are laid out alike.
+    pub fn items(&self) -> &'a [i64] {
+        unsafe { (*(self.value as *const List)).items() }
+    }
+
+    /// The definition of the struct or enum `name`.
+    pub fn definition(&self, name: &str) -> &'a [u8] {
+        self.types
+            .split(|&c| c == b'\n')
+            .skip(1)
+            .find_map(|line| line.strip_prefix(name.as_bytes())?.strip_prefix(b"="))
+            .unwrap_or_else(|| panic!("{} is not described", name))
+    }
+
+    /// The name of the variant of an enum value, with the length and the
+    /// types of its payload.
+    pub fn variant(&self) -> (&'a str, usize, &'a [u8]) {
+        let definition = self.definition(word(&self.ty[1..]).0);
+        let (_, mut types) = number(&definition[1..]);
+        for _ in 0..self.items()[0] {
+            let (_, payload) = word(types);
+            let (len, mut payload) = number(payload);
+            for _ in 0..len {
+                payload = skip(payload);
+            }
+            types = payload;
+        }
+        let (name, payload) = word(types);
+        let (len, payload) = number(payload);
+        (name, len, payload)
+    }
+
+    /// Shows `values` of the types that follow one another in `types`.
+    fn fields(&self, values: &[i64], mut types: &'a [u8], mut show: impl FnMut(Typed<'a>)) {
+        for &value in values {
+            show(self.of(value, types));
+            types = skip(types);
+        }
+    }
+}
+
+impl fmt::Debug for Typed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.value;
+        let rest = &self.ty[1..];
+        match self.ty[0] {
+            b'u' | b'v' => write!(f, "Unit"),
+            b'a' => unsafe { Typed::unbox(value) }.fmt(f),
+            b'F' => match word(rest).0 {
+                "" => write!(f, "Function"),
+                name => write!(f, "Function({})", name),
+            },
+            b'r' => {
+                let items = self.items();
+                let bound = |exclusive| match exclusive {
+                    0 => "Inclusive",
+                    _ => "Exclusive",
+                };
+                write!(
+                    f,
+                    "Range {{ from: Int({}), to: Int({}), lower: {}, upper: {} }}",
+                    items[0],
+                    items[1],
+                    bound(items[2]),
+                    bound(items[3])
+                )
+            }
+            b'i' => write!(f, "Int({:?})", value),
+            b'f' => write!(f, "Float({:?})", f64::from_bits(value as u64)),
+            b'b' => write!(f, "Bool({:?})", value != 0),
+            b'n' => write!(f, "BigInt({})", unsafe { &*(value as *const BigInt) }),
+            b's' => write!(f, "String({:?})", unsafe { text(value as *const c_char) }),
+            b'l' => {
+                let items = self.items().iter().map(|&item| self.of(item, rest));
+                write!(f, "List(")?;
+                f.debug_list().entries(items).finish()?;
+                write!(f, ")")
+            }
+            b'm' => {
+                let map = unsafe { &*(value as *const Map) };
+                let values = skip(rest);
+                let entries = map
+                    .entries()
+                    .map(|(k, v)| (self.of(k, rest), self.of(v, values)));
+                write!(f, "Map(")?;
+                f.debug_map().entries(entries).finish()?;
+                write!(f, ")")
+            }
+            b't' => {
+                let (count, types) = number(rest);
+                let mut tuple = f.debug_tuple("Tuple");
+                self.fields(&self.items()[..count], types, |item| {
+                    tuple.field(&item);
+                });
+                tuple.finish()
+            }
+            b'o' => {
+                let items = self.items();
+                let mut variant = f.debug_tuple(if items[0] == 0 { "some" } else { "none" });
+                if items[0] == 0 {
+                    variant.field(&self.of(items[1], rest));
+                }
+                variant.finish()
+            }
+            b'@' => {
+                let (name, _) = word(rest);
+                let definition = self.definition(name);
+                let (count, mut types) = number(&definition[1..]);
+                if definition[0] == b'r' {
+                    let mut record = f.debug_struct(name);
+                    for &field in &self.items()[..count] {
+                        let (name, ty) = word(types);
+                        record.field(name, &self.of(field, ty));
+                        types = skip(ty);
+                    }
+                    return record.finish();
+                }
+                let (name, len, payload) = self.variant();
+                let mut variant = f.debug_tuple(name);
+                self.fields(&self.items()[1..=len], payload, |value| {
+                    variant.field(&value);
+                });
+                variant.finish()
+            }
+            c => panic!("unknown type {}", c as char),
+        }
+    }
+}
+
+/// What follows the type at the start of `ty`.
+pub fn skip(ty: &[u8]) -> &[u8] {
+    let rest = &ty[1..];
+    match ty[0] {
+        b'l' | b'o' => skip(rest),
+        b'm' => skip(skip(rest)),
+        b't' => {
+            let (count, mut rest) = number(rest);
+            for _ in 0..count {
+                rest = skip(rest);
+            }
+            rest
+        }
+        b'@' | b'F' => word(rest).1,
+        _ => rest,
+    }
+}
+
+/// The text up to the next comma, and what follows the comma.
+pub fn word(s: &[u8]) -> (&str, &[u8]) {
+    let end = s.iter().position(|&c| c == b',').unwrap_or(s.len());
+    let word = core::str::from_utf8(&s[..end]).unwrap_or_default();
+    (word, s.get(end + 1..).unwrap_or_default())
+}
+
+pub fn number(s: &[u8]) -> (usize, &[u8]) {
+    let (number, rest) = word(s);
+    (number.parse().unwrap_or_default(), rest)
+}
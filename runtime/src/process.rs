@@ -0,0 +1,45 @@
+//! The builtins of src/process.rs that need the arguments of the program,
+//! which the `main` that src/comp.rs compiles passes to alpha_init. exit is
+//! libc's.
+
+use core::ffi::c_char;
+use core::ptr;
+use core::slice;
+
+use crate::libc;
+use crate::list::{alpha_list_new, list, List};
+
+/// Same tags as value::SOME and value::NONE.
+const SOME: i64 = 0;
+const NONE: i64 = 1;
+
+/// The arguments of `main`; libraries have none.
+static mut ARGS: (i64, *const *const c_char) = (0, ptr::null());
+
+#[no_mangle]
+pub extern "C" fn alpha_init(argc: i64, argv: *const *const c_char) {
+    unsafe { *ptr::addr_of_mut!(ARGS) = (argc, argv) };
+}
+
+/// The arguments after the name of the program.
+#[no_mangle]
+pub extern "C" fn alpha_args() -> &'static mut List {
+    let (argc, argv) = unsafe { *ptr::addr_of!(ARGS) };
+    let args = alpha_list_new((argc - 1).max(0));
+    if argc > 1 {
+        let argv = unsafe { slice::from_raw_parts(argv.add(1), args.items().len()) };
+        for (item, &arg) in args.items_mut().iter_mut().zip(argv) {
+            *item = arg as i64;
+        }
+    }
+    args
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn alpha_env(name: *const c_char) -> &'static mut List {
+    let var = libc::getenv(name);
+    match var.is_null() {
+        true => list(&[NONE]),
+        false => list(&[SOME, var as i64]),
+    }
+}
@@ -0,0 +1,190 @@
+//! The table of compiled functions and the frames of compiled code, which
+//! the collector marks the stack with and errors and `backtrace()` are
+//! traced with, like the interpreter traces them.
+//!
+//! src/comp.rs puts a record of every compiled function in the
+//! alpha_functions section. Frames are found by following frame pointers,
+//! which compiled code and the runtime keep, from the runtime up to the
+//! first frame that is not compiled alpha code: the `main` of a program or
+//! the C code calling a library.
+
+use core::ffi::c_char;
+use core::fmt::{self, Write};
+use core::ptr;
+use core::slice;
+
+use crate::print::{text, Stream};
+
+/// A compiled function, as written by comp::Compiler::define_function_record.
+#[repr(C)]
+pub struct Function {
+    pub start: usize,
+    pub size: i64,
+    /// Pairs of the offset of a return address and the offset from the SP
+    /// at the call of a pointer.
+    pub maps: *const [u32; 2],
+    pub count: i64,
+    /// Null for the top level, and empty for wrappers and thunks.
+    name: *const c_char,
+    /// Pairs of the offset where a line starts and the line.
+    lines: *const [u32; 2],
+    line_count: i64,
+}
+
+extern "C" {
+    static __start_alpha_functions: Function;
+    static __stop_alpha_functions: Function;
+}
+
+impl Function {
+    /// The function whose code `pc`, a return address, returns into.
+    fn at(pc: usize) -> Option<&'static Function> {
+        unsafe {
+            let first = ptr::addr_of!(__start_alpha_functions);
+            let last = ptr::addr_of!(__stop_alpha_functions);
+            let functions = slice::from_raw_parts(first, last.offset_from(first) as usize);
+            functions
+                .iter()
+                .find(|f| pc > f.start && pc <= f.start + f.size as usize)
+        }
+    }
+
+    /// The line of the call that `pc` returns from.
+    fn line_at(&self, pc: usize) -> u32 {
+        let offset = (pc - 1 - self.start) as u32;
+        let lines = unsafe { slice::from_raw_parts(self.lines, self.line_count as usize) };
+        lines
+            .iter()
+            .take_while(|&&[start, _]| start <= offset)
+            .last()
+            .map_or(0, |&[_, line]| line)
+    }
+
+    fn name(&self) -> Option<&str> {
+        match self.name.is_null() {
+            true => None,
+            false => Some(unsafe { text(self.name) }.trim_end_matches('\0')),
+        }
+    }
+}
+
+/// A frame of compiled code.
+pub struct Frame {
+    pub function: &'static Function,
+    /// The return address into the function.
+    pub pc: usize,
+    /// Its SP at the call, above the return address and the saved frame
+    /// pointer.
+    pub sp: *const u8,
+}
+
+/// Frames of the runtime to go through before the first compiled frame,
+/// in case the frame it is called from is not one.
+const MAX_RUNTIME_FRAMES: usize = 64;
+
+/// The compiled frames above `frame`, a frame pointer followed by a return
+/// address, innermost first.
+pub struct Frames {
+    frame: *const usize,
+    runtime: usize,
+}
+
+impl Frames {
+    pub unsafe fn from(frame: *const usize) -> Self {
+        Frames { frame, runtime: 0 }
+    }
+}
+
+impl Iterator for Frames {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        while !self.frame.is_null() {
+            let frame = self.frame;
+            let (caller, pc) = unsafe { (*frame as *const usize, *frame.add(1)) };
+            self.frame = caller;
+            match Function::at(pc) {
+                Some(function) => {
+                    self.runtime = MAX_RUNTIME_FRAMES;
+                    return Some(Frame {
+                        function,
+                        pc,
+                        sp: unsafe { frame.add(2) as *const u8 },
+                    });
+                }
+                // Frame pointers lead up the stack, unless the frame is
+                // not the runtime's after all.
+                None if self.runtime < MAX_RUNTIME_FRAMES && caller > frame => {
+                    self.runtime += 1;
+                }
+                None => break,
+            }
+        }
+        self.frame = ptr::null();
+        None
+    }
+}
+
+/// `message` on the line of the innermost compiled frame, with the
+/// functions it is in and the lines they were called from, like
+/// value::Error prints.
+pub struct Trace<M> {
+    message: M,
+    frame: *const usize,
+}
+
+impl<M: fmt::Display> fmt::Display for Trace<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The wrappers and thunks that compiled code calls functions
+        // through have records named "" and are left out.
+        let mut frames = unsafe { Frames::from(self.frame) }
+            .filter(|frame| frame.function.name() != Some(""))
+            .peekable();
+        if let Some(frame) = frames.peek() {
+            write!(f, "line {}: ", frame.function.line_at(frame.pc))?;
+        }
+        write!(f, "{}", self.message)?;
+        while let Some(frame) = frames.next() {
+            let Some(name) = frame.function.name() else {
+                break;
+            };
+            write!(f, "\n    in {}", name)?;
+            if let Some(caller) = frames.peek() {
+                write!(
+                    f,
+                    ", called from line {}",
+                    caller.function.line_at(caller.pc)
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Traces `message` from the frame of the caller.
+#[inline(always)]
+pub fn trace<M: fmt::Display>(message: M) -> Trace<M> {
+    Trace {
+        message,
+        frame: frame_pointer(),
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn frame_pointer() -> *const usize {
+    let frame;
+    unsafe { core::arch::asm!("mov {}, rbp", out(reg) frame, options(nomem, nostack)) };
+    frame
+}
+
+/// Only x86-64 frames are walked; elsewhere traces are just the message.
+#[cfg(not(target_arch = "x86_64"))]
+fn frame_pointer() -> *const usize {
+    ptr::null()
+}
+
+#[no_mangle]
+pub extern "C" fn alpha_backtrace() {
+    let _ = writeln!(Stream::stdout(), "{}", trace("Backtrace"));
+}
@@ -1,9 +1,13 @@
-use crate::eval;
+use std::rc::Rc;
+
+use crate::bigint::BigInt;
 
 #[derive(Debug, Clone)]
 pub enum Node {
     Nada,
-    Number(f64),
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Float(f64),
     Bool(bool),
     String(String),
     Range {
@@ -12,8 +16,11 @@ pub enum Node {
         lower: Bound,
         upper: Bound,
     },
+    /// `for var in iterable`, or `for var, value in iterable` to bind the
+    /// key (the position in lists and ranges) to `var` and the value to `value`.
     Loop {
-        var: String,
+        var: Pattern,
+        value: Option<Pattern>,
         iterable: Box<Node>,
         inner: Box<Node>,
     },
@@ -27,22 +34,206 @@ pub enum Node {
         else_block: Box<Node>,
     },
     Statements(Vec<Node>),
+    Line(usize, Box<Node>),
     FnCall(String, Vec<Node>),
-    FnDef(Option<String>, Vec<String>, Box<Node>, Option<String>),
-    ScopedFnDef(Option<String>, Vec<String>, Box<Node>, eval::Scope),
+    /// Parameters are patterns with optional type names.
+    FnDef(
+        Option<String>,
+        Vec<(Pattern, Option<String>)>,
+        Rc<Node>,
+        Option<String>,
+    ),
+    /// `pub fn`, a function that other modules can import.
+    Pub(Box<Node>),
+    /// `import path;`, or `import path.{name, ...};` to use the named
+    /// functions of the module without qualifying them. Only
+    /// `modules::load` sees these.
+    Import(ModulePath, Option<Vec<String>>),
+    /// `extern fn name(param: type, ...): type;`, a C function that is
+    /// called with its arguments converted to C types.
+    Extern(String, Vec<(String, String)>, Option<String>),
+    /// `struct name { field: type, ... }`.
+    StructDef(String, Vec<(String, String)>),
+    /// `name { field: value, ... }`, with the fields in source order.
+    StructLit(String, Vec<(String, Node)>),
+    /// `value.field`.
+    Field(Box<Node>, String),
+    /// `value?`: the value in `some(value)`, or for `none` a return of
+    /// `none` from the enclosing function.
+    Try(Box<Node>),
+    /// `throw value`: unwinds to the innermost `catch`.
+    Throw(Box<Node>),
+    /// `try { body } catch name { handler }`, where `name` is bound to the
+    /// thrown value, or to the message of a runtime error.
+    TryCatch {
+        body: Box<Node>,
+        name: String,
+        handler: Box<Node>,
+    },
+    /// `enum name { variant(payload, ...), ... }`.
+    EnumDef(String, Vec<VariantDef>),
+    Match {
+        subject: Box<Node>,
+        arms: Vec<Arm>,
+    },
     List(Vec<Node>),
+    Tuple(Vec<Node>),
+    Map(Vec<(Node, Node)>),
+    Index(Box<Node>, Box<Node>),
+    Slice {
+        list: Box<Node>,
+        from: Option<Box<Node>>,
+        to: Option<Box<Node>>,
+    },
     Expr {
         op: Op,
         lhs: Box<Node>,
         rhs: Box<Node>,
     },
-    Define(Mut, String, Box<Node>, Option<String>),
+    Define(Mut, Pattern, Box<Node>, Option<String>),
     TypeName(String),
     Assign(String, Box<Node>),
+    /// `a[i].f = v`: the variable, the steps from outermost in, the value.
+    IndexAssign(String, Vec<Step>, Box<Node>),
     VarRef(String),
 }
 
+impl Node {
+    /// The nodes directly inside this one, leaving out the bodies of
+    /// functions.
+    pub fn children(&self) -> Box<dyn Iterator<Item = &Node> + '_> {
+        match self {
+            Node::Range { from, to, .. } => Box::new([from.as_ref(), to.as_ref()].into_iter()),
+            Node::Loop {
+                iterable, inner, ..
+            } => Box::new([iterable.as_ref(), inner.as_ref()].into_iter()),
+            Node::While { condition, inner } => {
+                Box::new([condition.as_ref(), inner.as_ref()].into_iter())
+            }
+            Node::IfElse {
+                condition,
+                if_block,
+                else_block,
+            } => Box::new([condition.as_ref(), if_block.as_ref(), else_block.as_ref()].into_iter()),
+            Node::Statements(nodes)
+            | Node::List(nodes)
+            | Node::Tuple(nodes)
+            | Node::FnCall(_, nodes) => Box::new(nodes.iter()),
+            Node::Map(pairs) => Box::new(pairs.iter().flat_map(|(k, v)| [k, v])),
+            Node::StructLit(_, fields) => Box::new(fields.iter().map(|(_, value)| value)),
+            Node::Match { subject, arms } => Box::new(
+                std::iter::once(subject.as_ref()).chain(
+                    arms.iter()
+                        .flat_map(|arm| arm.guard.iter().chain(std::iter::once(&arm.body))),
+                ),
+            ),
+            Node::TryCatch { body, handler, .. } => {
+                Box::new([body.as_ref(), handler.as_ref()].into_iter())
+            }
+            Node::Index(list, index) => Box::new([list.as_ref(), index.as_ref()].into_iter()),
+            Node::Slice { list, from, to } => Box::new(
+                std::iter::once(list.as_ref())
+                    .chain(from.as_deref())
+                    .chain(to.as_deref()),
+            ),
+            Node::IndexAssign(_, steps, expr) => Box::new(
+                steps
+                    .iter()
+                    .filter_map(|step| match step {
+                        Step::Index(index) => Some(index),
+                        Step::Field(_) => None,
+                    })
+                    .chain(std::iter::once(expr.as_ref())),
+            ),
+            Node::Expr { lhs, rhs, .. } => Box::new([lhs.as_ref(), rhs.as_ref()].into_iter()),
+            Node::Define(_, _, expr, _)
+            | Node::Assign(_, expr)
+            | Node::Line(_, expr)
+            | Node::Field(expr, _)
+            | Node::Try(expr)
+            | Node::Throw(expr)
+            | Node::Pub(expr) => Box::new(std::iter::once(expr.as_ref())),
+            _ => Box::new(std::iter::empty()),
+        }
+    }
+}
+
+/// The module an `import` names: a file relative to the importing one, or
+/// names separated by dots, which `a.b` finds at `a/b.a` on the search path.
+#[derive(Debug, Clone)]
+pub enum ModulePath {
+    File(String),
+    Name(Vec<String>),
+}
+
+/// What `let`, `for` and parameters bind: a name, or a tuple of patterns
+/// that destructures a tuple or list of the same length.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Name(String),
+    Tuple(Vec<Pattern>),
+}
+
+impl Pattern {
+    /// The names bound by the pattern, from left to right.
+    pub fn names(&self) -> Vec<&String> {
+        match self {
+            Pattern::Name(name) => vec![name],
+            Pattern::Tuple(patterns) => patterns.iter().flat_map(Pattern::names).collect(),
+        }
+    }
+}
+
+/// A variant of an `enum`: the name and the payload fields with optional
+/// type names.
 #[derive(Debug, Clone)]
+pub struct VariantDef {
+    pub name: String,
+    pub fields: Vec<(String, Option<String>)>,
+}
+
+/// `pattern if guard => body`.
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pattern: MatchPattern,
+    pub guard: Option<Node>,
+    pub body: Node,
+}
+
+/// What a `match` arm tests its subject against. `N` is what a name binds
+/// to: the name, or the slot of a bytecode function.
+#[derive(Debug, Clone)]
+pub enum MatchPattern<N = String> {
+    Wildcard,
+    /// A binding, or a variant without payload if one of that name is
+    /// declared. See `matching::resolve`.
+    Name(N),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    Range {
+        from: i64,
+        to: i64,
+        lower: Bound,
+        upper: Bound,
+    },
+    Tuple(Vec<MatchPattern<N>>),
+    /// The leading items, and for a pattern ending in `..` or `..name` the
+    /// binding of the remaining items.
+    List(Vec<MatchPattern<N>>, Option<Option<N>>),
+    Variant(String, Vec<MatchPattern<N>>),
+}
+
+/// One step into the variable of an `IndexAssign`, where `T` is what the
+/// index evaluates to.
+#[derive(Debug, Clone)]
+pub enum Step<T = Node> {
+    Index(T),
+    Field(String),
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum Bound {
     Inclusive,
     Exclusive,
@@ -54,6 +245,8 @@ pub enum Op {
     Sub,
     Mul,
     Div,
+    Mod,
+    Pow,
     Eq,
     Neq,
     Gt,
@@ -0,0 +1,297 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// Limbs are decimal so that printing and parsing need no base conversion.
+const BASE: u64 = 1_000_000_000;
+const BASE_DIGITS: usize = 9;
+
+/// An arbitrary-precision integer in sign-magnitude form.
+///
+/// `limbs` holds the magnitude in base 10^9, least significant limb first,
+/// without trailing zero limbs. Zero has no limbs and is never negative.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        let negative = negative && !limbs.is_empty();
+        BigInt { negative, limbs }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Parses an optionally signed decimal number.
+    pub fn parse(s: &str) -> Option<BigInt> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let limbs = digits
+            .as_bytes()
+            .rchunks(BASE_DIGITS)
+            .map(|chunk| std::str::from_utf8(chunk).unwrap().parse::<u32>().unwrap())
+            .collect();
+        Some(BigInt::new(negative, limbs))
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        let mut magnitude: i128 = 0;
+        for limb in self.limbs.iter().rev() {
+            magnitude = magnitude * BASE as i128 + *limb as i128;
+            if magnitude > i64::MAX as i128 + 1 {
+                return None;
+            }
+        }
+        let n = if self.negative { -magnitude } else { magnitude };
+        i64::try_from(n).ok()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self
+            .limbs
+            .iter()
+            .rev()
+            .fold(0.0, |acc, limb| acc * BASE as f64 + *limb as f64);
+        if self.negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    /// Truncates a float towards zero, or `None` if it is not finite.
+    pub fn from_f64(n: f64) -> Option<BigInt> {
+        if !n.is_finite() {
+            return None;
+        }
+        BigInt::parse(&format!("{:.0}", n.trunc()))
+    }
+
+    /// Truncating division and the remainder that goes with it, which has
+    /// the sign of the dividend. `None` when dividing by zero.
+    pub fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let divisor = BigInt::new(false, other.limbs.clone());
+        let mut quotient = vec![0; self.limbs.len()];
+        let mut remainder = BigInt::default();
+        for (i, limb) in self.limbs.iter().enumerate().rev() {
+            remainder.limbs.insert(0, *limb);
+            remainder = BigInt::new(false, remainder.limbs);
+            // Binary search for the largest digit with divisor * digit <= remainder.
+            let (mut low, mut high) = (0, BASE as u32 - 1);
+            while low < high {
+                let mid = low + (high - low).div_ceil(2);
+                if divisor.mul_small(mid) <= remainder {
+                    low = mid;
+                } else {
+                    high = mid - 1;
+                }
+            }
+            if low > 0 {
+                remainder = &remainder - &divisor.mul_small(low);
+            }
+            quotient[i] = low;
+        }
+        Some((
+            BigInt::new(self.negative != other.negative, quotient),
+            BigInt::new(self.negative, remainder.limbs),
+        ))
+    }
+
+    pub fn pow(&self, mut exponent: u64) -> BigInt {
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    fn mul_small(&self, factor: u32) -> BigInt {
+        let mut limbs = Vec::with_capacity(self.limbs.len() + 1);
+        let mut carry = 0;
+        for limb in &self.limbs {
+            let n = *limb as u64 * factor as u64 + carry;
+            limbs.push((n % BASE) as u32);
+            carry = n / BASE;
+        }
+        limbs.push(carry as u32);
+        BigInt::new(self.negative, limbs)
+    }
+
+    fn cmp_magnitude(&self, other: &BigInt) -> Ordering {
+        self.limbs
+            .len()
+            .cmp(&other.limbs.len())
+            .then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+
+    fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+        let mut limbs = Vec::with_capacity(a.len().max(b.len()) + 1);
+        let mut carry = 0;
+        for i in 0..a.len().max(b.len()) {
+            let n = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+            limbs.push((n % BASE) as u32);
+            carry = n / BASE;
+        }
+        limbs.push(carry as u32);
+        limbs
+    }
+
+    /// `a - b` for magnitudes with `a >= b`.
+    fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+        let mut limbs = Vec::with_capacity(a.len());
+        let mut borrow = 0;
+        for (i, limb) in a.iter().enumerate() {
+            let mut n = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+            borrow = 0;
+            if n < 0 {
+                n += BASE as i64;
+                borrow = 1;
+            }
+            limbs.push(n as u32);
+        }
+        limbs
+    }
+}
+
+impl Default for BigInt {
+    fn default() -> Self {
+        BigInt::new(false, Vec::new())
+    }
+}
+
+impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let mut magnitude = n.unsigned_abs();
+        let mut limbs = Vec::new();
+        while magnitude > 0 {
+            limbs.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::new(n < 0, limbs)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.limbs.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(
+                self.negative,
+                BigInt::add_magnitude(&self.limbs, &other.limbs),
+            );
+        }
+        match self.cmp_magnitude(other) {
+            Ordering::Less => BigInt::new(
+                other.negative,
+                BigInt::sub_magnitude(&other.limbs, &self.limbs),
+            ),
+            _ => BigInt::new(
+                self.negative,
+                BigInt::sub_magnitude(&self.limbs, &other.limbs),
+            ),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        let mut limbs = vec![0u64; self.limbs.len() + other.limbs.len() + 1];
+        for (i, a) in self.limbs.iter().enumerate() {
+            let mut carry = 0;
+            for (j, b) in other.limbs.iter().enumerate() {
+                let n = limbs[i + j] + *a as u64 * *b as u64 + carry;
+                limbs[i + j] = n % BASE;
+                carry = n / BASE;
+            }
+            limbs[i + other.limbs.len()] += carry;
+        }
+        BigInt::new(
+            self.negative != other.negative,
+            limbs.into_iter().map(|limb| limb as u32).collect(),
+        )
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some((last, rest)) = self.limbs.split_last() else {
+            return write!(f, "0");
+        };
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", last)?;
+        for limb in rest.iter().rev() {
+            write!(f, "{:09}", limb)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
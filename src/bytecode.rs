@@ -0,0 +1,1415 @@
+use std::collections::{HashMap, HashSet};
+use std::iter::zip;
+use std::rc::Rc;
+
+use crate::ast;
+use crate::bigint::BigInt;
+use crate::ffi;
+use crate::io;
+use crate::lists;
+use crate::matching;
+use crate::math;
+use crate::process;
+use crate::value::{option_type, EnumType, StructType, Value};
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Opcode {
+    Const,
+    Nada,
+    True,
+    False,
+    Pop,
+    Dup,
+    GetLocal,
+    SetLocal,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Eq,
+    Neq,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Jump,
+    JumpIfFalse,
+    Loop,
+    List,
+    Range,
+    ForPrep,
+    ForNext,
+    Collect,
+    Closure,
+    Call,
+    Builtin,
+    Return,
+    CheckType,
+    Mod,
+    Pow,
+    Index,
+    Slice,
+    SetIndex,
+    Map,
+    Unpack,
+    Tuple,
+    Struct,
+    Field,
+    Variant,
+    Match,
+    Try,
+    Throw,
+    Catch,
+    EndCatch,
+}
+
+impl Opcode {
+    const ALL: [Opcode; 47] = [
+        Opcode::Const,
+        Opcode::Nada,
+        Opcode::True,
+        Opcode::False,
+        Opcode::Pop,
+        Opcode::Dup,
+        Opcode::GetLocal,
+        Opcode::SetLocal,
+        Opcode::Add,
+        Opcode::Sub,
+        Opcode::Mul,
+        Opcode::Div,
+        Opcode::Eq,
+        Opcode::Neq,
+        Opcode::Gt,
+        Opcode::Ge,
+        Opcode::Lt,
+        Opcode::Le,
+        Opcode::Jump,
+        Opcode::JumpIfFalse,
+        Opcode::Loop,
+        Opcode::List,
+        Opcode::Range,
+        Opcode::ForPrep,
+        Opcode::ForNext,
+        Opcode::Collect,
+        Opcode::Closure,
+        Opcode::Call,
+        Opcode::Builtin,
+        Opcode::Return,
+        Opcode::CheckType,
+        Opcode::Mod,
+        Opcode::Pow,
+        Opcode::Index,
+        Opcode::Slice,
+        Opcode::SetIndex,
+        Opcode::Map,
+        Opcode::Unpack,
+        Opcode::Tuple,
+        Opcode::Struct,
+        Opcode::Field,
+        Opcode::Variant,
+        Opcode::Match,
+        Opcode::Try,
+        Opcode::Throw,
+        Opcode::Catch,
+        Opcode::EndCatch,
+    ];
+
+    pub fn from_byte(byte: u8) -> Option<Opcode> {
+        Self::ALL.get(byte as usize).copied()
+    }
+
+    /// Number of operand bytes following the opcode.
+    pub fn operand_len(self) -> usize {
+        match self {
+            Opcode::Const
+            | Opcode::GetLocal
+            | Opcode::SetLocal
+            | Opcode::Jump
+            | Opcode::JumpIfFalse
+            | Opcode::Try
+            | Opcode::Catch
+            | Opcode::Loop
+            | Opcode::List
+            | Opcode::Map
+            | Opcode::Tuple
+            | Opcode::Struct
+            | Opcode::Field
+            | Opcode::Match
+            | Opcode::Collect
+            | Opcode::Closure
+            | Opcode::CheckType => 2,
+            Opcode::Range | Opcode::Call | Opcode::Slice | Opcode::Unpack => 1,
+            Opcode::Builtin => 2,
+            Opcode::SetIndex => 3,
+            Opcode::Variant => 4,
+            Opcode::ForPrep => 6,
+            Opcode::ForNext => 10,
+            _ => 0,
+        }
+    }
+}
+
+/// Flags in the operand of `Slice` for the bounds that are on the stack.
+pub const SLICE_FROM: u8 = 1;
+pub const SLICE_TO: u8 = 2;
+
+/// The value slot of a `ForNext` whose loop binds a single name.
+pub const NO_SLOT: u16 = u16::MAX;
+
+/// The pattern of the `Match` after the last arm, which fails with an error.
+pub const NO_ARM: u16 = u16::MAX;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Builtin {
+    Print,
+    Printf,
+    Int,
+    Float,
+    BigInt,
+    Keys,
+    Values,
+    Has,
+    Remove,
+    UnwrapOr,
+    Len,
+    Map,
+    Filter,
+    Reduce,
+    Sort,
+    SortBy,
+    Reverse,
+    Zip,
+    Enumerate,
+    Sum,
+    Min,
+    Max,
+    Contains,
+    Push,
+    Pop,
+    Sqrt,
+    Abs,
+    Floor,
+    Ceil,
+    Round,
+    Pow,
+    Exp,
+    Ln,
+    Log10,
+    Sin,
+    Cos,
+    Tan,
+    Asin,
+    Acos,
+    Atan,
+    Atan2,
+    Random,
+    Seed,
+    Eprint,
+    ReadLine,
+    ReadFile,
+    WriteFile,
+    AppendFile,
+    Lines,
+    Exists,
+    Args,
+    Env,
+    Exit,
+    Chars,
+    Concat,
+    Backtrace,
+}
+
+impl Builtin {
+    pub fn lookup(name: &str) -> Option<Builtin> {
+        match name {
+            "print" => Some(Builtin::Print),
+            "printf" => Some(Builtin::Printf),
+            "int" => Some(Builtin::Int),
+            "float" => Some(Builtin::Float),
+            "bigint" => Some(Builtin::BigInt),
+            "keys" => Some(Builtin::Keys),
+            "values" => Some(Builtin::Values),
+            "has" => Some(Builtin::Has),
+            "remove" => Some(Builtin::Remove),
+            "unwrap_or" => Some(Builtin::UnwrapOr),
+            "len" => Some(Builtin::Len),
+            "map" => Some(Builtin::Map),
+            "filter" => Some(Builtin::Filter),
+            "reduce" => Some(Builtin::Reduce),
+            "sort" => Some(Builtin::Sort),
+            "sort_by" => Some(Builtin::SortBy),
+            "reverse" => Some(Builtin::Reverse),
+            "zip" => Some(Builtin::Zip),
+            "enumerate" => Some(Builtin::Enumerate),
+            "sum" => Some(Builtin::Sum),
+            "min" => Some(Builtin::Min),
+            "max" => Some(Builtin::Max),
+            "contains" => Some(Builtin::Contains),
+            "push" => Some(Builtin::Push),
+            "pop" => Some(Builtin::Pop),
+            "sqrt" => Some(Builtin::Sqrt),
+            "abs" => Some(Builtin::Abs),
+            "floor" => Some(Builtin::Floor),
+            "ceil" => Some(Builtin::Ceil),
+            "round" => Some(Builtin::Round),
+            "pow" => Some(Builtin::Pow),
+            "exp" => Some(Builtin::Exp),
+            "ln" => Some(Builtin::Ln),
+            "log10" => Some(Builtin::Log10),
+            "sin" => Some(Builtin::Sin),
+            "cos" => Some(Builtin::Cos),
+            "tan" => Some(Builtin::Tan),
+            "asin" => Some(Builtin::Asin),
+            "acos" => Some(Builtin::Acos),
+            "atan" => Some(Builtin::Atan),
+            "atan2" => Some(Builtin::Atan2),
+            "random" => Some(Builtin::Random),
+            "seed" => Some(Builtin::Seed),
+            "eprint" => Some(Builtin::Eprint),
+            "read_line" => Some(Builtin::ReadLine),
+            "read_file" => Some(Builtin::ReadFile),
+            "write_file" => Some(Builtin::WriteFile),
+            "append_file" => Some(Builtin::AppendFile),
+            "lines" => Some(Builtin::Lines),
+            "exists" => Some(Builtin::Exists),
+            "args" => Some(Builtin::Args),
+            "env" => Some(Builtin::Env),
+            "exit" => Some(Builtin::Exit),
+            "chars" => Some(Builtin::Chars),
+            "concat" => Some(Builtin::Concat),
+            "backtrace" => Some(Builtin::Backtrace),
+            _ => None,
+        }
+    }
+
+    pub fn from_byte(byte: u8) -> Option<Builtin> {
+        match byte {
+            0 => Some(Builtin::Print),
+            1 => Some(Builtin::Printf),
+            2 => Some(Builtin::Int),
+            3 => Some(Builtin::Float),
+            4 => Some(Builtin::BigInt),
+            5 => Some(Builtin::Keys),
+            6 => Some(Builtin::Values),
+            7 => Some(Builtin::Has),
+            8 => Some(Builtin::Remove),
+            9 => Some(Builtin::UnwrapOr),
+            10 => Some(Builtin::Len),
+            11 => Some(Builtin::Map),
+            12 => Some(Builtin::Filter),
+            13 => Some(Builtin::Reduce),
+            14 => Some(Builtin::Sort),
+            15 => Some(Builtin::SortBy),
+            16 => Some(Builtin::Reverse),
+            17 => Some(Builtin::Zip),
+            18 => Some(Builtin::Enumerate),
+            19 => Some(Builtin::Sum),
+            20 => Some(Builtin::Min),
+            21 => Some(Builtin::Max),
+            22 => Some(Builtin::Contains),
+            23 => Some(Builtin::Push),
+            24 => Some(Builtin::Pop),
+            25 => Some(Builtin::Sqrt),
+            26 => Some(Builtin::Abs),
+            27 => Some(Builtin::Floor),
+            28 => Some(Builtin::Ceil),
+            29 => Some(Builtin::Round),
+            30 => Some(Builtin::Pow),
+            31 => Some(Builtin::Exp),
+            32 => Some(Builtin::Ln),
+            33 => Some(Builtin::Log10),
+            34 => Some(Builtin::Sin),
+            35 => Some(Builtin::Cos),
+            36 => Some(Builtin::Tan),
+            37 => Some(Builtin::Asin),
+            38 => Some(Builtin::Acos),
+            39 => Some(Builtin::Atan),
+            40 => Some(Builtin::Atan2),
+            41 => Some(Builtin::Random),
+            42 => Some(Builtin::Seed),
+            43 => Some(Builtin::Eprint),
+            44 => Some(Builtin::ReadLine),
+            45 => Some(Builtin::ReadFile),
+            46 => Some(Builtin::WriteFile),
+            47 => Some(Builtin::AppendFile),
+            48 => Some(Builtin::Lines),
+            49 => Some(Builtin::Exists),
+            50 => Some(Builtin::Args),
+            51 => Some(Builtin::Env),
+            52 => Some(Builtin::Exit),
+            53 => Some(Builtin::Chars),
+            54 => Some(Builtin::Concat),
+            55 => Some(Builtin::Backtrace),
+            _ => None,
+        }
+    }
+
+    /// Whether a function or variable of the same name hides the builtin.
+    pub fn shadowable(self) -> bool {
+        [lists::arity, math::arity, io::arity, process::arity]
+            .iter()
+            .any(|arity| arity(self.name()).is_some())
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Print => "print",
+            Builtin::Printf => "printf",
+            Builtin::Int => "int",
+            Builtin::Float => "float",
+            Builtin::BigInt => "bigint",
+            Builtin::Keys => "keys",
+            Builtin::Values => "values",
+            Builtin::Has => "has",
+            Builtin::Remove => "remove",
+            Builtin::UnwrapOr => "unwrap_or",
+            Builtin::Len => "len",
+            Builtin::Map => "map",
+            Builtin::Filter => "filter",
+            Builtin::Reduce => "reduce",
+            Builtin::Sort => "sort",
+            Builtin::SortBy => "sort_by",
+            Builtin::Reverse => "reverse",
+            Builtin::Zip => "zip",
+            Builtin::Enumerate => "enumerate",
+            Builtin::Sum => "sum",
+            Builtin::Min => "min",
+            Builtin::Max => "max",
+            Builtin::Contains => "contains",
+            Builtin::Push => "push",
+            Builtin::Pop => "pop",
+            Builtin::Sqrt => "sqrt",
+            Builtin::Abs => "abs",
+            Builtin::Floor => "floor",
+            Builtin::Ceil => "ceil",
+            Builtin::Round => "round",
+            Builtin::Pow => "pow",
+            Builtin::Exp => "exp",
+            Builtin::Ln => "ln",
+            Builtin::Log10 => "log10",
+            Builtin::Sin => "sin",
+            Builtin::Cos => "cos",
+            Builtin::Tan => "tan",
+            Builtin::Asin => "asin",
+            Builtin::Acos => "acos",
+            Builtin::Atan => "atan",
+            Builtin::Atan2 => "atan2",
+            Builtin::Random => "random",
+            Builtin::Seed => "seed",
+            Builtin::Eprint => "eprint",
+            Builtin::ReadLine => "read_line",
+            Builtin::ReadFile => "read_file",
+            Builtin::WriteFile => "write_file",
+            Builtin::AppendFile => "append_file",
+            Builtin::Lines => "lines",
+            Builtin::Exists => "exists",
+            Builtin::Args => "args",
+            Builtin::Env => "env",
+            Builtin::Exit => "exit",
+            Builtin::Chars => "chars",
+            Builtin::Concat => "concat",
+            Builtin::Backtrace => "backtrace",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Constant {
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Float(f64),
+    String(Rc<str>),
+    /// The C function of an `extern fn` declaration.
+    Extern(Rc<ffi::Extern>),
+}
+
+/// Copies the value of a slot in the enclosing frame into a slot of the
+/// new closure when it is created.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    pub from: u16,
+    pub to: u16,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: Option<String>,
+    pub arity: u16,
+    pub slots: Vec<String>,
+    pub self_slot: Option<u16>,
+    pub captures: Vec<Capture>,
+    pub code: Vec<u8>,
+    /// `(code offset, source line)` pairs, sorted by offset. Line 0 marks
+    /// code outside of any line, like the type checks of arguments and
+    /// return values, whose errors belong to the call.
+    pub lines: Vec<(u32, u32)>,
+}
+
+impl Function {
+    pub fn line_at(&self, ip: usize) -> Option<u32> {
+        self.lines
+            .iter()
+            .take_while(|(offset, _)| *offset as usize <= ip)
+            .last()
+            .map(|(_, line)| *line)
+            .filter(|line| *line != 0)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    pub constants: Vec<Constant>,
+    pub functions: Vec<Function>,
+    pub structs: Vec<StructType>,
+    pub enums: Vec<EnumType>,
+    /// The patterns of `Match`, whose bindings are slots.
+    pub patterns: Vec<ast::MatchPattern<u16>>,
+}
+
+impl Module {
+    pub const ENTRY: usize = 0;
+
+    pub fn disassemble(&self) -> String {
+        let mut out = String::new();
+        for (index, function) in self.functions.iter().enumerate() {
+            out += &format!(
+                "fn #{} {} ({} params, {} slots)\n",
+                index,
+                function.name.as_deref().unwrap_or("<anonymous>"),
+                function.arity,
+                function.slots.len()
+            );
+            for capture in &function.captures {
+                out += &format!("  capture {} -> {}\n", capture.from, capture.to);
+            }
+            let mut ip = 0;
+            while ip < function.code.len() {
+                let op = Opcode::from_byte(function.code[ip]).unwrap();
+                let operands = &function.code[ip + 1..ip + 1 + op.operand_len()];
+                let line = match function
+                    .lines
+                    .iter()
+                    .find(|(offset, _)| *offset as usize == ip)
+                {
+                    Some((_, line)) => format!("{:>4}", line),
+                    None => "   |".into(),
+                };
+                out += &format!("  {:04} {} {:<12}", ip, line, format!("{:?}", op));
+                match op {
+                    Opcode::Const | Opcode::CheckType | Opcode::Field => {
+                        let index = u16::from_le_bytes([operands[0], operands[1]]);
+                        out += &format!(" {} ({:?})", index, self.constants[index as usize]);
+                    }
+                    Opcode::Struct => {
+                        let index = u16::from_le_bytes([operands[0], operands[1]]);
+                        out += &format!(" {} ({})", index, self.structs[index as usize].name);
+                    }
+                    Opcode::Variant => {
+                        let index = u16::from_le_bytes([operands[0], operands[1]]);
+                        let tag = u16::from_le_bytes([operands[2], operands[3]]);
+                        let ty = &self.enums[index as usize];
+                        out += &format!(
+                            " {} {} ({}.{})",
+                            index, tag, ty.name, ty.variants[tag as usize].name
+                        );
+                    }
+                    Opcode::GetLocal | Opcode::SetLocal | Opcode::Collect => {
+                        let slot = u16::from_le_bytes([operands[0], operands[1]]);
+                        out += &format!(" {} ({})", slot, function.slots[slot as usize]);
+                    }
+                    Opcode::SetIndex => {
+                        let slot = u16::from_le_bytes([operands[0], operands[1]]);
+                        out += &format!(
+                            " {} ({}) {}",
+                            slot, function.slots[slot as usize], operands[2]
+                        );
+                    }
+                    Opcode::Range
+                    | Opcode::Call
+                    | Opcode::Builtin
+                    | Opcode::Slice
+                    | Opcode::Unpack => {
+                        for byte in operands {
+                            out += &format!(" {}", byte);
+                        }
+                    }
+                    _ => {
+                        for chunk in operands.chunks(2) {
+                            match chunk {
+                                [lo, hi] => out += &format!(" {}", u16::from_le_bytes([*lo, *hi])),
+                                [byte] => out += &format!(" {}", byte),
+                                _ => unreachable!(),
+                            }
+                        }
+                    }
+                }
+                out += "\n";
+                ip += 1 + op.operand_len();
+            }
+        }
+        out
+    }
+}
+
+/// Lowers an AST into a bytecode `Module`.
+///
+/// Every name used in a function is resolved to a local slot at compile time.
+/// A nested function gets its own slots for the names it uses from enclosing
+/// functions, and these are filled from the enclosing frame when the closure
+/// is created, mirroring how `Eval` snapshots the scope of a `ScopedFnDef`.
+#[derive(Default)]
+pub struct Compiler {
+    module: Module,
+    /// The index in `module.structs` of each struct declared so far.
+    structs: HashMap<String, u16>,
+    /// The index in `module.enums` and the tag of each variant. Unlike
+    /// structs, enums are registered before compiling, since whether a name
+    /// in a pattern binds depends on them.
+    variants: HashMap<String, (u16, u16)>,
+}
+
+struct FnState {
+    function: Function,
+    defined: HashSet<String>,
+    /// Defined names that were bound with `let mut`.
+    mutable: HashSet<String>,
+    /// The return type, which a `?` checks before returning early.
+    typename: Option<String>,
+}
+
+impl Compiler {
+    pub fn compile(node: &ast::Node) -> Result<Module, String> {
+        let mut compiler = Compiler::default();
+        compiler.declare_enum(option_type())?;
+        compiler.declare_enums(node)?;
+        compiler.module.functions.push(Function {
+            name: Some("main".into()),
+            arity: 0,
+            slots: Vec::new(),
+            self_slot: None,
+            captures: Vec::new(),
+            code: Vec::new(),
+            lines: Vec::new(),
+        });
+        let mut function = compiler.function(&None, &Vec::new(), node, &None, None)?;
+        function.name = Some("main".into());
+        compiler.module.functions[Module::ENTRY] = function;
+        Ok(compiler.module)
+    }
+
+    fn function(
+        &mut self,
+        name: &Option<String>,
+        params: &[(ast::Pattern, Option<String>)],
+        body: &ast::Node,
+        typename: &Option<String>,
+        parent: Option<&FnState>,
+    ) -> Result<Function, String> {
+        // Arguments arrive in the first slots, from where tuple parameters
+        // are destructured into the slots of their names.
+        let mut slots: Vec<String> = params
+            .iter()
+            .enumerate()
+            .map(|(i, (param, _))| match param {
+                ast::Pattern::Name(name) => name.clone(),
+                ast::Pattern::Tuple(_) => format!("$param{}", i),
+            })
+            .collect();
+        let param_names: Vec<String> = params
+            .iter()
+            .flat_map(|(param, _)| param.names())
+            .cloned()
+            .collect();
+        let push = |slots: &mut Vec<String>, name: &String| {
+            if !slots.contains(name) {
+                slots.push(name.clone());
+            }
+        };
+        for name in &param_names {
+            push(&mut slots, name);
+        }
+        if let Some(name) = name {
+            push(&mut slots, name);
+        }
+        let mut mentioned = Vec::new();
+        self.mentioned(body, &mut mentioned);
+        for name in &mentioned {
+            push(&mut slots, name);
+        }
+
+        let self_slot = name
+            .as_ref()
+            .filter(|name| !param_names.contains(name))
+            .map(|name| slots.iter().position(|slot| slot == name).unwrap() as u16);
+
+        let mut captures = Vec::new();
+        let mut defined = parent.map(|p| p.defined.clone()).unwrap_or_default();
+        if let Some(parent) = parent {
+            for (to, slot) in slots.iter().enumerate() {
+                if param_names.contains(slot) || Some(slot) == name.as_ref() {
+                    continue;
+                }
+                if let Some(from) = parent.function.slots.iter().position(|s| s == slot) {
+                    captures.push(Capture {
+                        from: from as u16,
+                        to: to as u16,
+                    });
+                }
+            }
+        }
+        let mut local = HashSet::new();
+        local.extend(param_names.iter().cloned());
+        local.extend(name.iter().cloned());
+        self.introduced(body, &mut local);
+        let mut mutable: HashSet<String> = parent
+            .map(|p| p.mutable.difference(&local).cloned().collect())
+            .unwrap_or_default();
+        self.introduced_mutable(body, &mut mutable);
+        // Until bound, `pi` and `e` are the constants, like in `Eval`.
+        let constants: Vec<(u16, f64)> = slots
+            .iter()
+            .enumerate()
+            .filter(|(to, slot)| {
+                local.contains(*slot)
+                    && !param_names.contains(slot)
+                    && Some(*slot) != name.as_ref()
+                    && !captures.iter().any(|capture| capture.to as usize == *to)
+            })
+            .filter_map(|(to, slot)| match math::constant(slot) {
+                Some(Value::Float(n)) => Some((to as u16, n)),
+                _ => None,
+            })
+            .collect();
+        defined.extend(local);
+
+        let mut state = FnState {
+            function: Function {
+                name: name.clone(),
+                arity: params.len() as u16,
+                slots,
+                self_slot,
+                captures,
+                code: Vec::new(),
+                lines: Vec::new(),
+            },
+            defined,
+            mutable,
+            typename: typename.clone(),
+        };
+        for (i, (param, typename)) in params.iter().enumerate() {
+            if typename.is_some() {
+                Self::emit_with(&mut state, Opcode::GetLocal, i as u16);
+                self.check_type(&mut state, typename)?;
+                Self::emit_with(&mut state, Opcode::SetLocal, i as u16);
+            }
+            if let ast::Pattern::Tuple(_) = param {
+                Self::emit_with(&mut state, Opcode::GetLocal, i as u16);
+                Self::bind(&mut state, param)?;
+            }
+        }
+        for (slot, n) in constants {
+            let index = self.constant(Constant::Float(n))?;
+            Self::emit_with(&mut state, Opcode::Const, index);
+            Self::emit_with(&mut state, Opcode::SetLocal, slot);
+        }
+        self.expr(&mut state, body)?;
+        self.check_return_type(&mut state)?;
+        state.function.code.push(Opcode::Return as u8);
+        Ok(state.function)
+    }
+
+    /// Names introduced by definitions and loops directly in a function body.
+    fn introduced(&self, node: &ast::Node, names: &mut HashSet<String>) {
+        match node {
+            ast::Node::Define(_, pattern, expr, _) => {
+                names.extend(pattern.names().into_iter().cloned());
+                self.introduced(expr, names);
+            }
+            ast::Node::Loop {
+                var,
+                value,
+                iterable,
+                inner,
+            } => {
+                names.extend(var.names().into_iter().cloned());
+                names.extend(value.iter().flat_map(ast::Pattern::names).cloned());
+                self.introduced(iterable, names);
+                self.introduced(inner, names);
+            }
+            ast::Node::FnDef(name, _, _, _) => {
+                names.extend(name.iter().cloned());
+            }
+            ast::Node::Extern(name, _, _) => {
+                names.insert(name.clone());
+            }
+            ast::Node::TryCatch {
+                body,
+                name,
+                handler,
+            } => {
+                names.insert(name.clone());
+                self.introduced(body, names);
+                self.introduced(handler, names);
+            }
+            ast::Node::Match { subject, arms } => {
+                self.introduced(subject, names);
+                for arm in arms {
+                    names.extend(self.bindings(&arm.pattern));
+                    arm.guard
+                        .iter()
+                        .for_each(|guard| self.introduced(guard, names));
+                    self.introduced(&arm.body, names);
+                }
+            }
+            _ => node
+                .children()
+                .for_each(|child| self.introduced(child, names)),
+        }
+    }
+
+    /// Names bound with `let mut` directly in a function body.
+    fn introduced_mutable(&self, node: &ast::Node, names: &mut HashSet<String>) {
+        match node {
+            ast::Node::Define(ast::Mut::Mutable, pattern, expr, _) => {
+                names.extend(pattern.names().into_iter().cloned());
+                self.introduced_mutable(expr, names);
+            }
+            ast::Node::FnDef(..) => {}
+            _ => node
+                .children()
+                .for_each(|child| self.introduced_mutable(child, names)),
+        }
+    }
+
+    /// Every name a function body uses, including the names its nested
+    /// functions need from it.
+    fn mentioned(&self, node: &ast::Node, names: &mut Vec<String>) {
+        let mut push = |name: &String| {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        };
+        match node {
+            ast::Node::Define(_, pattern, _, _) => pattern.names().into_iter().for_each(push),
+            ast::Node::Assign(name, _)
+            | ast::Node::IndexAssign(name, _, _)
+            | ast::Node::Extern(name, _, _)
+            | ast::Node::VarRef(name) => push(name),
+            ast::Node::Loop { var, value, .. } => {
+                var.names().into_iter().for_each(&mut push);
+                value.iter().flat_map(ast::Pattern::names).for_each(push);
+            }
+            // A list, math, io or process builtin may be shadowed by a function of the
+            // same name.
+            ast::Node::FnCall(name, _) if Builtin::lookup(name).is_none_or(Builtin::shadowable) => {
+                push(name)
+            }
+            ast::Node::TryCatch { name, .. } => push(name),
+            ast::Node::Match { arms, .. } => {
+                arms.iter()
+                    .flat_map(|arm| self.bindings(&arm.pattern))
+                    .for_each(|name| push(&name));
+            }
+            ast::Node::FnDef(name, params, body, _) => {
+                if let Some(name) = name {
+                    push(name);
+                }
+                let mut inner = Vec::new();
+                self.mentioned(body, &mut inner);
+                let params: Vec<&String> =
+                    params.iter().flat_map(|(param, _)| param.names()).collect();
+                inner
+                    .iter()
+                    .filter(|n| !params.contains(n) && Some(*n) != name.as_ref())
+                    .for_each(push);
+                return;
+            }
+            _ => {}
+        }
+        node.children()
+            .for_each(|child| self.mentioned(child, names));
+    }
+
+    /// Registers the enums declared anywhere in `node`.
+    fn declare_enums(&mut self, node: &ast::Node) -> Result<(), String> {
+        match node {
+            ast::Node::EnumDef(name, variants) => self.declare_enum(EnumType {
+                name: name.clone(),
+                variants: variants.clone(),
+            })?,
+            ast::Node::FnDef(_, _, body, _) => self.declare_enums(body)?,
+            _ => {
+                for child in node.children() {
+                    self.declare_enums(child)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn declare_enum(&mut self, ty: EnumType) -> Result<(), String> {
+        let index =
+            u16::try_from(self.module.enums.len()).map_err(|_| "Too many enums".to_string())?;
+        for (tag, variant) in ty.variants.iter().enumerate() {
+            self.variants
+                .insert(variant.name.clone(), (index, tag as u16));
+        }
+        self.module.enums.push(ty);
+        Ok(())
+    }
+
+    fn variant(&self, name: &str) -> Option<(&EnumType, u16, u16)> {
+        let (index, tag) = *self.variants.get(name)?;
+        Some((&self.module.enums[index as usize], index, tag))
+    }
+
+    fn arity(&self, name: &str) -> Option<usize> {
+        let (ty, _, tag) = self.variant(name)?;
+        Some(ty.variants[tag as usize].fields.len())
+    }
+
+    /// The names a `match` pattern binds, which leaves out its variants.
+    fn bindings(&self, pattern: &ast::MatchPattern) -> Vec<String> {
+        let mut names = Vec::new();
+        // Invalid patterns are reported when the `match` is compiled.
+        let _ = matching::resolve(pattern, &|name| self.arity(name), &mut |name| {
+            names.push(name.clone());
+            Ok(())
+        });
+        names
+    }
+
+    fn emit(state: &mut FnState, op: Opcode) {
+        state.function.code.push(op as u8);
+    }
+
+    fn emit_u16(state: &mut FnState, value: u16) {
+        state.function.code.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn emit_with(state: &mut FnState, op: Opcode, operand: u16) {
+        Self::emit(state, op);
+        Self::emit_u16(state, operand);
+    }
+
+    fn emit_jump(state: &mut FnState, op: Opcode) -> usize {
+        Self::emit_with(state, op, u16::MAX);
+        state.function.code.len() - 2
+    }
+
+    fn patch_jump(state: &mut FnState, at: usize) -> Result<(), String> {
+        let offset = u16::try_from(state.function.code.len() - at - 2)
+            .map_err(|_| "Jump too large".to_string())?;
+        state.function.code[at..at + 2].copy_from_slice(&offset.to_le_bytes());
+        Ok(())
+    }
+
+    fn emit_loop(state: &mut FnState, start: usize) -> Result<(), String> {
+        let offset = u16::try_from(state.function.code.len() + 3 - start)
+            .map_err(|_| "Loop too large".to_string())?;
+        Self::emit_with(state, Opcode::Loop, offset);
+        Ok(())
+    }
+
+    fn constant(&mut self, constant: Constant) -> Result<u16, String> {
+        let existing = self
+            .module
+            .constants
+            .iter()
+            .position(|c| match (c, &constant) {
+                (Constant::Int(a), Constant::Int(b)) => a == b,
+                (Constant::BigInt(a), Constant::BigInt(b)) => a == b,
+                (Constant::Float(a), Constant::Float(b)) => a.to_bits() == b.to_bits(),
+                (Constant::String(a), Constant::String(b)) => a == b,
+                _ => false,
+            });
+        let index = existing.unwrap_or_else(|| {
+            self.module.constants.push(constant);
+            self.module.constants.len() - 1
+        });
+        u16::try_from(index).map_err(|_| "Too many constants".to_string())
+    }
+
+    fn check_type(&mut self, state: &mut FnState, typename: &Option<String>) -> Result<(), String> {
+        if let Some(typename) = typename {
+            let index = self.constant(Constant::String(typename.as_str().into()))?;
+            Self::emit_with(state, Opcode::CheckType, index);
+        }
+        Ok(())
+    }
+
+    /// Attributes the code that follows to `line`.
+    fn mark_line(state: &mut FnState, line: u32) {
+        if state.function.lines.last().map(|(_, last)| *last) != Some(line) {
+            let offset = state.function.code.len() as u32;
+            state.function.lines.push((offset, line));
+        }
+    }
+
+    /// Checks the type of the value about to be returned, outside of any
+    /// line like the interpreter, which checks it after the call.
+    fn check_return_type(&mut self, state: &mut FnState) -> Result<(), String> {
+        let Some(typename) = state.typename.clone() else {
+            return Ok(());
+        };
+        let line = state.function.lines.last().map(|(_, line)| *line);
+        Self::mark_line(state, 0);
+        self.check_type(state, &Some(typename))?;
+        if let Some(line) = line {
+            Self::mark_line(state, line);
+        }
+        Ok(())
+    }
+
+    fn slot(state: &FnState, name: &String) -> Result<u16, String> {
+        if !state.defined.contains(name) {
+            return Err(format!("Undefined variable: '{}'", name));
+        }
+        Ok(state.function.slots.iter().position(|s| s == name).unwrap() as u16)
+    }
+
+    /// The slot a loop stores a pattern's value in: the slot of the name, or
+    /// a hidden slot to destructure from.
+    fn pattern_slot(state: &mut FnState, pattern: &ast::Pattern) -> Result<u16, String> {
+        match pattern {
+            ast::Pattern::Name(name) => Self::slot(state, name),
+            ast::Pattern::Tuple(_) => Ok(Self::hidden_slot(state, "item")),
+        }
+    }
+
+    /// Pops the value on top of the stack into the slots of a pattern.
+    fn bind(state: &mut FnState, pattern: &ast::Pattern) -> Result<(), String> {
+        match pattern {
+            ast::Pattern::Name(name) => {
+                let slot = Self::slot(state, name)?;
+                Self::emit_with(state, Opcode::SetLocal, slot);
+            }
+            ast::Pattern::Tuple(patterns) => {
+                let len =
+                    u8::try_from(patterns.len()).map_err(|_| "Pattern too long".to_string())?;
+                Self::emit(state, Opcode::Unpack);
+                state.function.code.push(len);
+                for pattern in patterns.iter().rev() {
+                    Self::bind(state, pattern)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn mutable_slot(state: &FnState, name: &String) -> Result<u16, String> {
+        let slot = Self::slot(state, name)?;
+        if !state.mutable.contains(name) {
+            return Err(format!("Cannot assign to immutable variable: '{}'", name));
+        }
+        Ok(slot)
+    }
+
+    fn hidden_slot(state: &mut FnState, name: &str) -> u16 {
+        state.function.slots.push(format!("${}", name));
+        (state.function.slots.len() - 1) as u16
+    }
+
+    fn expr(&mut self, state: &mut FnState, node: &ast::Node) -> Result<(), String> {
+        match node {
+            ast::Node::Statements(nodes) => {
+                if nodes.is_empty() {
+                    Self::emit(state, Opcode::Nada);
+                }
+                for (i, node) in nodes.iter().enumerate() {
+                    if i > 0 {
+                        Self::emit(state, Opcode::Pop);
+                    }
+                    self.expr(state, node)?;
+                }
+            }
+            ast::Node::Line(line, node) => {
+                let line = *line as u32;
+                Self::mark_line(state, line);
+                self.expr(state, node).map_err(|e| {
+                    if e.starts_with("line ") {
+                        e
+                    } else {
+                        format!("line {}: {}", line, e)
+                    }
+                })?;
+            }
+            ast::Node::Define(_, pattern, expr, typename) => {
+                self.expr(state, expr)?;
+                self.check_type(state, typename)?;
+                Self::bind(state, pattern)?;
+                Self::emit(state, Opcode::Nada);
+            }
+            ast::Node::Assign(name, expr) => {
+                self.expr(state, expr)?;
+                let slot = Self::mutable_slot(state, name)?;
+                Self::emit_with(state, Opcode::SetLocal, slot);
+                Self::emit(state, Opcode::Nada);
+            }
+            ast::Node::IndexAssign(name, steps, expr) => {
+                for step in steps {
+                    match step {
+                        ast::Step::Index(index) => self.expr(state, index)?,
+                        ast::Step::Field(field) => {
+                            let index = self.constant(Constant::String(field.as_str().into()))?;
+                            Self::emit_with(state, Opcode::Const, index);
+                        }
+                    }
+                }
+                self.expr(state, expr)?;
+                let slot = Self::mutable_slot(state, name)?;
+                let depth =
+                    u8::try_from(steps.len()).map_err(|_| "Too many indices".to_string())?;
+                Self::emit_with(state, Opcode::SetIndex, slot);
+                state.function.code.push(depth);
+                Self::emit(state, Opcode::Nada);
+            }
+            ast::Node::StructDef(name, fields) => {
+                let index = u16::try_from(self.module.structs.len())
+                    .map_err(|_| "Too many structs".to_string())?;
+                self.module.structs.push(StructType {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                });
+                self.structs.insert(name.clone(), index);
+                Self::emit(state, Opcode::Nada);
+            }
+            ast::Node::StructLit(name, fields) => {
+                let index = *self
+                    .structs
+                    .get(name)
+                    .ok_or_else(|| format!("Undefined struct: '{}'", name))?;
+                for value in self.module.structs[index as usize].order(fields)? {
+                    self.expr(state, value)?;
+                }
+                Self::emit_with(state, Opcode::Struct, index);
+            }
+            ast::Node::EnumDef(..) => Self::emit(state, Opcode::Nada),
+            ast::Node::Match { subject, arms } => {
+                let patterns = arms
+                    .iter()
+                    .map(|arm| {
+                        matching::resolve(&arm.pattern, &|name| self.arity(name), &mut |name| {
+                            Self::slot(state, name)
+                        })
+                    })
+                    .collect::<Result<Vec<ast::MatchPattern<u16>>, String>>()?;
+                let unguarded: Vec<&ast::MatchPattern<u16>> = zip(arms, &patterns)
+                    .filter(|(arm, _)| arm.guard.is_none())
+                    .map(|(_, pattern)| pattern)
+                    .collect();
+                matching::check_exhaustive(&unguarded, &|name| {
+                    self.variant(name).unwrap().0.arities()
+                })?;
+
+                self.expr(state, subject)?;
+                let subject = Self::hidden_slot(state, "subject");
+                Self::emit_with(state, Opcode::SetLocal, subject);
+                let mut ends = Vec::new();
+                for (arm, pattern) in zip(arms, patterns) {
+                    let index = u16::try_from(self.module.patterns.len())
+                        .ok()
+                        .filter(|index| *index != NO_ARM)
+                        .ok_or_else(|| "Too many patterns".to_string())?;
+                    self.module.patterns.push(pattern);
+                    Self::emit_with(state, Opcode::GetLocal, subject);
+                    Self::emit_with(state, Opcode::Match, index);
+                    let mut next = vec![Self::emit_jump(state, Opcode::JumpIfFalse)];
+                    if let Some(guard) = &arm.guard {
+                        self.expr(state, guard)?;
+                        next.push(Self::emit_jump(state, Opcode::JumpIfFalse));
+                    }
+                    self.expr(state, &arm.body)?;
+                    ends.push(Self::emit_jump(state, Opcode::Jump));
+                    for at in next {
+                        Self::patch_jump(state, at)?;
+                    }
+                }
+                Self::emit_with(state, Opcode::GetLocal, subject);
+                Self::emit_with(state, Opcode::Match, NO_ARM);
+                for at in ends {
+                    Self::patch_jump(state, at)?;
+                }
+            }
+            ast::Node::Try(value) => {
+                // `Try` jumps over the early return for `some`.
+                self.expr(state, value)?;
+                let end = Self::emit_jump(state, Opcode::Try);
+                self.check_return_type(state)?;
+                Self::emit(state, Opcode::Return);
+                Self::patch_jump(state, end)?;
+            }
+            ast::Node::Throw(value) => {
+                self.expr(state, value)?;
+                Self::emit(state, Opcode::Throw);
+            }
+            ast::Node::TryCatch {
+                body,
+                name,
+                handler,
+            } => {
+                // Until `EndCatch`, an error unwinds to the handler that
+                // `Catch` points to, with the value to bind on the stack.
+                let catch = Self::emit_jump(state, Opcode::Catch);
+                self.expr(state, body)?;
+                Self::emit(state, Opcode::EndCatch);
+                let end = Self::emit_jump(state, Opcode::Jump);
+                Self::patch_jump(state, catch)?;
+                let slot = Self::slot(state, name)?;
+                Self::emit_with(state, Opcode::SetLocal, slot);
+                self.expr(state, handler)?;
+                Self::patch_jump(state, end)?;
+            }
+            ast::Node::Field(record, field) => {
+                self.expr(state, record)?;
+                let index = self.constant(Constant::String(field.as_str().into()))?;
+                Self::emit_with(state, Opcode::Field, index);
+            }
+            ast::Node::Index(list, index) => {
+                self.expr(state, list)?;
+                self.expr(state, index)?;
+                Self::emit(state, Opcode::Index);
+            }
+            ast::Node::Slice { list, from, to } => {
+                self.expr(state, list)?;
+                let mut bounds = 0;
+                if let Some(from) = from {
+                    self.expr(state, from)?;
+                    bounds |= SLICE_FROM;
+                }
+                if let Some(to) = to {
+                    self.expr(state, to)?;
+                    bounds |= SLICE_TO;
+                }
+                Self::emit(state, Opcode::Slice);
+                state.function.code.push(bounds);
+            }
+            ast::Node::Nada => Self::emit(state, Opcode::Nada),
+            ast::Node::Bool(true) => Self::emit(state, Opcode::True),
+            ast::Node::Bool(false) => Self::emit(state, Opcode::False),
+            ast::Node::Int(n) => {
+                let index = self.constant(Constant::Int(*n))?;
+                Self::emit_with(state, Opcode::Const, index);
+            }
+            ast::Node::BigInt(n) => {
+                let index = self.constant(Constant::BigInt(n.clone()))?;
+                Self::emit_with(state, Opcode::Const, index);
+            }
+            ast::Node::Float(n) => {
+                let index = self.constant(Constant::Float(*n))?;
+                Self::emit_with(state, Opcode::Const, index);
+            }
+            ast::Node::String(s) => {
+                let index = self.constant(Constant::String(s.as_str().into()))?;
+                Self::emit_with(state, Opcode::Const, index);
+            }
+            ast::Node::List(nodes) => {
+                for node in nodes {
+                    self.expr(state, node)?;
+                }
+                let len = u16::try_from(nodes.len()).map_err(|_| "List too long".to_string())?;
+                Self::emit_with(state, Opcode::List, len);
+            }
+            ast::Node::Tuple(nodes) => {
+                for node in nodes {
+                    self.expr(state, node)?;
+                }
+                let len = u16::try_from(nodes.len()).map_err(|_| "Tuple too long".to_string())?;
+                Self::emit_with(state, Opcode::Tuple, len);
+            }
+            ast::Node::Map(pairs) => {
+                for (key, value) in pairs {
+                    self.expr(state, key)?;
+                    self.expr(state, value)?;
+                }
+                let len = u16::try_from(pairs.len()).map_err(|_| "Map too long".to_string())?;
+                Self::emit_with(state, Opcode::Map, len);
+            }
+            ast::Node::Range {
+                from,
+                to,
+                lower,
+                upper,
+            } => {
+                self.expr(state, from)?;
+                self.expr(state, to)?;
+                let mut flags = 0;
+                if let ast::Bound::Exclusive = lower {
+                    flags |= 1;
+                }
+                if let ast::Bound::Exclusive = upper {
+                    flags |= 2;
+                }
+                Self::emit(state, Opcode::Range);
+                state.function.code.push(flags);
+            }
+            ast::Node::VarRef(name) => match (self.variant(name), math::constant(name)) {
+                (Some(_), _) if !state.defined.contains(name) => {
+                    self.construct(state, name, &[])?
+                }
+                (_, Some(Value::Float(n))) if !state.defined.contains(name) => {
+                    let index = self.constant(Constant::Float(n))?;
+                    Self::emit_with(state, Opcode::Const, index);
+                }
+                _ => {
+                    let slot = Self::slot(state, name)?;
+                    Self::emit_with(state, Opcode::GetLocal, slot);
+                }
+            },
+            ast::Node::Expr { op, lhs, rhs } => {
+                self.expr(state, lhs)?;
+                self.expr(state, rhs)?;
+                Self::emit(
+                    state,
+                    match op {
+                        ast::Op::Add => Opcode::Add,
+                        ast::Op::Sub => Opcode::Sub,
+                        ast::Op::Mul => Opcode::Mul,
+                        ast::Op::Div => Opcode::Div,
+                        ast::Op::Mod => Opcode::Mod,
+                        ast::Op::Pow => Opcode::Pow,
+                        ast::Op::Eq => Opcode::Eq,
+                        ast::Op::Neq => Opcode::Neq,
+                        ast::Op::Gt => Opcode::Gt,
+                        ast::Op::Ge => Opcode::Ge,
+                        ast::Op::Lt => Opcode::Lt,
+                        ast::Op::Le => Opcode::Le,
+                    },
+                );
+            }
+            ast::Node::IfElse {
+                condition,
+                if_block,
+                else_block,
+            } => {
+                self.expr(state, condition)?;
+                let else_jump = Self::emit_jump(state, Opcode::JumpIfFalse);
+                self.expr(state, if_block)?;
+                let end_jump = Self::emit_jump(state, Opcode::Jump);
+                Self::patch_jump(state, else_jump)?;
+                self.expr(state, else_block)?;
+                Self::patch_jump(state, end_jump)?;
+            }
+            ast::Node::While { condition, inner } => {
+                Self::emit(state, Opcode::Nada);
+                let start = state.function.code.len();
+                self.expr(state, condition)?;
+                let exit = Self::emit_jump(state, Opcode::JumpIfFalse);
+                Self::emit(state, Opcode::Pop);
+                self.expr(state, inner)?;
+                Self::emit_loop(state, start)?;
+                Self::patch_jump(state, exit)?;
+            }
+            ast::Node::Loop {
+                var,
+                value,
+                iterable,
+                inner,
+            } => {
+                let patterns: Vec<&ast::Pattern> = std::iter::once(var).chain(value).collect();
+                let var_slot = Self::pattern_slot(state, var)?;
+                let value_slot = match value {
+                    Some(value) => Self::pattern_slot(state, value)?,
+                    None => NO_SLOT,
+                };
+                let iter = Self::hidden_slot(state, "iter");
+                let index = Self::hidden_slot(state, "index");
+                let acc = Self::hidden_slot(state, "acc");
+
+                self.expr(state, iterable)?;
+                Self::emit(state, Opcode::ForPrep);
+                for operand in [iter, index, acc] {
+                    Self::emit_u16(state, operand);
+                }
+                let start = state.function.code.len();
+                Self::emit(state, Opcode::ForNext);
+                for operand in [iter, index, var_slot, value_slot] {
+                    Self::emit_u16(state, operand);
+                }
+                Self::emit_u16(state, u16::MAX);
+                let exit = state.function.code.len() - 2;
+                for (pattern, slot) in patterns.into_iter().zip([var_slot, value_slot]) {
+                    if let ast::Pattern::Tuple(_) = pattern {
+                        Self::emit_with(state, Opcode::GetLocal, slot);
+                        Self::bind(state, pattern)?;
+                    }
+                }
+                self.expr(state, inner)?;
+                Self::emit_with(state, Opcode::Collect, acc);
+                Self::emit_loop(state, start)?;
+                Self::patch_jump(state, exit)?;
+                Self::emit_with(state, Opcode::GetLocal, acc);
+            }
+            ast::Node::FnDef(name, params, body, typename) => {
+                let function = self.function(name, params, body, typename, Some(state))?;
+                self.module.functions.push(function);
+                let index = u16::try_from(self.module.functions.len() - 1)
+                    .map_err(|_| "Too many functions".to_string())?;
+                Self::emit_with(state, Opcode::Closure, index);
+                if let Some(name) = name {
+                    let slot = Self::slot(state, name)?;
+                    Self::emit(state, Opcode::Dup);
+                    Self::emit_with(state, Opcode::SetLocal, slot);
+                }
+            }
+            ast::Node::FnCall(name, args) => {
+                let argc =
+                    u8::try_from(args.len()).map_err(|_| "Too many arguments".to_string())?;
+                if let Some(builtin) = Builtin::lookup(name)
+                    .filter(|builtin| !builtin.shadowable() || !state.defined.contains(name))
+                {
+                    for arg in args {
+                        self.expr(state, arg)?;
+                    }
+                    Self::emit(state, Opcode::Builtin);
+                    state
+                        .function
+                        .code
+                        .extend_from_slice(&[builtin as u8, argc]);
+                } else if self.variant(name).is_some() && !state.defined.contains(name) {
+                    self.construct(state, name, args)?;
+                } else {
+                    let slot = Self::slot(state, name)
+                        .map_err(|_| format!("Undefined function: '{}'", name))?;
+                    Self::emit_with(state, Opcode::GetLocal, slot);
+                    for arg in args {
+                        self.expr(state, arg)?;
+                    }
+                    Self::emit(state, Opcode::Call);
+                    state.function.code.push(argc);
+                }
+            }
+            ast::Node::Extern(name, params, typename) => {
+                let function = ffi::Extern::new(name, params, typename)?;
+                let index = self.constant(Constant::Extern(Rc::new(function)))?;
+                Self::emit_with(state, Opcode::Const, index);
+                let slot = Self::slot(state, name)?;
+                Self::emit_with(state, Opcode::SetLocal, slot);
+                Self::emit(state, Opcode::Nada);
+            }
+            ast::Node::TypeName(_) => Self::emit(state, Opcode::Nada),
+            ast::Node::Pub(_) | ast::Node::Import(..) => unreachable!("removed by modules::load"),
+        }
+        Ok(())
+    }
+    fn construct(
+        &mut self,
+        state: &mut FnState,
+        name: &str,
+        args: &[ast::Node],
+    ) -> Result<(), String> {
+        let (ty, index, tag) = self.variant(name).unwrap();
+        let fields = ty.variants[tag as usize].fields.len();
+        if args.len() != fields {
+            return Err(format!("{} takes {} arguments", name, fields));
+        }
+        for arg in args {
+            self.expr(state, arg)?;
+        }
+        Self::emit_with(state, Opcode::Variant, index);
+        Self::emit_u16(state, tag);
+        Ok(())
+    }
+}
@@ -1,36 +1,552 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::Write;
+use std::iter::zip;
 use std::path::Path;
 use std::process::Command;
+use std::rc::Rc;
 
 use cranelift_codegen::entity::EntityRef;
-use cranelift_codegen::ir::condcodes::IntCC;
-use cranelift_codegen::ir::{types::I64, Value};
-use cranelift_codegen::ir::{AbiParam, Function, InstBuilder, Signature, UserFuncName};
+use cranelift_codegen::gimli::RunTimeEndian;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::types::{F64, I32, I64, I8};
+use cranelift_codegen::ir::{AbiParam, Block, Function, InstBuilder, MemFlags, TrapCode};
+use cranelift_codegen::ir::{Endianness, Signature, SourceLoc, Type, UserFuncName, Value};
+use cranelift_codegen::ir::ValueLabel;
+use cranelift_codegen::isa::unwind::UnwindInfo;
+use cranelift_codegen::settings::Configurable;
 use cranelift_codegen::verifier::verify_function;
+use cranelift_codegen::LabelValueLoc;
 use cranelift_codegen::{isa, settings, Context};
 use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
-use cranelift_module::{DataDescription, FuncId, Linkage, Module};
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
 use cranelift_object::{ObjectBuilder, ObjectModule};
 
-use crate::ast::{self, Node};
+use crate::ast::{self, MatchPattern, Node};
+use crate::bytecode::{SLICE_FROM, SLICE_TO};
+use crate::dwarf;
+use crate::ffi;
+use crate::io;
+use crate::lists;
+use crate::matching;
+use crate::math;
+use crate::modules::{self, Program};
+use crate::process;
+use crate::value;
 
+/// The static type of a compiled value. Bigints, strings, lists, tuples,
+/// maps, structs, enums, options, ranges and boxes of any are pointers;
+/// everything else is held in registers.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+enum Ty {
+    /// What statements result in, held as a zero that nothing reads.
+    Unit,
+    Bool,
+    Int,
+    Float,
+    BigInt,
+    Str,
+    List(Box<Ty>),
+    /// Laid out like a list, with an item type per position.
+    Tuple(Vec<Ty>),
+    /// Key and value types.
+    Map(Box<Ty>, Box<Ty>),
+    /// Laid out like a tuple of the fields in declaration order.
+    Struct(String),
+    /// Laid out like a tuple of the tag and the payload of the variant.
+    Enum(String),
+    /// Laid out like the enum `value::option_type`, with the type of the
+    /// payload of `some`, which is never for a literal `none`.
+    Option(Box<Ty>),
+    /// Laid out like a list of the bounds and whether each is exclusive.
+    Range,
+    /// A function value, held as the list of what it captures, or like a
+    /// unit if that needs no memory.
+    Fn(Rc<Closure>),
+    /// A box of `runtime/src/any.rs`, holding a value together with the
+    /// description of its type, for what cannot be typed statically.
+    Any,
+    /// The type of what never has a value, like the result of a `throw` or
+    /// of a recursive call whose type is still being found.
+    Never,
+}
+
+/// What a function value calls and the variables it captured, each with
+/// its type and whether it was bound with `let mut`.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct Closure {
+    callee: Callee,
+    captures: Vec<(String, Ty, bool)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Callee {
+    /// A function definition, as a position in `Compiler::defs`.
+    Def(usize),
+    /// An `extern fn`, as a position in `Compiler::externs`.
+    Extern(usize),
+}
+
+impl Closure {
+    /// Whether values of the function need no memory, because nothing
+    /// they capture does.
+    fn is_static(&self) -> bool {
+        self.captures.iter().all(|(_, ty, _)| ty.is_static())
+    }
+}
+
+impl Ty {
+    /// The name a type annotation uses for this type.
+    fn name(&self) -> &str {
+        match self {
+            Ty::Bool => "bool",
+            Ty::Int => "int",
+            Ty::Float => "float",
+            Ty::BigInt => "bigint",
+            Ty::Str => "str",
+            Ty::List(_) => "list",
+            Ty::Tuple(_) => "tuple",
+            Ty::Map(..) => "map",
+            Ty::Struct(name) | Ty::Enum(name) => name,
+            Ty::Option(_) => value::OPTION,
+            Ty::Range => "range",
+            Ty::Unit | Ty::Never => "unit",
+            Ty::Fn(_) => "fn",
+            Ty::Any => "any",
+        }
+    }
+
+    /// Whether values of this type need no memory at all.
+    fn is_static(&self) -> bool {
+        match self {
+            Ty::Unit | Ty::Never => true,
+            Ty::Fn(closure) => closure.is_static(),
+            _ => false,
+        }
+    }
+
+    /// Whether values of this type point into the heap of `runtime/src/gc.rs`,
+    /// or to strings and descriptions that compiled code holds as data.
+    fn is_pointer(&self) -> bool {
+        !matches!(self, Ty::Bool | Ty::Int | Ty::Float) && !self.is_static()
+    }
+
+    /// How values of this type are held in registers.
+    fn clif_type(&self) -> Type {
+        match self {
+            Ty::Bool => I8,
+            Ty::Float => F64,
+            _ => I64,
+        }
+    }
+}
+
+/// The type that values of both `a` and `b` are converted to, like the
+/// results of two branches or two items of a list: the one type of both,
+/// the same kind of container of the joined items, or else any.
+fn join(a: &Ty, b: &Ty) -> Ty {
+    match (a, b) {
+        (a, b) if a == b => a.clone(),
+        (Ty::Never, ty) | (ty, Ty::Never) => ty.clone(),
+        (Ty::List(a), Ty::List(b)) => Ty::List(Box::new(join(a, b))),
+        (Ty::Option(a), Ty::Option(b)) => Ty::Option(Box::new(join(a, b))),
+        (Ty::Map(ak, av), Ty::Map(bk, bv)) => {
+            Ty::Map(Box::new(join(ak, bk)), Box::new(join(av, bv)))
+        }
+        (Ty::Tuple(a), Ty::Tuple(b)) if a.len() == b.len() => {
+            Ty::Tuple(zip(a, b).map(|(a, b)| join(a, b)).collect())
+        }
+        _ => Ty::Any,
+    }
+}
+
+/// Whether values of type `from` are already values of type `to`, because
+/// they differ only where `from` has never, like an empty list.
+fn retypes(from: &Ty, to: &Ty) -> bool {
+    match (from, to) {
+        (from, to) if from == to => true,
+        (Ty::Never, _) => true,
+        (Ty::List(a), Ty::List(b)) | (Ty::Option(a), Ty::Option(b)) => retypes(a, b),
+        (Ty::Map(ak, av), Ty::Map(bk, bv)) => retypes(ak, bk) && retypes(av, bv),
+        (Ty::Tuple(a), Ty::Tuple(b)) => {
+            a.len() == b.len() && zip(a, b).all(|(a, b)| retypes(a, b))
+        }
+        _ => false,
+    }
+}
+
+/// A compiled value and its static type.
 #[derive(Clone)]
-struct Fn {
+struct Val {
+    val: Value,
+    ty: Ty,
+}
+
+/// Why compiling a function stopped.
+enum Stop {
+    /// A variable was given a value of a type it cannot hold, and has been
+    /// widened for compiling the function again.
+    Widen,
+    /// The program cannot be compiled.
+    Error(String),
+}
+
+impl From<String> for Stop {
+    fn from(e: String) -> Self {
+        Stop::Error(e)
+    }
+}
+
+type Compiled<T = Val> = Result<T, Stop>;
+
+/// Functions of libc, libm and the runtime crate in `runtime/` that compiled
+/// code calls: name, params, returns and whether what they return may be
+/// newly allocated, after which compiled code lets `runtime/src/gc.rs`
+/// collect. Everything held by a pointer is passed as one.
+const IMPORTS: &[(&str, &[Type], &[Type], bool)] = &[
+    ("exit", &[I64], &[], false),
+    ("pow", &[F64, F64], &[F64], false),
+    ("fmod", &[F64, F64], &[F64], false),
+    ("sqrt", &[F64], &[F64], false),
+    ("fabs", &[F64], &[F64], false),
+    ("floor", &[F64], &[F64], false),
+    ("ceil", &[F64], &[F64], false),
+    ("round", &[F64], &[F64], false),
+    ("exp", &[F64], &[F64], false),
+    ("log", &[F64], &[F64], false),
+    ("log10", &[F64], &[F64], false),
+    ("sin", &[F64], &[F64], false),
+    ("cos", &[F64], &[F64], false),
+    ("tan", &[F64], &[F64], false),
+    ("asin", &[F64], &[F64], false),
+    ("acos", &[F64], &[F64], false),
+    ("atan", &[F64], &[F64], false),
+    ("atan2", &[F64, F64], &[F64], false),
+    ("alpha_random", &[], &[F64], false),
+    ("alpha_seed", &[I64], &[], false),
+    ("alpha_int_pow", &[I64, I64], &[I64], false),
+    ("alpha_bigint_from_i64", &[I64], &[I64], true),
+    ("alpha_bigint_from_f64", &[F64], &[I64], true),
+    ("alpha_bigint_parse", &[I64], &[I64], true),
+    ("alpha_bigint_add", &[I64, I64], &[I64], true),
+    ("alpha_bigint_sub", &[I64, I64], &[I64], true),
+    ("alpha_bigint_mul", &[I64, I64], &[I64], true),
+    ("alpha_bigint_div", &[I64, I64], &[I64], true),
+    ("alpha_bigint_rem", &[I64, I64], &[I64], true),
+    ("alpha_bigint_pow", &[I64, I64], &[I64], true),
+    ("alpha_bigint_cmp", &[I64, I64], &[I64], false),
+    ("alpha_bigint_to_i64", &[I64], &[I64], false),
+    ("alpha_bigint_to_f64", &[I64], &[F64], false),
+    ("alpha_bigint_to_str", &[I64], &[I64], true),
+    ("alpha_list_new", &[I64], &[I64], true),
+    ("alpha_list_index", &[I64, I64], &[I64], false),
+    ("alpha_list_set", &[I64, I64, I64], &[I64], true),
+    ("alpha_list_slice", &[I64, I64, I64, I64], &[I64], true),
+    ("alpha_list_range", &[I64, I64], &[I64], true),
+    ("alpha_list_unpack", &[I64, I64], &[], false),
+    ("alpha_range_start", &[I64], &[I64], false),
+    ("alpha_range_end", &[I64], &[I64], false),
+    ("alpha_list_sort", &[I64, I64, I64], &[I64], true),
+    ("alpha_list_extreme", &[I64, I64, I64], &[I64], false),
+    ("alpha_list_contains", &[I64, I64, I64, I64], &[I64], false),
+    ("alpha_list_reverse", &[I64], &[I64], true),
+    ("alpha_list_push", &[I64, I64], &[I64], true),
+    ("alpha_list_pop", &[I64], &[I64], true),
+    ("alpha_list_zip", &[I64, I64], &[I64], true),
+    ("alpha_list_enumerate", &[I64], &[I64], true),
+    ("alpha_str_len", &[I64], &[I64], false),
+    ("alpha_str_chars", &[I64], &[I64], true),
+    ("alpha_str_concat", &[I64, I64], &[I64], true),
+    ("alpha_map_new", &[I64], &[I64], true),
+    ("alpha_map_put", &[I64, I64, I64], &[I64], false),
+    ("alpha_map_get", &[I64, I64], &[I64], false),
+    ("alpha_map_set", &[I64, I64, I64], &[I64], true),
+    ("alpha_map_has", &[I64, I64], &[I64], false),
+    ("alpha_map_remove", &[I64, I64], &[I64], true),
+    ("alpha_map_keys", &[I64], &[I64], true),
+    ("alpha_map_values", &[I64], &[I64], true),
+    ("alpha_any_box", &[I64, I64], &[I64], true),
+    ("alpha_any_arg", &[I64, I64], &[I64], true),
+    ("alpha_any_convert", &[I64, I64, I64], &[I64], true),
+    ("alpha_any_cast", &[I64, I64, I64, I64], &[I64], true),
+    ("alpha_any_check", &[I64, I64], &[I64], true),
+    ("alpha_raise", &[I64, I64, I64], &[], false),
+    ("alpha_any_binary", &[I64, I64, I64, I64, I64], &[I64], true),
+    ("alpha_any_truth", &[I64, I64], &[I64], false),
+    ("alpha_any_index", &[I64, I64, I64, I64], &[I64], true),
+    ("alpha_any_slice", &[I64, I64, I64, I64, I64], &[I64], true),
+    ("alpha_any_field", &[I64, I64, I64], &[I64], true),
+    ("alpha_any_set", &[I64, I64, I64, I64, I64], &[I64], true),
+    ("alpha_any_len", &[I64, I64], &[I64], false),
+    ("alpha_any_entries", &[I64, I64, I64], &[I64], true),
+    ("alpha_any_unpack", &[I64, I64, I64], &[I64], true),
+    ("alpha_any_function", &[I64, I64], &[I64], false),
+    ("alpha_any_option", &[I64, I64], &[I64], true),
+    ("alpha_any_keys", &[I64, I64], &[I64], true),
+    ("alpha_any_values", &[I64, I64], &[I64], true),
+    ("alpha_any_has", &[I64, I64, I64, I64], &[I64], false),
+    ("alpha_any_remove", &[I64, I64, I64, I64], &[I64], true),
+    ("alpha_any_int", &[I64, I64], &[I64], false),
+    ("alpha_any_float", &[I64, I64], &[F64], false),
+    ("alpha_any_bigint", &[I64, I64], &[I64], true),
+    ("alpha_any_number", &[I64, I64], &[F64], false),
+    ("alpha_any_is", &[I64, I64, I64], &[I64], false),
+    ("alpha_any_variant", &[I64, I64, I64], &[I64], false),
+    ("alpha_any_matches", &[I64, I64, I64, I64], &[I64], false),
+    ("alpha_any_range", &[I64, I64, I64, I64, I64, I64], &[I64], true),
+    ("alpha_read_line", &[], &[I64], true),
+    ("alpha_read_file", &[I64], &[I64], true),
+    ("alpha_write_file", &[I64, I64], &[], false),
+    ("alpha_append_file", &[I64, I64], &[], false),
+    ("alpha_lines", &[I64], &[I64], true),
+    ("alpha_exists", &[I64], &[I64], false),
+    ("alpha_args", &[], &[I64], true),
+    ("alpha_env", &[I64], &[I64], true),
+    ("strcmp", &[I64, I64], &[I32], false),
+    ("alpha_printf_text", &[I64, I64], &[], false),
+    ("alpha_printf_int", &[I64, I64, I64], &[], false),
+    ("alpha_printf_float", &[I64, I64, F64], &[], false),
+    ("alpha_printf_str", &[I64, I64, I64], &[], false),
+    ("alpha_print", &[I64, I64, I64], &[], false),
+    ("alpha_panic", &[I64, I64], &[], false),
+    ("alpha_backtrace", &[], &[], false),
+    ("alpha_init", &[I64, I64], &[], false),
+    ("alpha_gc_safepoint", &[], &[], false),
+];
+
+/// The operators of `ast::Op`, numbered like `OPS` in `runtime/src/any.rs`.
+fn op_number(op: &ast::Op) -> i64 {
+    match op {
+        ast::Op::Add => 0,
+        ast::Op::Sub => 1,
+        ast::Op::Mul => 2,
+        ast::Op::Div => 3,
+        ast::Op::Mod => 4,
+        ast::Op::Pow => 5,
+        ast::Op::Eq => 6,
+        ast::Op::Neq => 7,
+        ast::Op::Gt => 8,
+        ast::Op::Ge => 9,
+        ast::Op::Lt => 10,
+        ast::Op::Le => 11,
+    }
+}
+
+/// The function that the top level of a program compiles to, which the
+/// `main` of `Compiler::define_main` calls.
+pub const ENTRY: &str = "alpha_main";
+
+/// The runtime crate in `runtime/`, as the static library that `build.rs`
+/// builds, which is linked into every compiled program.
+const RUNTIME: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/libalpha_runtime.a"));
+
+/// The section of the table of compiled functions, with their stack maps and
+/// lines, that `runtime/src/gc.rs` and `runtime/src/trace.rs` walk the stack
+/// with.
+const FUNCTIONS_SECTION: &str = "alpha_functions";
+
+/// The byte that tells `runtime/src/gc.rs` to collect at every allocation,
+/// defined by the object of the program itself.
+const GC_STRESS: &str = "alpha_gc_stress";
+
+/// How many instances of a function are compiled for different types of
+/// arguments, after which further calls share one taking any.
+const INSTANCES: usize = 8;
+
+/// How many times a function is compiled again with the type its recursive
+/// calls were found to return, before they are taken to return any.
+const RETURN_PASSES: usize = 4;
+
+/// How many times a function is translated again with variables widened,
+/// which is more than any function has variables that widen.
+const WIDENINGS: usize = 64;
+
+
+/// What the argument of a printf conversion is.
+#[derive(Clone, Copy)]
+enum Conversion {
+    Int,
+    Float,
+    Str,
+    Pointer,
+}
+
+impl Conversion {
+    fn expects(self) -> &'static str {
+        match self {
+            Conversion::Int => "an int",
+            Conversion::Float => "a float",
+            Conversion::Str => "a str",
+            Conversion::Pointer => "a list, map, struct or str",
+        }
+    }
+}
+
+/// A part of a printf format: text that ends with at most one conversion,
+/// as written in `spec`.
+struct Piece {
+    format: String,
+    spec: String,
+    conversion: Option<Conversion>,
+}
+
+/// Splits a printf format into pieces that each take at most one argument.
+/// Integer conversions are given the `l` length, since ints are 64 bits, and
+/// the `%%` of text without conversions is unescaped, as it is printed as is.
+fn format_pieces(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            text.push_str("%%");
+            continue;
+        }
+        let mut spec = String::from("%");
+        let mut flags = String::new();
+        while let Some(&c) = chars.peek() {
+            match c {
+                '*' => return Err("* widths and precisions are not supported".into()),
+                '-' | '+' | ' ' | '#' | '0'..='9' | '.' => flags.push(c),
+                'h' | 'l' | 'L' | 'q' | 'j' | 'z' | 't' => {}
+                _ => break,
+            }
+            spec.push(c);
+            chars.next();
+        }
+        let Some(c) = chars.next() else {
+            return Err(format!("incomplete conversion {} at the end", spec));
+        };
+        spec.push(c);
+        let (length, conversion) = match c {
+            'd' | 'i' | 'u' | 'o' | 'x' | 'X' => ("l", Conversion::Int),
+            'c' => ("", Conversion::Int),
+            'f' | 'F' | 'e' | 'E' | 'g' | 'G' | 'a' | 'A' => ("", Conversion::Float),
+            's' => ("", Conversion::Str),
+            'p' => ("", Conversion::Pointer),
+            _ => return Err(format!("unsupported conversion {}", spec)),
+        };
+        pieces.push(Piece {
+            format: format!("{}%{}{}{}", text, flags, length, c),
+            spec,
+            conversion: Some(conversion),
+        });
+        text.clear();
+    }
+    if !text.is_empty() {
+        pieces.push(Piece {
+            format: text.replace("%%", "%"),
+            spec: String::new(),
+            conversion: None,
+        });
+    }
+    Ok(pieces)
+}
+
+/// A function of `IMPORTS`.
+struct Import {
+    id: FuncId,
+    sig: Signature,
+    allocates: bool,
+}
+
+/// A function definition of the program, which is compiled once for each
+/// closure of it and types of arguments it is called with.
+struct Def {
+    name: Option<String>,
+    params: Vec<(ast::Pattern, Option<String>)>,
+    body: Rc<Node>,
+    returns: Option<String>,
+    /// Where it is defined, the file as a position in `Program::modules`.
+    line: usize,
+    file: usize,
+    /// The names it refers to that it does not bind itself, which it
+    /// captures from where it is defined if they are variables there.
+    free: Vec<String>,
+    /// How many instances have been compiled.
+    instances: usize,
+}
+
+/// The C function of an `extern fn`.
+struct Extern {
+    function: ffi::Extern,
     id: FuncId,
     sig: Signature,
 }
 
+/// What an instance of a function is compiled for: a closure of it and
+/// the types of the arguments.
+type InstanceKey = (Rc<Closure>, Vec<Ty>);
+
+/// A compiled instance, whose return type is `None` while it is being
+/// compiled.
+struct Instance {
+    id: FuncId,
+    returns: Option<Ty>,
+}
+
+/// An instance being compiled, with the type its recursive calls are
+/// assumed to return, and whether a call from another instance being
+/// compiled has made it return any.
+struct Pending {
+    key: InstanceKey,
+    assumed: Ty,
+    force_any: bool,
+}
+
 pub struct Compiler {
     module: ObjectModule,
     ctx: Context,
-    fn_decls: HashMap<String, Fn>,
+    /// The C functions of `IMPORTS`, by symbol.
+    fn_decls: HashMap<String, Import>,
+    structs: HashMap<String, value::StructType>,
+    enums: HashMap<String, value::EnumType>,
+    /// The enum and the tag of each variant.
+    variants: HashMap<String, (String, usize)>,
+    defs: Vec<Def>,
+    /// The definitions by body.
+    def_index: HashMap<*const Node, usize>,
+    externs: Vec<Extern>,
+    /// The externs by the node declaring them.
+    extern_index: HashMap<*const Node, usize>,
+    instances: HashMap<InstanceKey, Instance>,
+    pending: Vec<Pending>,
+    /// The functions that calls through values of type any go through, by
+    /// closure.
+    wrappers: HashMap<Rc<Closure>, FuncId>,
+    /// What values of closures that need no memory are when they are held
+    /// in memory.
+    pairs: HashMap<Rc<Closure>, DataId>,
+    /// Strings and descriptions, by contents.
+    data: HashMap<Vec<u8>, DataId>,
+    symbols: HashSet<String>,
+    /// Whether this is the object of a library, whose `pub` functions C
+    /// calls by their own name.
+    library: bool,
+    /// The line of the statement being translated, and its file as a
+    /// position in `Program::modules`.
+    line: usize,
+    file: usize,
+    /// The debug information of the functions translated so far.
+    debug_functions: Vec<dwarf::Function>,
+    debug: bool,
 }
 
 impl Compiler {
-    pub fn new(debug: bool) -> Self {
-        let settings = settings::builder();
+    /// A compiler of position-independent code if `pic`, which shared
+    /// libraries need.
+    pub fn new(pic: bool, debug: bool) -> Self {
+        let mut settings = settings::builder();
+        // `runtime/src/gc.rs` follows frame pointers to find the stack maps.
+        settings.set("preserve_frame_pointers", "true").unwrap();
+        if pic {
+            settings.set("is_pic", "true").unwrap();
+        }
         let shared_flags = settings::Flags::new(settings);
 
         let target_isa = isa::lookup(target_lexicon::Triple::host())
@@ -52,363 +568,2905 @@ impl Compiler {
         let mut module = ObjectModule::new(builder);
         let ctx = module.make_context();
 
-        let printf_sig = {
+        let mut fn_decls = HashMap::new();
+        for (name, params, returns, allocates) in IMPORTS {
             let mut sig = module.make_signature();
-            for _ in 0..10 {
-                sig.params.push(AbiParam::new(module.isa().pointer_type()));
-            }
-            sig.returns.push(AbiParam::new(I64));
-            sig.call_conv = module.isa().default_call_conv();
-            sig
-        };
-        let printf_func = module
-            .declare_function("printf", Linkage::Import, &printf_sig)
-            .unwrap();
-
-        let mut fn_decls: HashMap<String, Fn> = HashMap::new();
-        fn_decls.insert(
-            "printf".into(),
-            Fn {
-                id: printf_func,
-                sig: printf_sig,
-            },
-        );
+            sig.params
+                .extend(params.iter().map(|param| AbiParam::new(*param)));
+            sig.returns
+                .extend(returns.iter().map(|ret| AbiParam::new(*ret)));
+            let id = module
+                .declare_function(name, Linkage::Import, &sig)
+                .unwrap();
+            fn_decls.insert(
+                name.to_string(),
+                Import {
+                    id,
+                    sig,
+                    allocates: *allocates,
+                },
+            );
+        }
 
-        Self {
+        let mut compiler = Self {
             module,
             ctx,
             fn_decls,
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            variants: HashMap::new(),
+            defs: Vec::new(),
+            def_index: HashMap::new(),
+            externs: Vec::new(),
+            extern_index: HashMap::new(),
+            instances: HashMap::new(),
+            pending: Vec::new(),
+            wrappers: HashMap::new(),
+            pairs: HashMap::new(),
+            data: HashMap::new(),
+            symbols: HashSet::new(),
+            library: false,
+            line: 1,
+            file: 0,
+            debug_functions: Vec::new(),
+            debug,
+        };
+        compiler.declare_enum(value::option_type());
+        compiler
+    }
+
+    fn declare_enum(&mut self, ty: value::EnumType) {
+        for (tag, variant) in ty.variants.iter().enumerate() {
+            self.variants
+                .insert(variant.name.clone(), (ty.name.clone(), tag));
         }
+        self.enums.insert(ty.name.clone(), ty);
     }
 
-    pub fn declare_functions(&mut self, node: &ast::Node) {
+    /// Declares the structs and enums anywhere in `node` up front, like the
+    /// interpreter.
+    fn declare_types(&mut self, node: &Node) {
         match node {
-            ast::Node::Statements(statements) => {
-                for statement in statements {
-                    self.declare_functions(&statement)
-                }
+            Node::StructDef(name, fields) => {
+                let ty = value::StructType {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                };
+                self.structs.insert(name.clone(), ty);
             }
-            ast::Node::IfElse {
-                condition,
-                if_block: if_body,
-                else_block: else_body,
-            } => {
-                self.declare_functions(&condition);
-                self.declare_functions(&if_body);
-                self.declare_functions(&else_body);
-            }
-            ast::Node::Define(_mut, _name, expr, _typename) => {
-                self.declare_functions(&expr);
-            }
-            ast::Node::Assign(_name, expr) => {
-                self.declare_functions(&expr);
-            }
-            ast::Node::Expr { lhs, rhs, .. } => {
-                self.declare_functions(&lhs);
-                self.declare_functions(&rhs);
-            }
-            ast::Node::FnCall(_name, _args) => {}
-            ast::Node::VarRef(_name) => {}
-            ast::Node::Number(_num) => {}
-            ast::Node::Nada => {}
-            ast::Node::TypeName(_name) => {}
-            ast::Node::Loop {
-                var: _,
-                iterable,
-                inner,
-            } => {
-                self.declare_functions(iterable);
-                self.declare_functions(inner);
+            Node::EnumDef(name, variants) => self.declare_enum(value::EnumType {
+                name: name.clone(),
+                variants: variants.clone(),
+            }),
+            Node::FnDef(_, _, body, _) => self.declare_types(body),
+            _ => node.children().for_each(|child| self.declare_types(child)),
+        }
+    }
+
+    /// Finds the function definitions and externs in `node`, of the module
+    /// at `file`, and checks the patterns of every `match`, adding the names
+    /// that `node` refers to to `refs`.
+    fn declare_functions(
+        &mut self,
+        node: &Node,
+        file: usize,
+        refs: &mut Vec<String>,
+    ) -> Result<(), String> {
+        match node {
+            Node::Line(line, node) => {
+                self.line = *line;
+                self.declare_functions(node, file, refs)
+                    .map_err(|e| match e {
+                        e if e.starts_with("line ") => e,
+                        e => format!("line {}: {}", line, e),
+                    })
             }
-            ast::Node::While { condition, inner } => {
-                self.declare_functions(condition);
-                self.declare_functions(inner);
+            Node::FnDef(name, params, body, returns) => {
+                let line = self.line;
+                let mut inner = Vec::new();
+                self.declare_functions(body, file, &mut inner)?;
+                let bound: Vec<&String> = params
+                    .iter()
+                    .flat_map(|(param, _)| param.names())
+                    .chain(name)
+                    .collect();
+                let mut free: Vec<String> = Vec::new();
+                for name in inner {
+                    if !bound.contains(&&name) && !free.contains(&name) {
+                        free.push(name);
+                    }
+                }
+                refs.extend(free.iter().cloned());
+                self.def_index.insert(Rc::as_ptr(body), self.defs.len());
+                self.defs.push(Def {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    returns: returns.clone(),
+                    line,
+                    file,
+                    free,
+                    instances: 0,
+                });
+                Ok(())
             }
-            ast::Node::FnDef(name, params, body, _typename) => {
-                self.declare_functions(body);
-
+            Node::Extern(name, params, returns) => {
+                let function = ffi::Extern::new(name, params, returns)?;
+                let clif_type = |ty: ffi::CType| match ty {
+                    ffi::CType::CInt => I32,
+                    ty => self.parse_ty(ty.ty()).clif_type(),
+                };
                 let mut sig = self.module.make_signature();
-                sig.returns.push(AbiParam::new(I64));
-                for _ in params {
-                    sig.params.push(AbiParam::new(I64));
+                sig.params.extend(
+                    function
+                        .params
+                        .iter()
+                        .map(|&param| AbiParam::new(clif_type(param))),
+                );
+                if let Some(returns) = function.returns {
+                    sig.returns.push(AbiParam::new(clif_type(returns)));
                 }
+                // A function that the runtime imports too, like `strcmp` or
+                // `exit`, keeps the signature of `IMPORTS` and is called
+                // through its address with the signature declared here.
+                let id = match self.fn_decls.get(name) {
+                    Some(import) => import.id,
+                    None => self
+                        .module
+                        .declare_function(name, Linkage::Import, &sig)
+                        .map_err(|e| e.to_string())?,
+                };
+                self.extern_index
+                    .insert(node as *const Node, self.externs.len());
+                self.externs.push(Extern { function, id, sig });
+                Ok(())
+            }
+            Node::Match { arms, .. } => {
+                self.resolve_arms(arms)?;
+                node.children()
+                    .try_for_each(|child| self.declare_functions(child, file, refs))
+            }
+            Node::VarRef(name)
+            | Node::FnCall(name, _)
+            | Node::Assign(name, _)
+            | Node::IndexAssign(name, ..) => {
+                refs.push(name.clone());
+                node.children()
+                    .try_for_each(|child| self.declare_functions(child, file, refs))
+            }
+            _ => node
+                .children()
+                .try_for_each(|child| self.declare_functions(child, file, refs)),
+        }
+    }
+
+    /// The patterns of `arms` with their variants resolved, after checking
+    /// that they are exhaustive, like the interpreter.
+    fn resolve_arms(&self, arms: &[ast::Arm]) -> Result<Vec<MatchPattern>, String> {
+        let arity = |name: &str| self.variant_fields(name).map(|fields| fields.len());
+        let patterns = arms
+            .iter()
+            .map(|arm| matching::resolve(&arm.pattern, &arity, &mut |name| Ok(name.clone())))
+            .collect::<Result<Vec<MatchPattern>, String>>()?;
+        let unguarded: Vec<&MatchPattern> = zip(arms, &patterns)
+            .filter(|(arm, _)| arm.guard.is_none())
+            .map(|(_, pattern)| pattern)
+            .collect();
+        let siblings = |name: &str| {
+            let (enum_name, _) = &self.variants[name];
+            self.enums[enum_name].arities()
+        };
+        matching::check_exhaustive(&unguarded, &siblings)?;
+        Ok(patterns)
+    }
+
+    /// The payload fields of the variant `name`.
+    fn variant_fields(&self, name: &str) -> Option<&[(String, Option<String>)]> {
+        let (enum_name, tag) = self.variants.get(name)?;
+        Some(&self.enums[enum_name].variants[*tag].fields)
+    }
+
+    /// The type a type annotation names. Lists, options and maps are named
+    /// without the types of their items, which are any.
+    fn parse_ty(&self, typename: &str) -> Ty {
+        match typename {
+            "bool" => Ty::Bool,
+            "int" => Ty::Int,
+            "float" => Ty::Float,
+            "bigint" => Ty::BigInt,
+            "str" => Ty::Str,
+            "unit" => Ty::Unit,
+            "range" => Ty::Range,
+            "list" => Ty::List(Box::new(Ty::Any)),
+            "map" => Ty::Map(Box::new(Ty::Any), Box::new(Ty::Any)),
+            value::OPTION => Ty::Option(Box::new(Ty::Any)),
+            name if self.structs.contains_key(name) => Ty::Struct(name.to_string()),
+            name if self.enums.contains_key(name) => Ty::Enum(name.to_string()),
+            _ => Ty::Any,
+        }
+    }
+
+    /// The position and type of a field of the struct `name`.
+    fn field_ty(&self, name: &str, field: &str) -> Option<(usize, Ty)> {
+        let struct_ty = &self.structs[name];
+        let position = struct_ty.position(field).ok()?;
+        Some((position, self.parse_ty(&struct_ty.fields[position].1)))
+    }
 
-                let fn_name = name.clone().unwrap();
-                let id = self
-                    .module
-                    .declare_function(fn_name.as_str(), Linkage::Export, &sig)
-                    .unwrap();
+    /// The types of the payload of a variant, where untyped fields are any.
+    fn payload_tys(&self, name: &str) -> Vec<Ty> {
+        let (enum_name, tag) = &self.variants[name];
+        if enum_name == value::OPTION {
+            return vec![Ty::Any; *tag == value::SOME as usize];
+        }
+        self.enums[enum_name].variants[*tag]
+            .fields
+            .iter()
+            .map(|(_, typename)| match typename {
+                Some(typename) => self.parse_ty(typename),
+                None => Ty::Any,
+            })
+            .collect()
+    }
+
+    /// The description of `ty` that `runtime/src/print.rs` and
+    /// `runtime/src/any.rs` read values with, followed by the definitions of
+    /// the structs and enums that it names.
+    fn description(&self, ty: &Ty) -> String {
+        let mut description = String::new();
+        let mut named = Vec::new();
+        self.describe(ty, &mut description, &mut named);
+        let mut defined = 0;
+        while let Some(name) = named.get(defined).cloned() {
+            defined += 1;
+            description.push_str(&format!("\n{}=", name));
+            if let Some(struct_ty) = self.structs.get(&name) {
+                description.push_str(&format!("r{},", struct_ty.fields.len()));
+                for (field, typename) in &struct_ty.fields {
+                    description.push_str(&format!("{},", field));
+                    self.describe(&self.parse_ty(typename), &mut description, &mut named);
+                }
+                continue;
+            }
+            let variants = &self.enums[&name].variants;
+            description.push_str(&format!("e{},", variants.len()));
+            for variant in variants {
+                let payload = self.payload_tys(&variant.name);
+                description.push_str(&format!("{},{},", variant.name, payload.len()));
+                for ty in &payload {
+                    self.describe(ty, &mut description, &mut named);
+                }
+            }
+        }
+        description
+    }
 
-                self.fn_decls.insert(fn_name, Fn { id, sig });
+    /// Appends the description of `ty` to `description`, adding the structs
+    /// and enums it names to `named`.
+    fn describe(&self, ty: &Ty, description: &mut String, named: &mut Vec<String>) {
+        match ty {
+            Ty::Unit => description.push('u'),
+            Ty::Int => description.push('i'),
+            Ty::Float => description.push('f'),
+            Ty::Bool => description.push('b'),
+            Ty::BigInt => description.push('n'),
+            Ty::Str => description.push('s'),
+            Ty::Range => description.push('r'),
+            Ty::Any => description.push('a'),
+            Ty::Never => description.push('v'),
+            Ty::Fn(closure) => {
+                let name = match closure.callee {
+                    Callee::Def(def) => self.defs[def].name.as_deref().unwrap_or(""),
+                    Callee::Extern(function) => &self.externs[function].function.name,
+                };
+                description.push_str(&format!("F{},", name));
+            }
+            Ty::List(item) => {
+                description.push('l');
+                self.describe(item, description, named);
+            }
+            Ty::Option(payload) => {
+                description.push('o');
+                self.describe(payload, description, named);
+            }
+            Ty::Map(key, value) => {
+                description.push('m');
+                self.describe(key, description, named);
+                self.describe(value, description, named);
+            }
+            Ty::Tuple(items) => {
+                description.push_str(&format!("t{},", items.len()));
+                for item in items {
+                    self.describe(item, description, named);
+                }
+            }
+            Ty::Struct(name) | Ty::Enum(name) => {
+                description.push_str(&format!("@{},", name));
+                if !named.contains(name) {
+                    named.push(name.clone());
+                }
             }
-            n => todo!("{:?}", n),
         }
     }
 
-    pub fn translate_fn(
-        &mut self,
-        name: &Option<String>,
-        params: &Vec<String>,
-        body: &ast::Node,
-        debug: bool,
-    ) -> Value {
-        let fu = self.fn_decls.get(&name.clone().unwrap()).unwrap().clone();
+    /// The read-only data of `bytes`, defined once for all that use it.
+    fn define_data(&mut self, bytes: Vec<u8>) -> DataId {
+        if let Some(id) = self.data.get(&bytes) {
+            return *id;
+        }
+        let mut data = DataDescription::new();
+        data.define(bytes.clone().into_boxed_slice());
+        data.set_align(8);
+        let id = self.module.declare_anonymous_data(false, false).unwrap();
+        self.module.define_data(id, &data).unwrap();
+        self.data.insert(bytes, id);
+        id
+    }
 
-        let mut fn_builder_ctx = FunctionBuilderContext::new();
-        let mut func = Function::with_name_signature(
-            UserFuncName::testcase(name.clone().unwrap()),
-            fu.sig.clone(),
-        );
-        let mut builder = FunctionBuilder::new(&mut func, &mut fn_builder_ctx);
+    /// The address of the data `id`.
+    fn data_addr(&mut self, fb: &mut CustomFunctionBuilder, id: DataId) -> Value {
+        let global = self.module.declare_data_in_func(id, fb.builder.func);
+        fb.builder.ins().symbol_value(I64, global)
+    }
 
-        let block = builder.create_block();
-        builder.switch_to_block(block);
-        builder.append_block_params_for_function_params(block);
+    /// A NUL-terminated string of `text`, which compiled code holds like
+    /// any other.
+    fn string(&mut self, fb: &mut CustomFunctionBuilder, text: &str) -> Value {
+        let id = self.define_data(format!("{}\0", text).into_bytes());
+        self.data_addr(fb, id)
+    }
 
-        let mut fnbuilder = CustomFunctionBuilder {
-            var_index: 0,
-            variables: HashMap::new(),
-            builder,
-        };
+    /// The description of `ty`, for the runtime.
+    fn describe_value(&mut self, fb: &mut CustomFunctionBuilder, ty: &Ty) -> Value {
+        let description = self.description(ty);
+        self.string(fb, &description)
+    }
 
-        for name in params {
-            let var = fnbuilder.new_var(name);
-            fnbuilder.builder.declare_var(var, I64);
-            let val = fnbuilder.builder.block_params(block)[0];
-            fnbuilder.builder.def_var(var, val);
+    /// A symbol for a function named `name` that no other function has.
+    fn symbol(&mut self, name: &str) -> String {
+        let mut symbol = format!("alpha.{}", name);
+        let mut n = 1;
+        while !self.symbols.insert(symbol.clone()) {
+            symbol = format!("alpha.{}.{}", name, n);
+            n += 1;
         }
+        symbol
+    }
+
+    /// Translates a function of the signature `sig` defined on `line`,
+    /// whose parameters and body `body` translates, converting what it
+    /// returns to `returns`. A variable that is given a value of a type it
+    /// cannot hold is widened and the function translated again. Results in
+    /// the function, its parameters and variables for the debug information,
+    /// and the type of what it returns, joined over every return.
+    fn compile_function(
+        &mut self,
+        sig: &Signature,
+        line: usize,
+        returns: &Ty,
+        entry: bool,
+        body: &mut dyn FnMut(&mut Compiler, &mut CustomFunctionBuilder) -> Compiled,
+    ) -> Result<(Function, Vec<(Variable, dwarf::Local)>, Ty), String> {
+        let mut widened = HashMap::new();
+        for _ in 0..WIDENINGS {
+            let mut fn_builder_ctx = FunctionBuilderContext::new();
+            let mut func = Function::with_name_signature(UserFuncName::default(), sig.clone());
+            func.collect_debug_info();
+            let mut builder = FunctionBuilder::new(&mut func, &mut fn_builder_ctx);
+            builder.set_srcloc(SourceLoc::new(line as u32));
+            let block = builder.create_block();
+            builder.switch_to_block(block);
+            builder.append_block_params_for_function_params(block);
+            builder.seal_block(block);
+            let params = builder.block_params(block).to_vec();
 
-        let result = self.translate_wbuilder(&mut fnbuilder, &Node::Nada, debug);
-        let val = self.translate_wbuilder(&mut fnbuilder, body, debug);
-        fnbuilder.builder.ins().return_(&[val]);
+            let mut fnbuilder = CustomFunctionBuilder {
+                var_index: 0,
+                vars: HashMap::new(),
+                widened: std::mem::take(&mut widened),
+                own: None,
+                params,
+                returns: returns.clone(),
+                observed: Ty::Never,
+                entry,
+                locals: Vec::new(),
+                builder,
+            };
+            let outer = (self.line, self.file);
+            self.line = line;
+            let result = body(self, &mut fnbuilder).and_then(|val| self.ret(&mut fnbuilder, val));
+            (self.line, self.file) = outer;
+            match result {
+                Ok(()) => {}
+                Err(Stop::Widen) => {
+                    widened = fnbuilder.widened;
+                    continue;
+                }
+                Err(Stop::Error(e)) => return Err(e),
+            }
+            fnbuilder.builder.finalize();
+            let CustomFunctionBuilder {
+                locals, observed, ..
+            } = fnbuilder;
+            return Ok((func, locals, observed));
+        }
+        Err("The types of the variables of a function do not settle".to_string())
+    }
 
-        fnbuilder.builder.seal_block(block);
-        fnbuilder.builder.finalize();
+    /// Returns `val` from the function being translated, converted to the
+    /// type it returns.
+    fn ret(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val) -> Compiled<()> {
+        if fnbuilder.entry {
+            // Like `process::status`, a program that results in anything
+            // but an int exits successfully.
+            let status = match val.ty {
+                Ty::Int => val.val,
+                Ty::Any => {
+                    let description = fnbuilder
+                        .builder
+                        .ins()
+                        .load(I64, MemFlags::trusted(), val.val, 0);
+                    let kind = fnbuilder
+                        .builder
+                        .ins()
+                        .uload8(I64, MemFlags::trusted(), description, 0);
+                    let is_int = fnbuilder
+                        .builder
+                        .ins()
+                        .icmp_imm(IntCC::Equal, kind, b'i' as i64);
+                    let value = fnbuilder
+                        .builder
+                        .ins()
+                        .load(I64, MemFlags::trusted(), val.val, 8);
+                    let zero = fnbuilder.builder.ins().iconst(I64, 0);
+                    fnbuilder.builder.ins().select(is_int, value, zero)
+                }
+                _ => fnbuilder.builder.ins().iconst(I64, 0),
+            };
+            fnbuilder.builder.ins().return_(&[status]);
+            return Ok(());
+        }
+        fnbuilder.observed = join(&fnbuilder.observed, &val.ty);
+        let returns = fnbuilder.returns.clone();
+        let val = match join(&val.ty, &returns) == returns {
+            true => self.convert(fnbuilder, val, &returns)?,
+            // Only a translation that is done again returns this.
+            false => fnbuilder.dummy(&returns),
+        };
+        let raw = fnbuilder.raw(&val);
+        fnbuilder.builder.ins().return_(&[raw]);
+        Ok(())
+    }
 
+    /// Defines the function `id` translated to `func`, with a record for
+    /// `runtime/src/gc.rs` and traces, under `name` if it is not the top
+    /// level, and debug information under `debug_name` unless it has none.
+    #[allow(clippy::too_many_arguments)]
+    fn finish(
+        &mut self,
+        id: FuncId,
+        func: Function,
+        name: Option<&str>,
+        debug_name: Option<&str>,
+        line: usize,
+        returns: &Ty,
+        locals: Vec<(Variable, dwarf::Local)>,
+    ) -> Result<(), String> {
         let flags = settings::Flags::new(settings::builder());
         let res = verify_function(&func, &flags);
 
-        if debug {
+        if self.debug {
             println!("{}", func.display());
         }
         if let Err(errors) = res {
-            panic!("{}", errors);
+            return Err(errors.to_string());
         }
 
         self.ctx.func = func;
-        self.module.define_function(fu.id, &mut self.ctx).unwrap();
+        self.module
+            .define_function(id, &mut self.ctx)
+            .map_err(|e| e.to_string())?;
+        let compiled = self.ctx.compiled_code().unwrap();
+        let code = &compiled.buffer;
+        let size = code.data().len();
+        let maps: Vec<(u32, u32)> = code
+            .user_stack_maps()
+            .iter()
+            .flat_map(|(pc, _, map)| map.entries().map(|(_, offset)| (*pc, offset)))
+            .collect();
+
+        let isa = self.module.isa();
+        let mut lines: Vec<(u32, usize)> = Vec::new();
+        // What regalloc adds, like spills, has no line and stays with the
+        // line before.
+        for srcloc in code.get_srclocs_sorted() {
+            if srcloc.loc.is_default() {
+                continue;
+            }
+            let line = srcloc.loc.bits() as usize;
+            if lines.last().map(|&(_, last)| last) != Some(line) {
+                lines.push((srcloc.start, line));
+            }
+        }
+        let locals = locals
+            .into_iter()
+            .map(|(var, mut local)| {
+                let ranges = compiled
+                    .value_labels_ranges
+                    .get(&ValueLabel::new(var.index()));
+                local.ranges = ranges
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|range| {
+                        let place = match range.loc {
+                            LabelValueLoc::Reg(reg) => {
+                                dwarf::Place::Register(isa.map_regalloc_reg_to_dwarf(reg).ok()?)
+                            }
+                            LabelValueLoc::CFAOffset(offset) => dwarf::Place::Frame(offset),
+                        };
+                        Some((range.start, range.end, place))
+                    })
+                    .collect();
+                local
+            })
+            .collect();
+        let unwind = match compiled.create_unwind_info(isa).unwrap() {
+            Some(UnwindInfo::SystemV(unwind)) => Some(unwind),
+            _ => None,
+        };
         self.module.clear_context(&mut self.ctx);
+        self.define_function_record(id, name, size, &maps, line, &lines);
+        if let Some(debug_name) = debug_name {
+            self.debug_functions.push(dwarf::Function {
+                id,
+                name: debug_name.to_string(),
+                exported: debug_name == ENTRY,
+                file: self.file,
+                line,
+                size: size as u32,
+                lines,
+                returns: returns.name().to_string(),
+                locals,
+                unwind,
+            });
+        }
+        Ok(())
+    }
 
-        result
+    /// The instance of a function for `key`, compiled unless it has been,
+    /// and the type it returns. An instance that is being compiled returns
+    /// what it is assumed to if it calls itself, and any if another one
+    /// calls it, as that one would need to be compiled again otherwise.
+    fn instance(&mut self, key: InstanceKey) -> Result<(FuncId, Ty), String> {
+        if let Some(instance) = self.instances.get(&key) {
+            if let Some(returns) = &instance.returns {
+                return Ok((instance.id, returns.clone()));
+            }
+            let id = instance.id;
+            let top = self.pending.last_mut().unwrap();
+            if top.key == key {
+                return Ok((id, top.assumed.clone()));
+            }
+            let pending = self.pending.iter_mut().find(|p| p.key == key).unwrap();
+            pending.force_any = true;
+            return Ok((id, Ty::Any));
+        }
+        let Callee::Def(def) = key.0.callee else {
+            unreachable!("externs have no instances");
+        };
+        let (closure, args) = key.clone();
+        let mut sig = self.module.make_signature();
+        sig.params.push(AbiParam::new(I64));
+        sig.params
+            .extend(args.iter().map(|ty| AbiParam::new(ty.clif_type())));
+        sig.returns.push(AbiParam::new(I64));
+        let name = self.defs[def].name.clone();
+        let symbol = self.symbol(name.as_deref().unwrap_or("fn"));
+        let id = self
+            .module
+            .declare_function(&symbol, Linkage::Local, &sig)
+            .map_err(|e| e.to_string())?;
+        self.defs[def].instances += 1;
+        self.instances
+            .insert(key.clone(), Instance { id, returns: None });
+        self.pending.push(Pending {
+            key,
+            assumed: Ty::Never,
+            force_any: false,
+        });
+
+        let (line, file) = (self.defs[def].line, self.defs[def].file);
+        let outer = (self.line, self.file);
+        self.file = file;
+        let mut passes = 0;
+        let returns = loop {
+            let assumed = self.pending.last().unwrap().assumed.clone();
+            let body = self.defs[def].body.clone();
+            let params = self.defs[def].params.clone();
+            let (func, locals, observed) =
+                self.compile_function(&sig, line, &assumed, false, &mut |c, fnbuilder| {
+                    c.enter(fnbuilder, &closure, &params, &args)?;
+                    c.translate(fnbuilder, &body)
+                })?;
+            let pending = self.pending.last_mut().unwrap();
+            let returns = match pending.force_any || passes == RETURN_PASSES {
+                true => Ty::Any,
+                false => join(&assumed, &observed),
+            };
+            if returns == assumed {
+                let record = name.as_deref().unwrap_or("fn");
+                self.finish(id, func, Some(record), Some(record), line, &returns, locals)?;
+                break returns;
+            }
+            pending.assumed = returns;
+            passes += 1;
+        };
+        (self.line, self.file) = outer;
+        self.pending.pop();
+        let key = (closure, args);
+        self.instances.get_mut(&key).unwrap().returns = Some(returns.clone());
+        Ok((id, returns))
     }
 
-    fn translate_wbuilder(
+    /// Binds what an instance of `closure` captured and its parameters.
+    fn enter(
         &mut self,
         fnbuilder: &mut CustomFunctionBuilder,
-        node: &ast::Node,
-        debug: bool,
-    ) -> Value {
-        match node {
-            ast::Node::Statements(statements) => {
-                let mut val = self.translate_wbuilder(fnbuilder, &Node::Nada, debug);
-                for statement in statements {
-                    val = self.translate_wbuilder(fnbuilder, &statement, debug);
-                }
-                val
+        closure: &Rc<Closure>,
+        params: &[(ast::Pattern, Option<String>)],
+        args: &[Ty],
+    ) -> Compiled<()> {
+        let env = fnbuilder.params[0];
+        for (i, (name, ty, mutable)) in closure.captures.iter().enumerate() {
+            let raw = fnbuilder.builder.ins().load(
+                I64,
+                MemFlags::trusted(),
+                env,
+                8 + 8 * i as i32,
+            );
+            let val = fnbuilder.unraw(raw, ty.clone());
+            self.bind(fnbuilder, name, val, *mutable)?;
+        }
+        let Callee::Def(def) = closure.callee else {
+            unreachable!("externs have no instances");
+        };
+        if let Some(name) = &self.defs[def].name {
+            fnbuilder.own = Some((name.clone(), closure.clone()));
+        }
+        for (i, ((pattern, _), ty)) in zip(params, args).enumerate() {
+            let ast::Pattern::Name(name) = pattern else {
+                return Err("Tuple parameters are not supported when compiled"
+                    .to_string()
+                    .into());
+            };
+            let val = fnbuilder.params[i + 1];
+            let val = fnbuilder.val(val, ty.clone());
+            self.bind(fnbuilder, name, val, false)?;
+        }
+        for (_, local) in &mut fnbuilder.locals {
+            local.param = !closure.captures.iter().any(|(name, ..)| *name == local.name);
+        }
+        Ok(())
+    }
+
+    /// Calls `closure`, whose value is `env`, with `args`: an instance of
+    /// a function for the types of the arguments, after checking them
+    /// against the types of the parameters, or an `extern fn`.
+    fn call_closure(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        closure: &Rc<Closure>,
+        env: Value,
+        mut args: Vec<Val>,
+    ) -> Compiled {
+        let def = match closure.callee {
+            Callee::Def(def) => def,
+            Callee::Extern(function) => return self.call_extern(fnbuilder, function, args),
+        };
+        let params = self.defs[def].params.clone();
+        args.truncate(params.len());
+        while args.len() < params.len() {
+            let unit = fnbuilder.unit();
+            args.push(unit);
+        }
+        for (arg, (_, typename)) in args.iter_mut().zip(&params) {
+            if let Some(typename) = typename {
+                *arg = self.check(fnbuilder, arg.clone(), typename)?;
             }
-            ast::Node::FnDef(name, params, body, _typename) => {
-                self.translate_fn(name, params, body, debug)
+        }
+        let mut key = (
+            closure.clone(),
+            args.iter().map(|arg| arg.ty.clone()).collect::<Vec<_>>(),
+        );
+        if self.defs[def].instances >= INSTANCES && !self.instances.contains_key(&key) {
+            key.1 = vec![Ty::Any; args.len()];
+        }
+        let tys = key.1.clone();
+        let (id, returns) = self.instance(key)?;
+        let mut values = vec![env];
+        for (arg, ty) in args.into_iter().zip(&tys) {
+            let arg = self.convert(fnbuilder, arg, ty)?;
+            values.push(arg.val);
+        }
+        let callee = self.module.declare_func_in_func(id, fnbuilder.builder.func);
+        let call = fnbuilder.builder.ins().call(callee, &values);
+        let raw = fnbuilder.builder.inst_results(call)[0];
+        let result = fnbuilder.unraw(raw, returns);
+        Ok(match &self.defs[def].returns {
+            Some(typename) => {
+                let typename = typename.clone();
+                self.check(fnbuilder, result, &typename)?
             }
-            ast::Node::FnCall(name, args) => {
-                let fu = self.fn_decls.get(name).unwrap();
+            None => result,
+        })
+    }
 
-                let fn_ref = self
-                    .module
-                    .declare_func_in_func(fu.id, &mut fnbuilder.builder.func);
-                let mut evaled_args: Vec<Value> = args
-                    .iter()
-                    .map(|arg| self.translate_wbuilder(fnbuilder, arg, debug))
-                    .collect();
+    /// Calls the `extern fn` at `function`, converting the arguments to the
+    /// types of its parameters like the interpreter does.
+    fn call_extern(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        function: usize,
+        args: Vec<Val>,
+    ) -> Compiled {
+        let params = self.externs[function].function.params.clone();
+        if args.len() != params.len() {
+            return Ok(self.fail(
+                fnbuilder,
+                &value::arity_mismatch(params.len(), args.len()),
+            ));
+        }
+        let mut values = Vec::new();
+        for (arg, param) in args.into_iter().zip(params) {
+            let arg = self.check(fnbuilder, arg, param.ty())?;
+            let ty = self.parse_ty(param.ty());
+            let arg = self.convert(fnbuilder, arg, &ty)?;
+            values.push(arg.val);
+        }
+        let (id, sig) = (self.externs[function].id, self.externs[function].sig.clone());
+        let result = self.call_decl(fnbuilder, id, &sig, &values);
+        Ok(match self.externs[function].function.returns {
+            Some(returns) => {
+                let ty = self.parse_ty(returns.ty());
+                fnbuilder.val(result, ty)
+            }
+            None => fnbuilder.unit(),
+        })
+    }
 
-                if name == "printf" {
-                    if evaled_args.len() > 10 {
-                        panic!("prinft takes max 10 args");
-                    }
-                    evaled_args.append(
-                        &mut (evaled_args.len()..10)
-                            .map(|_| fnbuilder.builder.ins().iconst(I64, 0))
-                            .collect::<Vec<Value>>(),
-                    );
+    /// The function that calls through values of type any call `closure`
+    /// with: it takes the value and a list of boxes of the arguments, and
+    /// returns a box of the result.
+    fn wrapper(&mut self, closure: &Rc<Closure>) -> Result<FuncId, String> {
+        if let Some(id) = self.wrappers.get(closure) {
+            return Ok(*id);
+        }
+        let mut sig = self.module.make_signature();
+        sig.params.extend([AbiParam::new(I64), AbiParam::new(I64)]);
+        sig.returns.push(AbiParam::new(I64));
+        let id = self
+            .module
+            .declare_anonymous_function(&sig)
+            .map_err(|e| e.to_string())?;
+        self.wrappers.insert(closure.clone(), id);
+        let arity = match closure.callee {
+            Callee::Def(def) => self.defs[def].params.len(),
+            Callee::Extern(function) => self.externs[function].function.params.len(),
+        };
+        let line = self.line;
+        let (func, _, _) = self.compile_function(&sig, line, &Ty::Any, false, &mut |c, fnbuilder| {
+            let (env, list) = (fnbuilder.params[0], fnbuilder.params[1]);
+            let mut args = Vec::new();
+            for i in 0..arity {
+                let i = fnbuilder.builder.ins().iconst(I64, i as i64);
+                let arg = c.call(fnbuilder, "alpha_any_arg", &[list, i]);
+                args.push(fnbuilder.val(arg, Ty::Any));
+            }
+            let env = match closure.is_static() {
+                true => fnbuilder.builder.ins().iconst(I64, 0),
+                false => env,
+            };
+            c.call_closure(fnbuilder, closure, env, args)
+        })?;
+        // Traces leave out what has an empty name.
+        self.finish(id, func, Some(""), None, line, &Ty::Any, Vec::new())?;
+        Ok(id)
+    }
+
+    /// What a value of `closure` is in memory: a list of the function that
+    /// calls through values of type any call and the value.
+    fn pair(&mut self, fnbuilder: &mut CustomFunctionBuilder, closure: &Rc<Closure>, env: Value) -> Compiled<Value> {
+        let wrapper = self.wrapper(closure)?;
+        if closure.is_static() {
+            let id = match self.pairs.get(closure) {
+                Some(id) => *id,
+                None => {
+                    let mut data = DataDescription::new();
+                    let mut bytes = vec![0; 24];
+                    bytes[0..8].copy_from_slice(&2i64.to_le_bytes());
+                    data.define(bytes.into());
+                    data.set_align(8);
+                    let function = self.module.declare_func_in_data(wrapper, &mut data);
+                    data.write_function_addr(8, function);
+                    let id = self.module.declare_anonymous_data(false, false).unwrap();
+                    self.module.define_data(id, &data).unwrap();
+                    self.pairs.insert(closure.clone(), id);
+                    id
                 }
+            };
+            return Ok(self.data_addr(fnbuilder, id));
+        }
+        let len = fnbuilder.builder.ins().iconst(I64, 2);
+        let pair = self.call(fnbuilder, "alpha_list_new", &[len]);
+        let callee = self.module.declare_func_in_func(wrapper, fnbuilder.builder.func);
+        let address = fnbuilder.builder.ins().func_addr(I64, callee);
+        fnbuilder
+            .builder
+            .ins()
+            .store(MemFlags::trusted(), address, pair, 8);
+        fnbuilder
+            .builder
+            .ins()
+            .store(MemFlags::trusted(), env, pair, 16);
+        Ok(pair)
+    }
+
+    /// Calls a function of the runtime or libc, returning 0 for functions
+    /// without a result.
+    fn call(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str, args: &[Value]) -> Value {
+        let import = &self.fn_decls[name];
+        let (id, sig, allocates) = (import.id, import.sig.clone(), import.allocates);
+        let result = self.call_decl(fnbuilder, id, &sig, args);
+        // What the runtime returns may be a new object, which is only safe
+        // to collect from once it is in the stack maps.
+        if allocates {
+            fnbuilder.builder.declare_value_needs_stack_map(result);
+            self.call(fnbuilder, "alpha_gc_safepoint", &[]);
+        }
+        result
+    }
 
-                let call = fnbuilder.builder.ins().call(fn_ref, &evaled_args);
-                fnbuilder.builder.inst_results(call)[0]
+    /// Calls the function `id` of signature `sig`, passing ints as the C
+    /// `int`s it may take and sign-extending the C `int` it may return.
+    fn call_decl(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        id: FuncId,
+        sig: &Signature,
+        args: &[Value],
+    ) -> Value {
+        let args: Vec<Value> = args
+            .iter()
+            .zip(&sig.params)
+            .map(|(&arg, param)| match param.value_type {
+                I32 => fnbuilder.builder.ins().ireduce(I32, arg),
+                _ => arg,
+            })
+            .collect();
+        let fn_ref = self.module.declare_func_in_func(id, fnbuilder.builder.func);
+        let declared = &self.module.declarations().get_function_decl(id).signature;
+        let call = match declared == sig {
+            true => fnbuilder.builder.ins().call(fn_ref, &args),
+            false => {
+                let address = fnbuilder.builder.ins().func_addr(I64, fn_ref);
+                let sig = fnbuilder.builder.import_signature(sig.clone());
+                fnbuilder.builder.ins().call_indirect(sig, address, &args)
             }
-            ast::Node::IfElse {
-                condition,
-                if_block: if_body,
-                else_block: else_body,
-            } => {
-                let condition_value = self.translate_wbuilder(fnbuilder, condition, debug);
+        };
+        let Some(&result) = fnbuilder.builder.inst_results(call).first() else {
+            return fnbuilder.builder.ins().iconst(I64, 0);
+        };
+        match fnbuilder.type_of(result) {
+            I32 => fnbuilder.builder.ins().sextend(I64, result),
+            _ => result,
+        }
+    }
 
-                let if_block = fnbuilder.builder.create_block();
-                let else_block = fnbuilder.builder.create_block();
-                let return_block = fnbuilder.builder.create_block();
-                fnbuilder.builder.append_block_param(return_block, I64);
+    /// Calls a function of the runtime that returns something of type `ty`.
+    fn call_ty(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        name: &str,
+        args: &[Value],
+        ty: Ty,
+    ) -> Val {
+        let result = self.call(fnbuilder, name, args);
+        fnbuilder.val(result, ty)
+    }
 
-                fnbuilder
+    /// The 8 bytes that memory holds `val` in, like the items of lists and
+    /// the values of boxes: a function is the list of what calls through
+    /// values of type any call and its value.
+    fn to_mem(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: &Val) -> Compiled<Value> {
+        match &val.ty {
+            Ty::Fn(closure) => self.pair(fnbuilder, closure, val.val),
+            _ => Ok(fnbuilder.raw(val)),
+        }
+    }
+
+    /// The value of type `ty` that memory holds in `item`.
+    fn from_mem(&mut self, fnbuilder: &mut CustomFunctionBuilder, item: Value, ty: Ty) -> Val {
+        match &ty {
+            Ty::Fn(closure) if closure.is_static() => fnbuilder.dummy(&ty),
+            Ty::Fn(_) => {
+                let env = fnbuilder
                     .builder
                     .ins()
-                    .brif(condition_value, if_block, &[], else_block, &[]);
+                    .load(I64, MemFlags::trusted(), item, 16);
+                fnbuilder.val(env, ty)
+            }
+            _ => fnbuilder.unraw(item, ty),
+        }
+    }
 
-                fnbuilder.builder.switch_to_block(if_block);
-                fnbuilder.builder.seal_block(if_block);
-                let if_return = self.translate_wbuilder(fnbuilder, &if_body, debug);
-                fnbuilder.builder.ins().jump(return_block, &[if_return]);
+    /// `val` and its description, for the runtime.
+    fn typed(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: &Val) -> Compiled<[Value; 2]> {
+        let mem = self.to_mem(fnbuilder, val)?;
+        let description = self.describe_value(fnbuilder, &val.ty);
+        Ok([mem, description])
+    }
 
-                fnbuilder.builder.switch_to_block(else_block);
-                fnbuilder.builder.seal_block(else_block);
-                let else_return = self.translate_wbuilder(fnbuilder, &else_body, debug);
-                fnbuilder.builder.ins().jump(return_block, &[else_return]);
+    /// Converts `val` to a value of type `to`, which it must be a value of
+    /// once types are settled: the same type, or one with any for a part of
+    /// it.
+    fn convert(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val, to: &Ty) -> Compiled {
+        if retypes(&val.ty, to) {
+            return Ok(match val.ty {
+                Ty::Never => fnbuilder.dummy(to),
+                _ => fnbuilder.val(val.val, to.clone()),
+            });
+        }
+        if matches!(to, Ty::Fn(_)) || matches!(val.ty, Ty::Fn(_)) && *to != Ty::Any {
+            // Only in translations that are done again.
+            return Ok(fnbuilder.dummy(to));
+        }
+        let [mem, from] = self.typed(fnbuilder, &val)?;
+        if *to == Ty::Any {
+            return Ok(self.call_ty(fnbuilder, "alpha_any_box", &[mem, from], Ty::Any));
+        }
+        let description = self.describe_value(fnbuilder, to);
+        let raw = self.call(fnbuilder, "alpha_any_convert", &[mem, from, description]);
+        Ok(self.from_mem(fnbuilder, raw, to.clone()))
+    }
 
-                fnbuilder.builder.switch_to_block(return_block);
-                fnbuilder.builder.seal_block(return_block);
-                fnbuilder.builder.block_params(return_block)[0]
+    /// Checks `val` against the type annotation `typename`, like
+    /// `Value::check_type`, widening ints annotated as bigint.
+    fn check(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val, typename: &str) -> Compiled {
+        match &val.ty {
+            Ty::Never => Ok(val),
+            Ty::Any => {
+                let name = self.string(fnbuilder, typename);
+                let checked = self.call_ty(fnbuilder, "alpha_any_check", &[val.val, name], Ty::Any);
+                let ty = self.parse_ty(typename);
+                self.convert(fnbuilder, checked, &ty)
             }
-            ast::Node::While { condition, inner } => {
-                let condition_block = fnbuilder.builder.create_block();
-                fnbuilder.builder.append_block_param(condition_block, I64);
+            Ty::Int if typename == "bigint" => {
+                Ok(self.call_ty(fnbuilder, "alpha_bigint_from_i64", &[val.val], Ty::BigInt))
+            }
+            ty if ty.name() == typename => Ok(val),
+            _ => {
+                let format = format!("Type mismatch: expected {}, found {{}}", typename);
+                self.raise(fnbuilder, &format, &val)
+            }
+        }
+    }
 
-                let inner_block = fnbuilder.builder.create_block();
+    /// Fails with `format`, in which `{:?}` shows `val` like Debug and `{}`
+    /// is the name of its type, like `alpha_raise`.
+    fn raise(&mut self, fnbuilder: &mut CustomFunctionBuilder, format: &str, val: &Val) -> Compiled {
+        let [mem, description] = self.typed(fnbuilder, val)?;
+        let format = self.string(fnbuilder, format);
+        self.call(fnbuilder, "alpha_raise", &[format, mem, description]);
+        Ok(fnbuilder.unreachable())
+    }
 
-                let return_block = fnbuilder.builder.create_block();
-                fnbuilder.builder.append_block_param(return_block, I64);
+    /// Fails with `message` when `condition` is set.
+    fn error_if(&mut self, fnbuilder: &mut CustomFunctionBuilder, condition: Value, message: &str) {
+        let error_block = fnbuilder.builder.create_block();
+        let continue_block = fnbuilder.builder.create_block();
+        fnbuilder.builder.set_cold_block(error_block);
+        fnbuilder
+            .builder
+            .ins()
+            .brif(condition, error_block, &[], continue_block, &[]);
 
-                let zero = self.translate_wbuilder(fnbuilder, &ast::Node::Nada, debug);
-                fnbuilder.builder.ins().jump(condition_block, &[zero]);
+        fnbuilder.builder.switch_to_block(error_block);
+        fnbuilder.builder.seal_block(error_block);
+        self.fail(fnbuilder, message);
 
-                fnbuilder.builder.switch_to_block(condition_block);
-                let condition_value = self.translate_wbuilder(fnbuilder, &condition, debug);
-                let return_value = fnbuilder.builder.block_params(condition_block)[0];
-                fnbuilder.builder.ins().brif(
-                    condition_value,
-                    inner_block,
-                    &[],
-                    return_block,
-                    &[return_value],
-                );
+        fnbuilder.builder.switch_to_block(continue_block);
+        fnbuilder.builder.seal_block(continue_block);
+    }
 
-                fnbuilder.builder.switch_to_block(inner_block);
-                fnbuilder.builder.seal_block(inner_block);
-                let inner_return = self.translate_wbuilder(fnbuilder, &inner, debug);
+    /// Prints `message` and exits with status 1, resulting in a value that
+    /// never is.
+    fn fail(&mut self, fnbuilder: &mut CustomFunctionBuilder, message: &str) -> Val {
+        let message = self.string(fnbuilder, message);
+        let status = fnbuilder.builder.ins().iconst(I64, 1);
+        self.call(fnbuilder, "alpha_panic", &[message, status]);
+        fnbuilder.unreachable()
+    }
+
+    /// The bool of a condition, failing for anything else like the
+    /// interpreter.
+    fn truth(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val) -> Compiled<Value> {
+        match val.ty {
+            Ty::Bool => Ok(val.val),
+            Ty::Never => Ok(fnbuilder.builder.ins().iconst(I8, 0)),
+            Ty::Any => {
+                let description = self.describe_value(fnbuilder, &Ty::Any);
+                let truth = self.call(fnbuilder, "alpha_any_truth", &[val.val, description]);
+                Ok(fnbuilder.builder.ins().ireduce(I8, truth))
+            }
+            _ => {
+                self.raise(fnbuilder, "Not a bool: '{:?}'", &val)?;
+                Ok(fnbuilder.builder.ins().iconst(I8, 0))
+            }
+        }
+    }
+
+    /// Binds `name` to `val`. A variable holds values of one type, so one
+    /// that cannot hold `val` is widened to a type that can, and the
+    /// function translated again.
+    fn bind(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        name: &str,
+        val: Val,
+        mutable: bool,
+    ) -> Compiled<()> {
+        let ty = match (fnbuilder.vars.get(name), fnbuilder.widened.get(name)) {
+            (Some(var), _) => var.ty.clone(),
+            (None, Some(widened)) => join(widened, &val.ty),
+            (None, None) => val.ty.clone(),
+        };
+        let joined = join(&ty, &val.ty);
+        if joined != ty {
+            fnbuilder.widened.insert(name.to_string(), joined);
+            return Err(Stop::Widen);
+        }
+        let val = self.convert(fnbuilder, val, &ty)?;
+        let var = match fnbuilder.vars.get_mut(name) {
+            Some(var) => {
+                var.mutable = mutable;
+                var.var
+            }
+            None => fnbuilder.new_var(name, ty, mutable, self.line),
+        };
+        fnbuilder.define(var, val.val);
+        Ok(())
+    }
+
+    /// Binds the names of `pattern` to the items of `val`, like
+    /// `Scope::bind_pattern`.
+    fn bind_pattern(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        pattern: &ast::Pattern,
+        val: Val,
+        mutable: bool,
+    ) -> Compiled<()> {
+        let patterns = match pattern {
+            ast::Pattern::Name(name) => return self.bind(fnbuilder, name, val, mutable),
+            ast::Pattern::Tuple(patterns) => patterns,
+        };
+        let items = self.unpack(fnbuilder, val, patterns.len())?;
+        for (pattern, item) in zip(patterns, items) {
+            self.bind_pattern(fnbuilder, pattern, item, mutable)?;
+        }
+        Ok(())
+    }
+
+    /// The `len` items of the tuple or list `val`, failing like
+    /// `Value::destructure` for anything else.
+    fn unpack(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val, len: usize) -> Compiled<Vec<Val>> {
+        let (list, tys) = match &val.ty {
+            Ty::Never => return Ok(vec![fnbuilder.dummy(&Ty::Never); len]),
+            Ty::Tuple(items) if items.len() == len => (val.val, items.clone()),
+            Ty::Tuple(items) => {
+                let message = value::arity_mismatch(len, items.len());
+                self.fail(fnbuilder, &message);
+                return Ok(vec![fnbuilder.dummy(&Ty::Never); len]);
+            }
+            Ty::List(item) => {
+                let n = fnbuilder.builder.ins().iconst(I64, len as i64);
+                self.call(fnbuilder, "alpha_list_unpack", &[val.val, n]);
+                (val.val, vec![*item.clone(); len])
+            }
+            _ => {
+                let [mem, description] = self.typed(fnbuilder, &val)?;
+                let n = fnbuilder.builder.ins().iconst(I64, len as i64);
+                let list = self.call(fnbuilder, "alpha_any_unpack", &[mem, description, n]);
+                (list, vec![Ty::Any; len])
+            }
+        };
+        let mut items = Vec::new();
+        for (i, ty) in tys.into_iter().enumerate() {
+            let item = fnbuilder
+                .builder
+                .ins()
+                .load(I64, MemFlags::trusted(), list, 8 + 8 * i as i32);
+            items.push(self.from_mem(fnbuilder, item, ty));
+        }
+        Ok(items)
+    }
+
+    /// `name = val`, for a variable bound with `let mut`.
+    fn assign(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str, val: Val) -> Compiled {
+        match fnbuilder.vars.get(name) {
+            Some(var) if var.mutable => {
+                self.bind(fnbuilder, name, val, true)?;
+                Ok(fnbuilder.unit())
+            }
+            Some(_) => Ok(self.fail(
+                fnbuilder,
+                &format!("Cannot assign to immutable variable: '{}'", name),
+            )),
+            None if fnbuilder.is_own(name) => Ok(self.fail(
+                fnbuilder,
+                &format!("Cannot assign to immutable variable: '{}'", name),
+            )),
+            None => Ok(self.fail(fnbuilder, &format!("Undefined variable: '{}'", name))),
+        }
+    }
+
+    /// The value of the variable `name`, or of the function being
+    /// translated if it is named so.
+    fn lookup(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str) -> Option<Val> {
+        if let Some(var) = fnbuilder.vars.get(name) {
+            let (var, ty) = (var.var, var.ty.clone());
+            let val = fnbuilder.builder.use_var(var);
+            return Some(fnbuilder.val(val, ty));
+        }
+        let (_, closure) = fnbuilder.own.clone().filter(|(own, _)| own == name)?;
+        Some(Val {
+            val: fnbuilder.params[0],
+            ty: Ty::Fn(closure),
+        })
+    }
+
+    /// Continues with the value of whichever branch ran, converting the
+    /// results of all branches to the type they join to.
+    fn join(&mut self, fnbuilder: &mut CustomFunctionBuilder, branches: Vec<(Block, Ty)>) -> Compiled {
+        let ty = branches
+            .iter()
+            .fold(Ty::Never, |joined, (_, ty)| join(&joined, ty));
+        let return_block = fnbuilder.builder.create_block();
+        fnbuilder
+            .builder
+            .append_block_param(return_block, ty.clif_type());
+        for (merge_block, branch_ty) in branches {
+            fnbuilder.builder.switch_to_block(merge_block);
+            fnbuilder.builder.seal_block(merge_block);
+            let val = fnbuilder.builder.block_params(merge_block)[0];
+            let val = fnbuilder.val(val, branch_ty);
+            let val = self.convert(fnbuilder, val, &ty)?;
+            fnbuilder.builder.ins().jump(return_block, &[val.val]);
+        }
+
+        fnbuilder.builder.switch_to_block(return_block);
+        fnbuilder.builder.seal_block(return_block);
+        let val = fnbuilder.builder.block_params(return_block)[0];
+        Ok(fnbuilder.val(val, ty))
+    }
+
+    /// Translates `node`, resulting in its value.
+    fn translate(&mut self, fnbuilder: &mut CustomFunctionBuilder, node: &Node) -> Compiled {
+        match node {
+            Node::Statements(statements) => {
+                let mut val = fnbuilder.unit();
+                for statement in statements {
+                    val = self.translate(fnbuilder, statement)?;
+                }
+                Ok(val)
+            }
+            Node::Line(line, node) => {
+                let outer = std::mem::replace(&mut self.line, *line);
+                fnbuilder.builder.set_srcloc(SourceLoc::new(*line as u32));
+                let val = self.translate(fnbuilder, node).map_err(|stop| match stop {
+                    Stop::Error(e) if !e.starts_with("line ") => {
+                        Stop::Error(format!("line {}: {}", line, e))
+                    }
+                    stop => stop,
+                });
+                self.line = outer;
+                fnbuilder.builder.set_srcloc(SourceLoc::new(outer as u32));
+                val
+            }
+            Node::Nada
+            | Node::TypeName(_)
+            | Node::StructDef(..)
+            | Node::EnumDef(..)
+            | Node::Import(..) => Ok(fnbuilder.unit()),
+            Node::Pub(node) => self.translate(fnbuilder, node),
+            Node::Int(n) => {
+                let n = fnbuilder.builder.ins().iconst(I64, *n);
+                Ok(fnbuilder.val(n, Ty::Int))
+            }
+            Node::Float(n) => {
+                let n = fnbuilder.builder.ins().f64const(*n);
+                Ok(fnbuilder.val(n, Ty::Float))
+            }
+            Node::Bool(b) => {
+                let b = fnbuilder.builder.ins().iconst(I8, *b as i64);
+                Ok(fnbuilder.val(b, Ty::Bool))
+            }
+            Node::String(s) => {
+                let id = self.define_data(s.as_bytes().to_vec());
+                let s = self.data_addr(fnbuilder, id);
+                Ok(fnbuilder.val(s, Ty::Str))
+            }
+            Node::BigInt(n) => {
+                let digits = self.string(fnbuilder, &n.to_string());
+                Ok(self.call_ty(fnbuilder, "alpha_bigint_parse", &[digits], Ty::BigInt))
+            }
+            Node::FnDef(name, _, body, _) => {
+                let def = self.def_index[&Rc::as_ptr(body)];
+                let mut captures = Vec::new();
+                let mut vals = Vec::new();
+                for free in self.defs[def].free.clone() {
+                    let Some(val) = self.lookup(fnbuilder, &free) else {
+                        continue;
+                    };
+                    let mutable = fnbuilder.vars.get(&free).is_some_and(|var| var.mutable);
+                    captures.push((free, val.ty.clone(), mutable));
+                    vals.push(val);
+                }
+                let closure = Rc::new(Closure {
+                    callee: Callee::Def(def),
+                    captures,
+                });
+                let ty = Ty::Fn(closure.clone());
+                let val = match closure.is_static() {
+                    true => fnbuilder.dummy(&ty),
+                    false => {
+                        let len = fnbuilder.builder.ins().iconst(I64, vals.len() as i64);
+                        let env = self.call(fnbuilder, "alpha_list_new", &[len]);
+                        for (i, val) in vals.iter().enumerate() {
+                            let raw = fnbuilder.raw(val);
+                            fnbuilder.builder.ins().store(
+                                MemFlags::trusted(),
+                                raw,
+                                env,
+                                8 + 8 * i as i32,
+                            );
+                        }
+                        fnbuilder.val(env, ty)
+                    }
+                };
+                if let Some(name) = name {
+                    self.bind(fnbuilder, name, val.clone(), false)?;
+                }
+                Ok(val)
+            }
+            Node::Extern(name, ..) => {
+                let function = self.extern_index[&(node as *const Node)];
+                let closure = Rc::new(Closure {
+                    callee: Callee::Extern(function),
+                    captures: Vec::new(),
+                });
+                let val = fnbuilder.dummy(&Ty::Fn(closure));
+                self.bind(fnbuilder, name, val, false)?;
+                Ok(fnbuilder.unit())
+            }
+            Node::FnCall(name, args) => self.translate_call(fnbuilder, name, args),
+            Node::VarRef(name) => {
+                if let Some(val) = self.lookup(fnbuilder, name) {
+                    return Ok(val);
+                }
+                if self.variants.contains_key(name) {
+                    return self.construct(fnbuilder, name, Vec::new());
+                }
+                match math::constant(name) {
+                    Some(value::Value::Float(n)) => {
+                        let n = fnbuilder.builder.ins().f64const(n);
+                        Ok(fnbuilder.val(n, Ty::Float))
+                    }
+                    _ => Ok(self.fail(fnbuilder, &format!("Undefined variable: '{}'", name))),
+                }
+            }
+            Node::Define(mutable, pattern, expr, typename) => {
+                let mut val = self.translate(fnbuilder, expr)?;
+                if let Some(typename) = typename {
+                    val = self.check(fnbuilder, val, typename)?;
+                }
+                let mutable = matches!(mutable, ast::Mut::Mutable);
+                self.bind_pattern(fnbuilder, pattern, val, mutable)?;
+                Ok(fnbuilder.unit())
+            }
+            Node::Assign(name, expr) => {
+                let val = self.translate(fnbuilder, expr)?;
+                self.assign(fnbuilder, name, val)
+            }
+            Node::IndexAssign(name, steps, expr) => {
+                let mut translated = Vec::new();
+                for step in steps {
+                    translated.push(match step {
+                        ast::Step::Index(index) => ast::Step::Index(self.translate(fnbuilder, index)?),
+                        ast::Step::Field(field) => ast::Step::Field(field.clone()),
+                    });
+                }
+                let val = self.translate(fnbuilder, expr)?;
+                self.set_index(fnbuilder, name, translated, val)
+            }
+            Node::Index(container, index) => {
+                let container = self.translate(fnbuilder, container)?;
+                let index = self.translate(fnbuilder, index)?;
+                self.index(fnbuilder, container, index)
+            }
+            Node::Slice { list, from, to } => {
+                let list = self.translate(fnbuilder, list)?;
+                let mut bounds = Vec::new();
+                for bound in [from, to] {
+                    bounds.push(match bound {
+                        Some(bound) => Some(self.translate(fnbuilder, bound)?),
+                        None => None,
+                    });
+                }
+                self.slice(fnbuilder, list, bounds)
+            }
+            Node::Field(record, field) => {
+                let record = self.translate(fnbuilder, record)?;
+                self.field(fnbuilder, record, field)
+            }
+            Node::Range {
+                from,
+                to,
+                lower,
+                upper,
+            } => {
+                let from = self.translate(fnbuilder, from)?;
+                let to = self.translate(fnbuilder, to)?;
+                let lower = fnbuilder
+                    .builder
+                    .ins()
+                    .iconst(I64, matches!(lower, ast::Bound::Exclusive) as i64);
+                let upper = fnbuilder
+                    .builder
+                    .ins()
+                    .iconst(I64, matches!(upper, ast::Bound::Exclusive) as i64);
+                if from.ty == Ty::Int && to.ty == Ty::Int {
+                    let len = fnbuilder.builder.ins().iconst(I64, 4);
+                    let range = self.call(fnbuilder, "alpha_list_new", &[len]);
+                    for (i, part) in [from.val, to.val, lower, upper].into_iter().enumerate() {
+                        fnbuilder.builder.ins().store(
+                            MemFlags::trusted(),
+                            part,
+                            range,
+                            8 + 8 * i as i32,
+                        );
+                    }
+                    return Ok(fnbuilder.val(range, Ty::Range));
+                }
+                let [from, from_description] = self.typed(fnbuilder, &from)?;
+                let [to, to_description] = self.typed(fnbuilder, &to)?;
+                let args = [from, from_description, to, to_description, lower, upper];
+                Ok(self.call_ty(fnbuilder, "alpha_any_range", &args, Ty::Range))
+            }
+            Node::List(items) => {
+                let mut vals = Vec::new();
+                for item in items {
+                    vals.push(self.translate(fnbuilder, item)?);
+                }
+                let ty = vals.iter().fold(Ty::Never, |ty, val| join(&ty, &val.ty));
+                let tys = vec![ty.clone(); vals.len()];
+                let list = self.store_items(fnbuilder, vals, &tys)?;
+                Ok(fnbuilder.val(list, Ty::List(Box::new(ty))))
+            }
+            Node::Tuple(items) => {
+                let mut vals = Vec::new();
+                for item in items {
+                    vals.push(self.translate(fnbuilder, item)?);
+                }
+                let tys: Vec<Ty> = vals.iter().map(|val| val.ty.clone()).collect();
+                let tuple = self.store_items(fnbuilder, vals, &tys)?;
+                Ok(fnbuilder.val(tuple, Ty::Tuple(tys)))
+            }
+            Node::Map(pairs) => {
+                let mut vals = Vec::new();
+                for (key, value) in pairs {
+                    let key = self.translate(fnbuilder, key)?;
+                    vals.push((key, self.translate(fnbuilder, value)?));
+                }
+                let key_ty = vals.iter().fold(Ty::Never, |ty, (key, _)| join(&ty, &key.ty));
+                let ty = vals.iter().fold(Ty::Never, |ty, (_, value)| join(&ty, &value.ty));
+                let description = self.describe_value(fnbuilder, &key_ty);
+                let map = self.call(fnbuilder, "alpha_map_new", &[description]);
+                let map = fnbuilder.val(map, Ty::Map(Box::new(key_ty.clone()), Box::new(ty.clone())));
+                for (key, value) in vals {
+                    let key = self.convert(fnbuilder, key, &key_ty)?;
+                    let key = self.to_mem(fnbuilder, &key)?;
+                    let value = self.convert(fnbuilder, value, &ty)?;
+                    let value = self.to_mem(fnbuilder, &value)?;
+                    self.call(fnbuilder, "alpha_map_put", &[map.val, key, value]);
+                }
+                Ok(map)
+            }
+            Node::StructLit(name, fields) => {
+                let Some(struct_ty) = self.structs.get(name) else {
+                    return Ok(self.fail(fnbuilder, &format!("Undefined struct: '{}'", name)));
+                };
+                let typenames: Vec<String> = struct_ty
+                    .fields
+                    .iter()
+                    .map(|(_, typename)| typename.clone())
+                    .collect();
+                let values: Vec<Node> = match struct_ty.order(fields) {
+                    Ok(values) => values.into_iter().cloned().collect(),
+                    Err(e) => return Ok(self.fail(fnbuilder, &e)),
+                };
+                let mut vals = Vec::new();
+                let mut tys = Vec::new();
+                for (value, typename) in zip(&values, &typenames) {
+                    let val = self.translate(fnbuilder, value)?;
+                    vals.push(self.check(fnbuilder, val, typename)?);
+                    tys.push(self.parse_ty(typename));
+                }
+                let record = self.store_items(fnbuilder, vals, &tys)?;
+                Ok(fnbuilder.val(record, Ty::Struct(name.clone())))
+            }
+            Node::Expr { op, lhs, rhs } => {
+                let lhs = self.translate(fnbuilder, lhs)?;
+                let rhs = self.translate(fnbuilder, rhs)?;
+                self.binary(fnbuilder, op, lhs, rhs)
+            }
+            Node::IfElse {
+                condition,
+                if_block: if_body,
+                else_block: else_body,
+            } => {
+                let condition = self.translate(fnbuilder, condition)?;
+                let condition = self.truth(fnbuilder, condition)?;
+                let if_block = fnbuilder.builder.create_block();
+                let else_block = fnbuilder.builder.create_block();
                 fnbuilder
                     .builder
                     .ins()
-                    .jump(condition_block, &[inner_return]);
+                    .brif(condition, if_block, &[], else_block, &[]);
 
+                // The branches are converted to the type they join to, which
+                // is only known once both have been translated, so each
+                // passes its result through a block of its own.
+                let mut branches = Vec::new();
+                for (block, body) in [(if_block, if_body), (else_block, else_body)] {
+                    fnbuilder.builder.switch_to_block(block);
+                    fnbuilder.builder.seal_block(block);
+                    let val = self.translate(fnbuilder, body)?;
+                    branches.push(fnbuilder.branch(val));
+                }
+                self.join(fnbuilder, branches)
+            }
+            Node::While { condition, inner } => {
+                // What the last run of the body results in, held by a
+                // variable that has no name in the program.
+                let result = format!("{}.while", node as *const Node as usize);
+                let unit = fnbuilder.unit();
+                self.bind(fnbuilder, &result, unit, true)?;
+                let condition_block = fnbuilder.builder.create_block();
+                let inner_block = fnbuilder.builder.create_block();
+                let return_block = fnbuilder.builder.create_block();
+                fnbuilder.builder.ins().jump(condition_block, &[]);
+
+                fnbuilder.builder.switch_to_block(condition_block);
+                let condition = self.translate(fnbuilder, condition)?;
+                let condition = self.truth(fnbuilder, condition)?;
+                fnbuilder
+                    .builder
+                    .ins()
+                    .brif(condition, inner_block, &[], return_block, &[]);
+
+                fnbuilder.builder.switch_to_block(inner_block);
+                fnbuilder.builder.seal_block(inner_block);
+                let val = self.translate(fnbuilder, inner)?;
+                self.bind(fnbuilder, &result, val, true)?;
+                fnbuilder.builder.ins().jump(condition_block, &[]);
                 fnbuilder.builder.seal_block(condition_block);
 
                 fnbuilder.builder.switch_to_block(return_block);
                 fnbuilder.builder.seal_block(return_block);
-                fnbuilder.builder.block_params(return_block)[0]
+                Ok(self.lookup(fnbuilder, &result).unwrap())
             }
-            ast::Node::Define(_mut, name, expr, _typename) => {
-                let var = fnbuilder.new_var(name);
-                fnbuilder.builder.declare_var(var, I64);
-                let val = self.translate_wbuilder(fnbuilder, expr, debug);
-                fnbuilder.builder.def_var(var, val);
-                val
+            Node::Match { subject, arms } => {
+                let subject = self.translate(fnbuilder, subject)?;
+                self.translate_match(fnbuilder, subject, arms)
             }
-            ast::Node::Assign(name, expr) => {
-                let var = *fnbuilder.variables.get(name).unwrap();
-                let val = self.translate_wbuilder(fnbuilder, expr, debug);
-                fnbuilder.builder.def_var(var, val);
-                val
+            Node::Try(value) => {
+                let val = self.translate(fnbuilder, value)?;
+                let (option, payload_ty) = self.option_of(fnbuilder, val)?;
+                let tag = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), option.val, 8);
+                let is_some = fnbuilder
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::Equal, tag, value::SOME as i64);
+                let return_block = fnbuilder.builder.create_block();
+                fnbuilder.expect(is_some, return_block);
+                let next = fnbuilder.builder.current_block().unwrap();
+
+                // A `none` is returned as is, like the interpreter does.
+                fnbuilder.builder.switch_to_block(return_block);
+                fnbuilder.builder.seal_block(return_block);
+                self.ret(fnbuilder, option.clone())?;
+
+                fnbuilder.builder.switch_to_block(next);
+                let payload = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), option.val, 16);
+                Ok(self.from_mem(fnbuilder, payload, payload_ty))
+            }
+            Node::Throw(value) => {
+                let val = self.translate(fnbuilder, value)?;
+                self.raise(fnbuilder, "Uncaught {:?}", &val)
+            }
+            Node::TryCatch { .. } => {
+                Err("try and catch are not supported when compiled".to_string().into())
             }
-            ast::Node::VarRef(name) => {
-                let var = fnbuilder.variables.get(name).unwrap();
-                fnbuilder.builder.use_var(*var)
-            }
-            ast::Node::Expr { op, lhs, rhs } => {
-                let lhs = self.translate_wbuilder(fnbuilder, lhs, debug);
-                let rhs = self.translate_wbuilder(fnbuilder, rhs, debug);
-                match op {
-                    ast::Op::Add => fnbuilder.builder.ins().iadd(lhs, rhs),
-                    ast::Op::Sub => fnbuilder.builder.ins().isub(lhs, rhs),
-                    ast::Op::Mul => fnbuilder.builder.ins().imul(lhs, rhs),
-                    ast::Op::Div => todo!(),
-                    ast::Op::Eq => fnbuilder.builder.ins().icmp(IntCC::Equal, lhs, rhs),
-                    ast::Op::Neq => fnbuilder.builder.ins().icmp(IntCC::NotEqual, lhs, rhs),
-                    ast::Op::Gt => fnbuilder
+            Node::Loop { .. } => Err("for loops are not supported when compiled".to_string().into()),
+        }
+    }
+
+    /// A new list of `vals`, converted to `tys`.
+    fn store_items(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        vals: Vec<Val>,
+        tys: &[Ty],
+    ) -> Compiled<Value> {
+        let mut items = Vec::new();
+        for (val, ty) in zip(vals, tys) {
+            let val = self.convert(fnbuilder, val, ty)?;
+            items.push(self.to_mem(fnbuilder, &val)?);
+        }
+        let len = fnbuilder.builder.ins().iconst(I64, items.len() as i64);
+        let list = self.call(fnbuilder, "alpha_list_new", &[len]);
+        for (i, item) in items.into_iter().enumerate() {
+            fnbuilder
+                .builder
+                .ins()
+                .store(MemFlags::trusted(), item, list, 8 + 8 * i as i32);
+        }
+        Ok(list)
+    }
+
+    /// Calls the function `name` with `args`, looking it up like the
+    /// interpreter: the builtins that nothing shadows, then variables and
+    /// the function being translated, then the variants of enums.
+    fn translate_call(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        name: &str,
+        nodes: &[Node],
+    ) -> Compiled {
+        let mut args = Vec::new();
+        for node in nodes {
+            args.push(self.translate(fnbuilder, node)?);
+        }
+        let bound = fnbuilder.is_bound(name);
+        match name {
+            "print" => return self.print(fnbuilder, 1, args),
+            "printf" | "eprint" => return self.printf(fnbuilder, name, nodes, args),
+            "backtrace" if !args.is_empty() => {
+                return Ok(self.fail(fnbuilder, "backtrace takes no arguments"))
+            }
+            "backtrace" => {
+                self.call(fnbuilder, "alpha_backtrace", &[]);
+                return Ok(fnbuilder.unit());
+            }
+            "int" | "bigint" | "float" | "keys" | "values" if args.len() != 1 => {
+                return Ok(self.fail(fnbuilder, &format!("{} takes 1 argument", name)))
+            }
+            "has" | "remove" | "unwrap_or" if args.len() != 2 => {
+                return Ok(self.fail(fnbuilder, &format!("{} takes 2 arguments", name)))
+            }
+            "int" | "bigint" | "float" | "keys" | "values" | "has" | "remove" | "unwrap_or" => {
+                return self.value_builtin(fnbuilder, name, args)
+            }
+            _ if bound => {}
+            _ if lists::arity(name).is_some() => return self.list_builtin(fnbuilder, name, args),
+            _ if math::arity(name).is_some() => return self.math_builtin(fnbuilder, name, args),
+            _ if io::arity(name).is_some() => return self.io_builtin(fnbuilder, name, args),
+            _ if process::arity(name).is_some() => {
+                return self.process_builtin(fnbuilder, name, args)
+            }
+            _ => {}
+        }
+        if let Some(function) = self.lookup(fnbuilder, name) {
+            return self.call_value(fnbuilder, function, args);
+        }
+        if self.variants.contains_key(name) {
+            return self.construct(fnbuilder, name, args);
+        }
+        Ok(self.fail(fnbuilder, &format!("Undefined function: '{}'", name)))
+    }
+
+    /// Calls the function value `function` with `args`: statically for the
+    /// closures of a known function, and through the function that values
+    /// of type any hold for anything else.
+    fn call_value(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        function: Val,
+        args: Vec<Val>,
+    ) -> Compiled {
+        match &function.ty {
+            Ty::Fn(closure) => {
+                let closure = closure.clone();
+                self.call_closure(fnbuilder, &closure, function.val, args)
+            }
+            Ty::Never => Ok(fnbuilder.dummy(&Ty::Never)),
+            Ty::Any => {
+                let description = self.describe_value(fnbuilder, &Ty::Any);
+                let pair = self.call(fnbuilder, "alpha_any_function", &[function.val, description]);
+                let tys = vec![Ty::Any; args.len()];
+                let list = self.store_items(fnbuilder, args, &tys)?;
+                let wrapper = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), pair, 8);
+                let env = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), pair, 16);
+                let mut sig = self.module.make_signature();
+                sig.params.extend([AbiParam::new(I64), AbiParam::new(I64)]);
+                sig.returns.push(AbiParam::new(I64));
+                let sig = fnbuilder.builder.import_signature(sig);
+                let call = fnbuilder
+                    .builder
+                    .ins()
+                    .call_indirect(sig, wrapper, &[env, list]);
+                let result = fnbuilder.builder.inst_results(call)[0];
+                fnbuilder.builder.declare_value_needs_stack_map(result);
+                self.call(fnbuilder, "alpha_gc_safepoint", &[]);
+                Ok(fnbuilder.val(result, Ty::Any))
+            }
+            _ => self.raise(fnbuilder, "Not a function: '{:?}'", &function),
+        }
+    }
+
+    /// Builds the variant `name` with the payload `args`, which stores its
+    /// tag before the payload.
+    fn construct(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str, args: Vec<Val>) -> Compiled {
+        let tys = self.payload_tys(name);
+        if args.len() != tys.len() {
+            let message = format!("{} takes {} arguments", name, tys.len());
+            return Ok(self.fail(fnbuilder, &message));
+        }
+        let (enum_name, tag) = self.variants[name].clone();
+        if enum_name == value::OPTION {
+            return self.construct_option(fnbuilder, args.into_iter().next());
+        }
+        let fields = self.variant_fields(name).unwrap().to_vec();
+        let mut items = Vec::new();
+        for (arg, (_, typename)) in zip(args, &fields) {
+            items.push(match typename {
+                Some(typename) => self.check(fnbuilder, arg, typename)?,
+                None => arg,
+            });
+        }
+        let tag = fnbuilder.builder.ins().iconst(I64, tag as i64);
+        let tag = fnbuilder.val(tag, Ty::Int);
+        let variant = self.store_items(
+            fnbuilder,
+            std::iter::once(tag).chain(items).collect(),
+            &[&[Ty::Int], tys.as_slice()].concat(),
+        )?;
+        Ok(fnbuilder.val(variant, Ty::Enum(enum_name)))
+    }
+
+    /// Builds `some(arg)`, whose payload keeps the type of `arg`, or `none`.
+    fn construct_option(&mut self, fnbuilder: &mut CustomFunctionBuilder, arg: Option<Val>) -> Compiled {
+        let (tag, payload_ty) = match &arg {
+            Some(arg) => (value::SOME, arg.ty.clone()),
+            None => (value::NONE, Ty::Never),
+        };
+        let tag = fnbuilder.builder.ins().iconst(I64, tag as i64);
+        let tag = fnbuilder.val(tag, Ty::Int);
+        let items: Vec<Val> = std::iter::once(tag).chain(arg).collect();
+        let tys: Vec<Ty> = items.iter().map(|item| item.ty.clone()).collect();
+        let option = self.store_items(fnbuilder, items, &tys)?;
+        Ok(fnbuilder.val(option, Ty::Option(Box::new(payload_ty))))
+    }
+
+    /// The option that `val` is, with the type of its payload, failing like
+    /// `Value::option` for anything else.
+    fn option_of(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val) -> Compiled<(Val, Ty)> {
+        match &val.ty {
+            Ty::Option(payload) => {
+                let payload = *payload.clone();
+                Ok((val, payload))
+            }
+            Ty::Never => Ok((val, Ty::Never)),
+            Ty::Any => {
+                let description = self.describe_value(fnbuilder, &Ty::Any);
+                let option = self.call(fnbuilder, "alpha_any_option", &[val.val, description]);
+                let ty = Ty::Option(Box::new(Ty::Any));
+                Ok((fnbuilder.val(option, ty), Ty::Any))
+            }
+            _ => {
+                let option = self.raise(fnbuilder, "Not an option: '{:?}'", &val)?;
+                Ok((option, Ty::Never))
+            }
+        }
+    }
+
+    /// Tests the arms of a `match` in turn, like the interpreter.
+    fn translate_match(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        subject: Val,
+        arms: &[ast::Arm],
+    ) -> Compiled {
+        let patterns = self.resolve_arms(arms)?;
+        let mut branches = Vec::new();
+        for (arm, pattern) in zip(arms, &patterns) {
+            let next = fnbuilder.builder.create_block();
+            self.test_pattern(fnbuilder, pattern, subject.clone(), next)?;
+            if let Some(guard) = &arm.guard {
+                let guard = self.translate(fnbuilder, guard)?;
+                let guard = self.truth(fnbuilder, guard)?;
+                fnbuilder.expect(guard, next);
+            }
+            let val = self.translate(fnbuilder, &arm.body)?;
+            branches.push(fnbuilder.branch(val));
+            fnbuilder.builder.switch_to_block(next);
+            fnbuilder.builder.seal_block(next);
+        }
+        self.raise(fnbuilder, "No arm matches {:?}", &subject)?;
+        self.join(fnbuilder, branches)
+    }
+
+    /// Branches to `next` unless `val` matches `pattern`, and binds the
+    /// names of the pattern otherwise, like `matching::matches`. What cannot
+    /// match because of its type never does.
+    fn test_pattern(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        pattern: &MatchPattern,
+        val: Val,
+        next: Block,
+    ) -> Compiled<()> {
+        if let (Ty::Any, MatchPattern::Tuple(_) | MatchPattern::List(..) | MatchPattern::Variant(..) | MatchPattern::Range { .. }) = (&val.ty, pattern) {
+            return self.test_any_pattern(fnbuilder, pattern, val, next);
+        }
+        let matched = match (pattern, &val.ty) {
+            (MatchPattern::Wildcard, _) => return Ok(()),
+            (MatchPattern::Name(name), _) => return self.bind(fnbuilder, name, val, false),
+            (MatchPattern::Int(_) | MatchPattern::Float(_) | MatchPattern::Bool(_) | MatchPattern::String(_), Ty::Any) => {
+                let literal = match pattern {
+                    MatchPattern::Int(n) => Node::Int(*n),
+                    MatchPattern::Float(n) => Node::Float(*n),
+                    MatchPattern::Bool(b) => Node::Bool(*b),
+                    MatchPattern::String(s) => Node::String(s.clone()),
+                    _ => unreachable!(),
+                };
+                let literal = self.translate(fnbuilder, &literal)?;
+                let [literal, literal_description] = self.typed(fnbuilder, &literal)?;
+                let description = self.describe_value(fnbuilder, &Ty::Any);
+                let args = [val.val, description, literal, literal_description];
+                let matched = self.call(fnbuilder, "alpha_any_matches", &args);
+                fnbuilder.builder.ins().icmp_imm(IntCC::NotEqual, matched, 0)
+            }
+            (MatchPattern::Int(n), Ty::Int) => fnbuilder.builder.ins().icmp_imm(IntCC::Equal, val.val, *n),
+            (MatchPattern::Int(n), Ty::BigInt) => {
+                let n = fnbuilder.builder.ins().iconst(I64, *n);
+                let n = self.call(fnbuilder, "alpha_bigint_from_i64", &[n]);
+                let cmp = self.call(fnbuilder, "alpha_bigint_cmp", &[val.val, n]);
+                fnbuilder.builder.ins().icmp_imm(IntCC::Equal, cmp, 0)
+            }
+            (MatchPattern::Float(n), Ty::Float) => {
+                let n = fnbuilder.builder.ins().f64const(*n);
+                fnbuilder.builder.ins().fcmp(FloatCC::Equal, val.val, n)
+            }
+            (MatchPattern::Bool(b), Ty::Bool) => {
+                fnbuilder.builder.ins().icmp_imm(IntCC::Equal, val.val, *b as i64)
+            }
+            (MatchPattern::String(s), Ty::Str) => {
+                let s = self.string(fnbuilder, s.trim_end_matches('\0'));
+                let cmp = self.call(fnbuilder, "strcmp", &[val.val, s]);
+                fnbuilder.builder.ins().icmp_imm(IntCC::Equal, cmp, 0)
+            }
+            (
+                MatchPattern::Range {
+                    from,
+                    to,
+                    lower,
+                    upper,
+                },
+                Ty::Int,
+            ) => {
+                let range = value::Range {
+                    from: *from,
+                    to: *to,
+                    lower: *lower,
+                    upper: *upper,
+                };
+                let (start, end) = range.bounds();
+                let above = fnbuilder
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedGreaterThanOrEqual, val.val, start);
+                let below = fnbuilder
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::SignedLessThan, val.val, end);
+                fnbuilder.builder.ins().band(above, below)
+            }
+            (MatchPattern::Tuple(items), Ty::Tuple(tys)) if items.len() == tys.len() => {
+                for (i, (item, ty)) in zip(items, tys.clone()).enumerate() {
+                    let raw = fnbuilder
                         .builder
                         .ins()
-                        .icmp(IntCC::SignedGreaterThan, lhs, rhs),
-                    ast::Op::Ge => {
-                        fnbuilder
-                            .builder
-                            .ins()
-                            .icmp(IntCC::SignedGreaterThanOrEqual, lhs, rhs)
-                    }
-                    ast::Op::Lt => fnbuilder
+                        .load(I64, MemFlags::trusted(), val.val, 8 + 8 * i as i32);
+                    let item_val = self.from_mem(fnbuilder, raw, ty);
+                    self.test_pattern(fnbuilder, item, item_val, next)?;
+                }
+                return Ok(());
+            }
+            (MatchPattern::List(items, rest), Ty::List(item_ty)) => {
+                let item_ty = *item_ty.clone();
+                let len = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), val.val, 0);
+                let n = items.len() as i64;
+                let fits = match rest {
+                    Some(_) => fnbuilder
                         .builder
                         .ins()
-                        .icmp(IntCC::SignedLessThan, lhs, rhs),
-                    ast::Op::Le => {
-                        fnbuilder
-                            .builder
-                            .ins()
-                            .icmp(IntCC::SignedLessThanOrEqual, lhs, rhs)
-                    }
+                        .icmp_imm(IntCC::SignedGreaterThanOrEqual, len, n),
+                    None => fnbuilder.builder.ins().icmp_imm(IntCC::Equal, len, n),
+                };
+                fnbuilder.expect(fits, next);
+                for (i, item) in items.iter().enumerate() {
+                    let raw = fnbuilder
+                        .builder
+                        .ins()
+                        .load(I64, MemFlags::trusted(), val.val, 8 + 8 * i as i32);
+                    let item_val = self.from_mem(fnbuilder, raw, item_ty.clone());
+                    self.test_pattern(fnbuilder, item, item_val, next)?;
+                }
+                if let Some(Some(name)) = rest {
+                    let from = fnbuilder.builder.ins().iconst(I64, n);
+                    let to = fnbuilder.builder.ins().iconst(I64, 0);
+                    let bounds = fnbuilder.builder.ins().iconst(I64, SLICE_FROM);
+                    let args = [val.val, from, to, bounds];
+                    let slice = self.call_ty(fnbuilder, "alpha_list_slice", &args, val.ty.clone());
+                    self.bind(fnbuilder, name, slice, false)?;
                 }
+                return Ok(());
             }
-            ast::Node::String(str) => {
-                let mut data = DataDescription::new();
-                data.define(str.as_bytes().to_vec().into_boxed_slice());
-                let id = self.module.declare_anonymous_data(false, false).unwrap();
-                self.module.define_data(id, &data).unwrap();
-                let ptr = self
-                    .module
-                    .declare_data_in_func(id, &mut fnbuilder.builder.func);
+            (MatchPattern::Variant(name, items), Ty::Enum(_) | Ty::Option(_))
+                if Some(val.ty.name()) == self.variants.get(name).map(|(e, _)| e.as_str()) =>
+            {
+                let tag = self.variants[name].1;
+                let tys = match &val.ty {
+                    Ty::Option(payload) => vec![*payload.clone(); items.len()],
+                    _ => self.payload_tys(name),
+                };
+                let found = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), val.val, 8);
+                let matched = fnbuilder
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::Equal, found, tag as i64);
+                fnbuilder.expect(matched, next);
+                for (i, (item, ty)) in zip(items, tys).enumerate() {
+                    let raw = fnbuilder
+                        .builder
+                        .ins()
+                        .load(I64, MemFlags::trusted(), val.val, 16 + 8 * i as i32);
+                    let item_val = self.from_mem(fnbuilder, raw, ty);
+                    self.test_pattern(fnbuilder, item, item_val, next)?;
+                }
+                return Ok(());
+            }
+            _ => {
+                // Continues in a block that nothing reaches, as the arm is
+                // still translated for its bindings.
+                fnbuilder.builder.ins().jump(next, &[]);
+                let dead = fnbuilder.builder.create_block();
+                fnbuilder.builder.switch_to_block(dead);
+                fnbuilder.builder.seal_block(dead);
+                return Ok(());
+            }
+        };
+        fnbuilder.expect(matched, next);
+        Ok(())
+    }
+
+    /// Tests a value of type any against a pattern of a container or range,
+    /// converting it to the type the pattern takes apart once its kind is
+    /// known.
+    fn test_any_pattern(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        pattern: &MatchPattern,
+        val: Val,
+        next: Block,
+    ) -> Compiled<()> {
+        let description = self.describe_value(fnbuilder, &Ty::Any);
+        let (is, ty) = match pattern {
+            MatchPattern::Tuple(items) => {
+                let kind = self.string(fnbuilder, &format!("t{},", items.len()));
+                let is = self.call(fnbuilder, "alpha_any_is", &[val.val, description, kind]);
+                (is, Ty::Tuple(vec![Ty::Any; items.len()]))
+            }
+            MatchPattern::List(..) => {
+                let kind = self.string(fnbuilder, "l");
+                let is = self.call(fnbuilder, "alpha_any_is", &[val.val, description, kind]);
+                (is, Ty::List(Box::new(Ty::Any)))
+            }
+            MatchPattern::Range { .. } => {
+                let kind = self.string(fnbuilder, "i");
+                let is = self.call(fnbuilder, "alpha_any_is", &[val.val, description, kind]);
+                (is, Ty::Int)
+            }
+            MatchPattern::Variant(name, _) => {
+                let variant = self.string(fnbuilder, name);
+                let args = [val.val, description, variant];
+                let is = self.call(fnbuilder, "alpha_any_variant", &args);
+                let ty = match self.variants[name].0.as_str() {
+                    value::OPTION => Ty::Option(Box::new(Ty::Any)),
+                    enum_name => Ty::Enum(enum_name.to_string()),
+                };
+                (is, ty)
+            }
+            _ => unreachable!("literals are matched by value"),
+        };
+        let is = fnbuilder.builder.ins().icmp_imm(IntCC::NotEqual, is, 0);
+        fnbuilder.expect(is, next);
+        let val = self.convert(fnbuilder, val, &ty)?;
+        self.test_pattern(fnbuilder, pattern, val, next)
+    }
+
+    /// `container[index]`, like `Value::index`.
+    fn index(&mut self, fnbuilder: &mut CustomFunctionBuilder, container: Val, index: Val) -> Compiled {
+        match (&container.ty, &index.ty) {
+            (Ty::Never, _) | (_, Ty::Never) => Ok(fnbuilder.dummy(&Ty::Never)),
+            (Ty::List(item), Ty::Int) => {
+                let item = *item.clone();
+                let position = self.call(fnbuilder, "alpha_list_index", &[container.val, index.val]);
+                let offset = fnbuilder.builder.ins().ishl_imm(position, 3);
+                let address = fnbuilder.builder.ins().iadd(container.val, offset);
+                let raw = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), address, 8);
+                Ok(self.from_mem(fnbuilder, raw, item))
+            }
+            (Ty::List(_), Ty::Range) => {
+                let args = [container.val, index.val];
+                Ok(self.call_ty(fnbuilder, "alpha_list_range", &args, container.ty.clone()))
+            }
+            (Ty::Map(key, value), _) if join(key, &index.ty) == **key => {
+                let (key, value) = (*key.clone(), *value.clone());
+                let index = self.convert(fnbuilder, index, &key)?;
+                let index = self.to_mem(fnbuilder, &index)?;
+                let raw = self.call(fnbuilder, "alpha_map_get", &[container.val, index]);
+                Ok(self.from_mem(fnbuilder, raw, value))
+            }
+            _ => {
+                let [container, description] = self.typed(fnbuilder, &container)?;
+                let [index, index_description] = self.typed(fnbuilder, &index)?;
+                let args = [container, description, index, index_description];
+                Ok(self.call_ty(fnbuilder, "alpha_any_index", &args, Ty::Any))
+            }
+        }
+    }
+
+    /// `list[from:to]`, like `Value::slice`, where a missing bound is the
+    /// start or the end of the list.
+    fn slice(&mut self, fnbuilder: &mut CustomFunctionBuilder, list: Val, bounds: Vec<Option<Val>>) -> Compiled {
+        let mut given = 0;
+        for (bound, flag) in zip(&bounds, [SLICE_FROM, SLICE_TO]) {
+            if bound.is_some() {
+                given |= flag;
+            }
+        }
+        let given = fnbuilder.builder.ins().iconst(I64, given as i64);
+        let static_bounds = bounds.iter().flatten().all(|bound| bound.ty == Ty::Int);
+        if matches!(list.ty, Ty::List(_)) && static_bounds {
+            let mut args = vec![list.val];
+            for bound in bounds {
+                args.push(match bound {
+                    Some(bound) => bound.val,
+                    None => fnbuilder.builder.ins().iconst(I64, 0),
+                });
+            }
+            args.push(given);
+            return Ok(self.call_ty(fnbuilder, "alpha_list_slice", &args, list.ty.clone()));
+        }
+        let [list, description] = self.typed(fnbuilder, &list)?;
+        let mut args = vec![list, description];
+        for bound in bounds {
+            args.push(match bound {
+                Some(bound) => self.convert(fnbuilder, bound, &Ty::Any)?.val,
+                None => fnbuilder.builder.ins().iconst(I64, 0),
+            });
+        }
+        args.push(given);
+        Ok(self.call_ty(fnbuilder, "alpha_any_slice", &args, Ty::Any))
+    }
+
+    /// `record.field`, like `Value::field`.
+    fn field(&mut self, fnbuilder: &mut CustomFunctionBuilder, record: Val, field: &str) -> Compiled {
+        if let Ty::Struct(name) = &record.ty {
+            if let Some((position, ty)) = self.field_ty(name, field) {
+                let raw = fnbuilder.builder.ins().load(
+                    I64,
+                    MemFlags::trusted(),
+                    record.val,
+                    8 + 8 * position as i32,
+                );
+                return Ok(self.from_mem(fnbuilder, raw, ty));
+            }
+        }
+        if record.ty == Ty::Never {
+            return Ok(fnbuilder.dummy(&Ty::Never));
+        }
+        let [record, description] = self.typed(fnbuilder, &record)?;
+        let field = self.string(fnbuilder, field);
+        Ok(self.call_ty(fnbuilder, "alpha_any_field", &[record, description, field], Ty::Any))
+    }
+
+    /// `name[index].field = val` and the like, like `Value::set_index`: the
+    /// runtime builds the changed container, which the variable is then
+    /// given.
+    fn set_index(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        name: &str,
+        steps: Vec<ast::Step<Val>>,
+        val: Val,
+    ) -> Compiled {
+        let container = match fnbuilder.vars.get(name) {
+            Some(var) if var.mutable => self.lookup(fnbuilder, name).unwrap(),
+            Some(_) => {
+                let message = format!("Cannot assign to immutable variable: '{}'", name);
+                return Ok(self.fail(fnbuilder, &message));
+            }
+            None if fnbuilder.is_own(name) => {
+                let message = format!("Cannot assign to immutable variable: '{}'", name);
+                return Ok(self.fail(fnbuilder, &message));
+            }
+            None => return Ok(self.fail(fnbuilder, &format!("Undefined variable: '{}'", name))),
+        };
+        let ty = self.set_ty(&container.ty, &steps, &val.ty);
+        let mut boxes = Vec::new();
+        for step in steps {
+            boxes.push(match step {
+                ast::Step::Index(index) => index,
+                ast::Step::Field(field) => self.translate(fnbuilder, &Node::String(field))?,
+            });
+        }
+        let tys = vec![Ty::Any; boxes.len()];
+        let steps = self.store_items(fnbuilder, boxes, &tys)?;
+        let [container, description] = self.typed(fnbuilder, &container)?;
+        let [item, item_description] = self.typed(fnbuilder, &val)?;
+        let args = [container, description, steps, item, item_description];
+        let changed = self.call_ty(fnbuilder, "alpha_any_set", &args, Ty::Any);
+        let changed = self.convert(fnbuilder, changed, &ty)?;
+        self.bind(fnbuilder, name, changed, true)?;
+        Ok(fnbuilder.unit())
+    }
+
+    /// The type of a container of type `ty` once what `steps` lead to in it
+    /// is given a value of type `item`.
+    fn set_ty(&self, ty: &Ty, steps: &[ast::Step<Val>], item: &Ty) -> Ty {
+        let Some((step, rest)) = steps.split_first() else {
+            return item.clone();
+        };
+        match (ty, step) {
+            (Ty::Never, _) => Ty::Never,
+            (Ty::List(items), ast::Step::Index(_)) => {
+                Ty::List(Box::new(join(items, &self.set_ty(items, rest, item))))
+            }
+            (Ty::Map(key, value), ast::Step::Index(index)) => Ty::Map(
+                Box::new(join(key, &index.ty)),
+                Box::new(join(value, &self.set_ty(value, rest, item))),
+            ),
+            (Ty::Struct(name), ast::Step::Field(field)) if self.field_ty(name, field).is_some() => {
+                ty.clone()
+            }
+            _ => Ty::Any,
+        }
+    }
+
+    /// `lhs op rhs`, like `Value::binary`: on ints, floats and bigints
+    /// directly, and through the runtime for anything else.
+    fn binary(&mut self, fnbuilder: &mut CustomFunctionBuilder, op: &ast::Op, lhs: Val, rhs: Val) -> Compiled {
+        let comparison = matches!(
+            op,
+            ast::Op::Eq | ast::Op::Neq | ast::Op::Gt | ast::Op::Ge | ast::Op::Lt | ast::Op::Le
+        );
+        match (&lhs.ty, &rhs.ty) {
+            (Ty::Never, _) | (_, Ty::Never) => Ok(fnbuilder.dummy(&Ty::Never)),
+            (Ty::Int, Ty::Int) => {
+                let val = self.int_op(fnbuilder, op, lhs.val, rhs.val);
+                Ok(fnbuilder.val(val, if comparison { Ty::Bool } else { Ty::Int }))
+            }
+            (Ty::Float, Ty::Int | Ty::Float | Ty::BigInt) | (Ty::Int | Ty::BigInt, Ty::Float) => {
+                let lhs = self.number(fnbuilder, lhs)?;
+                let rhs = self.number(fnbuilder, rhs)?;
+                let val = self.float_op(fnbuilder, op, lhs, rhs);
+                Ok(fnbuilder.val(val, if comparison { Ty::Bool } else { Ty::Float }))
+            }
+            (Ty::BigInt, Ty::Int | Ty::BigInt) | (Ty::Int, Ty::BigInt) => {
+                let lhs = self.check(fnbuilder, lhs, "bigint")?;
+                let rhs = self.check(fnbuilder, rhs, "bigint")?;
+                let val = self.bigint_op(fnbuilder, op, lhs.val, rhs.val);
+                Ok(fnbuilder.val(val, if comparison { Ty::Bool } else { Ty::BigInt }))
+            }
+            (lhs_ty, rhs_ty) => {
+                let ty = match (op, lhs_ty, rhs_ty) {
+                    (ast::Op::Add, Ty::List(a), Ty::List(b)) => Ty::List(Box::new(join(a, b))),
+                    _ => Ty::Any,
+                };
+                let op = fnbuilder.builder.ins().iconst(I64, op_number(op));
+                let [lhs, lhs_description] = self.typed(fnbuilder, &lhs)?;
+                let [rhs, rhs_description] = self.typed(fnbuilder, &rhs)?;
+                let args = [op, lhs, lhs_description, rhs, rhs_description];
+                let result = self.call_ty(fnbuilder, "alpha_any_binary", &args, Ty::Any);
+                if comparison {
+                    let description = self.describe_value(fnbuilder, &Ty::Any);
+                    let truth = self.call(fnbuilder, "alpha_any_truth", &[result.val, description]);
+                    let truth = fnbuilder.builder.ins().ireduce(I8, truth);
+                    return Ok(fnbuilder.val(truth, Ty::Bool));
+                }
+                self.convert(fnbuilder, result, &ty)
+            }
+        }
+    }
+
+    fn int_op(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        op: &ast::Op,
+        lhs: Value,
+        rhs: Value,
+    ) -> Value {
+        let (val, overflow) = match op {
+            ast::Op::Add => fnbuilder.builder.ins().sadd_overflow(lhs, rhs),
+            ast::Op::Sub => fnbuilder.builder.ins().ssub_overflow(lhs, rhs),
+            ast::Op::Mul => fnbuilder.builder.ins().smul_overflow(lhs, rhs),
+            ast::Op::Div => {
+                let zero = fnbuilder.builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+                self.error_if(fnbuilder, zero, value::DIVISION_BY_ZERO);
+                let min = fnbuilder
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::Equal, lhs, i64::MIN);
+                let minus_one = fnbuilder.builder.ins().icmp_imm(IntCC::Equal, rhs, -1);
+                let overflow = fnbuilder.builder.ins().band(min, minus_one);
+                self.error_if(fnbuilder, overflow, value::OVERFLOW);
+                return fnbuilder.builder.ins().sdiv(lhs, rhs);
+            }
+            ast::Op::Mod => {
+                let zero = fnbuilder.builder.ins().icmp_imm(IntCC::Equal, rhs, 0);
+                self.error_if(fnbuilder, zero, value::DIVISION_BY_ZERO);
+                // `i64::MIN % -1` traps on x86, but anything % -1 is 0.
+                let minus_one = fnbuilder.builder.ins().icmp_imm(IntCC::Equal, rhs, -1);
+                let one = fnbuilder.builder.ins().iconst(I64, 1);
+                let rhs = fnbuilder.builder.ins().select(minus_one, one, rhs);
+                return fnbuilder.builder.ins().srem(lhs, rhs);
+            }
+            ast::Op::Pow => return self.call(fnbuilder, "alpha_int_pow", &[lhs, rhs]),
+            ast::Op::Eq => return fnbuilder.builder.ins().icmp(IntCC::Equal, lhs, rhs),
+            ast::Op::Neq => return fnbuilder.builder.ins().icmp(IntCC::NotEqual, lhs, rhs),
+            ast::Op::Gt => {
+                return fnbuilder
+                    .builder
+                    .ins()
+                    .icmp(IntCC::SignedGreaterThan, lhs, rhs)
+            }
+            ast::Op::Ge => {
+                return fnbuilder
+                    .builder
+                    .ins()
+                    .icmp(IntCC::SignedGreaterThanOrEqual, lhs, rhs)
+            }
+            ast::Op::Lt => {
+                return fnbuilder
+                    .builder
+                    .ins()
+                    .icmp(IntCC::SignedLessThan, lhs, rhs)
+            }
+            ast::Op::Le => {
+                return fnbuilder
+                    .builder
+                    .ins()
+                    .icmp(IntCC::SignedLessThanOrEqual, lhs, rhs)
+            }
+        };
+        self.error_if(fnbuilder, overflow, value::OVERFLOW);
+        val
+    }
+
+    fn float_op(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        op: &ast::Op,
+        lhs: Value,
+        rhs: Value,
+    ) -> Value {
+        if let ast::Op::Mod | ast::Op::Pow = op {
+            let name = if let ast::Op::Mod = op { "fmod" } else { "pow" };
+            return self.call(fnbuilder, name, &[lhs, rhs]);
+        }
+        let ins = fnbuilder.builder.ins();
+        match op {
+            ast::Op::Mod | ast::Op::Pow => unreachable!(),
+            ast::Op::Add => ins.fadd(lhs, rhs),
+            ast::Op::Sub => ins.fsub(lhs, rhs),
+            ast::Op::Mul => ins.fmul(lhs, rhs),
+            ast::Op::Div => ins.fdiv(lhs, rhs),
+            ast::Op::Eq => ins.fcmp(FloatCC::Equal, lhs, rhs),
+            ast::Op::Neq => ins.fcmp(FloatCC::NotEqual, lhs, rhs),
+            ast::Op::Gt => ins.fcmp(FloatCC::GreaterThan, lhs, rhs),
+            ast::Op::Ge => ins.fcmp(FloatCC::GreaterThanOrEqual, lhs, rhs),
+            ast::Op::Lt => ins.fcmp(FloatCC::LessThan, lhs, rhs),
+            ast::Op::Le => ins.fcmp(FloatCC::LessThanOrEqual, lhs, rhs),
+        }
+    }
+
+    fn bigint_op(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        op: &ast::Op,
+        lhs: Value,
+        rhs: Value,
+    ) -> Value {
+        let name = match op {
+            ast::Op::Add => "alpha_bigint_add",
+            ast::Op::Sub => "alpha_bigint_sub",
+            ast::Op::Mul => "alpha_bigint_mul",
+            ast::Op::Div => "alpha_bigint_div",
+            ast::Op::Mod => "alpha_bigint_rem",
+            ast::Op::Pow => "alpha_bigint_pow",
+            _ => {
+                let cmp = self.call(fnbuilder, "alpha_bigint_cmp", &[lhs, rhs]);
+                let cc = match op {
+                    ast::Op::Eq => IntCC::Equal,
+                    ast::Op::Neq => IntCC::NotEqual,
+                    ast::Op::Gt => IntCC::SignedGreaterThan,
+                    ast::Op::Ge => IntCC::SignedGreaterThanOrEqual,
+                    ast::Op::Lt => IntCC::SignedLessThan,
+                    _ => IntCC::SignedLessThanOrEqual,
+                };
+                return fnbuilder.builder.ins().icmp_imm(cc, cmp, 0);
+            }
+        };
+        self.call(fnbuilder, name, &[lhs, rhs])
+    }
+
+
+    /// The list `val` with the type of its items, failing like
+    /// `lists::apply` for anything else.
+    fn list_of(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val) -> Compiled<(Val, Ty)> {
+        match &val.ty {
+            Ty::List(item) => {
+                let item = *item.clone();
+                Ok((val, item))
+            }
+            Ty::Never => Ok((val, Ty::Never)),
+            _ => {
+                let list = self.cast(fnbuilder, val, &Ty::List(Box::new(Ty::Any)), "Not a list: '{:?}'")?;
+                Ok((list, Ty::Any))
+            }
+        }
+    }
+
+    /// The string `val`, failing with "Not a string" for anything else.
+    fn str_of(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val) -> Compiled {
+        match &val.ty {
+            Ty::Str | Ty::Never => Ok(val),
+            _ => self.cast(fnbuilder, val, &Ty::Str, "Not a string: '{:?}'"),
+        }
+    }
+
+    /// The int `val`, failing with "Not an int" for anything else.
+    fn int_of(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val) -> Compiled {
+        match &val.ty {
+            Ty::Int | Ty::Never => Ok(val),
+            _ => self.cast(fnbuilder, val, &Ty::Int, "Not an int: '{:?}'"),
+        }
+    }
+
+    /// Converts `val` to `ty` if it is a value of that kind, and fails with
+    /// `format` otherwise, like `alpha_raise`.
+    fn cast(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val, ty: &Ty, format: &str) -> Compiled {
+        if val.ty != Ty::Any {
+            self.raise(fnbuilder, format, &val)?;
+            return Ok(fnbuilder.dummy(ty));
+        }
+        let [mem, from] = self.typed(fnbuilder, &val)?;
+        let to = self.describe_value(fnbuilder, ty);
+        let format = self.string(fnbuilder, format);
+        let raw = self.call(fnbuilder, "alpha_any_cast", &[mem, from, to, format]);
+        Ok(self.from_mem(fnbuilder, raw, ty.clone()))
+    }
+
+    /// `val` as a float, widening ints and bigints, failing like
+    /// `math::float` for anything else.
+    fn number(&mut self, fnbuilder: &mut CustomFunctionBuilder, val: Val) -> Compiled<Value> {
+        Ok(match &val.ty {
+            Ty::Float => val.val,
+            Ty::Int => fnbuilder.builder.ins().fcvt_from_sint(F64, val.val),
+            Ty::BigInt => self.call(fnbuilder, "alpha_bigint_to_f64", &[val.val]),
+            Ty::Never => fnbuilder.builder.ins().f64const(0.0),
+            Ty::Any => {
+                let description = self.describe_value(fnbuilder, &Ty::Any);
+                self.call(fnbuilder, "alpha_any_number", &[val.val, description])
+            }
+            _ => {
+                self.raise(fnbuilder, "Not a number: '{:?}'", &val)?;
+                fnbuilder.builder.ins().f64const(0.0)
+            }
+        })
+    }
+
+    /// Translates `body` for the position and the item of every item of
+    /// `list`, whose items are of type `ty`, in order.
+    fn each_item(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        list: Value,
+        ty: &Ty,
+        body: &mut dyn FnMut(&mut Compiler, &mut CustomFunctionBuilder, Value, Val) -> Compiled<()>,
+    ) -> Compiled<()> {
+        let condition_block = fnbuilder.builder.create_block();
+        fnbuilder.builder.append_block_param(condition_block, I64);
+        let inner_block = fnbuilder.builder.create_block();
+        let return_block = fnbuilder.builder.create_block();
+
+        let zero = fnbuilder.builder.ins().iconst(I64, 0);
+        fnbuilder.builder.ins().jump(condition_block, &[zero]);
+
+        fnbuilder.builder.switch_to_block(condition_block);
+        let position = fnbuilder.builder.block_params(condition_block)[0];
+        let len = fnbuilder
+            .builder
+            .ins()
+            .load(I64, MemFlags::trusted(), list, 0);
+        let more = fnbuilder
+            .builder
+            .ins()
+            .icmp(IntCC::SignedLessThan, position, len);
+        fnbuilder
+            .builder
+            .ins()
+            .brif(more, inner_block, &[], return_block, &[]);
+
+        fnbuilder.builder.switch_to_block(inner_block);
+        fnbuilder.builder.seal_block(inner_block);
+        let offset = fnbuilder.builder.ins().ishl_imm(position, 3);
+        let address = fnbuilder.builder.ins().iadd(list, offset);
+        let raw = fnbuilder
+            .builder
+            .ins()
+            .load(I64, MemFlags::trusted(), address, 8);
+        let item = self.from_mem(fnbuilder, raw, ty.clone());
+        body(self, fnbuilder, position, item)?;
+        let next = fnbuilder.builder.ins().iadd_imm(position, 1);
+        fnbuilder.builder.ins().jump(condition_block, &[next]);
+        fnbuilder.builder.seal_block(condition_block);
+
+        fnbuilder.builder.switch_to_block(return_block);
+        fnbuilder.builder.seal_block(return_block);
+        Ok(())
+    }
+
+    /// The list of the results of calling `function` with every item of
+    /// `list`.
+    fn map_items(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        list: Value,
+        ty: &Ty,
+        function: &Val,
+    ) -> Compiled {
+        let len = fnbuilder
+            .builder
+            .ins()
+            .load(I64, MemFlags::trusted(), list, 0);
+        let mapped = self.call(fnbuilder, "alpha_list_new", &[len]);
+        let mut returns = Ty::Never;
+        self.each_item(fnbuilder, list, ty, &mut |c, fnbuilder, position, item| {
+            let result = c.call_value(fnbuilder, function.clone(), vec![item])?;
+            returns = result.ty.clone();
+            let result = c.to_mem(fnbuilder, &result)?;
+            let offset = fnbuilder.builder.ins().ishl_imm(position, 3);
+            let address = fnbuilder.builder.ins().iadd(mapped, offset);
+            fnbuilder
+                .builder
+                .ins()
+                .store(MemFlags::trusted(), result, address, 8);
+            Ok(())
+        })?;
+        Ok(fnbuilder.val(mapped, Ty::List(Box::new(returns))))
+    }
+
+    /// A variable for an intermediate result, which has no name in the
+    /// program and is left out of the debug information.
+    fn temp(&mut self, fnbuilder: &mut CustomFunctionBuilder, what: &str, val: Val) -> Compiled<String> {
+        let name = format!("{}.{}", fnbuilder.temps, what);
+        fnbuilder.temps += 1;
+        self.bind(fnbuilder, &name, val, true)?;
+        Ok(name)
+    }
+
+    /// Translates a call of a list builtin, like `lists::apply`.
+    fn list_builtin(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str, mut args: Vec<Val>) -> Compiled {
+        let pair = matches!(name, "min" | "max") && args.len() == 2;
+        match lists::arity(name).unwrap() {
+            _ if pair => {}
+            1 if args.len() != 1 => return Ok(self.fail(fnbuilder, &format!("{} takes 1 argument", name))),
+            n if args.len() != n => {
+                return Ok(self.fail(fnbuilder, &format!("{} takes {} arguments", name, n)))
+            }
+            _ => {}
+        }
+        let first = args.remove(0);
+        match name {
+            "len" => {
+                let len = match &first.ty {
+                    Ty::Str => self.call(fnbuilder, "alpha_str_len", &[first.val]),
+                    // Maps store their length first too.
+                    Ty::List(_) | Ty::Tuple(_) | Ty::Map(..) => fnbuilder
+                        .builder
+                        .ins()
+                        .load(I64, MemFlags::trusted(), first.val, 0),
+                    Ty::Any => {
+                        let description = self.describe_value(fnbuilder, &Ty::Any);
+                        self.call(fnbuilder, "alpha_any_len", &[first.val, description])
+                    }
+                    Ty::Never => fnbuilder.builder.ins().iconst(I64, 0),
+                    ty => {
+                        let message = format!("Cannot take len of {}", ty.name());
+                        self.fail(fnbuilder, &message);
+                        fnbuilder.builder.ins().iconst(I64, 0)
+                    }
+                };
+                return Ok(fnbuilder.val(len, Ty::Int));
+            }
+            "chars" => {
+                let s = self.str_of(fnbuilder, first)?;
+                let chars = Ty::List(Box::new(Ty::Str));
+                return Ok(self.call_ty(fnbuilder, "alpha_str_chars", &[s.val], chars));
+            }
+            _ => {}
+        }
+        // `min` and `max` of two values take them from a list of both.
+        let first = match pair {
+            true => {
+                let second = args.remove(0);
+                let ty = join(&first.ty, &second.ty);
+                let tys = [ty.clone(), ty.clone()];
+                let list = self.store_items(fnbuilder, vec![first, second], &tys)?;
+                fnbuilder.val(list, Ty::List(Box::new(ty)))
+            }
+            false => first,
+        };
+        let (list, ty) = self.list_of(fnbuilder, first)?;
+        if ty == Ty::Never {
+            // An empty list, or one that never is.
+            if let "map" | "filter" | "sort" | "sort_by" | "reverse" | "pop" = name {
+                return Ok(list);
+            }
+        }
+        let list_ty = list.ty.clone();
+        match name {
+            "map" => self.map_items(fnbuilder, list.val, &ty, &args[0]),
+            "filter" => {
+                let len = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), list.val, 0);
+                let kept = self.call(fnbuilder, "alpha_list_new", &[len]);
+                let kept = fnbuilder.val(kept, list_ty.clone());
+                let zero = fnbuilder.builder.ins().iconst(I64, 0);
+                let zero = fnbuilder.val(zero, Ty::Int);
+                let count = self.temp(fnbuilder, "count", zero)?;
+                let function = args[0].clone();
+                self.each_item(fnbuilder, list.val, &ty, &mut |c, fnbuilder, _, item| {
+                    let keep = c.call_value(fnbuilder, function.clone(), vec![item.clone()])?;
+                    let keep = c.truth(fnbuilder, keep)?;
+                    let keep_block = fnbuilder.builder.create_block();
+                    let next_block = fnbuilder.builder.create_block();
+                    fnbuilder
+                        .builder
+                        .ins()
+                        .brif(keep, keep_block, &[], next_block, &[]);
+                    fnbuilder.builder.switch_to_block(keep_block);
+                    fnbuilder.builder.seal_block(keep_block);
+                    let position = c.lookup(fnbuilder, &count).unwrap();
+                    let raw = fnbuilder.raw(&item);
+                    let offset = fnbuilder.builder.ins().ishl_imm(position.val, 3);
+                    let address = fnbuilder.builder.ins().iadd(kept.val, offset);
+                    fnbuilder
+                        .builder
+                        .ins()
+                        .store(MemFlags::trusted(), raw, address, 8);
+                    let next = fnbuilder.builder.ins().iadd_imm(position.val, 1);
+                    let next = fnbuilder.val(next, Ty::Int);
+                    c.bind(fnbuilder, &count, next, true)?;
+                    fnbuilder.builder.ins().jump(next_block, &[]);
+                    fnbuilder.builder.switch_to_block(next_block);
+                    fnbuilder.builder.seal_block(next_block);
+                    Ok(())
+                })?;
+                // The list keeps the memory of the items left out.
+                let len = self.lookup(fnbuilder, &count).unwrap();
+                fnbuilder
+                    .builder
+                    .ins()
+                    .store(MemFlags::trusted(), len.val, kept.val, 0);
+                Ok(kept)
+            }
+            "reduce" => {
+                let init = args.remove(0);
+                let function = args.remove(0);
+                let acc = self.temp(fnbuilder, "reduce", init)?;
+                self.each_item(fnbuilder, list.val, &ty, &mut |c, fnbuilder, _, item| {
+                    let val = c.lookup(fnbuilder, &acc).unwrap();
+                    let val = c.call_value(fnbuilder, function.clone(), vec![val, item])?;
+                    c.bind(fnbuilder, &acc, val, true)
+                })?;
+                Ok(self.lookup(fnbuilder, &acc).unwrap())
+            }
+            "sort" => {
+                let key = self.describe_value(fnbuilder, &ty);
+                let args = [list.val, list.val, key];
+                Ok(self.call_ty(fnbuilder, "alpha_list_sort", &args, list_ty))
+            }
+            "sort_by" => {
+                let keys = self.map_items(fnbuilder, list.val, &ty, &args[0])?;
+                let Ty::List(key) = &keys.ty else {
+                    unreachable!("map_items results in a list");
+                };
+                let key = self.describe_value(fnbuilder, key);
+                let args = [list.val, keys.val, key];
+                Ok(self.call_ty(fnbuilder, "alpha_list_sort", &args, list_ty))
+            }
+            "reverse" | "pop" => {
+                let name = format!("alpha_list_{}", name);
+                Ok(self.call_ty(fnbuilder, &name, &[list.val], list_ty))
+            }
+            "push" => {
+                let item = args.remove(0);
+                let ty = join(&ty, &item.ty);
+                let list_ty = Ty::List(Box::new(ty.clone()));
+                let list = self.convert(fnbuilder, list, &list_ty)?;
+                let item = self.convert(fnbuilder, item, &ty)?;
+                let item = self.to_mem(fnbuilder, &item)?;
+                Ok(self.call_ty(fnbuilder, "alpha_list_push", &[list.val, item], list_ty))
+            }
+            "zip" => {
+                let (other, other_ty) = self.list_of(fnbuilder, args.remove(0))?;
+                let ty = Ty::List(Box::new(Ty::Tuple(vec![ty, other_ty])));
+                Ok(self.call_ty(fnbuilder, "alpha_list_zip", &[list.val, other.val], ty))
+            }
+            "enumerate" => {
+                let ty = Ty::List(Box::new(Ty::Tuple(vec![Ty::Int, ty])));
+                Ok(self.call_ty(fnbuilder, "alpha_list_enumerate", &[list.val], ty))
+            }
+            "sum" => {
+                let zero = fnbuilder.builder.ins().iconst(I64, 0);
+                let zero = fnbuilder.val(zero, Ty::Int);
+                let sum = self.temp(fnbuilder, "sum", zero)?;
+                self.each_item(fnbuilder, list.val, &ty, &mut |c, fnbuilder, _, item| {
+                    let val = c.lookup(fnbuilder, &sum).unwrap();
+                    let val = c.binary(fnbuilder, &ast::Op::Add, val, item)?;
+                    c.bind(fnbuilder, &sum, val, true)
+                })?;
+                Ok(self.lookup(fnbuilder, &sum).unwrap())
+            }
+            "min" | "max" => {
+                let description = self.describe_value(fnbuilder, &ty);
+                let wanted = fnbuilder
+                    .builder
+                    .ins()
+                    .iconst(I64, if name == "min" { -1 } else { 1 });
+                let args = [list.val, description, wanted];
+                let raw = self.call(fnbuilder, "alpha_list_extreme", &args);
+                Ok(self.from_mem(fnbuilder, raw, ty))
+            }
+            "contains" => {
+                let items = self.describe_value(fnbuilder, &ty);
+                let [item, description] = self.typed(fnbuilder, &args[0])?;
+                let args = [list.val, items, item, description];
+                let found = self.call(fnbuilder, "alpha_list_contains", &args);
+                let found = fnbuilder.builder.ins().ireduce(I8, found);
+                Ok(fnbuilder.val(found, Ty::Bool))
+            }
+            "concat" => {
+                let item = self.describe_value(fnbuilder, &ty);
+                Ok(self.call_ty(fnbuilder, "alpha_str_concat", &[list.val, item], Ty::Str))
+            }
+            _ => unreachable!("not a list builtin: {}", name),
+        }
+    }
+
+    /// Translates a call of a math builtin, like `math::apply`: most are
+    /// libm calls on floats.
+    fn math_builtin(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str, args: Vec<Val>) -> Compiled {
+        match math::arity(name).unwrap() {
+            1 if args.len() != 1 => return Ok(self.fail(fnbuilder, &format!("{} takes 1 argument", name))),
+            n if args.len() != n => {
+                return Ok(self.fail(fnbuilder, &format!("{} takes {} arguments", name, n)))
+            }
+            _ => {}
+        }
+        match name {
+            "random" => Ok(self.call_ty(fnbuilder, "alpha_random", &[], Ty::Float)),
+            "seed" => {
+                let seed = self.int_of(fnbuilder, args[0].clone())?;
+                self.call(fnbuilder, "alpha_seed", &[seed.val]);
+                Ok(fnbuilder.unit())
+            }
+            "abs" if args[0].ty == Ty::Float => {
+                Ok(self.call_ty(fnbuilder, "fabs", &[args[0].val], Ty::Float))
+            }
+            "abs" => {
+                // Like the interpreter, the negation of what is below zero.
+                let val = args[0].clone();
+                self.number(fnbuilder, val.clone())?;
+                let zero = fnbuilder.builder.ins().iconst(I64, 0);
+                let zero = fnbuilder.val(zero, Ty::Int);
+                let negative = self.binary(fnbuilder, &ast::Op::Lt, val.clone(), zero.clone())?;
+                let negative = self.truth(fnbuilder, negative)?;
+                let negative_block = fnbuilder.builder.create_block();
+                let positive_block = fnbuilder.builder.create_block();
                 fnbuilder
                     .builder
                     .ins()
-                    .symbol_value(self.module.isa().pointer_type(), ptr)
+                    .brif(negative, negative_block, &[], positive_block, &[]);
+                fnbuilder.builder.switch_to_block(negative_block);
+                fnbuilder.builder.seal_block(negative_block);
+                let negated = self.binary(fnbuilder, &ast::Op::Sub, zero, val.clone())?;
+                let mut branches = vec![fnbuilder.branch(negated)];
+                fnbuilder.builder.switch_to_block(positive_block);
+                fnbuilder.builder.seal_block(positive_block);
+                branches.push(fnbuilder.branch(val));
+                self.join(fnbuilder, branches)
+            }
+            _ => {
+                let mut floats = Vec::new();
+                for arg in args {
+                    floats.push(self.number(fnbuilder, arg)?);
+                }
+                let libm = if name == "ln" { "log" } else { name };
+                Ok(self.call_ty(fnbuilder, libm, &floats, Ty::Float))
             }
-            ast::Node::Number(num) => fnbuilder.builder.ins().iconst(I64, *num as i64),
-            ast::Node::TypeName(_name) => fnbuilder.builder.ins().iconst(I64, 0),
-            ast::Node::Nada => fnbuilder.builder.ins().iconst(I64, 0),
-            n => todo!("{:?}", n),
         }
     }
 
-    pub fn compile(self) {
-        let obj = self.module.finish();
-        let bytes = obj.emit().unwrap();
-        if !Path::new("build").exists() {
-            fs::create_dir("build").unwrap();
+    /// Translates a call of a file or stdin builtin into a call of
+    /// `runtime/src/io.rs`.
+    fn io_builtin(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str, args: Vec<Val>) -> Compiled {
+        match io::arity(name).unwrap() {
+            1 if args.len() != 1 => return Ok(self.fail(fnbuilder, &format!("{} takes 1 argument", name))),
+            n if args.len() != n => {
+                return Ok(self.fail(fnbuilder, &format!("{} takes {} arguments", name, n)))
+            }
+            _ => {}
+        }
+        let mut strings = Vec::new();
+        for arg in args {
+            strings.push(self.str_of(fnbuilder, arg)?.val);
         }
-        let mut file = File::create("build/out.o").unwrap();
-        file.write_all(&bytes).unwrap();
+        let result = self.call(fnbuilder, &format!("alpha_{}", name), &strings);
+        Ok(match name {
+            "read_line" => fnbuilder.val(result, Ty::Option(Box::new(Ty::Str))),
+            "read_file" => fnbuilder.val(result, Ty::Str),
+            "write_file" | "append_file" => fnbuilder.unit(),
+            "lines" => fnbuilder.val(result, Ty::List(Box::new(Ty::Str))),
+            "exists" => {
+                let exists = fnbuilder.builder.ins().ireduce(I8, result);
+                fnbuilder.val(exists, Ty::Bool)
+            }
+            _ => unreachable!("not an io builtin: {}", name),
+        })
+    }
 
-        Command::new("musl-gcc")
-            .args(&["-static", "build/out.o", "-o", "build/out"])
-            .status()
-            .unwrap();
+    /// Prints `args` like the interpreter does, separated by spaces, to the
+    /// file descriptor `fd`.
+    fn print(&mut self, fnbuilder: &mut CustomFunctionBuilder, fd: i64, args: Vec<Val>) -> Compiled {
+        for (i, arg) in args.iter().enumerate() {
+            let fd = fnbuilder.builder.ins().iconst(I64, fd);
+            if i > 0 {
+                let space = self.string(fnbuilder, " ");
+                self.call(fnbuilder, "alpha_printf_text", &[fd, space]);
+            }
+            let [val, description] = self.typed(fnbuilder, arg)?;
+            self.call(fnbuilder, "alpha_print", &[fd, val, description]);
+        }
+        let fd = fnbuilder.builder.ins().iconst(I64, fd);
+        let newline = self.string(fnbuilder, "\n");
+        self.call(fnbuilder, "alpha_printf_text", &[fd, newline]);
+        Ok(fnbuilder.unit())
     }
-}
 
-struct CustomFunctionBuilder<'a> {
-    var_index: usize,
-    variables: HashMap<String, Variable>,
-    builder: FunctionBuilder<'a>,
-}
+    /// Translates `printf` and `eprint`, which print like C's printf when
+    /// compiled, to stdout and stderr.
+    fn printf(
+        &mut self,
+        fnbuilder: &mut CustomFunctionBuilder,
+        name: &str,
+        nodes: &[Node],
+        args: Vec<Val>,
+    ) -> Compiled {
+        let Some(Node::String(format)) = nodes.first() else {
+            return Err(format!("{} takes a literal format string when compiled", name).into());
+        };
+        let pieces = format_pieces(format.trim_end_matches('\0')).map_err(|e| format!("{}: {}", name, e))?;
+        let conversions = pieces.iter().filter(|p| p.conversion.is_some()).count();
+        if args.len() - 1 != conversions {
+            return Err(format!(
+                "{}: the format takes {} arguments, found {}",
+                name,
+                conversions,
+                args.len() - 1
+            )
+            .into());
+        }
+        let fd = if name == "eprint" { 2 } else { 1 };
+        let mut args = args.into_iter().skip(1);
+        for piece in pieces {
+            let fd = fnbuilder.builder.ins().iconst(I64, fd);
+            let format = self.string(fnbuilder, &piece.format);
+            let Some(conversion) = piece.conversion else {
+                self.call(fnbuilder, "alpha_printf_text", &[fd, format]);
+                continue;
+            };
+            let val = args.next().unwrap();
+            let (helper, val) = match (conversion, &val.ty) {
+                (Conversion::Int, Ty::Int) => ("alpha_printf_int", val.val),
+                (Conversion::Int, Ty::Bool) => {
+                    ("alpha_printf_int", fnbuilder.builder.ins().uextend(I64, val.val))
+                }
+                (Conversion::Float, Ty::Float) => ("alpha_printf_float", val.val),
+                (Conversion::Str, Ty::Str) => ("alpha_printf_str", val.val),
+                (Conversion::Str, Ty::BigInt) => (
+                    "alpha_printf_str",
+                    self.call(fnbuilder, "alpha_bigint_to_str", &[val.val]),
+                ),
+                (Conversion::Pointer, ty) if ty.is_pointer() => ("alpha_printf_int", val.val),
+                (conversion, ty) => {
+                    return Err(format!(
+                        "{}: {} expects {}, found {}",
+                        name,
+                        piece.spec,
+                        conversion.expects(),
+                        ty.name()
+                    )
+                    .into())
+                }
+            };
+            self.call(fnbuilder, helper, &[fd, format, val]);
+        }
+        // Like `print`, `printf` and `eprint` result in a unit.
+        Ok(fnbuilder.unit())
+    }
 
-impl<'a> CustomFunctionBuilder<'a> {
-    fn new_var(&mut self, name: &String) -> Variable {
-        let var = Variable::new(self.var_index);
-        self.var_index += 1;
+    /// Translates `args`, `env` and `exit`, like `process::apply`.
+    fn process_builtin(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str, args: Vec<Val>) -> Compiled {
+        match process::arity(name).unwrap() {
+            1 if args.len() != 1 => return Ok(self.fail(fnbuilder, &format!("{} takes 1 argument", name))),
+            n if args.len() != n => {
+                return Ok(self.fail(fnbuilder, &format!("{} takes {} arguments", name, n)))
+            }
+            _ => {}
+        }
+        match name {
+            "args" => Ok(self.call_ty(fnbuilder, "alpha_args", &[], Ty::List(Box::new(Ty::Str)))),
+            "env" => {
+                let var = self.str_of(fnbuilder, args[0].clone())?;
+                let ty = Ty::Option(Box::new(Ty::Str));
+                Ok(self.call_ty(fnbuilder, "alpha_env", &[var.val], ty))
+            }
+            "exit" => {
+                let status = self.int_of(fnbuilder, args[0].clone())?;
+                self.call(fnbuilder, "exit", &[status.val]);
+                Ok(fnbuilder.unit())
+            }
+            _ => unreachable!("not a process builtin: {}", name),
+        }
+    }
 
-        self.variables.insert(name.to_string(), var);
-        var
+    /// Translates the conversions and map builtins that no function or
+    /// variable shadows, like the interpreter.
+    fn value_builtin(&mut self, fnbuilder: &mut CustomFunctionBuilder, name: &str, args: Vec<Val>) -> Compiled {
+        let val = args[0].clone();
+        if val.ty == Ty::Never {
+            return Ok(fnbuilder.dummy(&Ty::Never));
+        }
+        match (name, &val.ty) {
+            ("int", Ty::Int) | ("float", Ty::Float) | ("bigint", Ty::BigInt) => Ok(val),
+            ("float", Ty::Int) => {
+                let n = fnbuilder.builder.ins().fcvt_from_sint(F64, val.val);
+                Ok(fnbuilder.val(n, Ty::Float))
+            }
+            ("bigint", Ty::Int) => self.check(fnbuilder, val, "bigint"),
+            ("int" | "float" | "bigint", _) => {
+                let ty = self.parse_ty(name);
+                let [mem, description] = self.typed(fnbuilder, &val)?;
+                let converted = self.call(fnbuilder, &format!("alpha_any_{}", name), &[mem, description]);
+                Ok(fnbuilder.val(converted, ty))
+            }
+            ("keys" | "values", Ty::Map(key, value)) => {
+                let item = if name == "keys" { key } else { value };
+                let ty = Ty::List(item.clone());
+                Ok(self.call_ty(fnbuilder, &format!("alpha_map_{}", name), &[val.val], ty))
+            }
+            ("keys" | "values", _) => {
+                let [mem, description] = self.typed(fnbuilder, &val)?;
+                let ty = Ty::List(Box::new(Ty::Any));
+                let name = format!("alpha_any_{}", name);
+                Ok(self.call_ty(fnbuilder, &name, &[mem, description], ty))
+            }
+            ("has" | "remove", Ty::Map(key, _)) if join(key, &args[1].ty) == **key => {
+                let key = *key.clone();
+                let ty = val.ty.clone();
+                let key = self.convert(fnbuilder, args[1].clone(), &key)?;
+                let key = self.to_mem(fnbuilder, &key)?;
+                let result = self.call(fnbuilder, &format!("alpha_map_{}", name), &[val.val, key]);
+                Ok(match name {
+                    "has" => {
+                        let has = fnbuilder.builder.ins().ireduce(I8, result);
+                        fnbuilder.val(has, Ty::Bool)
+                    }
+                    _ => fnbuilder.val(result, ty),
+                })
+            }
+            ("has" | "remove", _) => {
+                let [mem, description] = self.typed(fnbuilder, &val)?;
+                let [key, key_description] = self.typed(fnbuilder, &args[1])?;
+                let args = [mem, description, key, key_description];
+                let result = self.call(fnbuilder, &format!("alpha_any_{}", name), &args);
+                Ok(match name {
+                    "has" => {
+                        let has = fnbuilder.builder.ins().ireduce(I8, result);
+                        fnbuilder.val(has, Ty::Bool)
+                    }
+                    _ => fnbuilder.val(result, Ty::Any),
+                })
+            }
+            ("unwrap_or", _) => {
+                let (option, payload_ty) = self.option_of(fnbuilder, val)?;
+                let tag = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), option.val, 8);
+                let is_some = fnbuilder
+                    .builder
+                    .ins()
+                    .icmp_imm(IntCC::Equal, tag, value::SOME as i64);
+                let some_block = fnbuilder.builder.create_block();
+                let none_block = fnbuilder.builder.create_block();
+                fnbuilder
+                    .builder
+                    .ins()
+                    .brif(is_some, some_block, &[], none_block, &[]);
+                fnbuilder.builder.switch_to_block(some_block);
+                fnbuilder.builder.seal_block(some_block);
+                let raw = fnbuilder
+                    .builder
+                    .ins()
+                    .load(I64, MemFlags::trusted(), option.val, 16);
+                let payload = self.from_mem(fnbuilder, raw, payload_ty);
+                let mut branches = vec![fnbuilder.branch(payload)];
+                fnbuilder.builder.switch_to_block(none_block);
+                fnbuilder.builder.seal_block(none_block);
+                branches.push(fnbuilder.branch(args[1].clone()));
+                self.join(fnbuilder, branches)
+            }
+            _ => unreachable!("not a value builtin: {}", name),
+        }
     }
-}
@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+
+use cranelift_codegen::gimli::write::{
+    Address, AttributeValue, DwarfUnit, EndianVec, Expression, FileId, FrameTable, LineProgram,
+    LineString, Location, LocationList, Range, RangeList, Sections, UnitEntryId, Writer,
+};
+use cranelift_codegen::gimli::{self, constants, Encoding, Format, LineEncoding, RunTimeEndian};
+use cranelift_codegen::isa::unwind::CfaUnwindInfo;
+use cranelift_module::FuncId;
+use cranelift_object::object::write::{Relocation, SymbolId};
+use cranelift_object::object::{RelocationEncoding, RelocationFlags, RelocationKind, SectionKind};
+use cranelift_object::ObjectProduct;
+
+/// The debug information of a compiled alpha function.
+pub struct Function {
+    pub id: FuncId,
+    pub name: String,
+    /// Whether other objects can call it.
+    pub exported: bool,
+    /// The file it is defined in, as a position in the files of `write`.
+    pub file: usize,
+    /// The line it is defined on.
+    pub line: usize,
+    pub size: u32,
+    /// The offset in its code where each line starts, in order.
+    pub lines: Vec<(u32, usize)>,
+    /// The type annotation of what it returns.
+    pub returns: String,
+    pub locals: Vec<Local>,
+    /// How to find the caller's frame, for `.debug_frame`.
+    pub unwind: Option<CfaUnwindInfo>,
+}
+
+/// A parameter or variable of a `Function`, in the order they are bound.
+pub struct Local {
+    pub name: String,
+    /// The type annotation of its values.
+    pub ty: String,
+    pub param: bool,
+    pub line: usize,
+    /// Where it is held between two offsets in the code of the function.
+    pub ranges: Vec<(u32, u32, Place)>,
+}
+
+#[derive(Clone, Copy)]
+pub enum Place {
+    /// The DWARF number of a register.
+    Register(u16),
+    /// An offset from the canonical frame address, where the frame of the
+    /// function starts.
+    Frame(i64),
+}
+
+/// Adds the DWARF sections for `functions` to `product`, which compiled them
+/// from `files`, the program last: a compile unit with the line program, a
+/// subprogram of each function with its parameters and variables, and
+/// `.debug_frame`.
+pub fn write(
+    product: &mut ObjectProduct,
+    files: &[String],
+    functions: &[Function],
+    endian: RunTimeEndian,
+    cie: Option<gimli::write::CommonInformationEntry>,
+) {
+    let encoding = Encoding {
+        format: Format::Dwarf32,
+        version: 4,
+        address_size: 8,
+    };
+    let mut dwarf = DwarfUnit::new(encoding);
+    let dir = std::env::current_dir().unwrap().display().to_string();
+    let file = files.last().unwrap();
+    dwarf.unit.line_program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(dir.clone().into_bytes()),
+        LineString::String(file.as_bytes().to_vec()),
+        None,
+    );
+    let directory = dwarf.unit.line_program.default_directory();
+    let file_ids: Vec<FileId> = files
+        .iter()
+        .map(|file| {
+            dwarf.unit.line_program.add_file(
+                LineString::String(file.as_bytes().to_vec()),
+                directory,
+                None,
+            )
+        })
+        .collect();
+
+    let root = dwarf.unit.root();
+    let producer = format!("alpha {}", env!("CARGO_PKG_VERSION"));
+    let cu = dwarf.unit.get_mut(root);
+    cu.set(constants::DW_AT_producer, string(&producer));
+    cu.set(constants::DW_AT_name, string(file));
+    cu.set(constants::DW_AT_comp_dir, string(&dir));
+    cu.set(
+        constants::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(0)),
+    );
+    let ranges = RangeList(
+        functions
+            .iter()
+            .enumerate()
+            .map(|(symbol, function)| Range::StartLength {
+                begin: Address::Symbol { symbol, addend: 0 },
+                length: function.size as u64,
+            })
+            .collect(),
+    );
+    let ranges = dwarf.unit.ranges.add(ranges);
+    let cu = dwarf.unit.get_mut(root);
+    cu.set(
+        constants::DW_AT_ranges,
+        AttributeValue::RangeListRef(ranges),
+    );
+
+    let mut types = HashMap::new();
+    for (symbol, function) in functions.iter().enumerate() {
+        let file_id = file_ids[function.file];
+        line_rows(&mut dwarf.unit.line_program, file_id, symbol, function);
+        subprogram(&mut dwarf, &mut types, file_id, symbol, function);
+    }
+
+    let mut sections = Sections::new(Section::new(endian));
+    dwarf.write(&mut sections).unwrap();
+    if let Some(cie) = cie {
+        let mut frames = FrameTable::default();
+        let cie = frames.add_cie(cie);
+        for (symbol, function) in functions.iter().enumerate() {
+            if let Some(unwind) = &function.unwind {
+                frames.add_fde(cie, unwind.to_fde(Address::Symbol { symbol, addend: 0 }));
+            }
+        }
+        frames.write_debug_frame(&mut sections.debug_frame).unwrap();
+    }
+
+    let symbols: Vec<SymbolId> = functions
+        .iter()
+        .map(|function| product.function_symbol(function.id))
+        .collect();
+    let mut ids = HashMap::new();
+    sections
+        .for_each(|id, section| {
+            if !section.data.slice().is_empty() {
+                let object_id = product.object.add_section(
+                    Vec::new(),
+                    id.name().as_bytes().to_vec(),
+                    SectionKind::Debug,
+                );
+                product
+                    .object
+                    .append_section_data(object_id, section.data.slice(), 1);
+                ids.insert(id, object_id);
+            }
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+    sections
+        .for_each(|id, section| {
+            for reloc in &section.relocs {
+                let symbol = match reloc.target {
+                    Target::Symbol(symbol) => symbols[symbol],
+                    Target::Section(section) => product.object.section_symbol(ids[&section]),
+                };
+                let flags = RelocationFlags::Generic {
+                    kind: RelocationKind::Absolute,
+                    encoding: RelocationEncoding::Generic,
+                    size: reloc.size * 8,
+                };
+                let relocation = Relocation {
+                    offset: reloc.offset as u64,
+                    symbol,
+                    addend: reloc.addend,
+                    flags,
+                };
+                product.object.add_relocation(ids[&id], relocation).unwrap();
+            }
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+}
+
+fn string(s: &str) -> AttributeValue {
+    AttributeValue::String(s.as_bytes().to_vec())
+}
+
+/// Maps the code of `function`, the symbol `symbol`, to its lines.
+fn line_rows(program: &mut LineProgram, file: FileId, symbol: usize, function: &Function) {
+    program.begin_sequence(Some(Address::Symbol { symbol, addend: 0 }));
+    let start = (0, function.line);
+    for &(offset, line) in std::iter::once(&start).chain(&function.lines) {
+        let row = program.row();
+        row.address_offset = offset as u64;
+        row.file = file;
+        row.line = line as u64;
+        program.generate_row();
+    }
+    program.end_sequence(function.size as u64);
+}
+
+/// Adds the subprogram of `function`, the symbol `symbol`, with an entry for
+/// each of its locals.
+fn subprogram(
+    dwarf: &mut DwarfUnit,
+    types: &mut HashMap<String, UnitEntryId>,
+    file: FileId,
+    symbol: usize,
+    function: &Function,
+) {
+    let root = dwarf.unit.root();
+    let returns = base_type(dwarf, types, &function.returns);
+    let id = dwarf.unit.add(root, constants::DW_TAG_subprogram);
+    let entry = dwarf.unit.get_mut(id);
+    entry.set(constants::DW_AT_name, string(&function.name));
+    if function.exported {
+        entry.set(constants::DW_AT_external, AttributeValue::Flag(true));
+    }
+    entry.set(
+        constants::DW_AT_decl_file,
+        AttributeValue::FileIndex(Some(file)),
+    );
+    entry.set(
+        constants::DW_AT_decl_line,
+        AttributeValue::Udata(function.line as u64),
+    );
+    entry.set(constants::DW_AT_type, AttributeValue::UnitRef(returns));
+    entry.set(
+        constants::DW_AT_low_pc,
+        AttributeValue::Address(Address::Symbol { symbol, addend: 0 }),
+    );
+    entry.set(
+        constants::DW_AT_high_pc,
+        AttributeValue::Udata(function.size as u64),
+    );
+    let mut frame_base = Expression::new();
+    frame_base.op(constants::DW_OP_call_frame_cfa);
+    entry.set(
+        constants::DW_AT_frame_base,
+        AttributeValue::Exprloc(frame_base),
+    );
+
+    for local in &function.locals {
+        let ty = base_type(dwarf, types, &local.ty);
+        let locations = LocationList(
+            local
+                .ranges
+                .iter()
+                .map(|&(start, end, place)| {
+                    let mut data = Expression::new();
+                    match place {
+                        Place::Register(register) => data.op_reg(gimli::Register(register)),
+                        Place::Frame(offset) => data.op_fbreg(offset),
+                    }
+                    Location::StartLength {
+                        begin: Address::Symbol {
+                            symbol,
+                            addend: start as i64,
+                        },
+                        length: (end - start) as u64,
+                        data,
+                    }
+                })
+                .collect(),
+        );
+        let locations = dwarf.unit.locations.add(locations);
+        let tag = match local.param {
+            true => constants::DW_TAG_formal_parameter,
+            false => constants::DW_TAG_variable,
+        };
+        let variable = dwarf.unit.add(id, tag);
+        let entry = dwarf.unit.get_mut(variable);
+        entry.set(constants::DW_AT_name, string(&local.name));
+        entry.set(
+            constants::DW_AT_decl_file,
+            AttributeValue::FileIndex(Some(file)),
+        );
+        entry.set(
+            constants::DW_AT_decl_line,
+            AttributeValue::Udata(local.line as u64),
+        );
+        entry.set(constants::DW_AT_type, AttributeValue::UnitRef(ty));
+        entry.set(
+            constants::DW_AT_location,
+            AttributeValue::LocationListRef(locations),
+        );
+    }
+}
+
+/// The base type of the values of the alpha type `name`, added once: ints,
+/// floats and bools are held as such, anything else as a pointer.
+fn base_type(
+    dwarf: &mut DwarfUnit,
+    types: &mut HashMap<String, UnitEntryId>,
+    name: &str,
+) -> UnitEntryId {
+    if let Some(&id) = types.get(name) {
+        return id;
+    }
+    let (encoding, size) = match name {
+        "int" | "unit" => (constants::DW_ATE_signed, 8),
+        "float" => (constants::DW_ATE_float, 8),
+        "bool" => (constants::DW_ATE_boolean, 1),
+        _ => (constants::DW_ATE_address, 8),
+    };
+    let root = dwarf.unit.root();
+    let id = dwarf.unit.add(root, constants::DW_TAG_base_type);
+    let entry = dwarf.unit.get_mut(id);
+    entry.set(constants::DW_AT_name, string(name));
+    entry.set(
+        constants::DW_AT_encoding,
+        AttributeValue::Encoding(encoding),
+    );
+    entry.set(constants::DW_AT_byte_size, AttributeValue::Data1(size));
+    types.insert(name.to_string(), id);
+    id
+}
+
+/// What a relocation of a DWARF section refers to: a function, by its
+/// position in the functions given to `write`, or another section.
+#[derive(Clone, Copy)]
+enum Target {
+    Symbol(usize),
+    Section(gimli::SectionId),
+}
+
+#[derive(Clone)]
+struct Reloc {
+    offset: usize,
+    size: u8,
+    target: Target,
+    addend: i64,
+}
+
+/// A DWARF section, with the relocations that the linker applies to the
+/// addresses of functions and the offsets into other sections in it.
+#[derive(Clone)]
+struct Section {
+    data: EndianVec<RunTimeEndian>,
+    relocs: Vec<Reloc>,
+}
+
+impl Section {
+    fn new(endian: RunTimeEndian) -> Self {
+        Section {
+            data: EndianVec::new(endian),
+            relocs: Vec::new(),
+        }
+    }
+}
+
+impl Writer for Section {
+    type Endian = RunTimeEndian;
+
+    fn endian(&self) -> Self::Endian {
+        self.data.endian()
+    }
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> gimli::write::Result<()> {
+        self.data.write(bytes)
+    }
+
+    fn write_at(&mut self, offset: usize, bytes: &[u8]) -> gimli::write::Result<()> {
+        self.data.write_at(offset, bytes)
+    }
+
+    fn write_address(&mut self, address: Address, size: u8) -> gimli::write::Result<()> {
+        match address {
+            Address::Constant(val) => self.write_udata(val, size),
+            Address::Symbol { symbol, addend } => {
+                self.relocs.push(Reloc {
+                    offset: self.len(),
+                    size,
+                    target: Target::Symbol(symbol),
+                    addend,
+                });
+                self.write_udata(0, size)
+            }
+        }
+    }
+
+    fn write_offset(
+        &mut self,
+        val: usize,
+        section: gimli::SectionId,
+        size: u8,
+    ) -> gimli::write::Result<()> {
+        self.relocs.push(Reloc {
+            offset: self.len(),
+            size,
+            target: Target::Section(section),
+            addend: val as i64,
+        });
+        self.write_udata(0, size)
+    }
+
+    fn write_offset_at(
+        &mut self,
+        offset: usize,
+        val: usize,
+        section: gimli::SectionId,
+        size: u8,
+    ) -> gimli::write::Result<()> {
+        self.relocs.push(Reloc {
+            offset,
+            size,
+            target: Target::Section(section),
+            addend: val as i64,
+        });
+        self.write_udata_at(offset, 0, size)
+    }
+}
@@ -1,230 +1,409 @@
-use std::{collections::HashMap, iter::zip};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    iter::zip,
+    rc::Rc,
+};
 
 use crate::ast;
+use crate::ffi;
+use crate::io;
+use crate::lists;
+use crate::matching;
+use crate::math;
+use crate::process;
+use crate::value::{self, EnumType, Error, Function, Map, Range, StructType, Value};
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default)]
 pub struct Scope {
-    vars: HashMap<String, ast::Node>,
-    parent: HashMap<String, ast::Node>,
+    vars: HashMap<String, Value>,
+    parent: Rc<HashMap<String, Value>>,
+    /// The names in `vars` and in `parent` that were bound with `let mut`.
+    mutable: HashSet<String>,
+    parent_mutable: Rc<HashSet<String>>,
 }
 
 impl Scope {
-    fn get(&self, key: &String) -> Option<&ast::Node> {
+    fn get(&self, key: &String) -> Option<&Value> {
         self.vars.get(key).or_else(|| self.parent.get(key))
     }
 
-    fn combined(&self) -> HashMap<String, ast::Node> {
-        let mut combined = self.parent.clone();
-        combined.extend(self.vars.clone().into_iter());
-        combined
+    fn bind(&mut self, key: &str, value: Value, mutable: bool) {
+        self.vars.insert(key.to_string(), value);
+        if mutable {
+            self.mutable.insert(key.to_string());
+        } else {
+            self.mutable.remove(key);
+        }
+    }
+
+    fn bind_pattern(
+        &mut self,
+        pattern: &ast::Pattern,
+        value: Value,
+        mutable: bool,
+    ) -> Result<(), String> {
+        match pattern {
+            ast::Pattern::Name(name) => self.bind(name, value, mutable),
+            ast::Pattern::Tuple(patterns) => {
+                for (pattern, item) in zip(patterns, value.destructure(patterns.len())?) {
+                    self.bind_pattern(pattern, item, mutable)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The variable behind an assignment, copied out of `parent` if needed.
+    fn assignable(&mut self, key: &String) -> Result<&mut Value, String> {
+        if !self.vars.contains_key(key) {
+            let value = self
+                .parent
+                .get(key)
+                .ok_or_else(|| format!("Undefined variable: '{}'", key))?;
+            if !self.parent_mutable.contains(key) {
+                return Err(format!("Cannot assign to immutable variable: '{}'", key));
+            }
+            self.bind(key, value.clone(), true);
+        } else if !self.mutable.contains(key) {
+            return Err(format!("Cannot assign to immutable variable: '{}'", key));
+        }
+        Ok(self.vars.get_mut(key).unwrap())
+    }
+
+    fn combined_mutable(&self) -> Rc<HashSet<String>> {
+        if self.vars.is_empty() {
+            return self.parent_mutable.clone();
+        }
+        let mut combined: HashSet<String> = self
+            .parent_mutable
+            .iter()
+            .filter(|key| !self.vars.contains_key(*key))
+            .cloned()
+            .collect();
+        combined.extend(self.mutable.iter().cloned());
+        Rc::new(combined)
+    }
+
+    fn combined(&self) -> Rc<HashMap<String, Value>> {
+        if self.vars.is_empty() {
+            return self.parent.clone();
+        }
+        let mut combined = HashMap::clone(&self.parent);
+        combined.extend(self.vars.iter().map(|(k, v)| (k.clone(), v.clone())));
+        Rc::new(combined)
     }
 }
 
-#[derive(Default)]
-pub struct Eval {}
+/// A function together with a snapshot of the scope it was defined in.
+pub struct Closure {
+    pub name: Option<String>,
+    params: Vec<(ast::Pattern, Option<String>)>,
+    body: Rc<ast::Node>,
+    typename: Option<String>,
+    env: Rc<HashMap<String, Value>>,
+    mutable: Rc<HashSet<String>>,
+}
+
+/// Why evaluation stopped before reaching the end of a node.
+pub enum Unwind {
+    Error(Error),
+    /// A `?` returning `none` from the innermost function.
+    Return(Value),
+}
+
+impl From<String> for Unwind {
+    fn from(e: String) -> Self {
+        Unwind::Error(e.into())
+    }
+}
+
+pub struct Eval {
+    /// The structs declared so far, which are visible everywhere.
+    structs: RefCell<HashMap<String, Rc<StructType>>>,
+    /// The variants of the enums declared so far, with their tags.
+    variants: RefCell<HashMap<String, (Rc<EnumType>, usize)>>,
+    random: RefCell<math::Random>,
+    /// What `args()` returns.
+    args: Vec<String>,
+    /// Where the functions of `extern fn` declarations are found.
+    libraries: ffi::Libraries,
+    /// The functions being called, starting with the top level, each with
+    /// the line it is on, for `backtrace()`.
+    calls: RefCell<Vec<(Option<String>, Option<usize>)>>,
+}
 
 impl Eval {
-    pub fn run(&self, node: &ast::Node) {
-        self.eval(node, &mut Scope::default(), 0);
+    /// An interpreter for a program started with the arguments `args`,
+    /// calling C functions in `libraries`.
+    pub fn new(args: Vec<String>, libraries: ffi::Libraries) -> Self {
+        let eval = Eval {
+            structs: RefCell::default(),
+            variants: RefCell::default(),
+            random: RefCell::default(),
+            args,
+            libraries,
+            calls: RefCell::new(vec![(None, None)]),
+        };
+        eval.declare_enum(value::option_type());
+        eval
     }
 
-    fn eval(&self, node: &ast::Node, scope: &mut Scope, depth: usize) -> ast::Node {
-        match node {
+    pub fn run(&self, node: &ast::Node) -> Result<Value, String> {
+        self.declare_enums(node);
+        self.check(node)?;
+        match self.eval(node, &mut Scope::default()) {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(e)) => Err(e.to_string()),
+        }
+    }
+
+    fn eval(&self, node: &ast::Node, scope: &mut Scope) -> Result<Value, Unwind> {
+        Ok(match node {
             ast::Node::Statements(nodes) => {
-                let mut result = ast::Node::Nada;
+                let mut result = Value::Unit;
                 for node in nodes {
-                    // let pad = " ".repeat(2*depth);
-                    result = self.eval(node, scope, depth);
-                    // println!("{}{:?} - {:?}", pad, result, node);
+                    result = self.eval(node, scope)?;
                 }
                 result
             }
-            ast::Node::Define(_mutable, name, expr, _typename) => {
-                let val = self.eval(expr, scope, depth);
-                scope.vars.insert(name.clone(), val);
-                ast::Node::Nada
+            // Errors are tagged with the innermost line, like the vm does.
+            ast::Node::Line(line, node) => {
+                let outer = self.at_line(Some(*line));
+                let result = self.eval(node, scope);
+                self.at_line(outer);
+                result.map_err(|unwind| match unwind {
+                    Unwind::Error(mut e) => {
+                        e.at_line(*line);
+                        Unwind::Error(e)
+                    }
+                    unwind => unwind,
+                })?
+            }
+            ast::Node::Throw(value) => {
+                let value = self.eval(value, scope)?;
+                return Err(Unwind::Error(Error::throw(value)));
+            }
+            ast::Node::TryCatch {
+                body,
+                name,
+                handler,
+            } => match self.eval(body, scope) {
+                Err(Unwind::Error(e)) => {
+                    scope.bind(name, e.into_value(), false);
+                    self.eval(handler, scope)?
+                }
+                result => result?,
+            },
+            ast::Node::Define(mutable, pattern, expr, typename) => {
+                let mut val = self.eval(expr, scope)?;
+                if let Some(typename) = typename {
+                    val = val.check_type(typename)?;
+                }
+                scope.bind_pattern(pattern, val, matches!(mutable, ast::Mut::Mutable))?;
+                Value::Unit
             }
             ast::Node::Assign(name, expr) => {
-                let val = self.eval(expr, scope, depth);
-                scope.vars.insert(name.clone(), val);
-                ast::Node::Nada
+                let val = self.eval(expr, scope)?;
+                *scope.assignable(name)? = val;
+                Value::Unit
+            }
+            ast::Node::IndexAssign(name, steps, expr) => {
+                let indices = steps
+                    .iter()
+                    .map(|step| match step {
+                        ast::Step::Index(index) => self.eval(index, scope),
+                        ast::Step::Field(field) => Ok(Value::String(field.as_str().into())),
+                    })
+                    .collect::<Result<Vec<Value>, Unwind>>()?;
+                let val = self.eval(expr, scope)?;
+                scope.assignable(name)?.set_index(&indices, val)?;
+                Value::Unit
             }
-            ast::Node::Bool(b) => ast::Node::Bool(*b),
-            ast::Node::Number(n) => ast::Node::Number(*n),
-            ast::Node::String(s) => ast::Node::String(s.clone()),
-            ast::Node::List(list) => ast::Node::List(
+            ast::Node::Bool(b) => Value::Bool(*b),
+            ast::Node::Int(n) => Value::Int(*n),
+            ast::Node::BigInt(n) => Value::BigInt(n.clone()),
+            ast::Node::Float(n) => Value::Float(*n),
+            ast::Node::String(s) => Value::String(s.as_str().into()),
+            ast::Node::List(list) => Value::List(Rc::new(
                 list.iter()
-                    .map(|n| self.eval(n, scope, depth))
-                    .collect::<Vec<ast::Node>>(),
-            ),
+                    .map(|n| self.eval(n, scope))
+                    .collect::<Result<Vec<Value>, Unwind>>()?,
+            )),
+            ast::Node::Tuple(items) => Value::Tuple(Rc::new(
+                items
+                    .iter()
+                    .map(|n| self.eval(n, scope))
+                    .collect::<Result<Vec<Value>, Unwind>>()?,
+            )),
+            ast::Node::Map(pairs) => {
+                let mut map = Map::default();
+                for (key, value) in pairs {
+                    map.insert(self.eval(key, scope)?, self.eval(value, scope)?)?;
+                }
+                Value::Map(Rc::new(map))
+            }
+            ast::Node::StructDef(name, fields) => {
+                let ty = StructType {
+                    name: name.clone(),
+                    fields: fields.clone(),
+                };
+                self.structs.borrow_mut().insert(name.clone(), Rc::new(ty));
+                Value::Unit
+            }
+            ast::Node::StructLit(name, fields) => {
+                let ty = self
+                    .structs
+                    .borrow()
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| format!("Undefined struct: '{}'", name))?;
+                let values = ty
+                    .order(fields)?
+                    .into_iter()
+                    .map(|value| self.eval(value, scope))
+                    .collect::<Result<Vec<Value>, Unwind>>()?;
+                ty.construct(values)?
+            }
+            ast::Node::Field(record, field) => self.eval(record, scope)?.field(field)?,
+            // Declared up front by `declare_enums`.
+            ast::Node::EnumDef(..) => Value::Unit,
+            ast::Node::Try(value) => {
+                let value = self.eval(value, scope)?;
+                match value.option()? {
+                    Some(value) => value,
+                    None => return Err(Unwind::Return(value)),
+                }
+            }
+            ast::Node::Match { subject, arms } => {
+                let subject = self.eval(subject, scope)?;
+                let patterns = self.resolve_arms(arms)?;
+                for (arm, pattern) in zip(arms, &patterns) {
+                    let mut bindings = Vec::new();
+                    if !matching::matches(pattern, &subject, &mut bindings) {
+                        continue;
+                    }
+                    for (name, value) in bindings {
+                        scope.bind(name, value, false);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        match self.eval(guard, scope)? {
+                            Value::Bool(true) => {}
+                            Value::Bool(false) => continue,
+                            value => return Err(format!("Not a bool: '{:?}'", value).into()),
+                        }
+                    }
+                    return self.eval(&arm.body, scope);
+                }
+                return Err(format!("No arm matches {:?}", subject).into());
+            }
+            ast::Node::Index(list, index) => {
+                let list = self.eval(list, scope)?;
+                list.index(&self.eval(index, scope)?)?
+            }
+            ast::Node::Slice { list, from, to } => {
+                let list = self.eval(list, scope)?;
+                let from = from
+                    .as_ref()
+                    .map(|from| self.eval(from, scope))
+                    .transpose()?;
+                let to = to.as_ref().map(|to| self.eval(to, scope)).transpose()?;
+                list.slice(from.as_ref(), to.as_ref())?
+            }
+            ast::Node::Range {
+                from,
+                to,
+                lower,
+                upper,
+            } => match (self.eval(from, scope)?, self.eval(to, scope)?) {
+                (Value::Int(from), Value::Int(to)) => Value::Range(Range {
+                    from,
+                    to,
+                    lower: *lower,
+                    upper: *upper,
+                }),
+                (from, to) => return Err(format!("Not an int: '{:?}..{:?}'", from, to).into()),
+            },
             ast::Node::While { condition, inner } => {
-                let mut result = ast::Node::Nada;
+                let mut result = Value::Unit;
                 loop {
-                    let cond = self.eval(condition, scope, depth);
-                    match cond {
-                        ast::Node::Bool(b) => {
-                            if !b {
-                                break;
-                            }
-                        }
-                        _ => panic!("condition is not a bool"),
+                    match self.eval(condition, scope)? {
+                        Value::Bool(true) => {}
+                        Value::Bool(false) => break,
+                        value => return Err(format!("Not a bool: '{:?}'", value).into()),
                     };
-                    result = self.eval(&inner, scope, depth);
+                    result = self.eval(inner, scope)?;
                 }
                 result
             }
             ast::Node::Loop {
                 var,
+                value,
                 iterable,
                 inner,
             } => {
-                let iterable = self.eval(iterable, scope, depth);
-                let mut results = Vec::<ast::Node>::new();
-                match iterable {
-                    ast::Node::Range {
-                        from,
-                        to,
-                        lower,
-                        upper,
-                    } => {
-                        let mut start = match from.as_ref() {
-                            ast::Node::Number(n) => *n,
-                            ast::Node::VarRef(_) => {
-                                match self.eval(from.as_ref(), scope, depth + 1) {
-                                    ast::Node::Number(n) => n,
-                                    _ => panic!("Not a number: '{:?}'", node),
-                                }
-                            }
-                            _ => panic!("unsupported range start"),
-                        };
-
-                        let mut end: f64 = match to.as_ref() {
-                            ast::Node::Number(n) => *n,
-                            ast::Node::VarRef(_) => {
-                                match self.eval(to.as_ref(), scope, depth + 1) {
-                                    ast::Node::Number(n) => n,
-                                    _ => panic!("Not a number: '{:?}'", node),
-                                }
-                            }
-                            _ => panic!("unsupported range start"),
-                        };
-
-                        start += match lower {
-                            ast::Bound::Inclusive => 0.0,
-                            ast::Bound::Exclusive => 1.0,
-                        };
-                        end += match upper {
-                            ast::Bound::Inclusive => 1.0,
-                            ast::Bound::Exclusive => 0.0,
-                        };
-
-                        for i in start as i64..end as i64 {
-                            scope.vars.insert(var.clone(), ast::Node::Number(i as f64));
-                            match self.eval(inner, scope, depth + 1) {
-                                ast::Node::Nada => {}
-                                node => results.push(node),
-                            }
+                let mut results = Vec::<Value>::new();
+                let iterable = self.eval(iterable, scope)?;
+                let mut index = 0;
+                while let Some(entry) = iterable.entry(index)? {
+                    match value {
+                        Some(value) => {
+                            scope.bind_pattern(var, entry.0, false)?;
+                            scope.bind_pattern(value, entry.1, false)?;
                         }
+                        None => scope.bind_pattern(var, iterable.loop_var(entry), false)?,
                     }
-                    ast::Node::List(list) => {
-                        for i in list {
-                            scope.vars.insert(var.clone(), i);
-                            match self.eval(inner, scope, depth + 1) {
-                                ast::Node::Nada => {}
-                                node => results.push(node),
-                            }
-                        }
+                    match self.eval(inner, scope)? {
+                        Value::Unit => {}
+                        value => results.push(value),
                     }
-                    node => panic!("Not an iterable: '{:?}'", node),
+                    index += 1;
                 }
-                ast::Node::List(results)
+                Value::List(Rc::new(results))
             }
-            ast::Node::FnDef(name, params, inner, _typename) => {
-                let fn_scope = Scope {
-                    vars: HashMap::new(),
-                    parent: scope.combined(),
-                };
-                let def =
-                    ast::Node::ScopedFnDef(name.clone(), params.clone(), inner.clone(), fn_scope);
-
+            ast::Node::FnDef(name, params, body, typename) => {
+                let closure = Value::Function(Function::Interpreted(Rc::new(Closure {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    typename: typename.clone(),
+                    env: scope.combined(),
+                    mutable: scope.combined_mutable(),
+                })));
                 if let Some(name) = name {
-                    scope.vars.insert(name.clone(), def.clone());
+                    scope.bind(name, closure.clone(), false);
                 }
-                def
+                closure
+            }
+            ast::Node::Extern(name, params, typename) => {
+                let function = ffi::Extern::new(name, params, typename)?;
+                let function = Value::Function(Function::Foreign(Rc::new(function)));
+                scope.bind(name, function, false);
+                Value::Unit
             }
-            ast::Node::ScopedFnDef(_, _, _, _) => node.clone(),
-            ast::Node::TypeName(_) => node.clone(),
+            ast::Node::TypeName(_) => Value::Unit,
+            ast::Node::Pub(_) | ast::Node::Import(..) => unreachable!("removed by modules::load"),
             ast::Node::IfElse {
                 condition,
                 if_block,
                 else_block,
-            } => match self.eval(condition, scope, depth) {
-                ast::Node::Bool(true) => self.eval(if_block, scope, depth),
-                ast::Node::Bool(false) => self.eval(else_block, scope, depth),
-                node => panic!("Not a bool: '{:?}'", node),
+            } => match self.eval(condition, scope)? {
+                Value::Bool(true) => self.eval(if_block, scope)?,
+                Value::Bool(false) => self.eval(else_block, scope)?,
+                value => return Err(format!("Not a bool: '{:?}'", value).into()),
             },
             ast::Node::Expr { op, lhs, rhs } => {
-                let lhs = self.eval(lhs, scope, depth);
-                let rhs = self.eval(rhs, scope, depth);
-
-                match (op, lhs, rhs) {
-                    (_, ast::Node::Number(a), ast::Node::Number(b)) => match op {
-                        ast::Op::Add => ast::Node::Number(a + b),
-                        ast::Op::Sub => ast::Node::Number(a - b),
-                        ast::Op::Mul => ast::Node::Number(a * b),
-                        ast::Op::Div => ast::Node::Number(a / b),
-                        ast::Op::Eq => ast::Node::Bool(a == b),
-                        ast::Op::Neq => ast::Node::Bool(a != b),
-                        ast::Op::Gt => ast::Node::Bool(a > b),
-                        ast::Op::Ge => ast::Node::Bool(a >= b),
-                        ast::Op::Lt => ast::Node::Bool(a < b),
-                        ast::Op::Le => ast::Node::Bool(a <= b),
-                    },
-                    (ast::Op::Add, ast::Node::List(a), ast::Node::List(b)) => {
-                        ast::Node::List(a.iter().chain(b.iter()).cloned().collect())
-                    }
-                    (ast::Op::Mul, ast::Node::Number(a), ast::Node::List(b)) => ast::Node::List(
-                        b.iter()
-                            .map(|x| match x {
-                                ast::Node::Number(n) => ast::Node::Number(a * n),
-                                _ => self.eval(
-                                    &ast::Node::Expr {
-                                        op: ast::Op::Mul,
-                                        lhs: Box::new(ast::Node::Number(a)),
-                                        rhs: Box::new(x.clone()),
-                                    },
-                                    scope,
-                                    depth,
-                                ),
-                            })
-                            .collect(),
-                    ),
-                    (ast::Op::Div, ast::Node::List(a), ast::Node::Number(b)) => ast::Node::List(
-                        a.iter()
-                            .map(|x| match x {
-                                ast::Node::Number(n) => ast::Node::Number(n / b),
-                                _ => self.eval(
-                                    &ast::Node::Expr {
-                                        op: ast::Op::Div,
-                                        lhs: Box::new(x.clone()),
-                                        rhs: Box::new(ast::Node::Number(b)),
-                                    },
-                                    scope,
-                                    depth,
-                                ),
-                            })
-                            .collect(),
-                    ),
-                    _ => panic!(),
-                }
+                let lhs = self.eval(lhs, scope)?;
+                let rhs = self.eval(rhs, scope)?;
+                Value::binary(op, lhs, rhs)?
             }
             ast::Node::FnCall(name, args) => {
                 let args = args
                     .iter()
-                    .map(|arg| self.eval(arg, scope, depth))
-                    .collect::<Vec<ast::Node>>();
+                    .map(|arg| self.eval(arg, scope))
+                    .collect::<Result<Vec<Value>, Unwind>>()?;
                 match name.as_str() {
-                    "printf" => {
+                    "print" | "printf" => {
                         println!(
                             "{}",
                             args.iter()
@@ -232,34 +411,192 @@ impl Eval {
                                 .collect::<Vec<String>>()
                                 .join(" ")
                         );
-                        ast::Node::Nada
+                        Value::Unit
                     }
-                    _ => {
-                        let fndef = scope
-                            .get(name)
-                            .expect(format!("Undefined function: '{}'", name).as_str());
-                        let (defname, names, inner, mut fn_scope) = match fndef {
-                            ast::Node::ScopedFnDef(defname, names, inner, scope) => {
-                                (defname, names, inner, scope.clone())
-                            }
-                            _ => panic!("Not a function: '{}'", name),
-                        };
-                        if let Some(defname) = defname {
-                            fn_scope.vars.insert(defname.clone(), fndef.clone());
-                        }
-                        for (name, arg) in zip(names, args) {
-                            fn_scope.vars.insert(name.clone(), arg);
-                        }
-                        self.eval(inner, &mut fn_scope, depth + 1)
+                    "eprint" => {
+                        io::eprint(&args);
+                        Value::Unit
+                    }
+                    "backtrace" if !args.is_empty() => {
+                        return Err("backtrace takes no arguments".to_string().into())
                     }
+                    "backtrace" => {
+                        println!("{}", self.backtrace());
+                        Value::Unit
+                    }
+                    "int" | "bigint" | "float" | "keys" | "values" if args.len() != 1 => {
+                        return Err(format!("{} takes 1 argument", name).into())
+                    }
+                    "has" | "remove" | "unwrap_or" if args.len() != 2 => {
+                        return Err(format!("{} takes 2 arguments", name).into())
+                    }
+                    "int" => args[0].to_int()?,
+                    "bigint" => args[0].to_bigint()?,
+                    "float" => args[0].to_float()?,
+                    "keys" => args[0].keys()?,
+                    "values" => args[0].values()?,
+                    "has" => args[0].has(&args[1])?,
+                    "remove" => args[0].remove(&args[1])?,
+                    "unwrap_or" => args[0].option()?.unwrap_or_else(|| args[1].clone()),
+                    _ if lists::arity(name).is_some() && scope.get(name).is_none() => {
+                        lists::apply(name, args, &mut |function, args| self.call(function, args))?
+                    }
+                    _ if math::arity(name).is_some() && scope.get(name).is_none() => {
+                        math::apply(name, &args, &mut self.random.borrow_mut())?
+                    }
+                    _ if io::arity(name).is_some() && scope.get(name).is_none() => {
+                        io::apply(name, &args)?
+                    }
+                    _ if process::arity(name).is_some() && scope.get(name).is_none() => {
+                        process::apply(name, &args, &self.args)?
+                    }
+                    _ => match scope.get(name) {
+                        Some(function) => {
+                            let function = function.clone();
+                            self.call(&function, args)?
+                        }
+                        None => match self.variant(name) {
+                            Some((ty, tag)) => ty.construct(tag, args)?,
+                            None => return Err(format!("Undefined function: '{}'", name).into()),
+                        },
+                    },
                 }
             }
-            ast::Node::VarRef(name) => scope
-                .get(name)
-                .expect(format!("Undefined variable: '{}'", name).as_str())
-                .clone(),
-            ast::Node::Range { .. } => node.clone(),
-            ast::Node::Nada => ast::Node::Nada,
+            ast::Node::VarRef(name) => match (scope.get(name), self.variant(name)) {
+                (Some(value), _) => value.clone(),
+                (None, Some((ty, tag))) => ty.construct(tag, Vec::new())?,
+                (None, None) => match math::constant(name) {
+                    Some(value) => value,
+                    None => return Err(format!("Undefined variable: '{}'", name).into()),
+                },
+            },
+            ast::Node::Nada => Value::Unit,
+        })
+    }
+
+    /// Calls `function` with `args`, like the list builtins do.
+    fn call(&self, function: &Value, args: Vec<Value>) -> Result<Value, Unwind> {
+        let closure = match function {
+            Value::Function(Function::Interpreted(closure)) => closure.clone(),
+            Value::Function(Function::Foreign(function)) => {
+                return Ok(self.libraries.call(function, args)?)
+            }
+            value => return Err(format!("Not a function: '{:?}'", value).into()),
+        };
+        let mut fn_scope = Scope {
+            vars: HashMap::new(),
+            parent: closure.env.clone(),
+            mutable: HashSet::new(),
+            parent_mutable: closure.mutable.clone(),
+        };
+        if let Some(defname) = &closure.name {
+            fn_scope.vars.insert(
+                defname.clone(),
+                Value::Function(Function::Interpreted(closure.clone())),
+            );
         }
+        for ((pattern, typename), mut arg) in zip(&closure.params, args) {
+            if let Some(typename) = typename {
+                arg = arg.check_type(typename)?;
+            }
+            fn_scope.bind_pattern(pattern, arg, false)?;
+        }
+        self.calls.borrow_mut().push((closure.name.clone(), None));
+        let result = self.eval(&closure.body, &mut fn_scope);
+        self.calls.borrow_mut().pop();
+        let result = match result {
+            Ok(result) | Err(Unwind::Return(result)) => result,
+            Err(Unwind::Error(mut e)) => {
+                e.leave(closure.name.as_deref());
+                return Err(Unwind::Error(e));
+            }
+        };
+        Ok(match &closure.typename {
+            Some(typename) => result.check_type(typename)?,
+            None => result,
+        })
+    }
+
+    /// Moves the innermost call to `line`, resulting in the line it was on.
+    fn at_line(&self, line: Option<usize>) -> Option<usize> {
+        let mut calls = self.calls.borrow_mut();
+        std::mem::replace(&mut calls.last_mut().unwrap().1, line)
+    }
+
+    /// The calls being made, traced like an error leaving all of them.
+    fn backtrace(&self) -> Error {
+        let mut e = Error::from("Backtrace".to_string());
+        for (depth, (name, line)) in self.calls.borrow().iter().enumerate().rev() {
+            if let Some(line) = line {
+                e.at_line(*line);
+            }
+            if depth > 0 {
+                e.leave(name.as_deref());
+            }
+        }
+        e
+    }
+
+    /// Declares the enums anywhere in `node` up front, like the vm.
+    fn declare_enums(&self, node: &ast::Node) {
+        match node {
+            ast::Node::EnumDef(name, variants) => self.declare_enum(EnumType {
+                name: name.clone(),
+                variants: variants.clone(),
+            }),
+            ast::Node::FnDef(_, _, body, _) => self.declare_enums(body),
+            _ => node.children().for_each(|child| self.declare_enums(child)),
+        }
+    }
+
+    /// Checks the patterns of every `match` before running anything, so
+    /// that like in the vm they are errors of the program rather than of
+    /// the call that reaches them.
+    fn check(&self, node: &ast::Node) -> Result<(), String> {
+        match node {
+            ast::Node::Line(line, node) => self.check(node).map_err(|e| match e {
+                e if e.starts_with("line ") => e,
+                e => format!("line {}: {}", line, e),
+            }),
+            ast::Node::Match { arms, .. } => {
+                self.resolve_arms(arms)?;
+                node.children().try_for_each(|child| self.check(child))
+            }
+            ast::Node::FnDef(_, _, body, _) => self.check(body),
+            _ => node.children().try_for_each(|child| self.check(child)),
+        }
+    }
+
+    fn declare_enum(&self, ty: EnumType) {
+        let ty = Rc::new(ty);
+        let mut registry = self.variants.borrow_mut();
+        for (tag, variant) in ty.variants.iter().enumerate() {
+            registry.insert(variant.name.clone(), (ty.clone(), tag));
+        }
+    }
+
+    fn variant(&self, name: &str) -> Option<(Rc<EnumType>, usize)> {
+        self.variants.borrow().get(name).cloned()
+    }
+
+    /// The patterns of `arms` with their variants resolved, after checking
+    /// that they are exhaustive.
+    fn resolve_arms(&self, arms: &[ast::Arm]) -> Result<Vec<ast::MatchPattern>, String> {
+        let registry = self.variants.borrow();
+        let arity = |name: &str| {
+            registry
+                .get(name)
+                .map(|(ty, tag)| ty.variants[*tag].fields.len())
+        };
+        let patterns = arms
+            .iter()
+            .map(|arm| matching::resolve(&arm.pattern, &arity, &mut |name| Ok(name.clone())))
+            .collect::<Result<Vec<ast::MatchPattern>, String>>()?;
+        let unguarded: Vec<&ast::MatchPattern> = zip(arms, &patterns)
+            .filter(|(arm, _)| arm.guard.is_none())
+            .map(|(_, pattern)| pattern)
+            .collect();
+        matching::check_exhaustive(&unguarded, &|name| registry[name].0.arities())?;
+        Ok(patterns)
     }
 }
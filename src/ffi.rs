@@ -0,0 +1,292 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr};
+use std::fs;
+use std::path::Path;
+
+use libloading::Library;
+
+use crate::value::Value;
+
+/// The types that `extern fn` declarations take and return. Ints are C
+/// `long`s, cints C `int`s, bools C `bool`s and strings `char *`s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CType {
+    Int,
+    CInt,
+    Float,
+    Bool,
+    Str,
+}
+
+impl CType {
+    pub fn parse(typename: &str) -> Result<CType, String> {
+        match typename {
+            "int" => Ok(CType::Int),
+            "cint" => Ok(CType::CInt),
+            "float" => Ok(CType::Float),
+            "bool" => Ok(CType::Bool),
+            "str" => Ok(CType::Str),
+            typename => Err(format!("Unsupported type in extern fn: {}", typename)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CType::Int => "int",
+            CType::CInt => "cint",
+            CType::Float => "float",
+            CType::Bool => "bool",
+            CType::Str => "str",
+        }
+    }
+
+    /// The alpha type of the values of this type, where cints are ints.
+    pub fn ty(self) -> &'static str {
+        match self {
+            CType::CInt => "int",
+            ty => ty.name(),
+        }
+    }
+}
+
+/// A C function declared with `extern fn name(param: type, ...): type`,
+/// without a result if the return type is left out.
+#[derive(Debug)]
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<CType>,
+    pub returns: Option<CType>,
+}
+
+/// Variadic functions of libc, which take their variable arguments in a
+/// way of their own that neither the interpreter nor compiled code passes
+/// them in.
+const VARIADIC: &[&str] = &[
+    "printf", "fprintf", "dprintf", "sprintf", "snprintf", "asprintf", "scanf", "fscanf", "sscanf",
+    "syslog", "open", "openat", "fcntl", "ioctl", "prctl", "execl", "execlp", "execle", "ulimit",
+    "err", "errx", "warn", "warnx", "syscall",
+];
+
+/// Fails for the functions of `VARIADIC`.
+pub fn reject_variadic(name: &str) -> Result<(), String> {
+    match VARIADIC.contains(&name) {
+        true => Err(format!("Cannot declare variadic C function '{}'", name)),
+        false => Ok(()),
+    }
+}
+
+impl Extern {
+    pub fn new(
+        name: &str,
+        params: &[(String, String)],
+        returns: &Option<String>,
+    ) -> Result<Extern, String> {
+        reject_variadic(name)?;
+        Ok(Extern {
+            name: name.to_string(),
+            params: params
+                .iter()
+                .map(|(_, typename)| CType::parse(typename))
+                .collect::<Result<_, _>>()?,
+            returns: returns.as_deref().map(CType::parse).transpose()?,
+        })
+    }
+}
+
+/// How many ints and floats a call passes in registers, which is all the
+/// interpreter can pass: the C calling conventions of x86-64 and arm64
+/// assign ints and floats to registers of their own, in order, so any
+/// function taking at most as many of each can be called as one taking
+/// all of them.
+const INT_ARGS: usize = 6;
+const FLOAT_ARGS: usize = 8;
+
+type Ints = [i64; INT_ARGS];
+type Floats = [f64; FLOAT_ARGS];
+
+type IntFn = unsafe extern "C" fn(
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+) -> i64;
+type FloatFn = unsafe extern "C" fn(
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    i64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+) -> f64;
+
+/// The libraries that the interpreter finds the functions of `extern fn`
+/// declarations in: the ones given with `--link-lib`, then the libraries
+/// alpha itself is linked with, like libc.
+pub struct Libraries {
+    libraries: Vec<Library>,
+    symbols: RefCell<HashMap<String, *const c_void>>,
+}
+
+impl Libraries {
+    pub fn open(names: &[String]) -> Result<Libraries, String> {
+        let mut libraries = names
+            .iter()
+            .map(|name| open(name))
+            .collect::<Result<Vec<_>, _>>()?;
+        #[cfg(unix)]
+        libraries.push(libloading::os::unix::Library::this().into());
+        Ok(Libraries {
+            libraries,
+            symbols: RefCell::new(HashMap::new()),
+        })
+    }
+
+    fn symbol(&self, name: &str) -> Result<*const c_void, String> {
+        if let Some(&symbol) = self.symbols.borrow().get(name) {
+            return Ok(symbol);
+        }
+        let symbol = self
+            .libraries
+            .iter()
+            .find_map(|library| unsafe {
+                library
+                    .get::<*const c_void>(name.as_bytes())
+                    .ok()
+                    .map(|symbol| *symbol)
+            })
+            .ok_or_else(|| format!("Cannot find C function '{}'", name))?;
+        self.symbols.borrow_mut().insert(name.to_string(), symbol);
+        Ok(symbol)
+    }
+
+    /// Calls the C function of `function` with `args`, which must have
+    /// the types it declares.
+    pub fn call(&self, function: &Extern, args: Vec<Value>) -> Result<Value, String> {
+        if args.len() != function.params.len() {
+            return Err(match function.params.len() {
+                1 => format!("{} takes 1 argument", function.name),
+                n => format!("{} takes {} arguments", function.name, n),
+            });
+        }
+        let mut ints: Ints = [0; INT_ARGS];
+        let mut floats: Floats = [0.0; FLOAT_ARGS];
+        let (mut int, mut float) = (0, 0);
+        let too_many = || {
+            format!(
+                "Cannot call {} with more than {} int or {} float arguments when interpreted",
+                function.name, INT_ARGS, FLOAT_ARGS
+            )
+        };
+        for (ty, arg) in function.params.iter().zip(&args) {
+            let arg = arg.clone().check_type(ty.ty())?;
+            match arg {
+                Value::Float(_) if float == FLOAT_ARGS => return Err(too_many()),
+                Value::Float(n) => {
+                    floats[float] = n;
+                    float += 1;
+                }
+                _ if int == INT_ARGS => return Err(too_many()),
+                arg => {
+                    ints[int] = match &arg {
+                        Value::Int(n) => *n,
+                        Value::Bool(b) => *b as i64,
+                        // Strings end with a nul already.
+                        Value::String(s) => s.as_ptr() as i64,
+                        _ => unreachable!("checked to be {}", ty.ty()),
+                    };
+                    int += 1;
+                }
+            }
+        }
+
+        let symbol = self.symbol(&function.name)?;
+        let [a, b, c, d, e, f] = ints;
+        let [g, h, i, j, k, l, m, n] = floats;
+        // Safety: the declaration is trusted to match the C function, which
+        // is not variadic, and whose string arguments live in `args` for the
+        // duration of the call.
+        let result = unsafe {
+            match function.returns {
+                Some(CType::Float) => {
+                    let call: FloatFn = std::mem::transmute(symbol);
+                    Value::Float(call(a, b, c, d, e, f, g, h, i, j, k, l, m, n))
+                }
+                returns => {
+                    let call: IntFn = std::mem::transmute(symbol);
+                    let result = call(a, b, c, d, e, f, g, h, i, j, k, l, m, n);
+                    match returns {
+                        Some(CType::Int) => Value::Int(result),
+                        // The upper half of the register is left undefined.
+                        Some(CType::CInt) => Value::Int(result as i32 as i64),
+                        Some(CType::Bool) => Value::Bool(result as u8 != 0),
+                        Some(CType::Str) if result == 0 => {
+                            return Err(format!("{} returned a null string", function.name))
+                        }
+                        Some(CType::Str) => {
+                            let s = CStr::from_ptr(result as *const c_char).to_string_lossy();
+                            Value::String(format!("{}\0", s).into())
+                        }
+                        _ => Value::Unit,
+                    }
+                }
+            }
+        };
+        Ok(result)
+    }
+}
+
+/// Opens a library by path, or by a name like `m` as `libm.so`, falling back
+/// to a versioned `libm.so.6` in the usual directories since the unversioned
+/// file is often a linker script.
+fn open(name: &str) -> Result<Library, String> {
+    let failed = |e: &dyn std::fmt::Display| format!("Cannot load library '{}': {}", name, e);
+    if name.contains('/') {
+        return unsafe { Library::new(name) }.map_err(|e| failed(&e));
+    }
+    let e = match unsafe { Library::new(libloading::library_filename(name)) } {
+        Ok(library) => return Ok(library),
+        Err(e) => e,
+    };
+    let prefix = format!("lib{}.so.", name);
+    let arch = std::env::consts::ARCH;
+    let versioned = [
+        format!("/lib/{}-linux-gnu", arch),
+        format!("/usr/lib/{}-linux-gnu", arch),
+        "/lib64".into(),
+        "/usr/lib64".into(),
+        "/lib".into(),
+        "/usr/lib".into(),
+        "/usr/local/lib".into(),
+    ]
+    .into_iter()
+    .flat_map(|dir| fs::read_dir(dir).into_iter().flatten().flatten())
+    .map(|entry| entry.path())
+    .find(|path| {
+        path.file_name()
+            .and_then(|file| file.to_str())
+            .is_some_and(|file| file.starts_with(&prefix))
+    });
+    match versioned {
+        Some(path) => unsafe { Library::new(Path::new(&path)) }.map_err(|e| failed(&e)),
+        None => Err(failed(&e)),
+    }
+}
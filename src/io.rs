@@ -0,0 +1,102 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::value::{self, Value};
+
+/// The file and stdin builtins with the number of arguments they take. Like
+/// the list builtins, a function or variable of the same name shadows them.
+/// They fail with catchable errors, whose messages `runtime/src/io.rs` repeats.
+pub const BUILTINS: &[(&str, usize)] = &[
+    ("read_line", 0),
+    ("read_file", 1),
+    ("write_file", 2),
+    ("append_file", 2),
+    ("lines", 1),
+    ("exists", 1),
+];
+
+pub fn arity(name: &str) -> Option<usize> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, arity)| *arity)
+}
+
+/// `eprint(...)`, which prints like `print` but to stderr.
+pub fn eprint(args: &[Value]) {
+    eprintln!(
+        "{}",
+        args.iter()
+            .map(|arg| format!("{:?}", arg))
+            .collect::<Vec<String>>()
+            .join(" ")
+    );
+}
+
+fn string(value: &Value) -> Result<&str, String> {
+    match value {
+        Value::String(s) => Ok(s.trim_end_matches('\0')),
+        value => Err(format!("Not a string: '{:?}'", value)),
+    }
+}
+
+/// The message of a failed `action` on `path`, without the error code that
+/// Rust adds to the message of the OS, like `strerror`.
+fn failed(action: &str, path: &str, e: io::Error) -> String {
+    let e = e.to_string();
+    let reason = e.split(" (os error").next().unwrap_or_default();
+    format!("Cannot {} '{}': {}", action, path, reason)
+}
+
+fn read(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| failed("read", path, e))
+}
+
+fn write(path: &str, contents: &str, append: bool) -> Result<Value, String> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .map_err(|e| failed("write", path, e))?;
+    Ok(Value::Unit)
+}
+
+/// Runs the file or stdin builtin `name`.
+pub fn apply(name: &str, args: &[Value]) -> Result<Value, String> {
+    match arity(name) {
+        Some(1) if args.len() != 1 => return Err(format!("{} takes 1 argument", name)),
+        Some(n) if args.len() != n => return Err(format!("{} takes {} arguments", name, n)),
+        _ => {}
+    }
+    Ok(match name {
+        // The next line without its line break, or `none` at the end.
+        "read_line" => {
+            let mut line = String::new();
+            let read = io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .map_err(|e| failed("read", "stdin", e))?;
+            let line = match line.strip_suffix('\n') {
+                Some(line) => line.strip_suffix('\r').unwrap_or(line),
+                None => &line,
+            };
+            value::some_or_none((read > 0).then(|| Value::String(format!("{}\0", line).into())))
+        }
+        "read_file" => Value::String(format!("{}\0", read(string(&args[0])?)?).into()),
+        "write_file" => write(string(&args[0])?, string(&args[1])?, false)?,
+        "append_file" => write(string(&args[0])?, string(&args[1])?, true)?,
+        "lines" => Value::List(Rc::new(
+            read(string(&args[0])?)?
+                .lines()
+                .map(|line| Value::String(format!("{}\0", line).into()))
+                .collect(),
+        )),
+        "exists" => Value::Bool(Path::new(string(&args[0])?).exists()),
+        _ => unreachable!("not an io builtin: {}", name),
+    })
+}
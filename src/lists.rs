@@ -0,0 +1,200 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use crate::ast::Op;
+use crate::value::Value;
+
+/// The list builtins with the number of arguments they take. Unlike the
+/// other builtins, a function or variable of the same name shadows them.
+pub const BUILTINS: &[(&str, usize)] = &[
+    ("len", 1),
+    ("map", 2),
+    ("filter", 2),
+    ("reduce", 3),
+    ("sort", 1),
+    ("sort_by", 2),
+    ("reverse", 1),
+    ("zip", 2),
+    ("enumerate", 1),
+    ("sum", 1),
+    ("min", 1),
+    ("max", 1),
+    ("contains", 2),
+    ("push", 2),
+    ("pop", 1),
+    ("chars", 1),
+    ("concat", 1),
+];
+
+pub fn arity(name: &str) -> Option<usize> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, arity)| *arity)
+}
+
+fn items(value: &Value) -> Result<&Rc<Vec<Value>>, String> {
+    match value {
+        Value::List(items) => Ok(items),
+        value => Err(format!("Not a list: '{:?}'", value)),
+    }
+}
+
+fn list(items: Vec<Value>) -> Value {
+    Value::List(Rc::new(items))
+}
+
+/// Sorts `items` by `keys` stably. Every key must compare with the first,
+/// which makes them all comparable.
+fn sort(items: &[Value], keys: &[Value]) -> Result<Value, String> {
+    if let Some(first) = keys.first() {
+        for key in keys {
+            first.compare(key)?;
+        }
+    }
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by(|&a, &b| keys[a].compare(&keys[b]).unwrap_or(Ordering::Equal));
+    Ok(list(order.into_iter().map(|i| items[i].clone()).collect()))
+}
+
+/// The first of the smallest or, for `max`, the largest item.
+fn extreme(name: &str, items: &[Value], wanted: Ordering) -> Result<Value, String> {
+    let mut best = items
+        .first()
+        .ok_or_else(|| format!("{} of an empty list", name))?;
+    for item in items {
+        if item.compare(best)? == wanted {
+            best = item;
+        }
+    }
+    Ok(best.clone())
+}
+
+/// How `apply` calls an alpha function with arguments.
+pub type Call<'a, E> = dyn FnMut(&Value, Vec<Value>) -> Result<Value, E> + 'a;
+
+/// Runs the list builtin `name`, calling alpha functions with `call`.
+pub fn apply<E: From<String>>(
+    name: &str,
+    args: Vec<Value>,
+    call: &mut Call<E>,
+) -> Result<Value, E> {
+    match arity(name) {
+        // `min` and `max` also compare two values.
+        _ if matches!(name, "min" | "max") && args.len() == 2 => {
+            let wanted = if name == "min" {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            };
+            return Ok(extreme(name, &args, wanted)?);
+        }
+        Some(1) if args.len() != 1 => return Err(format!("{} takes 1 argument", name).into()),
+        Some(n) if args.len() != n => return Err(format!("{} takes {} arguments", name, n).into()),
+        _ => {}
+    }
+    Ok(match name {
+        "len" => Value::Int(match &args[0] {
+            Value::List(items) | Value::Tuple(items) => items.len(),
+            Value::Map(map) => map.len(),
+            Value::String(s) => s.chars().count() - 1,
+            value => return Err(format!("Cannot take len of {}", value.type_name()).into()),
+        } as i64),
+        "map" => list(
+            items(&args[0])?
+                .iter()
+                .map(|item| call(&args[1], vec![item.clone()]))
+                .collect::<Result<_, E>>()?,
+        ),
+        "filter" => {
+            let mut kept = Vec::new();
+            for item in items(&args[0])?.iter() {
+                match call(&args[1], vec![item.clone()])? {
+                    Value::Bool(true) => kept.push(item.clone()),
+                    Value::Bool(false) => {}
+                    value => return Err(format!("Not a bool: '{:?}'", value).into()),
+                }
+            }
+            list(kept)
+        }
+        "reduce" => {
+            let mut acc = args[1].clone();
+            for item in items(&args[0])?.iter() {
+                acc = call(&args[2], vec![acc, item.clone()])?;
+            }
+            acc
+        }
+        "sort" => {
+            let items = items(&args[0])?;
+            sort(items, items)?
+        }
+        "sort_by" => {
+            let items = items(&args[0])?;
+            let keys = items
+                .iter()
+                .map(|item| call(&args[1], vec![item.clone()]))
+                .collect::<Result<Vec<_>, E>>()?;
+            sort(items, &keys)?
+        }
+        "reverse" => list(items(&args[0])?.iter().rev().cloned().collect()),
+        "zip" => list(
+            items(&args[0])?
+                .iter()
+                .zip(items(&args[1])?.iter())
+                .map(|(a, b)| Value::Tuple(Rc::new(vec![a.clone(), b.clone()])))
+                .collect(),
+        ),
+        "enumerate" => list(
+            items(&args[0])?
+                .iter()
+                .enumerate()
+                .map(|(i, item)| Value::Tuple(Rc::new(vec![Value::Int(i as i64), item.clone()])))
+                .collect(),
+        ),
+        "sum" => {
+            let mut sum = Value::Int(0);
+            for item in items(&args[0])?.iter() {
+                sum = Value::binary(&Op::Add, sum, item.clone())?;
+            }
+            sum
+        }
+        "min" => extreme(name, items(&args[0])?, Ordering::Less)?,
+        "max" => extreme(name, items(&args[0])?, Ordering::Greater)?,
+        "contains" => Value::Bool(items(&args[0])?.iter().any(|item| item.equals(&args[1]))),
+        "push" => {
+            let mut items = items(&args[0])?.to_vec();
+            items.push(args[1].clone());
+            list(items)
+        }
+        "pop" => {
+            let items = items(&args[0])?;
+            match items.split_last() {
+                Some((_, rest)) => list(rest.to_vec()),
+                None => return Err("pop of an empty list".to_string().into()),
+            }
+        }
+        // The characters of a string, each as a string, and back.
+        "chars" => match &args[0] {
+            Value::String(s) => list(
+                s.strip_suffix('\0')
+                    .unwrap_or(s)
+                    .chars()
+                    .map(|c| Value::String(format!("{}\0", c).into()))
+                    .collect(),
+            ),
+            value => return Err(format!("Not a string: '{:?}'", value).into()),
+        },
+        "concat" => {
+            let mut concatenated = String::new();
+            for item in items(&args[0])?.iter() {
+                match item {
+                    Value::String(s) => concatenated.push_str(s.strip_suffix('\0').unwrap_or(s)),
+                    value => return Err(format!("Not a string: '{:?}'", value).into()),
+                }
+            }
+            concatenated.push('\0');
+            Value::String(concatenated.into())
+        }
+        _ => unreachable!("not a list builtin: {}", name),
+    })
+}
@@ -1,50 +1,274 @@
 use clap::Parser;
 use eval::Eval;
-use parser::AlphaParser;
-use std::{fs, process::Command};
+use modules::Program;
+use std::{fs, path::Path, process::Command};
 
 mod ast;
+mod bigint;
+mod bytecode;
 mod comp;
+mod dwarf;
 mod eval;
+mod ffi;
+mod io;
+mod lists;
+mod matching;
+mod math;
+mod modules;
 mod parser;
+mod process;
+mod serialize;
+mod value;
+mod vm;
 
 #[macro_use]
 extern crate lazy_static;
 
 #[derive(clap::Parser, Debug)]
-#[command(version, about)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    action: Option<Action>,
     #[arg(short = 'f', long)]
-    file: String,
+    file: Option<String>,
     #[arg(short = 'i', long)]
     interpret: bool,
+    #[arg(long)]
+    vm: bool,
     #[arg(short = 'r', long)]
     run: bool,
     #[arg(short = 'd', long)]
     debug: bool,
+    #[command(flatten)]
+    options: Options,
+}
+
+/// The options of every way to run or build a program.
+#[derive(clap::Args, Debug)]
+struct Options {
+    /// Directories to search for imported modules
+    #[arg(short = 'I', long = "path")]
+    paths: Vec<String>,
+    /// Leave out the functions of the standard prelude
+    #[arg(long)]
+    no_prelude: bool,
+    /// Libraries for the functions of `extern fn` declarations, like m for libm
+    #[arg(long = "link-lib")]
+    link_libs: Vec<String>,
+    /// Collect garbage at every allocation of the compiled program
+    #[arg(long)]
+    gc_stress: bool,
+    /// Arguments for the program, after --
+    #[arg(last = true)]
+    args: Vec<String>,
+}
+
+impl Options {
+    fn load(&self, file: &str, debug: bool) -> Result<Program, String> {
+        modules::load(file, &self.paths, !self.no_prelude, debug)
+    }
+
+    fn link(&self) -> comp::Link<'_> {
+        comp::Link {
+            libs: &self.link_libs,
+            gc_stress: self.gc_stress,
+        }
+    }
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Action {
+    /// Run a source file or a bytecode module on the vm
+    Run {
+        file: String,
+        /// Use the tree-walking interpreter instead of the vm
+        #[arg(short = 'i', long)]
+        interpret: bool,
+        #[arg(short = 'd', long)]
+        debug: bool,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Compile a source file
+    Build {
+        file: String,
+        #[arg(long, value_enum, default_value_t = Emit::Exe)]
+        emit: Emit,
+        /// The kind of library that --emit lib builds
+        #[arg(long, value_enum, default_value_t = CrateType::Staticlib)]
+        crate_type: CrateType,
+        /// Defaults to build/out for executables, build/lib<file>.a or .so for
+        /// libraries and <file>.abc for bytecode
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+        #[arg(short = 'd', long)]
+        debug: bool,
+        #[command(flatten)]
+        options: Options,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum Emit {
+    Exe,
+    /// A library of the pub functions of the program, with a C header
+    Lib,
+    Bytecode,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+enum CrateType {
+    Staticlib,
+    Cdylib,
 }
 
 fn main() {
     let args = Args::parse();
-    let program = fs::read_to_string(args.file).unwrap();
-
-    match AlphaParser::parse_source(program.as_str(), args.debug) {
-        Ok(ast) => {
-            if args.interpret {
-                Eval::default().run(&ast);
-            } else {
-                let start = ast::Node::FnDef(Some("main".into()), Vec::new(), Box::new(ast), None);
-                let mut compiler = comp::Compiler::new(args.debug);
-                compiler.declare_functions(&start);
-                if let ast::Node::FnDef(_, _, node, _) = start {
-                    compiler.translate_fn(&Some("main".into()), &Vec::new(), &node, args.debug);
-                }
-                compiler.compile();
-                if args.run {
-                    Command::new("./build/out").status().unwrap();
-                }
+
+    let result = match args.action {
+        Some(Action::Run {
+            file,
+            interpret,
+            debug,
+            options,
+        }) => run(&file, interpret, debug, options),
+        Some(Action::Build {
+            file,
+            emit,
+            crate_type,
+            output,
+            debug,
+            options,
+        }) => options
+            .load(&file, debug)
+            .and_then(|program| {
+                build(
+                    &program,
+                    &file,
+                    emit,
+                    crate_type,
+                    output,
+                    debug,
+                    &options.link(),
+                )
+            })
+            .map(|_| 0),
+        None => match args.file {
+            Some(file) if args.interpret || args.vm => {
+                run(&file, args.interpret, args.debug, args.options)
+            }
+            Some(file) => {
+                let options = args.options;
+                let built = options.load(&file, args.debug).and_then(|program| {
+                    build(
+                        &program,
+                        &file,
+                        Emit::Exe,
+                        CrateType::Staticlib,
+                        None,
+                        args.debug,
+                        &options.link(),
+                    )
+                });
+                built.map(|_| match args.run {
+                    true => {
+                        let status = Command::new("./build/out").args(options.args).status();
+                        status.unwrap().code().unwrap_or(1)
+                    }
+                    false => 0,
+                })
             }
+            None => Err("No input file, see --help".into()),
+        },
+    };
+
+    match result {
+        Ok(0) => {}
+        Ok(status) => std::process::exit(status),
+        Err(e) => {
+            println!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn compile(ast: &ast::Node, debug: bool) -> Result<bytecode::Module, String> {
+    let module = bytecode::Compiler::compile(ast)?;
+    if debug {
+        println!("---- Bytecode ----");
+        print!("{}", module.disassemble());
+        println!("------------------");
+    }
+    Ok(module)
+}
+
+/// Runs `file` with the arguments of `options`, resulting in the exit status.
+fn run(file: &str, interpret: bool, debug: bool, options: Options) -> Result<i32, String> {
+    let bytes = fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+    // A damaged module is not source either.
+    let module = if bytes.starts_with(serialize::MAGIC) || file.ends_with(".abc") {
+        if interpret {
+            return Err(format!("{}: cannot interpret a bytecode module", file));
+        }
+        let module = serialize::from_bytes(&bytes).map_err(|e| format!("{}: {}", file, e))?;
+        if debug {
+            println!("---- Bytecode ----");
+            print!("{}", module.disassemble());
+            println!("------------------");
+        }
+        module
+    } else {
+        let ast = options.load(file, debug)?.merged();
+        if interpret {
+            return Eval::new(options.args, ffi::Libraries::open(&options.link_libs)?)
+                .run(&ast)
+                .map(|result| process::status(&result));
+        }
+        compile(&ast, debug)?
+    };
+    vm::Vm::new(
+        module,
+        options.args,
+        ffi::Libraries::open(&options.link_libs)?,
+    )
+    .run()
+    .map(|result| process::status(&result))
+}
+
+/// Builds `program`, loaded from `file`.
+fn build(
+    program: &Program,
+    file: &str,
+    emit: Emit,
+    crate_type: CrateType,
+    output: Option<String>,
+    debug: bool,
+    link: &comp::Link,
+) -> Result<(), String> {
+    match emit {
+        Emit::Bytecode => {
+            let module = compile(&program.merged(), debug)?;
+            let output = output.unwrap_or_else(|| {
+                Path::new(file)
+                    .with_extension("abc")
+                    .to_string_lossy()
+                    .into()
+            });
+            fs::write(&output, serialize::to_bytes(&module))
+                .map_err(|e| format!("{}: {}", output, e))
+        }
+        Emit::Exe => {
+            let output = output.as_deref().unwrap_or("build/out");
+            comp::build(program, output, link, debug)
+        }
+        Emit::Lib => {
+            let shared = matches!(crate_type, CrateType::Cdylib);
+            let output = output.unwrap_or_else(|| {
+                let stem = Path::new(file).file_stem().unwrap().to_string_lossy();
+                let extension = if shared { "so" } else { "a" };
+                format!("build/lib{}.{}", stem, extension)
+            });
+            comp::build_lib(program, &output, shared, link, debug)
         }
-        Err(e) => println!("{}", e),
     }
 }
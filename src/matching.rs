@@ -0,0 +1,311 @@
+use std::iter::zip;
+use std::rc::Rc;
+
+use crate::ast::MatchPattern;
+use crate::value::{self, Value};
+
+/// Turns a parsed pattern into one whose names are all bindings: a name
+/// that `arity` knows as a variant becomes that variant, and `bind` maps the
+/// names of bindings, e.g. to slots.
+pub fn resolve<N>(
+    pattern: &MatchPattern,
+    arity: &dyn Fn(&str) -> Option<usize>,
+    bind: &mut dyn FnMut(&String) -> Result<N, String>,
+) -> Result<MatchPattern<N>, String> {
+    Ok(match pattern {
+        MatchPattern::Wildcard => MatchPattern::Wildcard,
+        MatchPattern::Name(name) => match arity(name) {
+            Some(0) => MatchPattern::Variant(name.clone(), Vec::new()),
+            Some(n) => return Err(value::arity_mismatch(n, 0)),
+            None => MatchPattern::Name(bind(name)?),
+        },
+        MatchPattern::Int(n) => MatchPattern::Int(*n),
+        MatchPattern::Float(n) => MatchPattern::Float(*n),
+        MatchPattern::Bool(b) => MatchPattern::Bool(*b),
+        MatchPattern::String(s) => MatchPattern::String(s.clone()),
+        MatchPattern::Range {
+            from,
+            to,
+            lower,
+            upper,
+        } => MatchPattern::Range {
+            from: *from,
+            to: *to,
+            lower: *lower,
+            upper: *upper,
+        },
+        MatchPattern::Tuple(items) => MatchPattern::Tuple(resolve_all(items, arity, bind)?),
+        MatchPattern::List(items, rest) => {
+            let items = resolve_all(items, arity, bind)?;
+            let rest = match rest {
+                Some(Some(name)) => Some(Some(bind(name)?)),
+                Some(None) => Some(None),
+                None => None,
+            };
+            MatchPattern::List(items, rest)
+        }
+        MatchPattern::Variant(name, items) => match arity(name) {
+            Some(n) if n == items.len() => {
+                MatchPattern::Variant(name.clone(), resolve_all(items, arity, bind)?)
+            }
+            Some(n) => return Err(value::arity_mismatch(n, items.len())),
+            None => return Err(format!("Undefined variant: '{}'", name)),
+        },
+    })
+}
+
+fn resolve_all<N>(
+    patterns: &[MatchPattern],
+    arity: &dyn Fn(&str) -> Option<usize>,
+    bind: &mut dyn FnMut(&String) -> Result<N, String>,
+) -> Result<Vec<MatchPattern<N>>, String> {
+    patterns
+        .iter()
+        .map(|pattern| resolve(pattern, arity, bind))
+        .collect()
+}
+
+/// Whether `value` matches a resolved pattern, pushing the values of its
+/// bindings to `bindings`.
+pub fn matches<'a, N>(
+    pattern: &'a MatchPattern<N>,
+    value: &Value,
+    bindings: &mut Vec<(&'a N, Value)>,
+) -> bool {
+    match (pattern, value) {
+        (MatchPattern::Wildcard, _) => true,
+        (MatchPattern::Name(name), value) => {
+            bindings.push((name, value.clone()));
+            true
+        }
+        (MatchPattern::Int(n), Value::Int(m)) => n == m,
+        (MatchPattern::Int(n), Value::BigInt(m)) => m.to_i64() == Some(*n),
+        (MatchPattern::Float(n), Value::Float(m)) => n == m,
+        (MatchPattern::Bool(a), Value::Bool(b)) => a == b,
+        (MatchPattern::String(a), Value::String(b)) => a.as_str() == &**b,
+        (
+            MatchPattern::Range {
+                from,
+                to,
+                lower,
+                upper,
+            },
+            Value::Int(n),
+        ) => {
+            let range = value::Range {
+                from: *from,
+                to: *to,
+                lower: *lower,
+                upper: *upper,
+            };
+            let (start, end) = range.bounds();
+            (start..end).contains(n)
+        }
+        (MatchPattern::Tuple(items), Value::Tuple(values)) => {
+            items.len() == values.len()
+                && zip(items, values.iter()).all(|(item, value)| matches(item, value, bindings))
+        }
+        (MatchPattern::List(items, rest), Value::List(values)) => {
+            let fits = match rest {
+                Some(_) => values.len() >= items.len(),
+                None => values.len() == items.len(),
+            };
+            if !fits
+                || !zip(items, values.iter()).all(|(item, value)| matches(item, value, bindings))
+            {
+                return false;
+            }
+            if let Some(Some(name)) = rest {
+                let rest = values[items.len()..].to_vec();
+                bindings.push((name, Value::List(Rc::new(rest))));
+            }
+            true
+        }
+        (MatchPattern::Variant(name, items), Value::Variant(variant)) => {
+            variant.name() == name
+                && zip(items, &variant.values).all(|(item, value)| matches(item, value, bindings))
+        }
+        _ => false,
+    }
+}
+
+/// Checks that the resolved patterns of the arms without a guard cover every
+/// value. `siblings` lists the variants, with their payload lengths, of the
+/// enum that declares a variant.
+pub fn check_exhaustive<N>(
+    patterns: &[&MatchPattern<N>],
+    siblings: &dyn Fn(&str) -> Vec<(String, usize)>,
+) -> Result<(), String> {
+    let rows: Vec<Row<N>> = patterns
+        .iter()
+        .map(|pattern| vec![Some(*pattern)])
+        .collect();
+    match missing(&rows, 1, siblings) {
+        Some(witness) => Err(format!("Non-exhaustive match: {} not covered", witness[0])),
+        None => Ok(()),
+    }
+}
+
+/// A row of the pattern matrix, in which `None` matches anything.
+type Row<'a, N> = Vec<Option<&'a MatchPattern<N>>>;
+
+/// A value that no row matches, one pattern per column, if there is one.
+///
+/// This is the usefulness check of "Warnings for pattern matching" (Maranget,
+/// 2007): when the first column mentions every constructor of its type,
+/// each constructor is checked in turn; otherwise only the rows that match
+/// anything in the first column can cover the missing constructors.
+fn missing<N>(
+    rows: &[Row<N>],
+    width: usize,
+    siblings: &dyn Fn(&str) -> Vec<(String, usize)>,
+) -> Option<Vec<String>> {
+    if width == 0 {
+        return rows.is_empty().then(Vec::new);
+    }
+    let heads: Vec<&MatchPattern<N>> = rows
+        .iter()
+        .filter_map(|row| row[0])
+        .filter(|pattern| !matches!(pattern, MatchPattern::Wildcard | MatchPattern::Name(_)))
+        .collect();
+    let ctors = Ctor::signature(&heads, siblings);
+    let present = |ctor: &Ctor| {
+        heads
+            .iter()
+            .any(|head| ctor.specialize(Some(head)).is_some())
+    };
+
+    if !ctors.is_empty() && ctors.iter().all(present) {
+        for ctor in &ctors {
+            let specialized: Vec<Row<N>> = rows
+                .iter()
+                .filter_map(|row| {
+                    let mut args = ctor.specialize(row[0])?;
+                    args.extend_from_slice(&row[1..]);
+                    Some(args)
+                })
+                .collect();
+            if let Some(witness) = missing(&specialized, ctor.arity() + width - 1, siblings) {
+                let (args, rest) = witness.split_at(ctor.arity());
+                return Some(
+                    std::iter::once(ctor.show(args))
+                        .chain(rest.to_vec())
+                        .collect(),
+                );
+            }
+        }
+        return None;
+    }
+
+    let defaults: Vec<Row<N>> = rows
+        .iter()
+        .filter(|row| ctor_free(row[0]))
+        .map(|row| row[1..].to_vec())
+        .collect();
+    let witness = missing(&defaults, width - 1, siblings)?;
+    let head = match ctors.iter().find(|ctor| !present(ctor)) {
+        Some(ctor) => ctor.show(&vec!["_".to_string(); ctor.arity()]),
+        None => "_".to_string(),
+    };
+    Some(std::iter::once(head).chain(witness).collect())
+}
+
+fn ctor_free<N>(pattern: Option<&MatchPattern<N>>) -> bool {
+    matches!(
+        pattern,
+        None | Some(MatchPattern::Wildcard | MatchPattern::Name(_))
+    )
+}
+
+/// What the values of a type with finitely many shapes start with. Lists
+/// of any length are split into the lengths up to the longest pattern and
+/// the lists that are longer than that.
+enum Ctor {
+    Variant(String, usize),
+    Bool(bool),
+    Tuple(usize),
+    List { len: usize, longer: bool },
+}
+
+impl Ctor {
+    /// Every constructor of the type of `heads`, or none for types like
+    /// ints and strings that have too many.
+    fn signature<N>(
+        heads: &[&MatchPattern<N>],
+        siblings: &dyn Fn(&str) -> Vec<(String, usize)>,
+    ) -> Vec<Ctor> {
+        match heads.first() {
+            Some(MatchPattern::Variant(name, _)) => siblings(name)
+                .into_iter()
+                .map(|(name, arity)| Ctor::Variant(name, arity))
+                .collect(),
+            Some(MatchPattern::Bool(_)) => vec![Ctor::Bool(true), Ctor::Bool(false)],
+            Some(MatchPattern::Tuple(items)) => vec![Ctor::Tuple(items.len())],
+            Some(MatchPattern::List(..)) => {
+                let longest = heads
+                    .iter()
+                    .filter_map(|head| match head {
+                        MatchPattern::List(items, _) => Some(items.len()),
+                        _ => None,
+                    })
+                    .max()
+                    .unwrap_or(0);
+                (0..=longest)
+                    .map(|len| Ctor::List { len, longer: false })
+                    .chain(std::iter::once(Ctor::List {
+                        len: longest + 1,
+                        longer: true,
+                    }))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Ctor::Variant(_, arity) | Ctor::Tuple(arity) | Ctor::List { len: arity, .. } => *arity,
+            Ctor::Bool(_) => 0,
+        }
+    }
+
+    /// The patterns of the arguments of this constructor in a pattern that
+    /// matches it, or `None` if the pattern matches other values only.
+    fn specialize<'a, N>(&self, pattern: Option<&'a MatchPattern<N>>) -> Option<Row<'a, N>> {
+        if ctor_free(pattern) {
+            return Some(vec![None; self.arity()]);
+        }
+        match (self, pattern?) {
+            (Ctor::Variant(name, _), MatchPattern::Variant(other, items)) if name == other => {
+                Some(items.iter().map(Some).collect())
+            }
+            (Ctor::Bool(b), MatchPattern::Bool(other)) if b == other => Some(Vec::new()),
+            (Ctor::Tuple(len), MatchPattern::Tuple(items)) if *len == items.len() => {
+                Some(items.iter().map(Some).collect())
+            }
+            (Ctor::List { len, longer }, MatchPattern::List(items, rest)) => {
+                let fits = match rest {
+                    Some(_) => *len >= items.len(),
+                    None => *len == items.len() && !longer,
+                };
+                fits.then(|| {
+                    let mut args: Row<N> = items.iter().map(Some).collect();
+                    args.resize(*len, None);
+                    args
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn show(&self, args: &[String]) -> String {
+        match self {
+            Ctor::Variant(name, 0) => name.clone(),
+            Ctor::Variant(name, _) => format!("{}({})", name, args.join(", ")),
+            Ctor::Bool(b) => b.to_string(),
+            Ctor::Tuple(_) => format!("({})", args.join(", ")),
+            Ctor::List { longer: false, .. } => format!("[{}]", args.join(", ")),
+            Ctor::List { longer: true, .. } => format!("[{}, ..]", args.join(", ")),
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::cmp::Ordering;
+
+use crate::ast::Op;
+use crate::value::Value;
+
+/// The math builtins with the number of arguments they take. Like the list
+/// builtins, a function or variable of the same name shadows them.
+pub const BUILTINS: &[(&str, usize)] = &[
+    ("sqrt", 1),
+    ("abs", 1),
+    ("floor", 1),
+    ("ceil", 1),
+    ("round", 1),
+    ("pow", 2),
+    ("exp", 1),
+    ("ln", 1),
+    ("log10", 1),
+    ("sin", 1),
+    ("cos", 1),
+    ("tan", 1),
+    ("asin", 1),
+    ("acos", 1),
+    ("atan", 1),
+    ("atan2", 2),
+    ("random", 0),
+    ("seed", 1),
+];
+
+pub fn arity(name: &str) -> Option<usize> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, arity)| *arity)
+}
+
+/// The value of `pi` and `e` where no variable of that name is defined.
+pub fn constant(name: &str) -> Option<Value> {
+    match name {
+        "pi" => Some(Value::Float(std::f64::consts::PI)),
+        "e" => Some(Value::Float(std::f64::consts::E)),
+        _ => None,
+    }
+}
+
+/// The generator behind `random`, which `seed` restarts. It is splitmix64,
+/// which `runtime/src/math.rs` implements too, so that compiled programs draw
+/// the same numbers.
+#[derive(Default)]
+pub struct Random {
+    state: u64,
+}
+
+impl Random {
+    fn next(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn float(value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Int(n) => Ok(*n as f64),
+        Value::BigInt(n) => Ok(n.to_f64()),
+        Value::Float(n) => Ok(*n),
+        value => Err(format!("Not a number: '{:?}'", value)),
+    }
+}
+
+/// Runs the math builtin `name`.
+pub fn apply(name: &str, args: &[Value], random: &mut Random) -> Result<Value, String> {
+    match arity(name) {
+        Some(1) if args.len() != 1 => return Err(format!("{} takes 1 argument", name)),
+        Some(n) if args.len() != n => return Err(format!("{} takes {} arguments", name, n)),
+        _ => {}
+    }
+    let f: fn(f64) -> f64 = match name {
+        "abs" => {
+            float(&args[0])?;
+            return match args[0].compare(&Value::Int(0))? {
+                Ordering::Less => Value::binary(&Op::Sub, Value::Int(0), args[0].clone()),
+                _ => Ok(args[0].clone()),
+            };
+        }
+        "pow" => return Ok(Value::Float(float(&args[0])?.powf(float(&args[1])?))),
+        "atan2" => return Ok(Value::Float(float(&args[0])?.atan2(float(&args[1])?))),
+        "random" => return Ok(Value::Float(random.next())),
+        "seed" => {
+            match &args[0] {
+                Value::Int(n) => random.state = *n as u64,
+                value => return Err(format!("Not an int: '{:?}'", value)),
+            }
+            return Ok(Value::Unit);
+        }
+        "sqrt" => f64::sqrt,
+        "floor" => f64::floor,
+        "ceil" => f64::ceil,
+        "round" => f64::round,
+        "exp" => f64::exp,
+        "ln" => f64::ln,
+        "log10" => f64::log10,
+        "sin" => f64::sin,
+        "cos" => f64::cos,
+        "tan" => f64::tan,
+        "asin" => f64::asin,
+        "acos" => f64::acos,
+        "atan" => f64::atan,
+        _ => unreachable!("not a math builtin: {}", name),
+    };
+    Ok(Value::Float(f(float(&args[0])?)))
+}
@@ -0,0 +1,634 @@
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::ast::{self, MatchPattern, ModulePath, Node};
+use crate::parser::AlphaParser;
+
+/// A source file of a program. The functions at the top level of an
+/// imported module are named `module.name`, and every reference to them is
+/// resolved to that name, so that modules can use the same names.
+pub struct Module {
+    /// What its functions are qualified with, `None` for the program itself.
+    pub name: Option<String>,
+    /// The file it was loaded from.
+    pub file: String,
+    /// The declarations of an imported module, or the whole program.
+    pub ast: Node,
+    /// The qualified names of its `pub` functions.
+    pub public: HashSet<String>,
+    /// The modules it imports, as positions in `Program::modules`.
+    pub imports: Vec<usize>,
+}
+
+/// The modules of a program, each after the ones it imports, ending with
+/// the program itself.
+pub struct Program {
+    pub modules: Vec<Module>,
+}
+
+impl Program {
+    /// Leaves out the functions of the prelude at `prelude` that no other
+    /// module calls, directly or through other functions of the prelude,
+    /// and the prelude itself if none are left.
+    fn prune(&mut self, prelude: usize) {
+        let mut used = HashSet::new();
+        for (index, module) in self.modules.iter().enumerate() {
+            if index != prelude {
+                references(&module.ast, &mut used);
+            }
+        }
+        let Node::Statements(statements) = &self.modules[prelude].ast else {
+            unreachable!("a module is statements");
+        };
+        let function = |statement: &Node| match statement {
+            Node::Line(_, node) => match node.as_ref() {
+                Node::FnDef(Some(name), ..) => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        };
+        let mut pending: Vec<String> = used.iter().cloned().collect();
+        while let Some(name) = pending.pop() {
+            let called = statements
+                .iter()
+                .find(|statement| function(statement).as_ref() == Some(&name));
+            if let Some(statement) = called {
+                let mut found = HashSet::new();
+                references(statement, &mut found);
+                pending.extend(found.into_iter().filter(|name| used.insert(name.clone())));
+            }
+        }
+        let kept: Vec<Node> = statements
+            .iter()
+            .filter(|statement| function(statement).is_some_and(|name| used.contains(&name)))
+            .cloned()
+            .collect();
+
+        if kept.is_empty() {
+            self.modules.remove(prelude);
+            for module in &mut self.modules {
+                module.imports.retain(|&index| index != prelude);
+                module
+                    .imports
+                    .iter_mut()
+                    .filter(|index| **index > prelude)
+                    .for_each(|index| *index -= 1);
+            }
+        } else {
+            let module = &mut self.modules[prelude];
+            module.public.retain(|name| used.contains(name));
+            module.ast = Node::Statements(kept);
+        }
+    }
+
+    /// The program with the declarations of the modules it imports before
+    /// it, as one tree for the interpreter and the bytecode compiler.
+    pub fn merged(&self) -> Node {
+        Node::Statements(
+            self.modules
+                .iter()
+                .flat_map(|module| match &module.ast {
+                    Node::Statements(statements) => statements.clone(),
+                    node => vec![node.clone()],
+                })
+                .collect(),
+        )
+    }
+}
+
+/// The functions every module sees unless they are turned off, written in
+/// alpha and built into the binary.
+const PRELUDE: &str = include_str!("../lib/prelude.a");
+
+fn parse(file: &str, source: &[u8], debug: bool) -> Result<Node, String> {
+    std::str::from_utf8(source)
+        .map_err(|e| e.to_string())
+        .and_then(|source| AlphaParser::parse_source(source, debug))
+        .map_err(|e| format!("{}: {}", file, e))
+}
+
+/// Loads the program in `file` and the modules it imports, which are found
+/// next to the importing file or in one of the directories of `paths`. With
+/// `prelude`, the functions of the prelude that the program uses come first.
+pub fn load(file: &str, paths: &[String], prelude: bool, debug: bool) -> Result<Program, String> {
+    let mut loader = Loader {
+        paths,
+        debug,
+        modules: Vec::new(),
+        loaded: HashMap::new(),
+        loading: Vec::new(),
+        prelude: None,
+    };
+    if prelude {
+        loader.load_prelude()?;
+    }
+    loader.load(Path::new(file), false)?;
+    let mut program = Program {
+        modules: loader.modules,
+    };
+    if let Some(prelude) = loader.prelude {
+        program.prune(prelude);
+    }
+    Ok(program)
+}
+
+struct Loader<'a> {
+    paths: &'a [String],
+    debug: bool,
+    modules: Vec<Module>,
+    /// The position of each loaded module, by canonical path.
+    loaded: HashMap<PathBuf, usize>,
+    /// The files being loaded, each importing the next.
+    loading: Vec<(PathBuf, String)>,
+    /// The position of the prelude, which every later module imports.
+    prelude: Option<usize>,
+}
+
+/// The names a part of a module sees: functions by the name they are
+/// called with, and the modules that qualify names.
+#[derive(Clone, Default)]
+struct Scope {
+    functions: HashMap<String, String>,
+    modules: HashMap<String, usize>,
+}
+
+impl Scope {
+    /// Hides a function or module behind a variable or nested function.
+    fn bind(&mut self, name: &str) {
+        self.functions.remove(name);
+        self.modules.remove(name);
+    }
+}
+
+impl Loader<'_> {
+    fn load(&mut self, path: &Path, imported: bool) -> Result<usize, String> {
+        let file = path.display().to_string();
+        let canonical = fs::canonicalize(path).map_err(|e| format!("{}: {}", file, e))?;
+        if let Some(start) = self.loading.iter().position(|(p, _)| *p == canonical) {
+            let cycle: Vec<&str> = self.loading[start..]
+                .iter()
+                .map(|(_, file)| file.as_str())
+                .chain([file.as_str()])
+                .collect();
+            return Err(format!("Import cycle: {}", cycle.join(" -> ")));
+        }
+        if let Some(&index) = self.loaded.get(&canonical) {
+            return Ok(index);
+        }
+        let source = fs::read(path).map_err(|e| format!("{}: {}", file, e))?;
+        let ast = parse(&file, &source, self.debug)?;
+
+        self.loading.push((canonical.clone(), file));
+        let module = self.declare(path, ast, imported);
+        self.loading.pop();
+
+        self.modules.push(module?);
+        self.loaded.insert(canonical, self.modules.len() - 1);
+        Ok(self.modules.len() - 1)
+    }
+
+    fn load_prelude(&mut self) -> Result<(), String> {
+        let path = Path::new("prelude.a");
+        let ast = parse("prelude.a", PRELUDE.as_bytes(), false)?;
+        let module = self.declare(path, ast, true)?;
+        self.modules.push(module);
+        self.prelude = Some(self.modules.len() - 1);
+        Ok(())
+    }
+
+    /// Loads the imports of the module in `path` and resolves its names.
+    fn declare(&mut self, path: &Path, ast: Node, imported: bool) -> Result<Module, String> {
+        let file = path.display().to_string();
+        let error = |line: usize, e: String| format!("{}: line {}: {}", file, line, e);
+        let Node::Statements(statements) = ast else {
+            unreachable!("a program is statements");
+        };
+
+        let mut scope = Scope::default();
+        let mut imports = Vec::new();
+        // The prelude is imported unqualified, before anything that hides it.
+        if let Some(index) = self.prelude {
+            let module = &self.modules[index];
+            let prefix = qualify(&module.name, "");
+            for qualified in &module.public {
+                let name = qualified.strip_prefix(&prefix).unwrap_or(qualified);
+                scope.functions.insert(name.to_string(), qualified.clone());
+            }
+            imports.push(index);
+        }
+        let mut declarations = Vec::new();
+        for statement in statements {
+            let Node::Line(line, node) = statement else {
+                unreachable!("statements are lines");
+            };
+            let Node::Import(module_path, names) = *node else {
+                declarations.push((line, *node));
+                continue;
+            };
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let found = self
+                .find(dir, &module_path)
+                .ok_or_else(|| error(line, format!("Cannot find module '{}'", module_path)))?;
+            let index = self.load(&found, true)?;
+            imports.push(index);
+            let module = &self.modules[index];
+            match names {
+                Some(names) => {
+                    for name in names {
+                        let qualified = qualify(&module.name, &name);
+                        if !module.public.contains(&qualified) {
+                            let message = not_public(&name, &module_path.alias());
+                            return Err(error(line, message));
+                        }
+                        scope.functions.insert(name, qualified);
+                    }
+                }
+                None => {
+                    scope.modules.insert(module_path.alias(), index);
+                }
+            }
+        }
+
+        let name = imported.then(|| self.unique_name(path));
+        let mut public = HashSet::new();
+        for (line, node) in &declarations {
+            let (function, exported) = match node {
+                Node::Pub(function) => (function.as_ref(), true),
+                node => (node, false),
+            };
+            match function {
+                Node::FnDef(Some(function), ..) => {
+                    let qualified = qualify(&name, function);
+                    if exported {
+                        public.insert(qualified.clone());
+                    }
+                    scope.functions.insert(function.clone(), qualified);
+                }
+                _ if exported => {
+                    return Err(error(*line, "Only named functions can be pub".into()))
+                }
+                // C functions keep their name, hiding any of the prelude.
+                Node::Extern(function, ..) => {
+                    scope.functions.insert(function.clone(), function.clone());
+                }
+                Node::StructDef(..) | Node::EnumDef(..) => {}
+                _ if imported => {
+                    let message = "Only declarations can be at the top level of a module";
+                    return Err(error(*line, message.into()));
+                }
+                _ => {}
+            }
+        }
+
+        let resolver = Resolver {
+            modules: &self.modules,
+            line: Cell::new(0),
+        };
+        let mut statements = Vec::new();
+        for (line, node) in declarations {
+            resolver.line.set(line);
+            let node = match node {
+                Node::Pub(function) => *function,
+                node => node,
+            };
+            let node = match node {
+                // Top-level functions keep their qualified name inside.
+                Node::FnDef(Some(function), params, body, typename) => {
+                    let mut inner = scope.clone();
+                    params
+                        .iter()
+                        .flat_map(|(param, _)| param.names())
+                        .for_each(|name| inner.bind(name));
+                    let body = resolver.resolve(&body, &mut inner);
+                    let body = body.map_err(|e| error(resolver.line.get(), e))?;
+                    let function = qualify(&name, &function);
+                    Node::FnDef(Some(function), params, Rc::new(body), typename)
+                }
+                node => resolver
+                    .resolve(&node, &mut scope)
+                    .map_err(|e| error(resolver.line.get(), e))?,
+            };
+            statements.push(Node::Line(line, Box::new(node)));
+        }
+
+        Ok(Module {
+            name,
+            file,
+            ast: Node::Statements(statements),
+            public,
+            imports,
+        })
+    }
+
+    /// The file of an imported module: a path relative to the directory of
+    /// the importing file, or a name looked up there and then in `paths`.
+    fn find(&self, dir: &Path, path: &ModulePath) -> Option<PathBuf> {
+        match path {
+            ModulePath::File(file) => Some(dir.join(file)).filter(|path| path.exists()),
+            ModulePath::Name(names) => {
+                let file = format!("{}.a", names.join("/"));
+                std::iter::once(dir)
+                    .chain(self.paths.iter().map(Path::new))
+                    .map(|dir| dir.join(&file))
+                    .find(|path| path.exists())
+            }
+        }
+    }
+
+    /// The name of the file without extension, numbered if another module
+    /// has the same.
+    fn unique_name(&self, path: &Path) -> String {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let taken = |name: &str| {
+            self.modules
+                .iter()
+                .any(|module| module.name.as_deref() == Some(name))
+        };
+        let mut name = stem.to_string();
+        let mut n = 1;
+        while taken(&name) {
+            n += 1;
+            name = format!("{}{}", stem, n);
+        }
+        name
+    }
+}
+
+fn qualify(module: &Option<String>, name: &str) -> String {
+    match module {
+        Some(module) => format!("{}.{}", module, name),
+        None => name.to_string(),
+    }
+}
+
+fn not_public(name: &str, module: &str) -> String {
+    format!("'{}' is not a public function of {}", name, module)
+}
+
+impl ModulePath {
+    /// The name that qualifies the functions of the module: the file name
+    /// without extension, or the last name.
+    fn alias(&self) -> String {
+        match self {
+            ModulePath::File(file) => Path::new(file)
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into(),
+            ModulePath::Name(names) => names.last().unwrap().clone(),
+        }
+    }
+}
+
+impl std::fmt::Display for ModulePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModulePath::File(file) => write!(f, "{}", file),
+            ModulePath::Name(names) => write!(f, "{}", names.join(".")),
+        }
+    }
+}
+
+/// Renames the references to functions in a module.
+struct Resolver<'a> {
+    modules: &'a [Module],
+    /// The line being resolved, which errors are on.
+    line: Cell<usize>,
+}
+
+impl Resolver<'_> {
+    fn resolve(&self, node: &Node, scope: &mut Scope) -> Result<Node, String> {
+        Ok(match node {
+            Node::Line(line, node) => {
+                self.line.set(*line);
+                Node::Line(*line, self.boxed(node, scope)?)
+            }
+            Node::Statements(nodes) => Node::Statements(self.all(nodes, &mut scope.clone())?),
+            Node::FnCall(name, args) => {
+                let name = match name.split_once('.') {
+                    Some((module, name)) => self.qualified(module, name, scope)?,
+                    None => scope.functions.get(name).unwrap_or(name).clone(),
+                };
+                Node::FnCall(name, self.all(args, scope)?)
+            }
+            Node::VarRef(name) => Node::VarRef(scope.functions.get(name).unwrap_or(name).clone()),
+            Node::Field(record, field) => match record.as_ref() {
+                Node::VarRef(module) if scope.modules.contains_key(module) => {
+                    Node::VarRef(self.qualified(module, field, scope)?)
+                }
+                _ => Node::Field(self.boxed(record, scope)?, field.clone()),
+            },
+            Node::FnDef(name, params, body, typename) => {
+                if let Some(name) = name {
+                    scope.bind(name);
+                }
+                let mut inner = scope.clone();
+                params
+                    .iter()
+                    .flat_map(|(param, _)| param.names())
+                    .for_each(|name| inner.bind(name));
+                let body = Rc::new(self.resolve(body, &mut inner)?);
+                Node::FnDef(name.clone(), params.clone(), body, typename.clone())
+            }
+            Node::Define(mutable, pattern, expr, typename) => {
+                let expr = self.boxed(expr, scope)?;
+                pattern
+                    .names()
+                    .into_iter()
+                    .for_each(|name| scope.bind(name));
+                Node::Define(mutable.clone(), pattern.clone(), expr, typename.clone())
+            }
+            Node::Loop {
+                var,
+                value,
+                iterable,
+                inner,
+            } => {
+                let iterable = self.boxed(iterable, scope)?;
+                let mut scope = scope.clone();
+                var.names()
+                    .into_iter()
+                    .chain(value.iter().flat_map(ast::Pattern::names))
+                    .for_each(|name| scope.bind(name));
+                Node::Loop {
+                    var: var.clone(),
+                    value: value.clone(),
+                    iterable,
+                    inner: Box::new(self.resolve(inner, &mut scope)?),
+                }
+            }
+            Node::TryCatch {
+                body,
+                name,
+                handler,
+            } => {
+                let body = self.boxed(body, scope)?;
+                let mut scope = scope.clone();
+                scope.bind(name);
+                Node::TryCatch {
+                    body,
+                    name: name.clone(),
+                    handler: Box::new(self.resolve(handler, &mut scope)?),
+                }
+            }
+            Node::Match { subject, arms } => Node::Match {
+                subject: self.boxed(subject, scope)?,
+                arms: arms
+                    .iter()
+                    .map(|arm| {
+                        let mut scope = scope.clone();
+                        bindings(&arm.pattern, &mut scope);
+                        Ok(ast::Arm {
+                            pattern: arm.pattern.clone(),
+                            guard: match &arm.guard {
+                                Some(guard) => Some(self.resolve(guard, &mut scope)?),
+                                None => None,
+                            },
+                            body: self.resolve(&arm.body, &mut scope)?,
+                        })
+                    })
+                    .collect::<Result<_, String>>()?,
+            },
+            Node::Range {
+                from,
+                to,
+                lower,
+                upper,
+            } => Node::Range {
+                from: self.boxed(from, scope)?,
+                to: self.boxed(to, scope)?,
+                lower: *lower,
+                upper: *upper,
+            },
+            Node::While { condition, inner } => Node::While {
+                condition: self.boxed(condition, scope)?,
+                inner: self.boxed(inner, scope)?,
+            },
+            Node::IfElse {
+                condition,
+                if_block,
+                else_block,
+            } => Node::IfElse {
+                condition: self.boxed(condition, scope)?,
+                if_block: self.boxed(if_block, scope)?,
+                else_block: self.boxed(else_block, scope)?,
+            },
+            Node::StructLit(name, fields) => Node::StructLit(
+                name.clone(),
+                fields
+                    .iter()
+                    .map(|(field, value)| Ok((field.clone(), self.resolve(value, scope)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            Node::Try(value) => Node::Try(self.boxed(value, scope)?),
+            Node::Throw(value) => Node::Throw(self.boxed(value, scope)?),
+            Node::List(items) => Node::List(self.all(items, scope)?),
+            Node::Tuple(items) => Node::Tuple(self.all(items, scope)?),
+            Node::Map(pairs) => Node::Map(
+                pairs
+                    .iter()
+                    .map(|(key, value)| {
+                        Ok((self.resolve(key, scope)?, self.resolve(value, scope)?))
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            Node::Index(list, index) => {
+                Node::Index(self.boxed(list, scope)?, self.boxed(index, scope)?)
+            }
+            Node::Slice { list, from, to } => Node::Slice {
+                list: self.boxed(list, scope)?,
+                from: from
+                    .as_deref()
+                    .map(|from| self.boxed(from, scope))
+                    .transpose()?,
+                to: to.as_deref().map(|to| self.boxed(to, scope)).transpose()?,
+            },
+            Node::Expr { op, lhs, rhs } => Node::Expr {
+                op: op.clone(),
+                lhs: self.boxed(lhs, scope)?,
+                rhs: self.boxed(rhs, scope)?,
+            },
+            Node::Assign(name, value) => Node::Assign(name.clone(), self.boxed(value, scope)?),
+            Node::IndexAssign(name, steps, value) => Node::IndexAssign(
+                name.clone(),
+                steps
+                    .iter()
+                    .map(|step| match step {
+                        ast::Step::Index(index) => {
+                            Ok(ast::Step::Index(self.resolve(index, scope)?))
+                        }
+                        ast::Step::Field(field) => Ok(ast::Step::Field(field.clone())),
+                    })
+                    .collect::<Result<_, String>>()?,
+                self.boxed(value, scope)?,
+            ),
+            Node::Extern(name, ..) => {
+                scope.bind(name);
+                node.clone()
+            }
+            Node::Pub(_) => return Err("Only top-level functions can be pub".into()),
+            Node::Import(..) => return Err("Imports must be at the top level".into()),
+            Node::Nada
+            | Node::Int(_)
+            | Node::BigInt(_)
+            | Node::Float(_)
+            | Node::Bool(_)
+            | Node::String(_)
+            | Node::TypeName(_)
+            | Node::StructDef(..)
+            | Node::EnumDef(..) => node.clone(),
+        })
+    }
+
+    fn boxed(&self, node: &Node, scope: &mut Scope) -> Result<Box<Node>, String> {
+        self.resolve(node, scope).map(Box::new)
+    }
+
+    fn all(&self, nodes: &[Node], scope: &mut Scope) -> Result<Vec<Node>, String> {
+        nodes.iter().map(|node| self.resolve(node, scope)).collect()
+    }
+
+    /// The qualified name of `module.name`, which must be public.
+    fn qualified(&self, module: &str, name: &str, scope: &Scope) -> Result<String, String> {
+        let Some(&index) = scope.modules.get(module) else {
+            return Err(format!("Unknown module '{}'", module));
+        };
+        let qualified = qualify(&self.modules[index].name, name);
+        match self.modules[index].public.contains(&qualified) {
+            true => Ok(qualified),
+            false => Err(not_public(name, module)),
+        }
+    }
+}
+
+/// Adds the names of the functions that `node` calls or refers to, also in
+/// the bodies of functions, to `names`.
+fn references(node: &Node, names: &mut HashSet<String>) {
+    match node {
+        Node::FnCall(name, _) | Node::VarRef(name) => {
+            names.insert(name.clone());
+        }
+        Node::FnDef(_, _, body, _) => references(body, names),
+        _ => {}
+    }
+    node.children().for_each(|child| references(child, names));
+}
+
+/// Hides what the names a match pattern binds hide.
+fn bindings(pattern: &MatchPattern, scope: &mut Scope) {
+    match pattern {
+        MatchPattern::Name(name) | MatchPattern::List(_, Some(Some(name))) => scope.bind(name),
+        _ => {}
+    }
+    match pattern {
+        MatchPattern::Tuple(patterns)
+        | MatchPattern::List(patterns, _)
+        | MatchPattern::Variant(_, patterns) => {
+            patterns.iter().for_each(|pattern| bindings(pattern, scope))
+        }
+        _ => {}
+    }
+}
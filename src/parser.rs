@@ -1,4 +1,7 @@
+use std::rc::Rc;
+
 use crate::ast;
+use crate::bigint::BigInt;
 
 use pest::{
     iterators::{Pair, Pairs},
@@ -14,7 +17,9 @@ lazy_static! {
         PrattParser::new()
             .op(Op::postfix(Rule::EOI))
             .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::sub, Assoc::Left))
-            .op(Op::infix(Rule::mul, Assoc::Left) | Op::infix(Rule::div, Assoc::Left))
+            .op(Op::infix(Rule::mul, Assoc::Left)
+                | Op::infix(Rule::div, Assoc::Left)
+                | Op::infix(Rule::rem, Assoc::Left))
             .op(Op::infix(Rule::pow, Assoc::Right))
             .op(Op::postfix(Rule::fac))
             .op(Op::prefix(Rule::neg))
@@ -23,6 +28,7 @@ lazy_static! {
                 | Op::infix(Rule::lt, Assoc::Right)
                 | Op::infix(Rule::le, Assoc::Right))
             .op(Op::infix(Rule::eq, Assoc::Right) | Op::infix(Rule::neq, Assoc::Right))
+            .op(Op::postfix(Rule::index) | Op::postfix(Rule::access) | Op::postfix(Rule::propagate))
     };
 }
 
@@ -32,7 +38,7 @@ pub struct AlphaParser;
 
 impl AlphaParser {
     fn print_pairs(pairs: &Pairs<'_, Rule>, depth: usize) {
-        for p in pairs.clone().into_iter() {
+        for p in pairs.clone() {
             println!(
                 "{: >3} {: >3} {} {: <12} {:?}",
                 p.as_span().start(),
@@ -67,7 +73,7 @@ impl AlphaParser {
             }
         }
 
-        Ok(ast?)
+        ast
     }
 
     fn parse_pair(pair: Pair<'_, Rule>) -> Result<ast::Node, String> {
@@ -75,7 +81,10 @@ impl AlphaParser {
             Rule::program | Rule::statement | Rule::expr => Self::parse_pairs(pair.into_inner()),
             Rule::statements => Ok(ast::Node::Statements(
                 pair.into_inner()
-                    .map(Self::parse_pair)
+                    .map(|pair| {
+                        let (line, _) = pair.line_col();
+                        Ok(ast::Node::Line(line, Box::new(Self::parse_pair(pair)?)))
+                    })
                     .collect::<Result<Vec<ast::Node>, String>>()?,
             )),
             Rule::list => Ok(ast::Node::List(
@@ -83,11 +92,33 @@ impl AlphaParser {
                     .map(Self::parse_pair)
                     .collect::<Result<Vec<ast::Node>, String>>()?,
             )),
-            Rule::int => pair
+            Rule::tuple => Ok(ast::Node::Tuple(
+                pair.into_inner()
+                    .map(Self::parse_pair)
+                    .collect::<Result<Vec<ast::Node>, String>>()?,
+            )),
+            Rule::map => Ok(ast::Node::Map(
+                pair.into_inner()
+                    .map(|pair| {
+                        let mut inner = pair.into_inner();
+                        let key = Self::parse_pair(inner.next().unwrap())?;
+                        let value = Self::parse_pair(inner.next().unwrap())?;
+                        Ok((key, value))
+                    })
+                    .collect::<Result<Vec<(ast::Node, ast::Node)>, String>>()?,
+            )),
+            Rule::int => match pair.as_str().parse::<i64>() {
+                Ok(n) => Ok(ast::Node::Int(n)),
+                Err(_) => BigInt::parse(pair.as_str())
+                    .map(|n| ast::Node::BigInt(Rc::new(n)))
+                    .ok_or_else(|| format!("Not an int: {}", pair.as_str())),
+            },
+            Rule::float => pair
                 .as_str()
                 .parse::<f64>()
                 .map_err(|err| err.to_string())
-                .map(ast::Node::Number),
+                .map(ast::Node::Float),
+            Rule::boolean => Ok(ast::Node::Bool(pair.as_str() == "true")),
             Rule::string => Ok(ast::Node::String(
                 pair.as_str()
                     .to_string()
@@ -121,11 +152,18 @@ impl AlphaParser {
             Rule::varref => Ok(ast::Node::VarRef(pair.as_str().to_string())),
             Rule::looop => {
                 let mut inner = pair.into_inner();
-                let name = inner.next().unwrap().as_str().to_string();
-                let iterable = Self::parse_pair(inner.next().unwrap())?;
+                let var = Self::pattern(inner.next().unwrap());
+                let mut next = inner.next().unwrap();
+                let mut value = None;
+                if matches!(next.as_rule(), Rule::name | Rule::tuplepattern) {
+                    value = Some(Self::pattern(next));
+                    next = inner.next().unwrap();
+                }
+                let iterable = Self::parse_pair(next)?;
                 let inner = Self::parse_pair(inner.next().unwrap())?;
                 Ok(ast::Node::Loop {
-                    var: name,
+                    var,
+                    value,
                     iterable: Box::new(iterable),
                     inner: Box::new(inner),
                 })
@@ -141,6 +179,10 @@ impl AlphaParser {
             }
             Rule::fundef => {
                 let mut inner = pair.into_inner();
+                let public = inner.peek().unwrap().as_rule() == Rule::pubb;
+                if public {
+                    inner.next();
+                }
                 let first = inner.next().unwrap();
                 let (name, next) = match first.as_rule() {
                     Rule::name => (Some(first.as_str().to_string()), inner.next().unwrap()),
@@ -149,8 +191,15 @@ impl AlphaParser {
                 let (names, next) = match next.as_rule() {
                     Rule::names => (
                         next.into_inner()
-                            .map(|n| n.as_str().to_string())
-                            .collect::<Vec<String>>(),
+                            .map(|param| {
+                                let mut inner = param.into_inner();
+                                let pattern = Self::pattern(inner.next().unwrap());
+                                let typename = inner
+                                    .next()
+                                    .map(|typeannot| typeannot.into_inner().as_str().to_string());
+                                (pattern, typename)
+                            })
+                            .collect::<Vec<(ast::Pattern, Option<String>)>>(),
                         inner.next().unwrap(),
                     ),
                     _ => (Vec::new(), next),
@@ -162,23 +211,22 @@ impl AlphaParser {
                     ),
                     _ => (None, next),
                 };
-                Ok(ast::Node::FnDef(
-                    name,
-                    names,
-                    Box::new(Self::parse_pair(next)?),
-                    typename,
-                ))
+                let fundef =
+                    ast::Node::FnDef(name, names, Rc::new(Self::parse_pair(next)?), typename);
+                Ok(match public {
+                    true => ast::Node::Pub(Box::new(fundef)),
+                    false => fundef,
+                })
             }
             Rule::var => {
                 let mut inner = pair.into_inner();
                 let first = inner.next().unwrap();
-                let (def, name) = match first.as_rule() {
-                    Rule::def => (Some(first), inner.next().unwrap()),
-                    Rule::name => (None, first),
+                let (def, pattern) = match first.as_rule() {
+                    Rule::def => (Some(first), Self::pattern(inner.next().unwrap())),
+                    Rule::name => (None, Self::pattern(first)),
                     _ => unreachable!(),
                 };
                 let next = inner.next().unwrap();
-                let name = name.as_str().to_string();
                 let (typename, next) = match next.as_rule() {
                     Rule::typeannot => (
                         Some(next.into_inner().as_str().to_string()),
@@ -194,17 +242,26 @@ impl AlphaParser {
                         } else {
                             ast::Mut::Immutable
                         };
-                        ast::Node::Define(mutable, name, expr, typename)
+                        ast::Node::Define(mutable, pattern, expr, typename)
                     }
-                    None => ast::Node::Assign(name, expr),
+                    None => match pattern {
+                        ast::Pattern::Name(name) => ast::Node::Assign(name, expr),
+                        ast::Pattern::Tuple(_) => unreachable!(),
+                    },
                 };
                 Ok(node)
             }
             Rule::fun => {
                 let mut inner = pair.into_inner();
-                let name = inner.next().unwrap().as_str().to_string();
+                let mut name = inner.next().unwrap().as_str().to_string();
+                // `module.name(...)` calls a function of an imported module.
+                if inner
+                    .peek()
+                    .is_some_and(|next| next.as_rule() == Rule::name)
+                {
+                    name = format!("{}.{}", name, inner.next().unwrap().as_str());
+                }
                 let args = inner
-                    .into_iter()
                     .map(Self::parse_pair)
                     .collect::<Result<Vec<ast::Node>, String>>()?;
                 Ok(ast::Node::FnCall(name, args))
@@ -223,6 +280,142 @@ impl AlphaParser {
                     else_block: Box::new(else_block),
                 })
             }
+            Rule::trry => {
+                let mut inner = pair.into_inner();
+                let body = Self::parse_pair(inner.next().unwrap())?;
+                let name = inner.next().unwrap().as_str().to_string();
+                let handler = Self::parse_pair(inner.next().unwrap())?;
+                Ok(ast::Node::TryCatch {
+                    body: Box::new(body),
+                    name,
+                    handler: Box::new(handler),
+                })
+            }
+            Rule::throww => {
+                let value = Self::parse_pair(pair.into_inner().next().unwrap())?;
+                Ok(ast::Node::Throw(Box::new(value)))
+            }
+            Rule::indexassign => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                let mut steps: Vec<Pair<'_, Rule>> = inner.collect();
+                let value = Self::parse_pair(steps.pop().unwrap())?;
+                let steps = steps
+                    .into_iter()
+                    .map(|step| match step.as_rule() {
+                        Rule::access => Ok(ast::Step::Field(Self::field(step))),
+                        _ => Ok(ast::Step::Index(Self::parse_pair(step)?)),
+                    })
+                    .collect::<Result<Vec<ast::Step>, String>>()?;
+                Ok(ast::Node::IndexAssign(name, steps, Box::new(value)))
+            }
+            Rule::externfn => {
+                let mut inner = pair.into_inner().skip(1);
+                let name = inner.next().unwrap().as_str().to_string();
+                let mut params = Vec::new();
+                let mut typename = None;
+                for next in inner {
+                    match next.as_rule() {
+                        Rule::member => {
+                            let mut inner = next.into_inner();
+                            let param = inner.next().unwrap().as_str().to_string();
+                            let typename = inner.next().unwrap().into_inner().as_str().to_string();
+                            params.push((param, typename));
+                        }
+                        _ => typename = Some(next.into_inner().as_str().to_string()),
+                    }
+                }
+                Ok(ast::Node::Extern(name, params, typename))
+            }
+            Rule::strukt => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                let fields = inner
+                    .map(|member| {
+                        let mut inner = member.into_inner();
+                        let field = inner.next().unwrap().as_str().to_string();
+                        let typename = inner.next().unwrap().into_inner().as_str().to_string();
+                        (field, typename)
+                    })
+                    .collect();
+                Ok(ast::Node::StructDef(name, fields))
+            }
+            Rule::enuum => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                let variants = inner
+                    .map(|variant| {
+                        let mut inner = variant.into_inner();
+                        let name = inner.next().unwrap().as_str().to_string();
+                        let fields = inner
+                            .map(|payload| {
+                                let mut inner = payload.into_inner();
+                                let field = inner.next().unwrap().as_str().to_string();
+                                let typename = inner
+                                    .next()
+                                    .map(|typeannot| typeannot.into_inner().as_str().to_string());
+                                (field, typename)
+                            })
+                            .collect();
+                        ast::VariantDef { name, fields }
+                    })
+                    .collect();
+                Ok(ast::Node::EnumDef(name, variants))
+            }
+            Rule::matsch => {
+                let mut inner = pair.into_inner();
+                let subject = Self::parse_pair(inner.next().unwrap())?;
+                let arms = inner
+                    .map(|arm| {
+                        let mut inner = arm.into_inner();
+                        let pattern = Self::match_pattern(inner.next().unwrap())?;
+                        let mut next = inner.next().unwrap();
+                        let mut guard = None;
+                        if next.as_rule() == Rule::guard {
+                            guard = Some(Self::parse_pair(next.into_inner().next().unwrap())?);
+                            next = inner.next().unwrap();
+                        }
+                        Ok(ast::Arm {
+                            pattern,
+                            guard,
+                            body: Self::parse_pair(next)?,
+                        })
+                    })
+                    .collect::<Result<Vec<ast::Arm>, String>>()?;
+                Ok(ast::Node::Match {
+                    subject: Box::new(subject),
+                    arms,
+                })
+            }
+            Rule::record => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                let fields = inner
+                    .map(|init| {
+                        let mut inner = init.into_inner();
+                        let field = inner.next().unwrap().as_str().to_string();
+                        Ok((field, Self::parse_pair(inner.next().unwrap())?))
+                    })
+                    .collect::<Result<Vec<(String, ast::Node)>, String>>()?;
+                Ok(ast::Node::StructLit(name, fields))
+            }
+            Rule::import => {
+                let mut inner = pair.into_inner().skip(1);
+                let path = inner.next().unwrap();
+                let path = match path.as_rule() {
+                    Rule::string => ast::ModulePath::File(path.as_str().trim_matches('"').into()),
+                    _ => ast::ModulePath::Name(
+                        path.into_inner()
+                            .map(|name| name.as_str().to_string())
+                            .collect(),
+                    ),
+                };
+                let names: Vec<String> = inner.map(|name| name.as_str().to_string()).collect();
+                Ok(ast::Node::Import(
+                    path,
+                    (!names.is_empty()).then_some(names),
+                ))
+            }
             Rule::typeannot => {
                 let inner = pair.into_inner();
                 Ok(ast::Node::TypeName(inner.as_str().to_string()))
@@ -234,14 +427,98 @@ impl AlphaParser {
         }
     }
 
+    fn pattern(pair: Pair<'_, Rule>) -> ast::Pattern {
+        match pair.as_rule() {
+            Rule::tuplepattern => {
+                ast::Pattern::Tuple(pair.into_inner().map(Self::pattern).collect())
+            }
+            _ => ast::Pattern::Name(pair.as_str().to_string()),
+        }
+    }
+
+    fn match_pattern(pair: Pair<'_, Rule>) -> Result<ast::MatchPattern, String> {
+        let all = |pairs: Vec<Pair<'_, Rule>>| {
+            pairs
+                .into_iter()
+                .map(Self::match_pattern)
+                .collect::<Result<Vec<ast::MatchPattern>, String>>()
+        };
+        Ok(match pair.as_rule() {
+            Rule::wildcard => ast::MatchPattern::Wildcard,
+            Rule::name => ast::MatchPattern::Name(pair.as_str().to_string()),
+            Rule::boolean => ast::MatchPattern::Bool(pair.as_str() == "true"),
+            Rule::patnum => Self::pattern_number(pair.as_str())?,
+            Rule::string => match Self::parse_pair(pair)? {
+                ast::Node::String(s) => ast::MatchPattern::String(s),
+                _ => unreachable!(),
+            },
+            Rule::patrange => {
+                let mut inner = pair.into_inner();
+                let lower = match inner.next().unwrap().as_str() {
+                    "[" => ast::Bound::Inclusive,
+                    _ => ast::Bound::Exclusive,
+                };
+                let mut bound = || match Self::pattern_number(inner.next().unwrap().as_str()) {
+                    Ok(ast::MatchPattern::Int(n)) => Ok(n),
+                    _ => Err("Range patterns take ints".to_string()),
+                };
+                let (from, to) = (bound()?, bound()?);
+                let upper = match inner.next().unwrap().as_str() {
+                    "]" => ast::Bound::Inclusive,
+                    _ => ast::Bound::Exclusive,
+                };
+                ast::MatchPattern::Range {
+                    from,
+                    to,
+                    lower,
+                    upper,
+                }
+            }
+            Rule::pattuple => ast::MatchPattern::Tuple(all(pair.into_inner().collect())?),
+            Rule::patlist => {
+                let mut items: Vec<Pair<'_, Rule>> = pair.into_inner().collect();
+                let rest = match items.last().map(Pair::as_rule) {
+                    Some(Rule::patrest) => {
+                        let rest = items.pop().unwrap().into_inner().next();
+                        Some(rest.map(|name| name.as_str().to_string()))
+                    }
+                    _ => None,
+                };
+                ast::MatchPattern::List(all(items)?, rest)
+            }
+            Rule::patvariant => {
+                let mut inner = pair.into_inner();
+                let name = inner.next().unwrap().as_str().to_string();
+                ast::MatchPattern::Variant(name, all(inner.collect())?)
+            }
+            _ => unreachable!(),
+        })
+    }
+
+    fn pattern_number(s: &str) -> Result<ast::MatchPattern, String> {
+        if s.contains('.') {
+            s.parse()
+                .map(ast::MatchPattern::Float)
+                .map_err(|err| err.to_string())
+        } else {
+            s.parse()
+                .map(ast::MatchPattern::Int)
+                .map_err(|err| err.to_string())
+        }
+    }
+
+    /// The name in an `access`.
+    fn field(pair: Pair<'_, Rule>) -> String {
+        pair.into_inner().next().unwrap().as_str().to_string()
+    }
+
     fn number_or_var(str: &str) -> Result<ast::Node, String> {
         if str.chars().next().unwrap().is_numeric() {
-            return str
-                .parse::<f64>()
-                .map(|f| ast::Node::Number(f))
-                .map_err(|err| err.to_string());
+            str.parse::<i64>()
+                .map(ast::Node::Int)
+                .map_err(|err| err.to_string())
         } else {
-            return Ok(ast::Node::VarRef(str.into()));
+            Ok(ast::Node::VarRef(str.into()))
         }
     }
 
@@ -251,7 +528,7 @@ impl AlphaParser {
             .map_prefix(|op, rhs| match op.as_rule() {
                 Rule::neg => Ok(ast::Node::Expr {
                     op: ast::Op::Mul,
-                    lhs: Box::new(ast::Node::Number(-1.0)),
+                    lhs: Box::new(ast::Node::Int(-1)),
                     rhs: Box::new(rhs?),
                 }),
                 Rule::name => Ok(ast::Node::Assign(op.as_str().to_string(), Box::new(rhs?))),
@@ -263,6 +540,35 @@ impl AlphaParser {
             .map_postfix(|lhs, op| match op.as_rule() {
                 Rule::EOI => lhs,
                 Rule::fac => todo!(),
+                Rule::index => {
+                    let inner = op.into_inner().next().unwrap();
+                    match inner.as_rule() {
+                        Rule::slice => {
+                            let mut from = None;
+                            let mut to = None;
+                            for bound in inner.into_inner() {
+                                let rule = bound.as_rule();
+                                let expr = bound.into_inner().next().unwrap();
+                                let expr = Some(Box::new(Self::parse_pair(expr)?));
+                                match rule {
+                                    Rule::from => from = expr,
+                                    _ => to = expr,
+                                }
+                            }
+                            Ok(ast::Node::Slice {
+                                list: Box::new(lhs?),
+                                from,
+                                to,
+                            })
+                        }
+                        _ => Ok(ast::Node::Index(
+                            Box::new(lhs?),
+                            Box::new(Self::parse_pair(inner)?),
+                        )),
+                    }
+                }
+                Rule::access => Ok(ast::Node::Field(Box::new(lhs?), Self::field(op))),
+                Rule::propagate => Ok(ast::Node::Try(Box::new(lhs?))),
                 _ => {
                     dbg!(lhs?, op);
                     unreachable!()
@@ -275,7 +581,8 @@ impl AlphaParser {
                         Rule::sub => ast::Op::Sub,
                         Rule::mul => ast::Op::Mul,
                         Rule::div => ast::Op::Div,
-                        Rule::pow => todo!(),
+                        Rule::rem => ast::Op::Mod,
+                        Rule::pow => ast::Op::Pow,
                         Rule::eq => ast::Op::Eq,
                         Rule::neq => ast::Op::Neq,
                         Rule::gt => ast::Op::Gt,
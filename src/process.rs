@@ -0,0 +1,59 @@
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::value::{self, Value};
+
+/// The builtins about the running program with the number of arguments they
+/// take. Like the list builtins, a function or variable of the same name
+/// shadows them.
+pub const BUILTINS: &[(&str, usize)] = &[("args", 0), ("env", 1), ("exit", 1)];
+
+pub fn arity(name: &str) -> Option<usize> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, arity)| *arity)
+}
+
+/// The exit status of a program that results in `result`: an int is the
+/// status, which the OS truncates like the argument of `exit`, and anything
+/// else exits successfully.
+pub fn status(result: &Value) -> i32 {
+    match result {
+        Value::Int(n) => *n as i32,
+        _ => 0,
+    }
+}
+
+/// Runs the builtin `name` of a program started with the arguments `argv`.
+pub fn apply(name: &str, args: &[Value], argv: &[String]) -> Result<Value, String> {
+    match arity(name) {
+        Some(1) if args.len() != 1 => return Err(format!("{} takes 1 argument", name)),
+        Some(n) if args.len() != n => return Err(format!("{} takes {} arguments", name, n)),
+        _ => {}
+    }
+    Ok(match name {
+        "args" => Value::List(Rc::new(
+            argv.iter()
+                .map(|arg| Value::String(format!("{}\0", arg).into()))
+                .collect(),
+        )),
+        // Unset variables and ones that are not unicode are `none`.
+        "env" => match &args[0] {
+            Value::String(name) => value::some_or_none(
+                std::env::var(name.trim_end_matches('\0'))
+                    .ok()
+                    .map(|var| Value::String(format!("{}\0", var).into())),
+            ),
+            value => return Err(format!("Not a string: '{:?}'", value)),
+        },
+        "exit" => match &args[0] {
+            Value::Int(code) => {
+                io::stdout().flush().ok();
+                std::process::exit(*code as i32)
+            }
+            value => return Err(format!("Not an int: '{:?}'", value)),
+        },
+        _ => unreachable!("not a process builtin: {}", name),
+    })
+}
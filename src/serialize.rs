@@ -0,0 +1,685 @@
+use std::rc::Rc;
+
+use crate::ast::{self, MatchPattern};
+use crate::bigint::BigInt;
+use crate::bytecode::{
+    Builtin, Capture, Constant, Function, Module, Opcode, NO_ARM, NO_SLOT, SLICE_FROM, SLICE_TO,
+};
+use crate::ffi::{self, CType, Extern};
+use crate::value::{EnumType, StructType};
+
+/// Binary layout of a precompiled `.abc` module, all integers little endian:
+///
+/// ```text
+/// magic      b"\x7fABC"
+/// version    u16
+/// constants  u32 count, then per constant a u8 tag (0 float: f64, 1 string: str, 2 int: i64,
+///            3 bigint: decimal str, 4 extern fn: name str, u32 count of parameter type
+///            name strs, u8 returns, type name str)
+/// functions  u32 count, then per function:
+///              name      u8 present, str
+///              arity     u16
+///              slots     u32 count, str each
+///              self slot u8 present, u16
+///              captures  u32 count, (u16 from, u16 to) each
+///              code      u32 length, bytes
+///              lines     u32 count, (u32 offset, u32 line) each
+/// structs    u32 count, then per struct a name str and a u32 count of
+///            (field str, type name str)
+/// enums      u32 count, then per enum a name str and a u32 count of variants,
+///            each a name str and a u32 count of (field str, u8 typed, type name str)
+/// patterns   u32 count of patterns, each a u8 tag followed by
+///              0 wildcard, 1 name: u16 slot, 2 int: i64, 3 float: f64, 4 bool: u8,
+///              5 string: str, 6 range: i64 from, i64 to, u8 exclusive flags,
+///              7 tuple: u32 count, patterns, 8 list: u32 count, patterns, u8 rest
+///              (0 none, 1 unnamed, 2 named: u16 slot), 9 variant: name str, u32
+///              count, patterns
+/// ```
+///
+/// A `str` is a u32 byte length followed by UTF-8 bytes.
+pub const MAGIC: &[u8; 4] = b"\x7fABC";
+pub const VERSION: u16 = 17;
+
+const FLOAT: u8 = 0;
+const STRING: u8 = 1;
+const INT: u8 = 2;
+const BIGINT: u8 = 3;
+const EXTERN: u8 = 4;
+
+pub fn to_bytes(module: &Module) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u16(VERSION);
+
+    writer.u32(module.constants.len() as u32);
+    for constant in &module.constants {
+        match constant {
+            Constant::Int(n) => {
+                writer.u8(INT);
+                writer.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Constant::BigInt(n) => {
+                writer.u8(BIGINT);
+                writer.str(&n.to_string());
+            }
+            Constant::Float(n) => {
+                writer.u8(FLOAT);
+                writer.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            Constant::String(s) => {
+                writer.u8(STRING);
+                writer.str(s);
+            }
+            Constant::Extern(function) => {
+                writer.u8(EXTERN);
+                writer.str(&function.name);
+                writer.u32(function.params.len() as u32);
+                for param in &function.params {
+                    writer.str(param.name());
+                }
+                writer.u8(function.returns.is_some() as u8);
+                writer.str(function.returns.map(CType::name).unwrap_or_default());
+            }
+        }
+    }
+
+    writer.u32(module.functions.len() as u32);
+    for function in &module.functions {
+        writer.u8(function.name.is_some() as u8);
+        if let Some(name) = &function.name {
+            writer.str(name);
+        }
+        writer.u16(function.arity);
+        writer.u32(function.slots.len() as u32);
+        for slot in &function.slots {
+            writer.str(slot);
+        }
+        writer.u8(function.self_slot.is_some() as u8);
+        if let Some(slot) = function.self_slot {
+            writer.u16(slot);
+        }
+        writer.u32(function.captures.len() as u32);
+        for capture in &function.captures {
+            writer.u16(capture.from);
+            writer.u16(capture.to);
+        }
+        writer.u32(function.code.len() as u32);
+        writer.bytes.extend_from_slice(&function.code);
+        writer.u32(function.lines.len() as u32);
+        for (offset, line) in &function.lines {
+            writer.u32(*offset);
+            writer.u32(*line);
+        }
+    }
+
+    writer.u32(module.structs.len() as u32);
+    for ty in &module.structs {
+        writer.str(&ty.name);
+        writer.u32(ty.fields.len() as u32);
+        for (field, typename) in &ty.fields {
+            writer.str(field);
+            writer.str(typename);
+        }
+    }
+
+    writer.u32(module.enums.len() as u32);
+    for ty in &module.enums {
+        writer.str(&ty.name);
+        writer.u32(ty.variants.len() as u32);
+        for variant in &ty.variants {
+            writer.str(&variant.name);
+            writer.u32(variant.fields.len() as u32);
+            for (field, typename) in &variant.fields {
+                writer.str(field);
+                writer.u8(typename.is_some() as u8);
+                writer.str(typename.as_deref().unwrap_or_default());
+            }
+        }
+    }
+
+    writer.u32(module.patterns.len() as u32);
+    for pattern in &module.patterns {
+        writer.pattern(pattern);
+    }
+    writer.bytes
+}
+
+pub fn from_bytes(bytes: &[u8]) -> Result<Module, String> {
+    let mut reader = Reader { bytes, at: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Not an alpha bytecode module".into());
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(format!(
+            "Unsupported bytecode version {} (expected {})",
+            version, VERSION
+        ));
+    }
+
+    let mut module = Module::default();
+    for _ in 0..reader.u32()? {
+        module.constants.push(match reader.u8()? {
+            INT => Constant::Int(i64::from_le_bytes(reader.take(8)?.try_into().unwrap())),
+            FLOAT => Constant::Float(f64::from_le_bytes(reader.take(8)?.try_into().unwrap())),
+            STRING => Constant::String(reader.str()?.into()),
+            BIGINT => {
+                let digits = reader.str()?;
+                let n =
+                    BigInt::parse(&digits).ok_or_else(|| format!("Invalid bigint: {}", digits))?;
+                Constant::BigInt(Rc::new(n))
+            }
+            EXTERN => {
+                let name = reader.str()?;
+                ffi::reject_variadic(&name)?;
+                let params = (0..reader.u32()?)
+                    .map(|_| CType::parse(&reader.str()?))
+                    .collect::<Result<_, _>>()?;
+                let returns = reader.u8()? != 0;
+                let typename = reader.str()?;
+                let returns = returns.then(|| CType::parse(&typename)).transpose()?;
+                Constant::Extern(Rc::new(Extern {
+                    name,
+                    params,
+                    returns,
+                }))
+            }
+            tag => return Err(format!("Invalid constant tag: {}", tag)),
+        });
+    }
+
+    for _ in 0..reader.u32()? {
+        let name = match reader.u8()? {
+            0 => None,
+            _ => Some(reader.str()?),
+        };
+        let arity = reader.u16()?;
+        let slots = (0..reader.u32()?)
+            .map(|_| reader.str())
+            .collect::<Result<Vec<String>, String>>()?;
+        let self_slot = match reader.u8()? {
+            0 => None,
+            _ => Some(reader.u16()?),
+        };
+        let captures = (0..reader.u32()?)
+            .map(|_| {
+                Ok(Capture {
+                    from: reader.u16()?,
+                    to: reader.u16()?,
+                })
+            })
+            .collect::<Result<Vec<Capture>, String>>()?;
+        let len = reader.u32()? as usize;
+        let code = reader.take(len)?.to_vec();
+        let lines = (0..reader.u32()?)
+            .map(|_| Ok((reader.u32()?, reader.u32()?)))
+            .collect::<Result<Vec<(u32, u32)>, String>>()?;
+        module.functions.push(Function {
+            name,
+            arity,
+            slots,
+            self_slot,
+            captures,
+            code,
+            lines,
+        });
+    }
+
+    for _ in 0..reader.u32()? {
+        let name = reader.str()?;
+        let fields = (0..reader.u32()?)
+            .map(|_| Ok((reader.str()?, reader.str()?)))
+            .collect::<Result<Vec<(String, String)>, String>>()?;
+        module.structs.push(StructType { name, fields });
+    }
+
+    for _ in 0..reader.u32()? {
+        let name = reader.str()?;
+        let variants = (0..reader.u32()?)
+            .map(|_| {
+                let name = reader.str()?;
+                let fields = (0..reader.u32()?)
+                    .map(|_| {
+                        let field = reader.str()?;
+                        let typed = reader.u8()? != 0;
+                        let typename = reader.str()?;
+                        Ok((field, typed.then_some(typename)))
+                    })
+                    .collect::<Result<Vec<(String, Option<String>)>, String>>()?;
+                Ok(ast::VariantDef { name, fields })
+            })
+            .collect::<Result<Vec<ast::VariantDef>, String>>()?;
+        module.enums.push(EnumType { name, variants });
+    }
+
+    for _ in 0..reader.u32()? {
+        let pattern = reader.pattern()?;
+        module.patterns.push(pattern);
+    }
+
+    if reader.at != bytes.len() {
+        return Err("Trailing bytes after module".into());
+    }
+    validate(&module)?;
+    Ok(module)
+}
+
+/// Checks that a loaded module only refers to constants, slots, functions,
+/// structs, enums, patterns and jump targets that exist, and never pops more
+/// values than it pushed, so that the `Vm` can index and pop them without
+/// checks.
+fn validate(module: &Module) -> Result<(), String> {
+    if module.functions.get(Module::ENTRY).is_none() {
+        return Err("Module has no entry function".into());
+    }
+    for (index, function) in module.functions.iter().enumerate() {
+        let fail = |msg: String| format!("Invalid function #{}: {}", index, msg);
+        let slots = function.slots.len();
+        if function.arity as usize > slots {
+            return Err(fail("arity exceeds slot count".into()));
+        }
+        if function
+            .self_slot
+            .is_some_and(|slot| slot as usize >= slots)
+        {
+            return Err(fail("self slot out of range".into()));
+        }
+        if function.captures.iter().any(|c| c.to as usize >= slots) {
+            return Err(fail("capture slot out of range".into()));
+        }
+
+        let code = &function.code;
+        let mut boundaries = Vec::new();
+        let mut targets = Vec::new();
+        let mut ip = 0;
+        while ip < code.len() {
+            boundaries.push(ip);
+            let op = Opcode::from_byte(code[ip])
+                .ok_or_else(|| fail(format!("invalid opcode {} at {}", code[ip], ip)))?;
+            let end = ip + 1 + op.operand_len();
+            if end > code.len() {
+                return Err(fail(format!("truncated {:?} at {}", op, ip)));
+            }
+            let u16_at = |at: usize| u16::from_le_bytes([code[at], code[at + 1]]) as usize;
+            let slot = |at: usize| match u16_at(at) < slots {
+                true => Ok(()),
+                false => Err(fail(format!("slot out of range at {}", ip))),
+            };
+            match op {
+                Opcode::Const if u16_at(ip + 1) >= module.constants.len() => {
+                    return Err(fail(format!("constant out of range at {}", ip)))
+                }
+                Opcode::CheckType
+                    if !matches!(
+                        module.constants.get(u16_at(ip + 1)),
+                        Some(Constant::String(_))
+                    ) =>
+                {
+                    return Err(fail(format!("type name is not a string at {}", ip)))
+                }
+                Opcode::Field
+                    if !matches!(
+                        module.constants.get(u16_at(ip + 1)),
+                        Some(Constant::String(_))
+                    ) =>
+                {
+                    return Err(fail(format!("field name is not a string at {}", ip)))
+                }
+                Opcode::Struct if u16_at(ip + 1) >= module.structs.len() => {
+                    return Err(fail(format!("struct out of range at {}", ip)))
+                }
+                Opcode::Variant => {
+                    let ty = module
+                        .enums
+                        .get(u16_at(ip + 1))
+                        .ok_or_else(|| fail(format!("enum out of range at {}", ip)))?;
+                    if u16_at(ip + 3) >= ty.variants.len() {
+                        return Err(fail(format!("variant out of range at {}", ip)));
+                    }
+                }
+                Opcode::Match if u16_at(ip + 1) != NO_ARM as usize => {
+                    let pattern = module
+                        .patterns
+                        .get(u16_at(ip + 1))
+                        .ok_or_else(|| fail(format!("pattern out of range at {}", ip)))?;
+                    if !pattern_slots(pattern).all(|slot| (slot as usize) < slots) {
+                        return Err(fail(format!("slot out of range at {}", ip)));
+                    }
+                }
+                Opcode::GetLocal | Opcode::SetLocal | Opcode::Collect | Opcode::SetIndex => {
+                    slot(ip + 1)?
+                }
+                Opcode::ForPrep => {
+                    for at in [ip + 1, ip + 3, ip + 5] {
+                        slot(at)?;
+                    }
+                }
+                Opcode::ForNext => {
+                    for at in [ip + 1, ip + 3, ip + 5] {
+                        slot(at)?;
+                    }
+                    if u16_at(ip + 7) != NO_SLOT as usize {
+                        slot(ip + 7)?;
+                    }
+                    targets.push(end + u16_at(ip + 9));
+                }
+                Opcode::Jump | Opcode::JumpIfFalse | Opcode::Try | Opcode::Catch => {
+                    targets.push(end + u16_at(ip + 1))
+                }
+                Opcode::Loop => match end.checked_sub(u16_at(ip + 1)) {
+                    Some(target) => targets.push(target),
+                    None => return Err(fail(format!("loop target out of range at {}", ip))),
+                },
+                Opcode::Closure => {
+                    let inner = module
+                        .functions
+                        .get(u16_at(ip + 1))
+                        .ok_or_else(|| fail(format!("function out of range at {}", ip)))?;
+                    if inner.captures.iter().any(|c| c.from as usize >= slots) {
+                        return Err(fail(format!("captured slot out of range at {}", ip)));
+                    }
+                }
+                Opcode::Builtin if Builtin::from_byte(code[ip + 1]).is_none() => {
+                    return Err(fail(format!("invalid builtin at {}", ip)))
+                }
+                _ => {}
+            }
+            ip = end;
+        }
+        if code.last() != Some(&(Opcode::Return as u8)) || !boundaries.contains(&(code.len() - 1)) {
+            return Err(fail("code does not end with Return".into()));
+        }
+        if let Some(target) = targets.iter().find(|t| !boundaries.contains(t)) {
+            return Err(fail(format!("jump to {} is not an instruction", target)));
+        }
+        check_stack(module, code).map_err(fail)?;
+    }
+    Ok(())
+}
+
+/// Checks that every instruction reachable in `code`, which `validate` has
+/// decoded, finds the values it pops on the stack above the slots, and the
+/// same number of them along every path to it.
+fn check_stack(module: &Module, code: &[u8]) -> Result<(), String> {
+    let mut depths = vec![None; code.len()];
+    let mut pending: Vec<(usize, usize)> = vec![(0, 0)];
+    while let Some((ip, depth)) = pending.pop() {
+        match depths[ip] {
+            Some(known) if known == depth => continue,
+            Some(known) => {
+                return Err(format!(
+                    "stack depth {} at {} was {} before",
+                    depth, ip, known
+                ))
+            }
+            None => depths[ip] = Some(depth),
+        }
+        let op = Opcode::from_byte(code[ip]).unwrap();
+        let end = ip + 1 + op.operand_len();
+        let u16_at = |at: usize| u16::from_le_bytes([code[at], code[at + 1]]) as usize;
+        let (pops, pushes) = match op {
+            Opcode::Const
+            | Opcode::Nada
+            | Opcode::True
+            | Opcode::False
+            | Opcode::GetLocal
+            | Opcode::Closure => (0, 1),
+            Opcode::Dup => (1, 2),
+            Opcode::Pop
+            | Opcode::SetLocal
+            | Opcode::JumpIfFalse
+            | Opcode::ForPrep
+            | Opcode::Collect
+            | Opcode::Return
+            | Opcode::Throw => (1, 0),
+            Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::Mod
+            | Opcode::Pow
+            | Opcode::Eq
+            | Opcode::Neq
+            | Opcode::Gt
+            | Opcode::Ge
+            | Opcode::Lt
+            | Opcode::Le
+            | Opcode::Range
+            | Opcode::Index => (2, 1),
+            Opcode::CheckType | Opcode::Field | Opcode::Match | Opcode::Try => (1, 1),
+            Opcode::Jump | Opcode::Loop | Opcode::ForNext | Opcode::Catch | Opcode::EndCatch => {
+                (0, 0)
+            }
+            Opcode::List | Opcode::Tuple => (u16_at(ip + 1), 1),
+            Opcode::Map => (2 * u16_at(ip + 1), 1),
+            Opcode::Struct => (module.structs[u16_at(ip + 1)].fields.len(), 1),
+            Opcode::Variant => {
+                let variant = &module.enums[u16_at(ip + 1)].variants[u16_at(ip + 3)];
+                (variant.fields.len(), 1)
+            }
+            Opcode::Call => (code[ip + 1] as usize + 1, 1),
+            Opcode::Builtin => (code[ip + 2] as usize, 1),
+            Opcode::Slice => (
+                1 + (code[ip + 1] & (SLICE_FROM | SLICE_TO)).count_ones() as usize,
+                1,
+            ),
+            Opcode::SetIndex => (code[ip + 3] as usize + 1, 0),
+            Opcode::Unpack => (1, code[ip + 1] as usize),
+        };
+        let Some(depth) = depth.checked_sub(pops) else {
+            return Err(format!("{:?} at {} pops from an empty stack", op, ip));
+        };
+        let depth = depth + pushes;
+        // The handler of a `Catch` starts from the depth of the `Catch`,
+        // with the error pushed.
+        match op {
+            Opcode::Return | Opcode::Throw => {}
+            Opcode::Jump => pending.push((end + u16_at(ip + 1), depth)),
+            Opcode::Loop => pending.push((end - u16_at(ip + 1), depth)),
+            Opcode::JumpIfFalse | Opcode::Try => {
+                pending.push((end + u16_at(ip + 1), depth));
+                pending.push((end, depth));
+            }
+            Opcode::ForNext => {
+                pending.push((end + u16_at(ip + 9), depth));
+                pending.push((end, depth));
+            }
+            Opcode::Catch => {
+                pending.push((end + u16_at(ip + 1), depth + 1));
+                pending.push((end, depth));
+            }
+            _ => pending.push((end, depth)),
+        }
+    }
+    Ok(())
+}
+
+/// The slots a pattern binds.
+fn pattern_slots(pattern: &MatchPattern<u16>) -> Box<dyn Iterator<Item = u16> + '_> {
+    match pattern {
+        MatchPattern::Name(slot) => Box::new(std::iter::once(*slot)),
+        MatchPattern::Tuple(items) | MatchPattern::Variant(_, items) => {
+            Box::new(items.iter().flat_map(pattern_slots))
+        }
+        MatchPattern::List(items, rest) => Box::new(
+            items
+                .iter()
+                .flat_map(pattern_slots)
+                .chain(rest.iter().flatten().copied()),
+        ),
+        _ => Box::new(std::iter::empty()),
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn patterns(&mut self, patterns: &[MatchPattern<u16>]) {
+        self.u32(patterns.len() as u32);
+        for pattern in patterns {
+            self.pattern(pattern);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &MatchPattern<u16>) {
+        match pattern {
+            MatchPattern::Wildcard => self.u8(0),
+            MatchPattern::Name(slot) => {
+                self.u8(1);
+                self.u16(*slot);
+            }
+            MatchPattern::Int(n) => {
+                self.u8(2);
+                self.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            MatchPattern::Float(n) => {
+                self.u8(3);
+                self.bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            MatchPattern::Bool(b) => {
+                self.u8(4);
+                self.u8(*b as u8);
+            }
+            MatchPattern::String(s) => {
+                self.u8(5);
+                self.str(s);
+            }
+            MatchPattern::Range {
+                from,
+                to,
+                lower,
+                upper,
+            } => {
+                self.u8(6);
+                self.bytes.extend_from_slice(&from.to_le_bytes());
+                self.bytes.extend_from_slice(&to.to_le_bytes());
+                let exclusive = |bound: &ast::Bound| matches!(bound, ast::Bound::Exclusive) as u8;
+                self.u8(exclusive(lower) | exclusive(upper) << 1);
+            }
+            MatchPattern::Tuple(items) => {
+                self.u8(7);
+                self.patterns(items);
+            }
+            MatchPattern::List(items, rest) => {
+                self.u8(8);
+                self.patterns(items);
+                match rest {
+                    None => self.u8(0),
+                    Some(None) => self.u8(1),
+                    Some(Some(slot)) => {
+                        self.u8(2);
+                        self.u16(*slot);
+                    }
+                }
+            }
+            MatchPattern::Variant(name, items) => {
+                self.u8(9);
+                self.str(name);
+                self.patterns(items);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.at..self.at + len)
+            .ok_or_else(|| "Unexpected end of module".to_string())?;
+        self.at += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+
+    fn i64(&mut self) -> Result<i64, String> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn patterns(&mut self) -> Result<Vec<MatchPattern<u16>>, String> {
+        (0..self.u32()?).map(|_| self.pattern()).collect()
+    }
+
+    fn pattern(&mut self) -> Result<MatchPattern<u16>, String> {
+        Ok(match self.u8()? {
+            0 => MatchPattern::Wildcard,
+            1 => MatchPattern::Name(self.u16()?),
+            2 => MatchPattern::Int(self.i64()?),
+            3 => MatchPattern::Float(f64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            4 => MatchPattern::Bool(self.u8()? != 0),
+            5 => MatchPattern::String(self.str()?),
+            6 => {
+                let from = self.i64()?;
+                let to = self.i64()?;
+                let flags = self.u8()?;
+                let bound = |exclusive: bool| match exclusive {
+                    true => ast::Bound::Exclusive,
+                    false => ast::Bound::Inclusive,
+                };
+                MatchPattern::Range {
+                    from,
+                    to,
+                    lower: bound(flags & 1 != 0),
+                    upper: bound(flags & 2 != 0),
+                }
+            }
+            7 => MatchPattern::Tuple(self.patterns()?),
+            8 => {
+                let items = self.patterns()?;
+                let rest = match self.u8()? {
+                    0 => None,
+                    1 => Some(None),
+                    2 => Some(Some(self.u16()?)),
+                    tag => return Err(format!("Invalid list rest: {}", tag)),
+                };
+                MatchPattern::List(items, rest)
+            }
+            9 => {
+                let name = self.str()?;
+                MatchPattern::Variant(name, self.patterns()?)
+            }
+            tag => return Err(format!("Invalid pattern tag: {}", tag)),
+        })
+    }
+}
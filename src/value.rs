@@ -0,0 +1,859 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast;
+use crate::bigint::BigInt;
+use crate::eval;
+use crate::ffi;
+use crate::vm;
+
+/// Runtime errors that compiled code reports with the exact same message.
+pub const OVERFLOW: &str = "Integer overflow";
+pub const DIVISION_BY_ZERO: &str = "Division by zero";
+pub const FLOAT_TO_INT: &str = "Float out of int range";
+pub const NEGATIVE_EXPONENT: &str = "Negative exponent";
+
+pub fn index_out_of_bounds(index: i64, len: usize) -> String {
+    format!("Index out of bounds: {} (len {})", index, len)
+}
+
+pub fn slice_out_of_bounds(from: i64, to: i64, len: usize) -> String {
+    format!("Slice out of bounds: {}..{} (len {})", from, to, len)
+}
+
+pub fn arity_mismatch(expected: usize, found: usize) -> String {
+    format!(
+        "Arity mismatch: expected {} values, found {}",
+        expected, found
+    )
+}
+
+pub fn key_not_found(key: &Value) -> String {
+    format!("Key not found: {:?}", key)
+}
+
+/// A runtime error or a thrown value on its way out to a `catch`.
+pub struct Error {
+    /// The value of a `throw`, or `None` for a runtime error.
+    pub thrown: Option<Value>,
+    pub message: String,
+    /// The line the error happened on.
+    pub line: Option<usize>,
+    /// The functions the error has left, innermost first, with the lines
+    /// they were called from.
+    pub trace: Vec<(String, Option<usize>)>,
+}
+
+impl Error {
+    pub fn throw(value: Value) -> Self {
+        Error {
+            message: format!("Uncaught {:?}", value),
+            thrown: Some(value),
+            line: None,
+            trace: Vec::new(),
+        }
+    }
+
+    /// Records a line the error passed: the line it happened on, or else
+    /// the line the function it left last was called from.
+    pub fn at_line(&mut self, line: usize) {
+        match self.trace.last_mut() {
+            None if self.line.is_none() => self.line = Some(line),
+            Some((_, call @ None)) => *call = Some(line),
+            _ => {}
+        }
+    }
+
+    /// Records that the error left the function `name`.
+    pub fn leave(&mut self, name: Option<&str>) {
+        self.trace.push((name.unwrap_or("fn").to_string(), None));
+    }
+
+    /// What a `catch` binds.
+    pub fn into_value(self) -> Value {
+        self.thrown
+            .unwrap_or_else(|| Value::String(format!("{}\0", self.message).into()))
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error {
+            thrown: None,
+            message,
+            line: None,
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(f, "{}", self.message)?;
+        for (name, call) in &self.trace {
+            write!(f, "\n    in {}", name)?;
+            if let Some(line) = call {
+                write!(f, ", called from line {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A runtime value, shared by `Eval` and the `Vm`.
+#[derive(Clone)]
+pub enum Value {
+    Unit,
+    Int(i64),
+    BigInt(Rc<BigInt>),
+    Float(f64),
+    Bool(bool),
+    String(Rc<str>),
+    List(Rc<Vec<Value>>),
+    Tuple(Rc<Vec<Value>>),
+    Map(Rc<Map>),
+    Struct(Rc<Struct>),
+    Variant(Rc<Variant>),
+    Range(Range),
+    Function(Function),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Range {
+    pub from: i64,
+    pub to: i64,
+    pub lower: ast::Bound,
+    pub upper: ast::Bound,
+}
+
+impl Range {
+    /// The first value and the exclusive end of the range.
+    pub fn bounds(&self) -> (i64, i64) {
+        let start = match self.lower {
+            ast::Bound::Inclusive => self.from,
+            ast::Bound::Exclusive => self.from.saturating_add(1),
+        };
+        let end = match self.upper {
+            ast::Bound::Inclusive => self.to.saturating_add(1),
+            ast::Bound::Exclusive => self.to,
+        };
+        (start, end)
+    }
+}
+
+/// A map that keeps its keys in insertion order. Keys are ints, bigints,
+/// bools or strings.
+#[derive(Clone, Default)]
+pub struct Map {
+    entries: Vec<(Value, Value)>,
+    positions: HashMap<Key, usize>,
+}
+
+/// The hashable form of a map key, in which bigints that fit are ints.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Int(i64),
+    BigInt(BigInt),
+    Bool(bool),
+    String(Rc<str>),
+}
+
+impl Map {
+    fn key(key: &Value) -> Result<Key, String> {
+        match key {
+            Value::Int(n) => Ok(Key::Int(*n)),
+            Value::BigInt(n) => Ok(n
+                .to_i64()
+                .map_or_else(|| Key::BigInt((**n).clone()), Key::Int)),
+            Value::Bool(b) => Ok(Key::Bool(*b)),
+            Value::String(s) => Ok(Key::String(s.clone())),
+            value => Err(format!("Cannot use {} as a map key", value.type_name())),
+        }
+    }
+
+    pub fn get(&self, key: &Value) -> Result<Option<&Value>, String> {
+        Ok(self
+            .positions
+            .get(&Self::key(key)?)
+            .map(|&i| &self.entries[i].1))
+    }
+
+    fn get_mut(&mut self, key: &Value) -> Result<Option<&mut Value>, String> {
+        Ok(self
+            .positions
+            .get(&Self::key(key)?)
+            .map(|&i| &mut self.entries[i].1))
+    }
+
+    /// Replaces the value of an existing key in place, or appends the key.
+    pub fn insert(&mut self, key: Value, value: Value) -> Result<(), String> {
+        match self.get_mut(&key)? {
+            Some(old) => *old = value,
+            None => {
+                self.positions.insert(Self::key(&key)?, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn remove(&mut self, key: &Value) -> Result<(), String> {
+        if let Some(position) = self.positions.remove(&Self::key(key)?) {
+            self.entries.remove(position);
+            for i in self.positions.values_mut() {
+                if *i > position {
+                    *i -= 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A `struct` declaration: the name and the fields with their type names.
+#[derive(Debug, Clone)]
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<(String, String)>,
+}
+
+impl StructType {
+    /// The position of `field` in the declaration.
+    pub fn position(&self, field: &str) -> Result<usize, String> {
+        self.fields
+            .iter()
+            .position(|(name, _)| name == field)
+            .ok_or_else(|| format!("No field '{}' in {}", field, self.name))
+    }
+
+    /// The values of a struct literal in declaration order, which must name
+    /// every field once.
+    pub fn order<'a, T>(&self, fields: &'a [(String, T)]) -> Result<Vec<&'a T>, String> {
+        let mut ordered = vec![None; self.fields.len()];
+        for (field, value) in fields {
+            let position = self.position(field)?;
+            if ordered[position].replace(value).is_some() {
+                return Err(format!("Field '{}' given twice", field));
+            }
+        }
+        ordered
+            .into_iter()
+            .zip(&self.fields)
+            .map(|(value, (field, _))| {
+                value.ok_or_else(|| format!("Missing field '{}' in {}", field, self.name))
+            })
+            .collect()
+    }
+
+    /// A struct of this type, checking the values, given in declaration
+    /// order, against the types of the fields.
+    pub fn construct(self: &Rc<Self>, values: Vec<Value>) -> Result<Value, String> {
+        let fields = values
+            .into_iter()
+            .zip(&self.fields)
+            .map(|(value, (_, typename))| value.check_type(typename))
+            .collect::<Result<Vec<Value>, String>>()?;
+        Ok(Value::Struct(Rc::new(Struct {
+            ty: self.clone(),
+            fields,
+        })))
+    }
+}
+
+/// An instance of a `StructType`, with its values in declaration order.
+#[derive(Clone)]
+pub struct Struct {
+    pub ty: Rc<StructType>,
+    pub fields: Vec<Value>,
+}
+
+/// An `enum` declaration.
+#[derive(Debug, Clone)]
+pub struct EnumType {
+    pub name: String,
+    pub variants: Vec<ast::VariantDef>,
+}
+
+impl EnumType {
+    /// The names and payload lengths of the variants.
+    pub fn arities(&self) -> Vec<(String, usize)> {
+        self.variants
+            .iter()
+            .map(|variant| (variant.name.clone(), variant.fields.len()))
+            .collect()
+    }
+
+    /// Variant `tag` of this type, checking the payload against the types of
+    /// its fields.
+    pub fn construct(self: &Rc<Self>, tag: usize, values: Vec<Value>) -> Result<Value, String> {
+        let variant = &self.variants[tag];
+        if values.len() != variant.fields.len() {
+            return Err(format!(
+                "{} takes {} arguments",
+                variant.name,
+                variant.fields.len()
+            ));
+        }
+        let values = values
+            .into_iter()
+            .zip(&variant.fields)
+            .map(|(value, (_, typename))| match typename {
+                Some(typename) => value.check_type(typename),
+                None => Ok(value),
+            })
+            .collect::<Result<Vec<Value>, String>>()?;
+        Ok(Value::Variant(Rc::new(Variant {
+            ty: self.clone(),
+            tag,
+            values,
+        })))
+    }
+}
+
+/// The enum of optional values, which every program has declared as
+/// `enum option { some(value), none }`.
+pub const OPTION: &str = "option";
+pub const SOME: usize = 0;
+pub const NONE: usize = 1;
+
+pub fn option_type() -> EnumType {
+    EnumType {
+        name: OPTION.into(),
+        variants: vec![
+            ast::VariantDef {
+                name: "some".into(),
+                fields: vec![("value".into(), None)],
+            },
+            ast::VariantDef {
+                name: "none".into(),
+                fields: Vec::new(),
+            },
+        ],
+    }
+}
+
+/// `some(value)`, or `none` for `None`.
+pub fn some_or_none(value: Option<Value>) -> Value {
+    let (tag, values) = match value {
+        Some(value) => (SOME, vec![value]),
+        None => (NONE, Vec::new()),
+    };
+    Value::Variant(Rc::new(Variant {
+        ty: Rc::new(option_type()),
+        tag,
+        values,
+    }))
+}
+
+/// A value of an `EnumType`: the index of its variant and the payload.
+pub struct Variant {
+    pub ty: Rc<EnumType>,
+    pub tag: usize,
+    pub values: Vec<Value>,
+}
+
+impl Variant {
+    pub fn name(&self) -> &str {
+        &self.ty.variants[self.tag].name
+    }
+}
+
+/// A closure, in the representation of the engine that created it, or a C
+/// function declared with `extern fn`.
+#[derive(Clone)]
+pub enum Function {
+    Interpreted(Rc<eval::Closure>),
+    Compiled(Rc<vm::Closure>),
+    Foreign(Rc<ffi::Extern>),
+}
+
+impl Function {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Function::Interpreted(closure) => closure.name.as_deref(),
+            Function::Compiled(closure) => closure.function.name.as_deref(),
+            Function::Foreign(function) => Some(&function.name),
+        }
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unit => write!(f, "Unit"),
+            Value::Int(n) => write!(f, "Int({:?})", n),
+            Value::BigInt(n) => write!(f, "BigInt({})", n),
+            Value::Float(n) => write!(f, "Float({:?})", n),
+            Value::Bool(b) => write!(f, "Bool({:?})", b),
+            Value::String(s) => write!(f, "String({:?})", s),
+            Value::List(list) => write!(f, "List({:?})", list),
+            Value::Tuple(items) => {
+                let mut tuple = f.debug_tuple("Tuple");
+                for item in items.iter() {
+                    tuple.field(item);
+                }
+                tuple.finish()
+            }
+            Value::Map(map) => {
+                write!(f, "Map(")?;
+                f.debug_map()
+                    .entries(map.entries.iter().map(|(k, v)| (k, v)))
+                    .finish()?;
+                write!(f, ")")
+            }
+            Value::Struct(record) => {
+                let mut debug = f.debug_struct(&record.ty.name);
+                for ((name, _), value) in record.ty.fields.iter().zip(&record.fields) {
+                    debug.field(name, value);
+                }
+                debug.finish()
+            }
+            Value::Variant(variant) => {
+                let mut debug = f.debug_tuple(variant.name());
+                for value in &variant.values {
+                    debug.field(value);
+                }
+                debug.finish()
+            }
+            Value::Range(range) => write!(
+                f,
+                "Range {{ from: Int({:?}), to: Int({:?}), lower: {:?}, upper: {:?} }}",
+                range.from, range.to, range.lower, range.upper
+            ),
+            Value::Function(function) => match function.name() {
+                Some(name) => write!(f, "Function({})", name),
+                None => write!(f, "Function"),
+            },
+        }
+    }
+}
+
+impl Value {
+    /// The name a type annotation uses for the type of this value.
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Unit => "unit",
+            Value::Int(_) => "int",
+            Value::BigInt(_) => "bigint",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::String(_) => "str",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Map(_) => "map",
+            Value::Struct(record) => &record.ty.name,
+            Value::Variant(variant) => &variant.ty.name,
+            Value::Range(_) => "range",
+            Value::Function(_) => "fn",
+        }
+    }
+
+    /// Checks a value against a `typeannot`, widening ints annotated as
+    /// `bigint`. Any other name is the name of a struct.
+    pub fn check_type(self, typename: &str) -> Result<Value, String> {
+        match (typename, self) {
+            ("bigint", Value::Int(n)) => Ok(Value::BigInt(Rc::new(BigInt::from(n)))),
+            (_, value) if typename != value.type_name() => Err(format!(
+                "Type mismatch: expected {}, found {}",
+                typename,
+                value.type_name()
+            )),
+            (_, value) => Ok(value),
+        }
+    }
+
+    /// The value in `some(value)`, or `None` for `none`.
+    pub fn option(&self) -> Result<Option<Value>, String> {
+        match self {
+            Value::Variant(variant) if variant.ty.name == OPTION => match variant.tag {
+                SOME => Ok(Some(variant.values[0].clone())),
+                _ => Ok(None),
+            },
+            value => Err(format!("Not an option: '{:?}'", value)),
+        }
+    }
+
+    /// `int(x)`: truncates floats and parses strings.
+    pub fn to_int(&self) -> Result<Value, String> {
+        match self {
+            Value::Int(n) => Ok(Value::Int(*n)),
+            Value::Float(n) if *n >= -(2f64.powi(63)) && *n < 2f64.powi(63) => {
+                Ok(Value::Int(*n as i64))
+            }
+            Value::Float(_) => Err(FLOAT_TO_INT.into()),
+            Value::BigInt(n) => n.to_i64().map(Value::Int).ok_or(OVERFLOW.into()),
+            Value::String(s) => s
+                .trim_end_matches('\0')
+                .trim()
+                .parse::<i64>()
+                .map(Value::Int)
+                .map_err(|_| format!("Not an int: {:?}", s)),
+            value => Err(format!("Cannot convert {} to int", value.type_name())),
+        }
+    }
+
+    /// `float(x)`: widens ints and parses strings.
+    pub fn to_float(&self) -> Result<Value, String> {
+        match self {
+            Value::Int(n) => Ok(Value::Float(*n as f64)),
+            Value::BigInt(n) => Ok(Value::Float(n.to_f64())),
+            Value::Float(n) => Ok(Value::Float(*n)),
+            Value::String(s) => s
+                .trim_end_matches('\0')
+                .trim()
+                .parse::<f64>()
+                .map(Value::Float)
+                .map_err(|_| format!("Not a float: {:?}", s)),
+            value => Err(format!("Cannot convert {} to float", value.type_name())),
+        }
+    }
+
+    /// `bigint(x)`: widens ints, truncates floats and parses strings.
+    pub fn to_bigint(&self) -> Result<Value, String> {
+        let n = match self {
+            Value::Int(n) => BigInt::from(*n),
+            Value::BigInt(n) => return Ok(Value::BigInt(n.clone())),
+            Value::Float(n) => BigInt::from_f64(*n).ok_or(FLOAT_TO_INT)?,
+            Value::String(s) => BigInt::parse(s.trim_end_matches('\0').trim())
+                .ok_or_else(|| format!("Not an int: {:?}", s))?,
+            value => return Err(format!("Cannot convert {} to bigint", value.type_name())),
+        };
+        Ok(Value::BigInt(Rc::new(n)))
+    }
+
+    /// `list[index]`, counting negative indices from the end. A range as the
+    /// index slices the list. Tuples are indexed like lists and maps by key.
+    pub fn index(&self, index: &Value) -> Result<Value, String> {
+        match (self, index) {
+            (Value::Tuple(items), Value::Int(i)) => Self::position(*i, items.len())
+                .map(|i| items[i].clone())
+                .ok_or_else(|| index_out_of_bounds(*i, items.len())),
+            (Value::Map(map), key) => map.get(key)?.cloned().ok_or_else(|| key_not_found(key)),
+            (Value::List(list), Value::Int(i)) => Self::position(*i, list.len())
+                .map(|i| list[i].clone())
+                .ok_or_else(|| index_out_of_bounds(*i, list.len())),
+            (Value::List(_), Value::Range(range)) => {
+                let (from, to) = range.bounds();
+                self.slice(Some(&Value::Int(from)), Some(&Value::Int(to)))
+            }
+            (value, index) => Err(format!(
+                "Cannot index {} with {}",
+                value.type_name(),
+                index.type_name()
+            )),
+        }
+    }
+
+    /// `record.field`.
+    pub fn field(&self, field: &str) -> Result<Value, String> {
+        match self {
+            Value::Struct(record) => Ok(record.fields[record.ty.position(field)?].clone()),
+            value => Err(format!("No field '{}' in {}", field, value.type_name())),
+        }
+    }
+
+    /// `list[from..to]`, where missing bounds are the start and the end of the
+    /// list.
+    pub fn slice(&self, from: Option<&Value>, to: Option<&Value>) -> Result<Value, String> {
+        let Value::List(list) = self else {
+            return Err(format!("Cannot slice {}", self.type_name()));
+        };
+        let bound = |bound: Option<&Value>, default: usize| match bound {
+            None => Ok(default as i64),
+            Some(Value::Int(i)) => Ok(*i),
+            Some(value) => Err(format!("Cannot slice with {}", value.type_name())),
+        };
+        let (from, to) = (bound(from, 0)?, bound(to, list.len())?);
+        let len = list.len() as i64;
+        let normalize = |i: i64| if i < 0 { i + len } else { i };
+        match (normalize(from), normalize(to)) {
+            (start, end) if 0 <= start && start <= end && end <= len => Ok(Value::List(Rc::new(
+                list[start as usize..end as usize].to_vec(),
+            ))),
+            _ => Err(slice_out_of_bounds(from, to, list.len())),
+        }
+    }
+
+    /// Replaces the element at `indices` in nested lists, maps and structs,
+    /// copying only the ones that are shared. Structs are indexed by field
+    /// name and keep the declared types of their fields. Nothing changes when
+    /// an index is out of bounds.
+    pub fn set_index(&mut self, indices: &[Value], value: Value) -> Result<(), String> {
+        let Some((index, rest)) = indices.split_first() else {
+            *self = value;
+            return Ok(());
+        };
+        match (self, index) {
+            (Value::List(list), Value::Int(i)) => {
+                let position = Self::position(*i, list.len())
+                    .ok_or_else(|| index_out_of_bounds(*i, list.len()))?;
+                Rc::make_mut(list)[position].set_index(rest, value)
+            }
+            (Value::Map(map), key) if rest.is_empty() => {
+                Rc::make_mut(map).insert(key.clone(), value)
+            }
+            (Value::Map(map), key) => match Rc::make_mut(map).get_mut(key)? {
+                Some(item) => item.set_index(rest, value),
+                None => Err(key_not_found(key)),
+            },
+            (Value::Struct(record), Value::String(field)) => {
+                let position = record.ty.position(field)?;
+                let record = Rc::make_mut(record);
+                if rest.is_empty() {
+                    let typename = &record.ty.fields[position].1;
+                    record.fields[position] = value.check_type(typename)?;
+                    return Ok(());
+                }
+                record.fields[position].set_index(rest, value)
+            }
+            (value, index) => Err(format!(
+                "Cannot assign to an index of {} with {}",
+                value.type_name(),
+                index.type_name()
+            )),
+        }
+    }
+
+    /// The items of a tuple or list that a pattern of `len` names binds.
+    pub fn destructure(&self, len: usize) -> Result<Vec<Value>, String> {
+        match self {
+            Value::Tuple(items) | Value::List(items) if items.len() == len => Ok(items.to_vec()),
+            Value::Tuple(items) | Value::List(items) => Err(arity_mismatch(len, items.len())),
+            value => Err(format!("Cannot destructure {}", value.type_name())),
+        }
+    }
+
+    /// `keys(map)` in insertion order.
+    pub fn keys(&self) -> Result<Value, String> {
+        match self {
+            Value::Map(map) => Ok(Value::List(Rc::new(
+                map.entries.iter().map(|(k, _)| k.clone()).collect(),
+            ))),
+            value => Err(format!("Not a map: '{:?}'", value)),
+        }
+    }
+
+    /// `values(map)` in insertion order.
+    pub fn values(&self) -> Result<Value, String> {
+        match self {
+            Value::Map(map) => Ok(Value::List(Rc::new(
+                map.entries.iter().map(|(_, v)| v.clone()).collect(),
+            ))),
+            value => Err(format!("Not a map: '{:?}'", value)),
+        }
+    }
+
+    /// `has(map, key)`.
+    pub fn has(&self, key: &Value) -> Result<Value, String> {
+        match self {
+            Value::Map(map) => Ok(Value::Bool(map.get(key)?.is_some())),
+            value => Err(format!("Not a map: '{:?}'", value)),
+        }
+    }
+
+    /// `remove(map, key)`: the map without `key`, which need not be in it.
+    pub fn remove(&self, key: &Value) -> Result<Value, String> {
+        match self {
+            Value::Map(map) => {
+                let mut map = map.clone();
+                Rc::make_mut(&mut map).remove(key)?;
+                Ok(Value::Map(map))
+            }
+            value => Err(format!("Not a map: '{:?}'", value)),
+        }
+    }
+
+    /// The key and value that iteration `index` of `for k, v in self`
+    /// visits, where lists and ranges are keyed by position.
+    pub fn entry(&self, index: usize) -> Result<Option<(Value, Value)>, String> {
+        Ok(match self {
+            Value::List(list) => list
+                .get(index)
+                .map(|value| (Value::Int(index as i64), value.clone())),
+            Value::Map(map) => map.entries.get(index).cloned(),
+            Value::Range(range) => {
+                let (start, end) = range.bounds();
+                let i = start + index as i64;
+                (i < end).then_some((Value::Int(index as i64), Value::Int(i)))
+            }
+            value => return Err(format!("Not an iterable: '{:?}'", value)),
+        })
+    }
+
+    /// What `for x in self` binds `x` to: the value of an entry, or the key
+    /// when iterating a map.
+    pub fn loop_var(&self, (key, value): (Value, Value)) -> Value {
+        match self {
+            Value::Map(_) => key,
+            _ => value,
+        }
+    }
+
+    /// The order of `sort`, `min` and `max`: numbers by value, and strings
+    /// and bools among themselves.
+    pub fn compare(&self, other: &Value) -> Result<Ordering, String> {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => Ok(a.cmp(b)),
+            (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+            (Value::Bool(a), Value::Bool(b)) => Ok(a.cmp(b)),
+            (
+                a @ (Value::Int(_) | Value::BigInt(_) | Value::Float(_)),
+                b @ (Value::Int(_) | Value::BigInt(_) | Value::Float(_)),
+            ) => {
+                for (op, ordering) in [
+                    (ast::Op::Lt, Ordering::Less),
+                    (ast::Op::Gt, Ordering::Greater),
+                    (ast::Op::Eq, Ordering::Equal),
+                ] {
+                    if let Value::Bool(true) = Self::binary(&op, a.clone(), b.clone())? {
+                        return Ok(ordering);
+                    }
+                }
+                Err(format!("Cannot compare {:?} and {:?}", a, b))
+            }
+            (a, b) => Err(format!("Cannot compare {:?} and {:?}", a, b)),
+        }
+    }
+
+    /// Whether two values are equal for `contains`: the items of lists,
+    /// tuples, structs and variants pairwise, and anything else that
+    /// `compare` orders as equal.
+    pub fn equals(&self, other: &Value) -> bool {
+        let all = |a: &[Value], b: &[Value]| {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.equals(b))
+        };
+        match (self, other) {
+            (Value::Unit, Value::Unit) => true,
+            (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => all(a, b),
+            (Value::Struct(a), Value::Struct(b)) => {
+                Rc::ptr_eq(&a.ty, &b.ty) && all(&a.fields, &b.fields)
+            }
+            (Value::Variant(a), Value::Variant(b)) => {
+                a.ty.name == b.ty.name && a.tag == b.tag && all(&a.values, &b.values)
+            }
+            (a, b) => a.compare(b) == Ok(Ordering::Equal),
+        }
+    }
+
+    fn position(index: i64, len: usize) -> Option<usize> {
+        let position = if index < 0 { index + len as i64 } else { index };
+        (0..len as i64)
+            .contains(&position)
+            .then_some(position as usize)
+    }
+
+    /// Applies a binary operator. Integer arithmetic that overflows is
+    /// redone on bigints, and an int combined with a float is widened to a
+    /// float.
+    pub fn binary(op: &ast::Op, lhs: Value, rhs: Value) -> Result<Value, String> {
+        Ok(match (op, lhs, rhs) {
+            (_, Value::Int(a), Value::Int(b)) => Self::int(op, a, b)?,
+            (_, Value::BigInt(a), Value::BigInt(b)) => Self::big(op, &a, &b)?,
+            (_, Value::BigInt(a), Value::Int(b)) => Self::big(op, &a, &BigInt::from(b))?,
+            (_, Value::Int(a), Value::BigInt(b)) => Self::big(op, &BigInt::from(a), &b)?,
+            (_, Value::BigInt(a), Value::Float(b)) => Self::float(op, a.to_f64(), b),
+            (_, Value::Float(a), Value::BigInt(b)) => Self::float(op, a, b.to_f64()),
+            (_, Value::Float(a), Value::Float(b)) => Self::float(op, a, b),
+            (_, Value::Int(a), Value::Float(b)) => Self::float(op, a as f64, b),
+            (_, Value::Float(a), Value::Int(b)) => Self::float(op, a, b as f64),
+            (ast::Op::Add, Value::List(a), Value::List(b)) => {
+                Value::List(Rc::new(a.iter().chain(b.iter()).cloned().collect()))
+            }
+            (
+                ast::Op::Mul,
+                a @ (Value::Int(_) | Value::BigInt(_) | Value::Float(_)),
+                Value::List(b),
+            ) => Value::List(Rc::new(
+                b.iter()
+                    .map(|x| Self::binary(op, a.clone(), x.clone()))
+                    .collect::<Result<_, _>>()?,
+            )),
+            (
+                ast::Op::Div,
+                Value::List(a),
+                b @ (Value::Int(_) | Value::BigInt(_) | Value::Float(_)),
+            ) => Value::List(Rc::new(
+                a.iter()
+                    .map(|x| Self::binary(op, x.clone(), b.clone()))
+                    .collect::<Result<_, _>>()?,
+            )),
+            (op, lhs, rhs) => {
+                return Err(format!(
+                    "Unsupported operation: {:?} {:?} {:?}",
+                    lhs, op, rhs
+                ))
+            }
+        })
+    }
+
+    fn int(op: &ast::Op, a: i64, b: i64) -> Result<Value, String> {
+        let checked = |result: Option<i64>| match result {
+            Some(n) => Ok(Value::Int(n)),
+            None => Self::big(op, &BigInt::from(a), &BigInt::from(b)),
+        };
+        match op {
+            ast::Op::Add => checked(a.checked_add(b)),
+            ast::Op::Sub => checked(a.checked_sub(b)),
+            ast::Op::Mul => checked(a.checked_mul(b)),
+            ast::Op::Div | ast::Op::Mod if b == 0 => Err(DIVISION_BY_ZERO.into()),
+            ast::Op::Div => checked(a.checked_div(b)),
+            ast::Op::Mod => checked(a.checked_rem(b)),
+            ast::Op::Pow if b < 0 => Err(NEGATIVE_EXPONENT.into()),
+            ast::Op::Pow => checked(u32::try_from(b).ok().and_then(|b| a.checked_pow(b))),
+            ast::Op::Eq => Ok(Value::Bool(a == b)),
+            ast::Op::Neq => Ok(Value::Bool(a != b)),
+            ast::Op::Gt => Ok(Value::Bool(a > b)),
+            ast::Op::Ge => Ok(Value::Bool(a >= b)),
+            ast::Op::Lt => Ok(Value::Bool(a < b)),
+            ast::Op::Le => Ok(Value::Bool(a <= b)),
+        }
+    }
+
+    fn big(op: &ast::Op, a: &BigInt, b: &BigInt) -> Result<Value, String> {
+        let big = |n: BigInt| Ok(Value::BigInt(Rc::new(n)));
+        match op {
+            ast::Op::Add => big(a + b),
+            ast::Op::Sub => big(a - b),
+            ast::Op::Mul => big(a * b),
+            ast::Op::Div => big(a.div_rem(b).ok_or(DIVISION_BY_ZERO)?.0),
+            ast::Op::Mod => big(a.div_rem(b).ok_or(DIVISION_BY_ZERO)?.1),
+            ast::Op::Pow if b.is_negative() => Err(NEGATIVE_EXPONENT.into()),
+            ast::Op::Pow => match b.to_i64() {
+                Some(b) => big(a.pow(b as u64)),
+                None => Err(OVERFLOW.into()),
+            },
+            ast::Op::Eq => Ok(Value::Bool(a == b)),
+            ast::Op::Neq => Ok(Value::Bool(a != b)),
+            ast::Op::Gt => Ok(Value::Bool(a > b)),
+            ast::Op::Ge => Ok(Value::Bool(a >= b)),
+            ast::Op::Lt => Ok(Value::Bool(a < b)),
+            ast::Op::Le => Ok(Value::Bool(a <= b)),
+        }
+    }
+
+    fn float(op: &ast::Op, a: f64, b: f64) -> Value {
+        match op {
+            ast::Op::Add => Value::Float(a + b),
+            ast::Op::Sub => Value::Float(a - b),
+            ast::Op::Mul => Value::Float(a * b),
+            ast::Op::Div => Value::Float(a / b),
+            ast::Op::Mod => Value::Float(a % b),
+            ast::Op::Pow => Value::Float(a.powf(b)),
+            ast::Op::Eq => Value::Bool(a == b),
+            ast::Op::Neq => Value::Bool(a != b),
+            ast::Op::Gt => Value::Bool(a > b),
+            ast::Op::Ge => Value::Bool(a >= b),
+            ast::Op::Lt => Value::Bool(a < b),
+            ast::Op::Le => Value::Bool(a <= b),
+        }
+    }
+}
@@ -0,0 +1,532 @@
+use std::rc::Rc;
+
+use crate::ast;
+use crate::bytecode::{
+    Builtin, Constant, Function, Module, Opcode, NO_ARM, NO_SLOT, SLICE_FROM, SLICE_TO,
+};
+use crate::ffi;
+use crate::io;
+use crate::lists;
+use crate::matching;
+use crate::math;
+use crate::process;
+use crate::value::{self, EnumType, Error, Map, Range, StructType, Value};
+
+pub struct Closure {
+    pub function: Rc<Function>,
+    captured: Vec<Value>,
+}
+
+#[derive(Clone)]
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    base: usize,
+}
+
+/// Where an error inside a `try` resumes: the frame at its handler, with
+/// the depth of the call and value stacks when the `try` began.
+struct Handler {
+    frame: Frame,
+    frames: usize,
+    stack: usize,
+}
+
+/// A stack machine executing a bytecode `Module`.
+pub struct Vm {
+    constants: Vec<Value>,
+    functions: Vec<Rc<Function>>,
+    structs: Vec<Rc<StructType>>,
+    enums: Vec<Rc<EnumType>>,
+    patterns: Vec<ast::MatchPattern<u16>>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    handlers: Vec<Handler>,
+    random: math::Random,
+    /// What `args()` returns.
+    args: Vec<String>,
+    /// Where the functions of `extern fn` declarations are found.
+    libraries: ffi::Libraries,
+}
+
+impl Vm {
+    pub fn new(module: Module, args: Vec<String>, libraries: ffi::Libraries) -> Self {
+        let constants = module
+            .constants
+            .into_iter()
+            .map(|constant| match constant {
+                Constant::Int(n) => Value::Int(n),
+                Constant::BigInt(n) => Value::BigInt(n),
+                Constant::Float(n) => Value::Float(n),
+                Constant::String(s) => Value::String(s),
+                Constant::Extern(function) => Value::Function(value::Function::Foreign(function)),
+            })
+            .collect();
+        Self {
+            constants,
+            functions: module.functions.into_iter().map(Rc::new).collect(),
+            structs: module.structs.into_iter().map(Rc::new).collect(),
+            enums: module.enums.into_iter().map(Rc::new).collect(),
+            patterns: module.patterns,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            handlers: Vec::new(),
+            random: math::Random::default(),
+            args,
+            libraries,
+        }
+    }
+
+    pub fn run(&mut self) -> Result<Value, String> {
+        let closure = Rc::new(Closure {
+            function: self.functions[Module::ENTRY].clone(),
+            captured: Vec::new(),
+        });
+        self.stack
+            .push(Value::Function(value::Function::Compiled(closure)));
+        let frame = self.call(0)?;
+        self.complete(frame, false).map_err(|e| e.to_string())
+    }
+
+    /// Executes `frame` until it returns, resuming at the handler of any
+    /// `try` entered meanwhile. An uncaught error gets the lines of the
+    /// calls made since, and of `frame` itself if it was `called` from a
+    /// builtin.
+    fn complete(&mut self, mut frame: Frame, called: bool) -> Result<Value, Error> {
+        let floor = self.frames.len();
+        let handlers = self.handlers.len();
+        loop {
+            let e = match self.execute(&mut frame, floor) {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            if self.handlers.len() > handlers {
+                let handler = self.handlers.pop().unwrap();
+                self.frames.truncate(handler.frames);
+                self.stack.truncate(handler.stack);
+                self.stack.push(e.into_value());
+                frame = handler.frame;
+            } else {
+                let e = self.trace(e, &frame, floor, called);
+                self.frames.truncate(floor);
+                return Err(e);
+            }
+        }
+    }
+
+    /// Calls `function` with `args` for a builtin executing in `caller`.
+    fn call_value(
+        &mut self,
+        caller: &Frame,
+        function: &Value,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        if let Value::Function(value::Function::Foreign(function)) = function {
+            return Ok(self.libraries.call(function, args)?);
+        }
+        let argc = args.len();
+        self.stack.push(function.clone());
+        self.stack.extend(args);
+        self.frames.push(caller.clone());
+        let result = match self.call(argc) {
+            Ok(callee) => self.complete(callee, true),
+            Err(e) => Err(e.into()),
+        };
+        self.frames.pop();
+        result
+    }
+
+    /// Adds the line of every active call above `floor` to an uncaught
+    /// error, from the innermost out.
+    fn trace(&self, mut e: Error, frame: &Frame, floor: usize, called: bool) -> Error {
+        let line = |frame: &Frame| frame.closure.function.line_at(frame.ip.saturating_sub(1));
+        let mut callee = frame;
+        let mut line_of_callee = line(frame);
+        if let Some(line) = line_of_callee {
+            e.at_line(line as usize);
+        }
+        for caller in self.frames[floor..].iter().rev() {
+            // Errors outside of any line are the caller's, like argument
+            // type errors in the interpreter.
+            if line_of_callee.is_some() {
+                e.leave(callee.closure.function.name.as_deref());
+            }
+            line_of_callee = line(caller);
+            if let Some(line) = line_of_callee {
+                e.at_line(line as usize);
+            }
+            callee = caller;
+        }
+        if called && line_of_callee.is_some() {
+            e.leave(callee.closure.function.name.as_deref());
+        }
+        e
+    }
+
+    fn call(&mut self, argc: usize) -> Result<Frame, String> {
+        let base = self.stack.len() - argc;
+        let closure = match &self.stack[base - 1] {
+            Value::Function(value::Function::Compiled(closure)) => closure.clone(),
+            value => return Err(format!("Not a function: '{:?}'", value)),
+        };
+        let function = &closure.function;
+        self.stack
+            .truncate(base + argc.min(function.arity as usize));
+        self.stack.resize(base + function.slots.len(), Value::Unit);
+        for (capture, value) in function.captures.iter().zip(&closure.captured) {
+            self.stack[base + capture.to as usize] = value.clone();
+        }
+        if let Some(slot) = function.self_slot {
+            self.stack[base + slot as usize] =
+                Value::Function(value::Function::Compiled(closure.clone()));
+        }
+        Ok(Frame {
+            closure,
+            ip: 0,
+            base,
+        })
+    }
+
+    /// Executes `frame` until it returns to the frames below `floor`.
+    fn execute(&mut self, frame: &mut Frame, floor: usize) -> Result<Value, Error> {
+        let mut function = frame.closure.function.clone();
+        loop {
+            let op = Opcode::from_byte(function.code[frame.ip])
+                .ok_or_else(|| format!("Invalid opcode: {}", function.code[frame.ip]))?;
+            let operands = frame.ip + 1;
+            frame.ip = operands + op.operand_len();
+            let code = &function.code;
+            let u16_at = |at: usize| u16::from_le_bytes([code[at], code[at + 1]]) as usize;
+
+            match op {
+                Opcode::Const => self.stack.push(self.constants[u16_at(operands)].clone()),
+                Opcode::Nada => self.stack.push(Value::Unit),
+                Opcode::True => self.stack.push(Value::Bool(true)),
+                Opcode::False => self.stack.push(Value::Bool(false)),
+                Opcode::Pop => {
+                    self.stack.pop();
+                }
+                Opcode::Dup => self.stack.push(self.stack.last().unwrap().clone()),
+                Opcode::GetLocal => {
+                    let value = self.stack[frame.base + u16_at(operands)].clone();
+                    self.stack.push(value);
+                }
+                Opcode::SetLocal => {
+                    let value = self.stack.pop().unwrap();
+                    self.stack[frame.base + u16_at(operands)] = value;
+                }
+                Opcode::Add
+                | Opcode::Sub
+                | Opcode::Mul
+                | Opcode::Div
+                | Opcode::Mod
+                | Opcode::Pow
+                | Opcode::Eq
+                | Opcode::Neq
+                | Opcode::Gt
+                | Opcode::Ge
+                | Opcode::Lt
+                | Opcode::Le => {
+                    let rhs = self.stack.pop().unwrap();
+                    let lhs = self.stack.pop().unwrap();
+                    let op = match op {
+                        Opcode::Add => ast::Op::Add,
+                        Opcode::Sub => ast::Op::Sub,
+                        Opcode::Mul => ast::Op::Mul,
+                        Opcode::Div => ast::Op::Div,
+                        Opcode::Mod => ast::Op::Mod,
+                        Opcode::Pow => ast::Op::Pow,
+                        Opcode::Eq => ast::Op::Eq,
+                        Opcode::Neq => ast::Op::Neq,
+                        Opcode::Gt => ast::Op::Gt,
+                        Opcode::Ge => ast::Op::Ge,
+                        Opcode::Lt => ast::Op::Lt,
+                        _ => ast::Op::Le,
+                    };
+                    self.stack.push(Value::binary(&op, lhs, rhs)?);
+                }
+                Opcode::Jump => frame.ip += u16_at(operands),
+                Opcode::JumpIfFalse => match self.stack.pop().unwrap() {
+                    Value::Bool(true) => {}
+                    Value::Bool(false) => frame.ip += u16_at(operands),
+                    value => return Err(format!("Not a bool: '{:?}'", value).into()),
+                },
+                Opcode::Loop => frame.ip -= u16_at(operands),
+                Opcode::Try => {
+                    // `none` stays on the stack for the return that follows.
+                    if let Some(value) = self.stack.last().unwrap().option()? {
+                        *self.stack.last_mut().unwrap() = value;
+                        frame.ip += u16_at(operands);
+                    }
+                }
+                Opcode::Throw => return Err(Error::throw(self.stack.pop().unwrap())),
+                Opcode::Catch => self.handlers.push(Handler {
+                    frame: Frame {
+                        ip: frame.ip + u16_at(operands),
+                        ..frame.clone()
+                    },
+                    frames: self.frames.len(),
+                    stack: self.stack.len(),
+                }),
+                Opcode::EndCatch => {
+                    self.handlers.pop();
+                }
+                Opcode::List => {
+                    let len = u16_at(operands);
+                    let list = self.stack.split_off(self.stack.len() - len);
+                    self.stack.push(Value::List(Rc::new(list)));
+                }
+                Opcode::Tuple => {
+                    let len = u16_at(operands);
+                    let items = self.stack.split_off(self.stack.len() - len);
+                    self.stack.push(Value::Tuple(Rc::new(items)));
+                }
+                Opcode::Struct => {
+                    let ty = &self.structs[u16_at(operands)];
+                    let values = self.stack.split_off(self.stack.len() - ty.fields.len());
+                    self.stack.push(ty.construct(values)?);
+                }
+                Opcode::Field => {
+                    if let Value::String(field) = &self.constants[u16_at(operands)] {
+                        let record = self.stack.pop().unwrap();
+                        self.stack.push(record.field(field)?);
+                    }
+                }
+                Opcode::Variant => {
+                    let ty = &self.enums[u16_at(operands)];
+                    let tag = u16_at(operands + 2);
+                    let len = ty.variants[tag].fields.len();
+                    let values = self.stack.split_off(self.stack.len() - len);
+                    self.stack.push(ty.construct(tag, values)?);
+                }
+                Opcode::Match => {
+                    let subject = self.stack.pop().unwrap();
+                    let index = u16_at(operands);
+                    if index == NO_ARM as usize {
+                        return Err(format!("No arm matches {:?}", subject).into());
+                    }
+                    let mut bindings = Vec::new();
+                    let matched = matching::matches(&self.patterns[index], &subject, &mut bindings);
+                    if matched {
+                        for (slot, value) in bindings {
+                            self.stack[frame.base + *slot as usize] = value;
+                        }
+                    }
+                    self.stack.push(Value::Bool(matched));
+                }
+                Opcode::Unpack => {
+                    let value = self.stack.pop().unwrap();
+                    let items = value.destructure(code[operands] as usize)?;
+                    self.stack.extend(items);
+                }
+                Opcode::Map => {
+                    let len = u16_at(operands);
+                    let pairs = self.stack.split_off(self.stack.len() - 2 * len);
+                    let mut map = Map::default();
+                    for pair in pairs.chunks(2) {
+                        map.insert(pair[0].clone(), pair[1].clone())?;
+                    }
+                    self.stack.push(Value::Map(Rc::new(map)));
+                }
+                Opcode::Range => {
+                    let flags = code[operands];
+                    let to = self.stack.pop().unwrap();
+                    let from = self.stack.pop().unwrap();
+                    let bound = |exclusive: bool| match exclusive {
+                        true => ast::Bound::Exclusive,
+                        false => ast::Bound::Inclusive,
+                    };
+                    match (from, to) {
+                        (Value::Int(from), Value::Int(to)) => {
+                            self.stack.push(Value::Range(Range {
+                                from,
+                                to,
+                                lower: bound(flags & 1 != 0),
+                                upper: bound(flags & 2 != 0),
+                            }))
+                        }
+                        (from, to) => {
+                            return Err(format!("Not an int: '{:?}..{:?}'", from, to).into())
+                        }
+                    }
+                }
+                Opcode::ForPrep => {
+                    let iterable = self.stack.pop().unwrap();
+                    if !matches!(iterable, Value::List(_) | Value::Map(_) | Value::Range(_)) {
+                        return Err(format!("Not an iterable: '{:?}'", iterable).into());
+                    }
+                    self.stack[frame.base + u16_at(operands)] = iterable;
+                    self.stack[frame.base + u16_at(operands + 2)] = Value::Int(0);
+                    self.stack[frame.base + u16_at(operands + 4)] =
+                        Value::List(Rc::new(Vec::new()));
+                }
+                Opcode::ForNext => {
+                    let index_slot = frame.base + u16_at(operands + 2);
+                    let index = match self.stack[index_slot] {
+                        Value::Int(n) => n as usize,
+                        _ => return Err("Corrupt loop index".to_string().into()),
+                    };
+                    let iterable = &self.stack[frame.base + u16_at(operands)];
+                    match iterable.entry(index)? {
+                        Some(entry) => {
+                            let value_slot = u16_at(operands + 6);
+                            if value_slot == NO_SLOT as usize {
+                                self.stack[frame.base + u16_at(operands + 4)] =
+                                    iterable.loop_var(entry);
+                            } else {
+                                self.stack[frame.base + u16_at(operands + 4)] = entry.0;
+                                self.stack[frame.base + value_slot] = entry.1;
+                            }
+                            self.stack[index_slot] = Value::Int(index as i64 + 1);
+                        }
+                        None => frame.ip += u16_at(operands + 8),
+                    }
+                }
+                Opcode::Collect => {
+                    let value = self.stack.pop().unwrap();
+                    if !matches!(value, Value::Unit) {
+                        if let Value::List(acc) = &mut self.stack[frame.base + u16_at(operands)] {
+                            Rc::make_mut(acc).push(value);
+                        }
+                    }
+                }
+                Opcode::Closure => {
+                    let function = self.functions[u16_at(operands)].clone();
+                    let captured = function
+                        .captures
+                        .iter()
+                        .map(|capture| self.stack[frame.base + capture.from as usize].clone())
+                        .collect();
+                    self.stack
+                        .push(Value::Function(value::Function::Compiled(Rc::new(
+                            Closure { function, captured },
+                        ))));
+                }
+                Opcode::Call => {
+                    let argc = code[operands] as usize;
+                    let base = self.stack.len() - argc;
+                    if let Value::Function(value::Function::Foreign(function)) =
+                        &self.stack[base - 1]
+                    {
+                        let function = function.clone();
+                        let args = self.stack.split_off(base);
+                        self.stack.pop();
+                        self.stack.push(self.libraries.call(&function, args)?);
+                    } else {
+                        let callee = self.call(argc)?;
+                        self.frames.push(std::mem::replace(frame, callee));
+                        function = frame.closure.function.clone();
+                    }
+                }
+                Opcode::Index => {
+                    let index = self.stack.pop().unwrap();
+                    let list = self.stack.pop().unwrap();
+                    self.stack.push(list.index(&index)?);
+                }
+                Opcode::Slice => {
+                    let bounds = code[operands];
+                    let to = (bounds & SLICE_TO != 0).then(|| self.stack.pop().unwrap());
+                    let from = (bounds & SLICE_FROM != 0).then(|| self.stack.pop().unwrap());
+                    let list = self.stack.pop().unwrap();
+                    self.stack.push(list.slice(from.as_ref(), to.as_ref())?);
+                }
+                Opcode::SetIndex => {
+                    let value = self.stack.pop().unwrap();
+                    let depth = code[operands + 2] as usize;
+                    let indices = self.stack.split_off(self.stack.len() - depth);
+                    self.stack[frame.base + u16_at(operands)].set_index(&indices, value)?;
+                }
+                Opcode::Builtin => {
+                    let builtin = Builtin::from_byte(code[operands])
+                        .ok_or_else(|| format!("Invalid builtin: {}", code[operands]))?;
+                    let argc = code[operands + 1] as usize;
+                    let args = self.stack.split_off(self.stack.len() - argc);
+                    let result = self.builtin(frame, builtin, args)?;
+                    self.stack.push(result);
+                }
+                Opcode::CheckType => {
+                    if let Value::String(typename) = &self.constants[u16_at(operands)] {
+                        let value = self.stack.pop().unwrap();
+                        self.stack.push(value.check_type(typename)?);
+                    }
+                }
+                Opcode::Return => {
+                    let result = self.stack.pop().unwrap();
+                    self.stack.truncate(frame.base - 1);
+                    // A `?` may return from inside a `try`.
+                    let depth = self.frames.len();
+                    self.handlers.retain(|handler| handler.frames < depth);
+                    if self.frames.len() == floor {
+                        return Ok(result);
+                    }
+                    *frame = self.frames.pop().unwrap();
+                    function = frame.closure.function.clone();
+                    self.stack.push(result);
+                }
+            }
+        }
+    }
+
+    fn builtin(
+        &mut self,
+        frame: &Frame,
+        builtin: Builtin,
+        args: Vec<Value>,
+    ) -> Result<Value, Error> {
+        if lists::arity(builtin.name()).is_some() {
+            return lists::apply(builtin.name(), args, &mut |function, args| {
+                self.call_value(frame, function, args)
+            });
+        }
+        if math::arity(builtin.name()).is_some() {
+            return Ok(math::apply(builtin.name(), &args, &mut self.random)?);
+        }
+        if io::arity(builtin.name()).is_some() {
+            return Ok(io::apply(builtin.name(), &args)?);
+        }
+        if process::arity(builtin.name()).is_some() {
+            return Ok(process::apply(builtin.name(), &args, &self.args)?);
+        }
+        Ok(match builtin {
+            Builtin::Print | Builtin::Printf => {
+                println!(
+                    "{}",
+                    args.iter()
+                        .map(|arg| format!("{:?}", arg))
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                );
+                Value::Unit
+            }
+            Builtin::Eprint => {
+                io::eprint(&args);
+                Value::Unit
+            }
+            Builtin::Backtrace if !args.is_empty() => {
+                return Err("backtrace takes no arguments".to_string().into())
+            }
+            Builtin::Backtrace => {
+                let e = self.trace(Error::from("Backtrace".to_string()), frame, 0, false);
+                println!("{}", e);
+                Value::Unit
+            }
+            Builtin::Int | Builtin::Float | Builtin::BigInt | Builtin::Keys | Builtin::Values
+                if args.len() != 1 =>
+            {
+                return Err(format!("{} takes 1 argument", builtin.name()).into())
+            }
+            Builtin::Has | Builtin::Remove | Builtin::UnwrapOr if args.len() != 2 => {
+                return Err(format!("{} takes 2 arguments", builtin.name()).into())
+            }
+            Builtin::Int => args[0].to_int()?,
+            Builtin::Float => args[0].to_float()?,
+            Builtin::BigInt => args[0].to_bigint()?,
+            Builtin::Keys => args[0].keys()?,
+            Builtin::Values => args[0].values()?,
+            Builtin::Has => args[0].has(&args[1])?,
+            Builtin::Remove => args[0].remove(&args[1])?,
+            Builtin::UnwrapOr => args[0].option()?.unwrap_or_else(|| args[1].clone()),
+            _ => unreachable!("{} is a list, math, io or process builtin", builtin.name()),
+        })
+    }
+}
@@ -0,0 +1,176 @@
+//! Precompiled modules that `alpha run` must reject before executing them.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+
+const CONST: u8 = 0;
+const NADA: u8 = 1;
+const TRUE: u8 = 2;
+const POP: u8 = 4;
+const GET_LOCAL: u8 = 6;
+const JUMP: u8 = 18;
+const JUMP_IF_FALSE: u8 = 19;
+const RETURN: u8 = 29;
+
+fn path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("alpha-bytecode-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// The magic and version that `alpha build --emit bytecode` writes.
+fn header() -> Vec<u8> {
+    static HEADER: OnceLock<Vec<u8>> = OnceLock::new();
+    HEADER
+        .get_or_init(|| {
+            let source = path("header.a");
+            let module = path("header.abc");
+            fs::write(&source, "print(1);\n").unwrap();
+            let status = Command::new(env!("CARGO_BIN_EXE_alpha"))
+                .args(["build", "--no-prelude", "--emit", "bytecode", "-o"])
+                .arg(&module)
+                .arg(&source)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            fs::read(&module).unwrap()[..6].to_vec()
+        })
+        .clone()
+}
+
+/// A module without constants, structs, enums or patterns, whose entry
+/// function has no parameters and the slots `slots`.
+fn module(slots: u32, code: &[u8]) -> Vec<u8> {
+    let mut bytes = header();
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend(1u32.to_le_bytes());
+    bytes.push(0);
+    bytes.extend(0u16.to_le_bytes());
+    bytes.extend(slots.to_le_bytes());
+    for _ in 0..slots {
+        bytes.extend(1u32.to_le_bytes());
+        bytes.push(b'x');
+    }
+    bytes.push(0);
+    bytes.extend(0u32.to_le_bytes());
+    bytes.extend((code.len() as u32).to_le_bytes());
+    bytes.extend(code);
+    bytes.extend(0u32.to_le_bytes());
+    for _ in 0..3 {
+        bytes.extend(0u32.to_le_bytes());
+    }
+    bytes
+}
+
+/// Runs the module `bytes` on the vm, resulting in its exit status and
+/// what it printed.
+fn run(name: &str, bytes: &[u8]) -> (i32, String) {
+    let module = path(name);
+    fs::write(&module, bytes).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_alpha"))
+        .arg("run")
+        .arg(&module)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let message = stdout.split_once(": ").map_or(&*stdout, |(_, e)| e);
+    (
+        output.status.code().unwrap(),
+        message.trim_end().to_string(),
+    )
+}
+
+fn rejects(name: &str, bytes: &[u8], error: &str) {
+    assert_eq!(run(name, bytes), (1, error.to_string()));
+}
+
+#[test]
+fn runs_a_valid_module() {
+    assert_eq!(
+        run("valid.abc", &module(0, &[NADA, RETURN])),
+        (0, String::new())
+    );
+}
+
+#[test]
+fn rejects_bad_magic() {
+    let mut bytes = module(0, &[NADA, RETURN]);
+    bytes[1] = b'X';
+    rejects("magic.abc", &bytes, "Not an alpha bytecode module");
+}
+
+#[test]
+fn rejects_bad_version() {
+    let mut bytes = module(0, &[NADA, RETURN]);
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]) + 1;
+    bytes[4..6].copy_from_slice(&version.to_le_bytes());
+    let error = format!(
+        "Unsupported bytecode version {} (expected {})",
+        version,
+        version - 1
+    );
+    rejects("version.abc", &bytes, &error);
+}
+
+#[test]
+fn rejects_truncated_modules() {
+    let bytes = module(0, &[NADA, RETURN]);
+    for len in [3, 6, 20, bytes.len() - 1] {
+        rejects("truncated.abc", &bytes[..len], "Unexpected end of module");
+    }
+    let mut bytes = module(0, &[NADA, RETURN]);
+    bytes.push(0);
+    rejects("trailing.abc", &bytes, "Trailing bytes after module");
+}
+
+#[test]
+fn rejects_out_of_range_operands() {
+    rejects(
+        "constant.abc",
+        &module(0, &[CONST, 0, 0, RETURN]),
+        "Invalid function #0: constant out of range at 0",
+    );
+    rejects(
+        "slot.abc",
+        &module(1, &[GET_LOCAL, 1, 0, RETURN]),
+        "Invalid function #0: slot out of range at 0",
+    );
+    rejects(
+        "jump.abc",
+        &module(0, &[NADA, JUMP, 1, 0, RETURN]),
+        "Invalid function #0: jump to 5 is not an instruction",
+    );
+    rejects(
+        "return.abc",
+        &module(0, &[NADA, NADA]),
+        "Invalid function #0: code does not end with Return",
+    );
+}
+
+#[test]
+fn rejects_popping_an_empty_stack() {
+    rejects(
+        "pop.abc",
+        &module(0, &[POP, POP, POP, RETURN]),
+        "Invalid function #0: Pop at 0 pops from an empty stack",
+    );
+    rejects(
+        "empty.abc",
+        &module(0, &[RETURN]),
+        "Invalid function #0: Return at 0 pops from an empty stack",
+    );
+    // A local is not a value on the stack.
+    rejects(
+        "local.abc",
+        &module(1, &[GET_LOCAL, 0, 0, POP, POP, NADA, RETURN]),
+        "Invalid function #0: Pop at 4 pops from an empty stack",
+    );
+    // Only one of the paths to the second `Nada` pushed a value.
+    rejects(
+        "join.abc",
+        &module(0, &[TRUE, JUMP_IF_FALSE, 1, 0, NADA, NADA, RETURN]),
+        "Invalid function #0: stack depth 0 at 5 was 1 before",
+    );
+}